@@ -29,7 +29,7 @@ pub fn main() {
 
             println!("Created context: {:#?}", ctx);
 
-            let mut queue = QueueBuilder::new(&ctx, &device).build().unwrap();
+            let queue = QueueBuilder::new(&ctx, &device).build().unwrap();
 
             println!("Created command queue: {:#?}", queue);
 
@@ -0,0 +1,53 @@
+extern crate dynamic_ocl;
+
+use dynamic_ocl::replay::{LaunchRecord, RecordedArg};
+use dynamic_ocl::session::ComputeSession;
+use std::env;
+
+pub fn main() {
+    let path = env::args()
+        .nth(1)
+        .expect("usage: replay <replay file> [device index]");
+    let device_index = env::args().nth(2).map(|i| i.parse::<usize>().unwrap());
+
+    let record = LaunchRecord::load(&path).unwrap();
+    println!(
+        "Loaded launch of kernel {:?} with global work size {:?} (options: {:?})",
+        record.kernel_name, record.global_work_size, record.options
+    );
+
+    let session = match device_index {
+        Some(index) => {
+            dynamic_ocl::load_opencl().unwrap();
+            let (_, device) = dynamic_ocl::all_devices()
+                .unwrap()
+                .dedup()
+                .nth(index)
+                .expect("device index out of range");
+            ComputeSession::with_device(device).unwrap()
+        }
+        None => ComputeSession::try_new().expect("no OpenCL device available"),
+    };
+    println!(
+        "Replaying on device {}",
+        session.device().name().unwrap().to_string_lossy()
+    );
+
+    let outputs = record.replay(session.queue()).unwrap();
+
+    let buffer_args = record
+        .args
+        .iter()
+        .enumerate()
+        .filter(|(_, arg)| matches!(arg, RecordedArg::Buffer(_)))
+        .map(|(index, _)| index);
+
+    for (index, output) in buffer_args.zip(outputs) {
+        println!(
+            "Argument {} after launch ({} bytes): {:?}",
+            index,
+            output.len(),
+            output
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Kernel source embedding and build-time validation
+//!
+//! `include_cl!` embeds an OpenCL C source file in the binary, to be built at
+//! runtime as usual. Since OpenCL C is normally only compiled at runtime,
+//! syntax errors in embedded kernels aren't caught until the program is built
+//! on a user's machine. To catch them earlier, call `validate_cl` for each
+//! embedded file from your build script (with this crate added as a build
+//! dependency):
+//!
+//! ```no_run
+//! // in build.rs
+//! dynamic_ocl::build_support::validate_cl("kernels/sum.cl");
+//! ```
+//!
+//! Validation is only performed when the `OPENCL_VALIDATE_AT_BUILD`
+//! environment variable is set, and uses an offline compiler - clang with its
+//! OpenCL C target, found using the `CLANG` environment variable if set, or
+//! from the `PATH` otherwise. If the compiler can't be found, a warning is
+//! emitted rather than failing the build.
+
+use std::env;
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+
+/// Embed an OpenCL C source file as a `&'static str`, given a path relative
+/// to the crate's manifest directory (matching the paths passed to
+/// `validate_cl`).
+///
+/// ```ignore
+/// const SUM: &str = dynamic_ocl::include_cl!("kernels/sum.cl");
+/// ```
+#[macro_export]
+macro_rules! include_cl {
+    ($path:literal) => {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $path))
+    };
+}
+
+/// The environment variable enabling build-time validation
+pub const VALIDATE_VAR: &str = "OPENCL_VALIDATE_AT_BUILD";
+
+/// Check an OpenCL C source file for errors with an offline compiler, if
+/// `OPENCL_VALIDATE_AT_BUILD` is set, given a path relative to the crate's
+/// manifest directory. This is meant to be called from a build script, and
+/// panics (failing the build) with the compiler output if the source doesn't
+/// compile. See the module documentation for details.
+pub fn validate_cl(path: impl AsRef<Path>) {
+    let path = path.as_ref();
+
+    println!("cargo:rerun-if-changed={}", path.display());
+    println!("cargo:rerun-if-env-changed={}", VALIDATE_VAR);
+    println!("cargo:rerun-if-env-changed=CLANG");
+
+    match env::var_os(VALIDATE_VAR) {
+        Some(v) if !v.is_empty() && v != "0" => {}
+        _ => return,
+    }
+
+    let clang = env::var_os("CLANG").unwrap_or_else(|| OsString::from("clang"));
+
+    let output = Command::new(&clang)
+        .args(["-x", "cl", "-cl-std=CL1.2", "-fsyntax-only"])
+        .args(["-Xclang", "-finclude-default-header"])
+        .arg(path)
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => panic!(
+            "OpenCL C validation of {} failed:\n{}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr)
+        ),
+        Err(e) if e.kind() == ErrorKind::NotFound => println!(
+            "cargo:warning=Not validating {}: {:?} not found",
+            path.display(),
+            clang
+        ),
+        Err(e) => panic!("Couldn't run {:?}: {}", clang, e),
+    }
+}
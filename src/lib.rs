@@ -10,40 +10,88 @@
 //! is enabled by default. If you prefer only the unsafe raw bindings, you can
 //! disable the feature flag by setting `default_features = false` for the
 //! dependency in your project manifest.
+//!
+//! To reduce compile time, the `safe` feature can also be replaced by
+//! `safe-core` and only the parts of the safe API which are needed:
+//!
+//! - `kernel-tuples`: kernel argument lists of more than 8 arguments
+//! - `images`: the `image` module
+//! - `svm`: the `svm` module, and shared virtual memory queries
+//! - `extensions`: the `ext` module (each extension also requires its own
+//!   feature flag)
 
 pub extern crate dlopen;
 
 #[macro_use]
 mod macros;
 
+pub mod build_support;
 pub mod raw;
 
-#[cfg(feature = "safe")]
+#[cfg(feature = "safe-core")]
 mod safe {
+    pub mod accounting;
+    pub mod backend;
     pub mod buffer;
     pub mod context;
+    pub mod debug_log;
+    pub mod dependencies;
+    pub mod deterministic;
     pub mod device;
     mod error;
+    pub mod event;
+    #[cfg(feature = "extensions")]
+    pub mod ext;
+    #[cfg(feature = "images")]
+    pub mod image;
     pub mod kernel;
+    pub mod labels;
     pub mod platform;
+    #[cfg(feature = "primitives")]
+    pub mod primitives;
     pub mod program;
     pub mod queue;
+    pub mod release;
+    pub mod replay;
+    pub mod scope;
+    pub mod session;
+    pub(crate) mod shutdown;
+    pub mod strict;
+    #[cfg(feature = "svm")]
+    pub mod svm;
+    #[cfg(feature = "tuner")]
+    pub mod tuner;
     pub mod util;
+    pub mod validation;
+    pub mod version;
 
     pub use error::*;
 }
 
-#[cfg(feature = "safe")]
+#[cfg(feature = "safe-core")]
 pub use safe::*;
 
+#[cfg(feature = "safe-core")]
+pub use safe::device::all_devices;
+
+#[cfg(feature = "safe-core")]
+pub use safe::shutdown::shutdown;
+
+#[cfg(feature = "safe-core")]
+pub use safe::scope::ocl_scope;
+
+#[cfg(feature = "safe-core")]
+pub use safe::release::set_release_error_handler;
+
 use crate::raw::OpenCLVersion;
 
 /// Attempt to load the system OpenCL library, if not already loaded.
 ///
 /// This function will search for the OpenCL library using the absolute path or
 /// library name specified by the `OPENCL_LIBRARY` environment variable if set,
-/// and from the system library path using a platform-specific version of the
-/// name `OpenCL` if not set.
+/// and from the system library path using each of the platform-specific names
+/// in `raw::CANDIDATES` in turn if not set. If no library can be found, the
+/// error lists the names which were tried.
 ///
 /// Once the library has been found and opened, symbols will be loaded from it
 /// and bound to the appropriate function pointers in the `raw` module. This
@@ -54,6 +102,7 @@ use crate::raw::OpenCLVersion;
 ///
 /// Calling any raw function before OpenCL has been loaded with this function
 /// will implicitly call this function to load OpenCL, panicking on failure.
+/// Implicit loads can be observed with `set_implicit_load_hook`.
 ///
 /// The result of this call (including implicit calls from raw function shims)
 /// will be stored in static memory, and future calls will simply return the
@@ -63,3 +112,52 @@ use crate::raw::OpenCLVersion;
 pub fn load_opencl() -> std::result::Result<OpenCLVersion, &'static dlopen::Error> {
     raw::functions::load_opencl()
 }
+
+/// Attempt to load the system OpenCL library as with `load_opencl`, without
+/// binding any functions introduced after the given version, even if the
+/// library provides them. The returned version is at most `max_version`, and
+/// calling a newer function panics as if the library didn't provide it.
+///
+/// This makes it possible to test an application's code paths for older
+/// OpenCL versions on a newer system, and to make sure it doesn't
+/// accidentally rely on newer functions. If OpenCL has already been loaded,
+/// the result of the earlier load is returned, without limiting the version.
+pub fn load_opencl_with_max_version(
+    max_version: OpenCLVersion,
+) -> std::result::Result<OpenCLVersion, &'static dlopen::Error> {
+    raw::functions::load_opencl_limited(raw::DlopenBackend::system, Some(max_version))
+}
+
+/// Set a hook called whenever OpenCL is loaded implicitly by calling a raw
+/// function before `load_opencl`, with the name of that function. The name is
+/// also included in the panic message if the implicit load fails.
+///
+/// This is useful to track down which code path first touches OpenCL in large
+/// applications, e.g. by logging the name along with a backtrace. The hook
+/// may be called from any thread.
+pub fn set_implicit_load_hook(hook: raw::ImplicitLoadHook) {
+    raw::functions::set_implicit_load_hook(hook)
+}
+
+/// Set how raw functions are bound when OpenCL is loaded - eagerly when
+/// loading (the default), or lazily when each function is first called. This
+/// must be called before OpenCL is loaded, and has no effect afterwards.
+///
+/// Function availability can be checked with `raw::is_available` without
+/// resolving other functions.
+pub fn set_binding_mode(mode: raw::BindingMode) {
+    raw::functions::set_binding_mode(mode)
+}
+
+/// Attempt to load OpenCL from the given backend, if not already loaded.
+///
+/// This behaves like `load_opencl`, but binds the raw functions to those
+/// provided by the given backend rather than the system OpenCL library. It
+/// must be called before OpenCL is loaded by any other means (including
+/// implicitly by calling a raw function) - otherwise, the backend is dropped
+/// and the result of the earlier load is returned.
+pub fn load_opencl_from(
+    backend: impl raw::Backend + 'static,
+) -> std::result::Result<OpenCLVersion, &'static dlopen::Error> {
+    raw::functions::load_opencl_with(|| Ok(backend))
+}
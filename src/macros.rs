@@ -13,16 +13,25 @@ macro_rules! opaque_type {
 }
 
 /// Define OpenCL error code constants and a function to get the name of an
-/// error code
+/// error code. Extension error codes are only included in the name table, since
+/// their constants are defined in the modules for each extension.
 macro_rules! error_codes {
-    ( $($name:ident = $value:expr),* $(,)? ) => {
+    (
+        $($name:ident = $value:expr),* $(,)?
+        ; extensions { $($ename:ident = $evalue:expr),* $(,)? }
+    ) => {
         $( pub const $name: i32 = $value; )*
 
+        mod extension_error_codes {
+            $( pub const $ename: i32 = $evalue; )*
+        }
+
         /// Get the name of an OpenCL error code, returning `None` if the error
         /// code is unknown
         pub fn error_name(code: i32) -> Option<&'static str> {
             match code {
                 $($name => Some(stringify!($name)),)*
+                $(extension_error_codes::$ename => Some(stringify!($ename)),)*
                 _ => None,
             }
         }
@@ -69,6 +78,166 @@ macro_rules! raw_functions {
         /// has been loaded.
         pub static mut SYSTEM_OPENCL_VERSION: OpenCLVersion = OpenCLVersion::None;
 
+        /// A table of the raw OpenCL functions loaded from the system library,
+        /// with `None` for any functions which the library doesn't provide.
+        #[derive(Clone, Copy)]
+        pub struct RawApi {
+            $(
+                $(
+                    pub $fname: Option<unsafe extern "C" fn( $( $pname : $pty ),* ) $( -> $rty )?>,
+                )*
+            )*
+        }
+
+        impl std::fmt::Debug for RawApi {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.debug_struct("RawApi")
+                    $(
+                        $(
+                            .field(stringify!($fname), &self.$fname.map(|p| p as *const std::ffi::c_void))
+                        )*
+                    )*
+                    .finish()
+            }
+        }
+
+        static mut RAW_API: Option<&'static RawApi> = None;
+
+        /// The backend OpenCL was loaded from, used to resolve functions when
+        /// binding lazily
+        static mut BACKEND: Option<&'static dyn crate::raw::Backend> = None;
+
+        /// The highest OpenCL version whose functions may be bound, if limited
+        /// by `load_opencl_with_max_version`
+        static mut MAX_VERSION: Option<OpenCLVersion> = None;
+
+        /// Check whether functions of the given version may be bound
+        fn within_max_version(version: OpenCLVersion) -> bool {
+            match unsafe { MAX_VERSION } {
+                Some(max) => version <= max,
+                None => true,
+            }
+        }
+
+        /// Get the OpenCL version introducing the raw function with the given
+        /// name, if it's a known function
+        fn version_of(name: &std::ffi::CStr) -> Option<OpenCLVersion> {
+            $(
+                $(
+                    if name == fnames::$fname.as_cstr() {
+                        return Some(OpenCLVersion::$apiname);
+                    }
+                )*
+            )*
+
+            None
+        }
+
+        /// A hook called when OpenCL is loaded implicitly, with the name of
+        /// the function which triggered the load
+        pub type ImplicitLoadHook = fn(&'static str);
+
+        lazy_static::lazy_static! {
+            static ref IMPLICIT_LOAD_HOOK: std::sync::RwLock<Option<ImplicitLoadHook>> = Default::default();
+        }
+
+        /// Set a hook called whenever a raw function (or `api`) is called
+        /// before OpenCL has been loaded, with the name of that function, just
+        /// before it attempts to load OpenCL implicitly. This makes it possible
+        /// to find which code path first uses OpenCL.
+        pub fn set_implicit_load_hook(hook: ImplicitLoadHook) {
+            *IMPLICIT_LOAD_HOOK.write().unwrap() = Some(hook);
+        }
+
+        /// Load OpenCL implicitly on a call to the given function if it hasn't
+        /// been loaded yet, calling the implicit load hook first and panicking
+        /// on failure
+        fn load_implicitly(caller: &'static str) {
+            let loaded = *OPENCL_LIB.lock().unwrap();
+            let result = loaded.unwrap_or_else(|| {
+                let hook = *IMPLICIT_LOAD_HOOK.read().unwrap();
+                if let Some(hook) = hook {
+                    hook(caller);
+                }
+
+                load_opencl()
+            });
+
+            if let Err(e) = result {
+                panic!(
+                    "error implicitly loading OpenCL library on call to {}: {}",
+                    caller, e
+                );
+            }
+        }
+
+        /// Whether functions are bound lazily - see `BindingMode`
+        static LAZY: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+        /// Guards resolving the function table when binding lazily
+        static RESOLVE_API: std::sync::Once = std::sync::Once::new();
+
+        /// How raw functions are bound to the symbols of the OpenCL library
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum BindingMode {
+            /// Resolve every function when OpenCL is loaded. This is the
+            /// default.
+            Eager,
+            /// Resolve each function the first time it's called. The OpenCL
+            /// version is detected from one function of each version, so
+            /// loading only looks up a handful of symbols - this avoids
+            /// resolving dozens of newer functions in applications which only
+            /// use a small subset of the API.
+            Lazy,
+        }
+
+        /// Set how raw functions are bound when OpenCL is loaded. This has no
+        /// effect once OpenCL has been loaded.
+        pub fn set_binding_mode(mode: BindingMode) {
+            LAZY.store(mode == BindingMode::Lazy, std::sync::atomic::Ordering::Relaxed);
+        }
+
+        /// Get the table of raw OpenCL functions, implicitly loading the system
+        /// OpenCL library if it hasn't been loaded yet and panicking on failure.
+        ///
+        /// Unlike the function pointers in `ptrs`, this table is immutable once
+        /// loaded, and can be used to check whether a function is available
+        /// before calling it. If functions are bound lazily, the first call
+        /// resolves every function.
+        pub fn api() -> &'static RawApi {
+            load_implicitly("api");
+
+            unsafe {
+                RESOLVE_API.call_once(|| {
+                    let resolved = RAW_API;
+                    if resolved.is_none() {
+                        let backend = BACKEND.expect("OpenCL library loaded without backend");
+                        RAW_API = Some(Box::leak(Box::new(resolve_all(backend).0)));
+                    }
+                });
+
+                RAW_API.expect("OpenCL library loaded without function table")
+            }
+        }
+
+        /// Check whether the loaded OpenCL library provides the function with
+        /// the given name (e.g. `clCreateCommandQueueWithProperties`),
+        /// implicitly loading the system OpenCL library if it hasn't been
+        /// loaded yet and panicking on failure. Unlike `api`, this only looks
+        /// up the given function.
+        pub fn is_available(name: &std::ffi::CStr) -> bool {
+            load_implicitly("is_available");
+
+            if !version_of(name).map_or(true, within_max_version) {
+                return false;
+            }
+
+            unsafe {
+                let backend = BACKEND.expect("OpenCL library loaded without backend");
+                matches!(backend.symbol(name), Some(addr) if !addr.is_null())
+            }
+        }
+
         mod fnames {
             use const_cstr::const_cstr;
             const_cstr! {
@@ -80,76 +249,165 @@ macro_rules! raw_functions {
             }
         }
 
-        pub unsafe fn load_opencl_internal() -> Result<OpenCLVersion, dlopen::Error> {
-            use std::env::var_os;
-            use dlopen::utils::platform_file_name;
-            use dlopen::raw::Library;
-            use dlopen::Error;
-
-            // load library
-            // prevent dangling symbols by ensuring it's never dropped
-            let name = var_os("OPENCL_LIBRARY").unwrap_or_else(|| platform_file_name("OpenCL"));
-            let lib = std::mem::ManuallyDrop::new(Library::open(name)?);
-
+        /// Resolve all raw functions provided by the given backend, returning
+        /// the function table and the supported OpenCL version
+        unsafe fn resolve_all(backend: &dyn crate::raw::Backend) -> (RawApi, OpenCLVersion) {
             // set OpenCL version compatibility flags
             $(
                 let mut $apiname = true;
             )*
 
             // load symbols and update version compatibility flags
-            $(
+            let api = RawApi {
                 $(
-                    let $fname: unsafe extern "C" fn( $( $pname : $pty ),*) $( -> $rty )* = match lib.symbol_cstr(fnames::$fname.as_cstr()) {
-                        Ok(addr) => addr,
-                        Err(Error::SymbolGettingError(_)) => {
-                        $apiname = false;
-                            missing_stubs::$fname
-                        }
-                        Err(e) => return Err(e),
-                    };
+                    $(
+                        $fname: match backend.symbol(fnames::$fname.as_cstr()) {
+                            _ if !within_max_version(OpenCLVersion::$apiname) => {
+                                $apiname = false;
+                                None
+                            }
+                            Some(addr) if !addr.is_null() => Some(std::mem::transmute::<
+                                *const std::ffi::c_void,
+                                unsafe extern "C" fn( $( $pty ),* ) $( -> $rty )?,
+                            >(addr)),
+                            _ => {
+                                $apiname = false;
+                                None
+                            }
+                        },
+                    )*
                 )*
+            };
+
+            let mut version = OpenCLVersion::None;
+            $(
+                if $apiname {
+                    version = OpenCLVersion::$apiname;
+                }
             )*
 
+            (api, version)
+        }
+
+        /// Bind all raw functions to those provided by the given backend,
+        /// returning the supported OpenCL version
+        unsafe fn load_backend(backend: &'static dyn crate::raw::Backend) -> OpenCLVersion {
+            BACKEND = Some(backend);
+
+            if LAZY.load(std::sync::atomic::Ordering::Relaxed) {
+                // detect the version from the first function of each version,
+                // and resolve the rest on first use
+                $(
+                    let first: Option<&const_cstr::ConstCStr> = [ $( &fnames::$fname ),* ].first().copied();
+                    if within_max_version(OpenCLVersion::$apiname)
+                        && first.map_or(true, |name| matches!(backend.symbol(name.as_cstr()), Some(addr) if !addr.is_null()))
+                    {
+                        SYSTEM_OPENCL_VERSION = OpenCLVersion::$apiname;
+                    }
+                )*
+
+                $(
+                    $(
+                        ptrs::$fname = lazy_stubs::$fname;
+                    )*
+                )*
+
+                return SYSTEM_OPENCL_VERSION;
+            }
+
+            let (api, version) = resolve_all(backend);
+
             // set function pointers once all symbols have been loaded
             $(
                 $(
-                    ptrs::$fname = $fname;
+                    ptrs::$fname = api.$fname.unwrap_or(missing_stubs::$fname);
                 )*
             )*
 
-            $(
-                if $apiname {
-                    SYSTEM_OPENCL_VERSION = OpenCLVersion::$apiname;
-                }
-            )*
+            RAW_API = Some(Box::leak(Box::new(api)));
+            SYSTEM_OPENCL_VERSION = version;
+            SYSTEM_OPENCL_VERSION
+        }
 
-            Ok(SYSTEM_OPENCL_VERSION)
+        /// Load OpenCL using the backend returned by the given function, if
+        /// not already loaded. The backend is kept alive for the rest of the
+        /// process.
+        pub fn load_opencl_with<B: crate::raw::Backend + 'static>(
+            backend: impl FnOnce() -> Result<B, dlopen::Error>,
+        ) -> Result<OpenCLVersion, &'static dlopen::Error> {
+            load_opencl_limited(backend, None)
         }
 
-        pub fn load_opencl() -> Result<OpenCLVersion, &'static dlopen::Error> {
+        /// Load OpenCL as with `load_opencl_with`, without binding functions
+        /// introduced after the given version (if any)
+        pub fn load_opencl_limited<B: crate::raw::Backend + 'static>(
+            backend: impl FnOnce() -> Result<B, dlopen::Error>,
+            max_version: Option<OpenCLVersion>,
+        ) -> Result<OpenCLVersion, &'static dlopen::Error> {
             let mut lock = OPENCL_LIB.lock().unwrap();
             if let Some(r) = *lock {
                 return r;
             }
 
-            let r = unsafe { load_opencl_internal() }.map_err(|e| Box::leak(Box::new(e)) as &_);
+            unsafe {
+                MAX_VERSION = max_version;
+            }
+
+            let r = backend()
+                .map(|b| unsafe { load_backend(Box::leak(Box::new(b)) as &'static B) })
+                .map_err(|e| Box::leak(Box::new(e)) as &_);
             *lock = Some(r);
             r
         }
 
+        pub fn load_opencl() -> Result<OpenCLVersion, &'static dlopen::Error> {
+            load_opencl_with(crate::raw::DlopenBackend::system)
+        }
+
         mod load_stubs {
             use super::*;
 
             $(
                 $(
                     pub unsafe extern "C" fn $fname( $( $pname : $pty ),* ) $( -> $rty )* {
-                        load_opencl().expect("error implicitly loading OpenCL library");
+                        load_implicitly(stringify!($fname));
                         ptrs::$fname( $( $pname ),* )
                     }
                 )*
             )*
         }
 
+        mod lazy_stubs {
+            use super::*;
+            use std::ffi::c_void;
+            use std::sync::atomic::{AtomicPtr, Ordering};
+
+            $(
+                $(
+                    pub unsafe extern "C" fn $fname( $( $pname : $pty ),* ) $( -> $rty )* {
+                        type Ptr = unsafe extern "C" fn( $( $pty ),* ) $( -> $rty )?;
+
+                        // the resolved function is cached here rather than in
+                        // `ptrs`, which other threads may be reading
+                        static RESOLVED: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+                        let mut addr = RESOLVED.load(Ordering::Acquire);
+                        if addr.is_null() {
+                            let backend = BACKEND.expect("OpenCL library loaded without backend");
+                            addr = match backend.symbol(fnames::$fname.as_cstr()) {
+                                _ if !within_max_version(OpenCLVersion::$apiname) => missing_stubs::$fname as Ptr as *mut c_void,
+                                Some(addr) if !addr.is_null() => addr as *mut c_void,
+                                _ => missing_stubs::$fname as Ptr as *mut c_void,
+                            };
+                            RESOLVED.store(addr, Ordering::Release);
+                        }
+
+                        std::mem::transmute::<*mut c_void, Ptr>(addr)( $( $pname ),* )
+                    }
+                )*
+            )*
+        }
+
         #[allow(unused_variables)]
         mod missing_stubs {
             use super::*;
@@ -175,8 +433,72 @@ macro_rules! raw_functions {
     }
 }
 
+/// Define a table of OpenCL extension functions, which are loaded at runtime
+/// for a given platform rather than when the library is loaded
+#[allow(unused_macros)]
+macro_rules! extension_functions {
+    (
+        $( #[ $outer:meta ] )*
+        pub struct $name:ident {
+            $(
+                fn $fname:ident ( $( $pname:ident : $pty:ty ),* $(,)? ) $( -> $rty:ty )? ;
+            )*
+        }
+    ) => {
+        $( #[ $outer ] )*
+        #[derive(Clone, Copy)]
+        pub struct $name {
+            $(
+                pub $fname: unsafe extern "C" fn( $( $pname : $pty ),* ) $( -> $rty )?,
+            )*
+        }
+
+        impl $name {
+            /// Load the functions of this extension for the given platform,
+            /// returning `None` if any of them are unavailable.
+            ///
+            /// # Safety
+            ///
+            /// If the given handle is not a valid OpenCL platform ID, behavior
+            /// is undefined.
+            pub unsafe fn load(platform: crate::raw::cl_platform_id) -> Option<Self> {
+                Some(Self {
+                    $(
+                        $fname: {
+                            let name = concat!(stringify!($fname), "\0");
+                            let addr = crate::raw::ext::get_extension_function_address(
+                                platform,
+                                std::ffi::CStr::from_bytes_with_nul_unchecked(name.as_bytes()),
+                            );
+
+                            if addr.is_null() {
+                                return None;
+                            }
+
+                            std::mem::transmute::<
+                                *mut std::ffi::c_void,
+                                unsafe extern "C" fn( $( $pname : $pty ),* ) $( -> $rty )?,
+                            >(addr)
+                        },
+                    )*
+                })
+            }
+        }
+
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.debug_struct(stringify!($name))
+                    $(
+                        .field(stringify!($fname), &(self.$fname as *const std::ffi::c_void))
+                    )*
+                    .finish()
+            }
+        }
+    };
+}
+
 /// Wrap an OpenCL error code in a result
-#[cfg(feature = "safe")]
+#[cfg(feature = "safe-core")]
 macro_rules! wrap_result {
     ( $ctx:expr => $e:expr ) => {
         match $e {
@@ -188,7 +510,7 @@ macro_rules! wrap_result {
 
 macro_rules! check_ocl_version {
     ( $context:expr => $version:ident ) => {
-        if crate::raw::SYSTEM_OPENCL_VERSION <= crate::raw::OpenCLVersion::$version {
+        if crate::raw::SYSTEM_OPENCL_VERSION < crate::raw::OpenCLVersion::$version {
             Err(crate::Error::UnsupportedVersion {
                 expected: crate::raw::OpenCLVersion::$version,
                 actual: crate::raw::SYSTEM_OPENCL_VERSION,
@@ -201,7 +523,7 @@ macro_rules! check_ocl_version {
 }
 
 /// Define OpenCL info functions
-#[cfg(feature = "safe")]
+#[cfg(feature = "safe-core")]
 macro_rules! info_funcs {
     (
         $(
@@ -234,7 +556,7 @@ macro_rules! info_funcs {
 }
 
 /// Define a wrapper for an OpenCL bitfield type
-#[cfg(feature = "safe")]
+#[cfg(feature = "safe-core")]
 macro_rules! bitfield {
     (
         $( #[ $outer:meta ] )*
@@ -344,7 +666,7 @@ macro_rules! bitfield {
 }
 
 /// Define a wrapper for an OpenCL flag type, with a fixed set of valid values
-#[cfg(feature = "safe")]
+#[cfg(feature = "safe-core")]
 macro_rules! flag_enum {
     (
         $( #[ $outer:meta ] )*
@@ -395,14 +717,14 @@ macro_rules! flag_enum {
 }
 
 /// Count the length of a tuple type
-#[cfg(feature = "safe")]
+#[cfg(feature = "safe-core")]
 macro_rules! tuple_len {
     ( () ) => { 0 };
     ( ( $head:ty $( , $tail:ty )* $(,)? ) ) => { 1 + tuple_len! { ( $( $tail ),* ) } };
 }
 
 /// Define KernelArgList implementations for tuple types
-#[cfg(feature = "safe")]
+#[cfg(feature = "safe-core")]
 macro_rules! kernel_arg_list_tuples {
     (
         $(
@@ -411,6 +733,7 @@ macro_rules! kernel_arg_list_tuples {
         ),* $(,)?
     ) => {
         $(
+            $( #[ $meta ] )*
             #[allow(unused_parens)]
             impl<
                 $( $tyvar : KernelArg ),*
@@ -429,9 +752,64 @@ macro_rules! kernel_arg_list_tuples {
 
                     Ok(Kernel {
                         kernel,
-                        args: ( $( $tyvar ),* )
+                        args: ( $( $tyvar ),* ),
+                        trailing: vec![],
                     })
                 }
+
+                #[allow(non_snake_case)]
+                fn buffer_lens(kernel: &Kernel<Self>) -> Vec<Option<usize>> {
+                    let ( $( $tyvar ),* ) = &kernel.args;
+                    let mut lens = vec![ $( $tyvar.get().buffer_len() ),* ];
+                    lens.extend(kernel.trailing.iter().map(|b| Some(b.len)));
+                    lens
+                }
+
+                #[allow(non_snake_case)]
+                fn buffer_mems(kernel: &Kernel<Self>) -> Vec<$crate::raw::cl_mem> {
+                    let ( $( $tyvar ),* ) = &kernel.args;
+                    let mems: Vec<Option<$crate::raw::cl_mem>> = vec![ $( $tyvar.get().buffer_mem() ),* ];
+                    mems.into_iter()
+                        .flatten()
+                        .chain(kernel.trailing.iter().map(|b| b.handle))
+                        .collect()
+                }
+
+                #[allow(non_snake_case, unused_mut)]
+                fn raw_args(kernel: &Kernel<Self>) -> Vec<sealed::RawArg> {
+                    let ( $( $tyvar ),* ) = &kernel.args;
+                    let mut args: Vec<sealed::RawArg> = vec![ $( {
+                        let arg = $tyvar.get();
+                        match (arg.buffer_mem(), arg.local_size()) {
+                            (Some(mem), _) => sealed::RawArg::Mem(mem),
+                            (None, Some(size)) => sealed::RawArg::Local(size),
+                            (None, None) => {
+                                let (size, value) = arg.as_raw_kernel_arg();
+                                let bytes = unsafe {
+                                    std::slice::from_raw_parts(value as *const _ as *const u8, size)
+                                };
+                                sealed::RawArg::Value(bytes.to_vec())
+                            }
+                        }
+                    } ),* ];
+                    args.extend(kernel.trailing.iter().map(|b| sealed::RawArg::Mem(b.handle)));
+                    args
+                }
+
+                #[allow(non_snake_case, unused_variables, unused_assignments, unused_mut)]
+                fn value_mut(kernel: &mut Kernel<Self>, index: usize) -> Option<&mut dyn std::any::Any> {
+                    let ( $( $tyvar ),* ) = &mut kernel.args;
+                    let mut idx = 0;
+
+                    $(
+                        if idx == index {
+                            return $tyvar.value.as_value_mut();
+                        }
+                        idx += 1;
+                    )*
+
+                    None
+                }
             }
 
             $( #[ $meta ] )*
@@ -448,6 +826,7 @@ macro_rules! kernel_arg_list_tuples {
                 const NUM_ARGS: usize = tuple_len! { ( $( $tyvar ),* ) };
             }
 
+            $( #[ $meta ] )*
             #[allow(unused_parens)]
             impl<
                 'a,
@@ -456,8 +835,13 @@ macro_rules! kernel_arg_list_tuples {
                 type Projected = (
                     $( Pin<&'a mut Bound<$tyvar>> ),*
                 );
+
+                type Accessors = (
+                    $( ArgMut<'a, $tyvar> ),*
+                );
             }
 
+            $( #[ $meta ] )*
             #[allow(unused_parens)]
             impl<
                 'a,
@@ -470,6 +854,14 @@ macro_rules! kernel_arg_list_tuples {
                         ( $( Pin::new_unchecked($tyvar) ),* )
                     }
                 }
+
+                #[allow(non_snake_case, clippy::unused_unit)]
+                fn accessors(self: Pin<&'a mut Self>) -> <Self as BindProject<'a>>::Accessors {
+                    unsafe {
+                        let ( $( $tyvar ),* ) = self.get_unchecked_mut();
+                        ( $( ArgMut(Pin::new_unchecked($tyvar)) ),* )
+                    }
+                }
             }
         )*
     }
@@ -111,6 +111,11 @@ mod types {
     pub type cl_profiling_info = cl_uint;
     pub type cl_sampler_properties = cl_bitfield;
     pub type cl_kernel_exec_info = cl_uint;
+    pub type cl_properties = cl_ulong;
+    pub type cl_version = cl_uint;
+    pub type cl_mem_properties = cl_properties;
+    pub type cl_device_atomic_capabilities = cl_bitfield;
+    pub type cl_device_device_enqueue_capabilities = cl_bitfield;
 
     #[derive(Debug, Clone, Copy)]
     #[repr(C)]
@@ -134,6 +139,19 @@ mod types {
         pub mem_object: cl_mem,
     }
 
+    pub const CL_NAME_VERSION_MAX_NAME_SIZE: usize = 64;
+
+    pub const CL_VERSION_MAJOR_BITS: cl_version = 10;
+    pub const CL_VERSION_MINOR_BITS: cl_version = 10;
+    pub const CL_VERSION_PATCH_BITS: cl_version = 12;
+
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    pub struct cl_name_version {
+        pub version: cl_version,
+        pub name: [libc::c_char; CL_NAME_VERSION_MAX_NAME_SIZE],
+    }
+
     #[derive(Debug, Clone, Copy)]
     #[repr(C)]
     pub struct cl_buffer_region {
@@ -209,6 +227,47 @@ mod constants {
         CL_INVALID_DEVICE_QUEUE = -70,
         CL_INVALID_SPEC_ID = -71,
         CL_MAX_SIZE_RESTRICTION_EXCEEDED = -72,
+
+        /* cl_khr_icd, returned by the ICD loader */
+        CL_PLATFORM_NOT_FOUND_KHR = -1001;
+
+        extensions {
+            CL_INVALID_GL_SHAREGROUP_REFERENCE_KHR = -1000,
+            CL_INVALID_D3D10_DEVICE_KHR = -1002,
+            CL_INVALID_D3D10_RESOURCE_KHR = -1003,
+            CL_D3D10_RESOURCE_ALREADY_ACQUIRED_KHR = -1004,
+            CL_D3D10_RESOURCE_NOT_ACQUIRED_KHR = -1005,
+            CL_INVALID_D3D11_DEVICE_KHR = -1006,
+            CL_INVALID_D3D11_RESOURCE_KHR = -1007,
+            CL_D3D11_RESOURCE_ALREADY_ACQUIRED_KHR = -1008,
+            CL_D3D11_RESOURCE_NOT_ACQUIRED_KHR = -1009,
+            CL_INVALID_DX9_MEDIA_ADAPTER_KHR = -1010,
+            CL_INVALID_DX9_MEDIA_SURFACE_KHR = -1011,
+            CL_DX9_MEDIA_SURFACE_ALREADY_ACQUIRED_KHR = -1012,
+            CL_DX9_MEDIA_SURFACE_NOT_ACQUIRED_KHR = -1013,
+            CL_DEVICE_PARTITION_FAILED_EXT = -1057,
+            CL_INVALID_PARTITION_COUNT_EXT = -1058,
+            CL_INVALID_PARTITION_NAME_EXT = -1059,
+            CL_EGL_RESOURCE_NOT_ACQUIRED_KHR = -1092,
+            CL_INVALID_EGL_OBJECT_KHR = -1093,
+            CL_INVALID_ACCELERATOR_INTEL = -1094,
+            CL_INVALID_ACCELERATOR_TYPE_INTEL = -1095,
+            CL_INVALID_ACCELERATOR_DESCRIPTOR_INTEL = -1096,
+            CL_ACCELERATOR_TYPE_NOT_SUPPORTED_INTEL = -1097,
+            CL_INVALID_VA_API_MEDIA_ADAPTER_INTEL = -1098,
+            CL_INVALID_VA_API_MEDIA_SURFACE_INTEL = -1099,
+            CL_VA_API_MEDIA_SURFACE_ALREADY_ACQUIRED_INTEL = -1100,
+            CL_VA_API_MEDIA_SURFACE_NOT_ACQUIRED_INTEL = -1101,
+            CL_COMMAND_TERMINATED_ITSELF_WITH_FAILURE_ARM = -1108,
+            CL_CONTEXT_TERMINATED_KHR = -1121,
+            CL_INVALID_COMMAND_BUFFER_KHR = -1138,
+            CL_INVALID_SYNC_POINT_WAIT_LIST_KHR = -1139,
+            CL_INCOMPATIBLE_COMMAND_QUEUE_KHR = -1140,
+            CL_INVALID_MUTABLE_COMMAND_KHR = -1141,
+            CL_INVALID_SEMAPHORE_KHR = -1142,
+            CL_GRALLOC_RESOURCE_NOT_ACQUIRED_IMG = -6000,
+            CL_INVALID_GRALLOC_OBJECT_IMG = -6001,
+        }
     }
 
     pub const CL_FALSE: cl_bool = 0;
@@ -222,6 +281,8 @@ mod constants {
     pub const CL_PLATFORM_VENDOR: cl_platform_info = 0x0903;
     pub const CL_PLATFORM_EXTENSIONS: cl_platform_info = 0x0904;
     pub const CL_PLATFORM_HOST_TIMER_RESOLUTION: cl_platform_info = 0x0905;
+    pub const CL_PLATFORM_NUMERIC_VERSION: cl_platform_info = 0x0906;
+    pub const CL_PLATFORM_EXTENSIONS_WITH_VERSION: cl_platform_info = 0x0907;
 
     pub const CL_DEVICE_TYPE_DEFAULT: cl_device_type = (1 << 0);
     pub const CL_DEVICE_TYPE_CPU: cl_device_type = (1 << 1);
@@ -324,6 +385,21 @@ mod constants {
     pub const CL_DEVICE_IL_VERSION: cl_device_info = 0x105B;
     pub const CL_DEVICE_MAX_NUM_SUB_GROUPS: cl_device_info = 0x105C;
     pub const CL_DEVICE_SUB_GROUP_INDEPENDENT_FORWARD_PROGRESS: cl_device_info = 0x105D;
+    pub const CL_DEVICE_NUMERIC_VERSION: cl_device_info = 0x105E;
+    pub const CL_DEVICE_EXTENSIONS_WITH_VERSION: cl_device_info = 0x1060;
+    pub const CL_DEVICE_ILS_WITH_VERSION: cl_device_info = 0x1061;
+    pub const CL_DEVICE_BUILT_IN_KERNELS_WITH_VERSION: cl_device_info = 0x1062;
+    pub const CL_DEVICE_NON_UNIFORM_WORK_GROUP_SUPPORT: cl_device_info = 0x1065;
+    pub const CL_DEVICE_OPENCL_C_ALL_VERSIONS: cl_device_info = 0x1066;
+    pub const CL_DEVICE_ATOMIC_MEMORY_CAPABILITIES: cl_device_info = 0x1063;
+    pub const CL_DEVICE_ATOMIC_FENCE_CAPABILITIES: cl_device_info = 0x1064;
+    pub const CL_DEVICE_PREFERRED_WORK_GROUP_SIZE_MULTIPLE: cl_device_info = 0x1067;
+    pub const CL_DEVICE_WORK_GROUP_COLLECTIVE_FUNCTIONS_SUPPORT: cl_device_info = 0x1068;
+    pub const CL_DEVICE_GENERIC_ADDRESS_SPACE_SUPPORT: cl_device_info = 0x1069;
+    pub const CL_DEVICE_OPENCL_C_FEATURES: cl_device_info = 0x106F;
+    pub const CL_DEVICE_DEVICE_ENQUEUE_CAPABILITIES: cl_device_info = 0x1070;
+    pub const CL_DEVICE_PIPE_SUPPORT: cl_device_info = 0x1071;
+    pub const CL_DEVICE_LATEST_CONFORMANCE_VERSION_PASSED: cl_device_info = 0x1072;
 
     pub const CL_FP_DENORM: cl_device_fp_config = (1 << 0);
     pub const CL_FP_INF_NAN: cl_device_fp_config = (1 << 1);
@@ -357,6 +433,19 @@ mod constants {
     pub const CL_CONTEXT_PLATFORM: cl_context_properties = 0x1084;
     pub const CL_CONTEXT_INTEROP_USER_SYNC: cl_context_properties = 0x1085;
 
+    // context properties defined by the cl_khr_gl_sharing, cl_khr_d3d10_sharing,
+    // cl_khr_d3d11_sharing, and cl_khr_dx9_media_sharing extensions
+    pub const CL_GL_CONTEXT_KHR: cl_context_properties = 0x2008;
+    pub const CL_EGL_DISPLAY_KHR: cl_context_properties = 0x2009;
+    pub const CL_GLX_DISPLAY_KHR: cl_context_properties = 0x200A;
+    pub const CL_WGL_HDC_KHR: cl_context_properties = 0x200B;
+    pub const CL_CGL_SHAREGROUP_KHR: cl_context_properties = 0x200C;
+    pub const CL_CONTEXT_ADAPTER_D3D9_KHR: cl_context_properties = 0x2025;
+    pub const CL_CONTEXT_ADAPTER_D3D9EX_KHR: cl_context_properties = 0x2026;
+    pub const CL_CONTEXT_ADAPTER_DXVA_KHR: cl_context_properties = 0x2027;
+    pub const CL_CONTEXT_D3D10_DEVICE_KHR: cl_context_properties = 0x4014;
+    pub const CL_CONTEXT_D3D11_DEVICE_KHR: cl_context_properties = 0x401D;
+
     pub const CL_DEVICE_PARTITION_EQUALLY: cl_device_partition_property = 0x1086;
     pub const CL_DEVICE_PARTITION_BY_COUNTS: cl_device_partition_property = 0x1087;
     pub const CL_DEVICE_PARTITION_BY_COUNTS_LIST_END: cl_device_partition_property = 0x0;
@@ -374,12 +463,24 @@ mod constants {
     pub const CL_DEVICE_SVM_FINE_GRAIN_SYSTEM: cl_device_svm_capabilities = (1 << 2);
     pub const CL_DEVICE_SVM_ATOMICS: cl_device_svm_capabilities = (1 << 3);
 
+    pub const CL_DEVICE_ATOMIC_ORDER_RELAXED: cl_device_atomic_capabilities = 1 << 0;
+    pub const CL_DEVICE_ATOMIC_ORDER_ACQ_REL: cl_device_atomic_capabilities = 1 << 1;
+    pub const CL_DEVICE_ATOMIC_ORDER_SEQ_CST: cl_device_atomic_capabilities = 1 << 2;
+    pub const CL_DEVICE_ATOMIC_SCOPE_WORK_ITEM: cl_device_atomic_capabilities = 1 << 3;
+    pub const CL_DEVICE_ATOMIC_SCOPE_WORK_GROUP: cl_device_atomic_capabilities = 1 << 4;
+    pub const CL_DEVICE_ATOMIC_SCOPE_DEVICE: cl_device_atomic_capabilities = 1 << 5;
+    pub const CL_DEVICE_ATOMIC_SCOPE_ALL_DEVICES: cl_device_atomic_capabilities = 1 << 6;
+
+    pub const CL_DEVICE_QUEUE_SUPPORTED: cl_device_device_enqueue_capabilities = 1 << 0;
+    pub const CL_DEVICE_QUEUE_REPLACEABLE_DEFAULT: cl_device_device_enqueue_capabilities = 1 << 1;
+
     pub const CL_QUEUE_CONTEXT: cl_command_queue_info = 0x1090;
     pub const CL_QUEUE_DEVICE: cl_command_queue_info = 0x1091;
     pub const CL_QUEUE_REFERENCE_COUNT: cl_command_queue_info = 0x1092;
     pub const CL_QUEUE_PROPERTIES: cl_command_queue_info = 0x1093;
     pub const CL_QUEUE_SIZE: cl_command_queue_info = 0x1094;
     pub const CL_QUEUE_DEVICE_DEFAULT: cl_command_queue_info = 0x1095;
+    pub const CL_QUEUE_PROPERTIES_ARRAY: cl_command_queue_info = 0x1098;
 
     pub const CL_MEM_READ_WRITE: cl_mem_flags = (1 << 0);
     pub const CL_MEM_WRITE_ONLY: cl_mem_flags = (1 << 1);
@@ -455,6 +556,7 @@ mod constants {
     pub const CL_MEM_ASSOCIATED_MEMOBJECT: cl_mem_info = 0x1107;
     pub const CL_MEM_OFFSET: cl_mem_info = 0x1108;
     pub const CL_MEM_USES_SVM_POINTER: cl_mem_info = 0x1109;
+    pub const CL_MEM_PROPERTIES: cl_mem_info = 0x110A;
 
     pub const CL_IMAGE_FORMAT: cl_image_info = 0x1110;
     pub const CL_IMAGE_ELEMENT_SIZE: cl_image_info = 0x1111;
@@ -470,6 +572,7 @@ mod constants {
 
     pub const CL_PIPE_PACKET_SIZE: cl_pipe_info = 0x1120;
     pub const CL_PIPE_MAX_PACKETS: cl_pipe_info = 0x1121;
+    pub const CL_PIPE_PROPERTIES: cl_pipe_info = 0x1122;
 
     pub const CL_ADDRESS_NONE: cl_addressing_mode = 0x1130;
     pub const CL_ADDRESS_CLAMP_TO_EDGE: cl_addressing_mode = 0x1131;
@@ -488,6 +591,7 @@ mod constants {
     pub const CL_SAMPLER_MIP_FILTER_MODE: cl_sampler_info = 0x1155;
     pub const CL_SAMPLER_LOD_MIN: cl_sampler_info = 0x1156;
     pub const CL_SAMPLER_LOD_MAX: cl_sampler_info = 0x1157;
+    pub const CL_SAMPLER_PROPERTIES: cl_sampler_info = 0x1158;
 
     pub const CL_MAP_READ: cl_map_flags = (1 << 0);
     pub const CL_MAP_WRITE: cl_map_flags = (1 << 1);
@@ -603,6 +707,7 @@ mod constants {
     pub const CL_COMMAND_SVM_MEMFILL: cl_command_type = 0x120B;
     pub const CL_COMMAND_SVM_MAP: cl_command_type = 0x120C;
     pub const CL_COMMAND_SVM_UNMAP: cl_command_type = 0x120D;
+    pub const CL_COMMAND_SVM_MIGRATE_MEM: cl_command_type = 0x120E;
 
     pub const CL_COMPLETE: cl_int = 0x0;
     pub const CL_RUNNING: cl_int = 0x1;
@@ -693,8 +798,8 @@ pub(crate) mod functions {
             fn clEnqueueCopyImage(command_queue: cl_command_queue, src_image: cl_mem, dst_image: cl_mem, src_origin: *const size_t, dst_origin: *const size_t, region: *const size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
             fn clEnqueueCopyImageToBuffer(command_queue: cl_command_queue, src_image: cl_mem, dst_buffer: cl_mem, src_origin: *const size_t, region: *const size_t, dst_offset: size_t, num_events_in_wait_list: cl_uint, event: *mut cl_event) -> cl_int;
             fn clEnqueueCopyBufferToImage(command_queue: cl_command_queue, src_buffer: cl_mem, dst_image: cl_mem, src_offset: size_t, dst_origin: *const size_t, region: *const size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
-            fn clEnqueueMapBuffer(command_queue: cl_command_queue, buffer: cl_mem, blocking_map: cl_bool, map_flags: cl_map_flags, offset: size_t, size: size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event, errcode_ret: *mut cl_int);
-            fn clEnqueueMapImage(command_queue: cl_command_queue, image: cl_mem, blocking_map: cl_bool, map_flags: cl_map_flags, origin: *const size_t, region: *const size_t, image_row_pitch: *mut size_t, image_slice_pitch: *mut size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event, errcode_ret: *mut cl_int);
+            fn clEnqueueMapBuffer(command_queue: cl_command_queue, buffer: cl_mem, blocking_map: cl_bool, map_flags: cl_map_flags, offset: size_t, size: size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event, errcode_ret: *mut cl_int) -> *mut c_void;
+            fn clEnqueueMapImage(command_queue: cl_command_queue, image: cl_mem, blocking_map: cl_bool, map_flags: cl_map_flags, origin: *const size_t, region: *const size_t, image_row_pitch: *mut size_t, image_slice_pitch: *mut size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event, errcode_ret: *mut cl_int) -> *mut c_void;
             fn clEnqueueUnmapMemObject(command_queue: cl_command_queue, memobj: cl_mem, mapped_ptr: *mut c_void, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
             fn clEnqueueNDRangeKernel(command_queue: cl_command_queue, kernel: cl_kernel, work_dim: cl_uint, global_work_offset: *const size_t, global_work_size: *const size_t, local_work_size: *const size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
             fn clEnqueueTask(command_queue: cl_command_queue, kernel: cl_kernel, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
@@ -729,7 +834,7 @@ pub(crate) mod functions {
 
             fn clCreateProgramWithBuiltInKernels(context: cl_context, num_devices: cl_uint, device_list: *const cl_device_id, kernel_names: *const c_char, errcode_ret: *mut cl_int) -> cl_program;
             fn clCompileProgram(program: cl_program, num_devices: cl_uint, device_list: *const cl_device_id, options: *const c_char, num_input_headers: cl_uint, input_headers: *const cl_program, header_include_names: *const *const c_char, pfn_notify: Option<extern "C" fn(program: cl_program, user_data: *mut c_void)>, user_data: *mut c_void) -> cl_int;
-            fn clLinkProgram(program: cl_program, num_devices: cl_uint, device_list: *const cl_device_id, options: *const c_char, num_input_heade4rs: cl_uint, input_headers: *const cl_program, header_include_names: *const *const c_char, pfn_notify: Option<extern "C" fn(program: cl_program, user_data: *mut c_void)>, user_data: *mut c_void) -> cl_int;
+            fn clLinkProgram(context: cl_context, num_devices: cl_uint, device_list: *const cl_device_id, options: *const c_char, num_input_programs: cl_uint, input_programs: *const cl_program, pfn_notify: Option<extern "C" fn(program: cl_program, user_data: *mut c_void)>, user_data: *mut c_void, errcode_ret: *mut cl_int) -> cl_program;
 
             fn clUnloadPlatformCompiler(platform: cl_platform_id) -> cl_int;
 
@@ -761,7 +866,7 @@ pub(crate) mod functions {
             fn clEnqueueSVMFree(command_queue: cl_command_queue, num_svm_pointers: cl_uint, svm_pointers: *mut *mut c_void, pfn_free_func: Option<extern "C" fn(command_queue: cl_command_queue, num_svm_pointers: cl_uint, svm_pointers: *mut *mut c_void, user_data: *mut c_void)>, user_data: *mut c_void, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
             fn clEnqueueSVMMemcpy(command_queue: cl_command_queue, blocking_copy: cl_bool, dst_ptr: *mut c_void, src_ptr: *const c_void, size: size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
             fn clEnqueueSVMMemFill(command_queue: cl_command_queue, svm_ptr: *mut c_void, pattern: *const c_void, pattern_size: size_t, size: size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
-            fn clEnqueueSVMMap(command_queue: cl_command_queue, blocking_map: cl_bool, flags: cl_map_flags, svm_ptr: *mut c_void, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
+            fn clEnqueueSVMMap(command_queue: cl_command_queue, blocking_map: cl_bool, flags: cl_map_flags, svm_ptr: *mut c_void, size: size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
             fn clEnqueueSVMUnmap(command_queue: cl_command_queue, svm_ptr: *mut c_void, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
         }
 
@@ -782,10 +887,24 @@ pub(crate) mod functions {
             fn clSetProgramReleaseCallback(program: cl_program, pfn_notify: extern "C" fn(program: cl_program, user_data: *mut c_void), user_data: *mut c_void) -> cl_int;
             fn clSetProgramSpecializationConstant(program: cl_program, spec_id: cl_uint, spec_size: size_t, spec_value: *const c_void) -> cl_int;
         }
+
+        CL30 = "OpenCL 3.0" => {
+            fn clSetContextDestructorCallback(context: cl_context, pfn_notify: extern "C" fn(context: cl_context, user_data: *mut c_void), user_data: *mut c_void) -> cl_int;
+
+            fn clCreateBufferWithProperties(context: cl_context, properties: *const cl_mem_properties, flags: cl_mem_flags, size: size_t, host_ptr: *mut c_void, errcode_ret: *mut cl_int) -> cl_mem;
+            fn clCreateImageWithProperties(context: cl_context, properties: *const cl_mem_properties, flags: cl_mem_flags, image_format: *const cl_image_format, image_desc: *const cl_image_desc, host_ptr: *mut c_void, errcode_ret: *mut cl_int) -> cl_mem;
+        }
     }
 }
 
+mod backend;
+pub mod ext;
+
+pub use backend::*;
+
 pub use constants::*;
 pub use functions::ptrs::*;
-pub use functions::{OpenCLVersion, SYSTEM_OPENCL_VERSION};
+pub use functions::{
+    api, is_available, BindingMode, ImplicitLoadHook, OpenCLVersion, RawApi, SYSTEM_OPENCL_VERSION,
+};
 pub use types::*;
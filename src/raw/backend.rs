@@ -0,0 +1,111 @@
+use dlopen::raw::Library;
+use dlopen::utils::platform_file_name;
+use std::env::var_os;
+use std::ffi::{c_void, CStr, OsStr};
+use std::io;
+
+/// A source of raw OpenCL function pointers
+///
+/// The default backend, `DlopenBackend`, loads the system OpenCL library at
+/// runtime, but other sources of the API (e.g. a statically linked OpenCL
+/// library, a mock for testing, or a layered implementation loaded from a
+/// custom path) can be used by implementing this trait and passing the backend
+/// to `load_opencl_from`.
+pub trait Backend: Send + Sync {
+    /// Get the address of the OpenCL function with the given name (e.g.
+    /// `clGetPlatformIDs`), or `None` if this backend doesn't provide it.
+    ///
+    /// # Safety
+    ///
+    /// The returned address must point to a function with the signature
+    /// defined for that name by the OpenCL headers, which remains valid for
+    /// the rest of the process.
+    unsafe fn symbol(&self, name: &CStr) -> Option<*const c_void>;
+}
+
+/// Library names tried in order by `DlopenBackend::system`. Many Linux
+/// distributions only install the versioned ICD loader (`libOpenCL.so.1`)
+/// unless development packages are installed, and Android devices ship vendor
+/// libraries outside the default library path.
+pub const CANDIDATES: &[&str] = PLATFORM_CANDIDATES;
+
+#[cfg(target_os = "android")]
+const PLATFORM_CANDIDATES: &[&str] = &[
+    "libOpenCL.so",
+    "/system/vendor/lib64/libOpenCL.so",
+    "/system/vendor/lib/libOpenCL.so",
+    "/system/lib64/libOpenCL.so",
+    "/system/lib/libOpenCL.so",
+    "/vendor/lib64/libOpenCL.so",
+    "/vendor/lib/libOpenCL.so",
+];
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const PLATFORM_CANDIDATES: &[&str] = &[
+    "/System/Library/Frameworks/OpenCL.framework/OpenCL",
+    "libOpenCL.dylib",
+];
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "android", target_os = "macos", target_os = "ios"))
+))]
+const PLATFORM_CANDIDATES: &[&str] = &["libOpenCL.so.1", "libOpenCL.so"];
+
+#[cfg(not(unix))]
+const PLATFORM_CANDIDATES: &[&str] = &["OpenCL.dll"];
+
+/// A backend loading OpenCL functions from a dynamic library
+pub struct DlopenBackend(Library);
+
+impl DlopenBackend {
+    /// Open the dynamic library with the given absolute path or name
+    pub fn open(name: impl AsRef<OsStr>) -> Result<Self, dlopen::Error> {
+        Library::open(name).map(Self)
+    }
+
+    /// Open the system OpenCL library, using the absolute path or library
+    /// name specified by the `OPENCL_LIBRARY` environment variable if set, or
+    /// the first of `CANDIDATES` (followed by a platform-specific version of
+    /// the name `OpenCL`) which can be opened if not. If none can be opened,
+    /// the error lists each candidate tried and why it failed.
+    pub fn system() -> Result<Self, dlopen::Error> {
+        if let Some(name) = var_os("OPENCL_LIBRARY") {
+            return Self::open(name);
+        }
+
+        let default = platform_file_name("OpenCL");
+        let mut failures = vec![];
+
+        for name in CANDIDATES
+            .iter()
+            .map(OsStr::new)
+            .chain(Some(default.as_os_str()))
+        {
+            if failures.iter().any(|(tried, _)| *tried == name) {
+                continue;
+            }
+
+            match Self::open(name) {
+                Ok(backend) => return Ok(backend),
+                Err(e) => failures.push((name, e)),
+            }
+        }
+
+        let tried: Vec<String> = failures
+            .iter()
+            .map(|(name, e)| format!("{} ({})", name.to_string_lossy(), e))
+            .collect();
+
+        Err(dlopen::Error::OpeningLibraryError(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("couldn't open OpenCL library, tried: {}", tried.join(", ")),
+        )))
+    }
+}
+
+impl Backend for DlopenBackend {
+    unsafe fn symbol(&self, name: &CStr) -> Option<*const c_void> {
+        self.0.symbol_cstr(name).ok()
+    }
+}
@@ -0,0 +1,61 @@
+//! Raw bindings to OpenCL extensions.
+//!
+//! Unlike the core API, extension functions aren't exported by the OpenCL
+//! library, and must instead be queried from a specific platform at runtime.
+//! Each submodule corresponds to a single extension, and provides the types
+//! and constants it defines along with a table of its functions, which can be
+//! loaded for a platform using `load`.
+//!
+//! Each extension is only available with the corresponding feature flag set.
+
+use super::{
+    clGetExtensionFunctionAddress, clGetExtensionFunctionAddressForPlatform, cl_platform_id,
+    OpenCLVersion, SYSTEM_OPENCL_VERSION,
+};
+use std::ffi::{c_void, CStr};
+
+#[cfg(feature = "amd-p2p")]
+pub mod cl_amd_copy_buffer_p2p;
+#[cfg(feature = "intel-global-variable-pointers")]
+pub mod cl_intel_global_variable_pointers;
+#[cfg(feature = "intel-usm")]
+pub mod cl_intel_unified_shared_memory;
+#[cfg(feature = "va-interop")]
+pub mod cl_intel_va_api_media_sharing;
+#[cfg(feature = "khr-command-buffer")]
+pub mod cl_khr_command_buffer;
+#[cfg(feature = "khr-device-uuid")]
+pub mod cl_khr_device_uuid;
+#[cfg(feature = "gl-interop")]
+pub mod cl_khr_egl_event;
+#[cfg(feature = "khr-external-memory")]
+pub mod cl_khr_external_memory;
+#[cfg(feature = "gl-interop")]
+pub mod cl_khr_gl_event;
+#[cfg(feature = "khr-icd")]
+pub mod cl_khr_icd;
+#[cfg(feature = "khr-device-uuid")]
+pub mod cl_khr_pci_bus_info;
+#[cfg(feature = "khr-semaphore")]
+pub mod cl_khr_semaphore;
+
+/// Get the address of an extension function for the given platform, returning
+/// a null pointer if the function is unavailable.
+///
+/// This uses `clGetExtensionFunctionAddressForPlatform` when available, falling
+/// back to the deprecated `clGetExtensionFunctionAddress` for OpenCL 1.1.
+///
+/// # Safety
+///
+/// If the given handle is not a valid OpenCL platform ID, behavior is
+/// undefined.
+pub unsafe fn get_extension_function_address(
+    platform: cl_platform_id,
+    func_name: &CStr,
+) -> *mut c_void {
+    if SYSTEM_OPENCL_VERSION >= OpenCLVersion::CL12 {
+        clGetExtensionFunctionAddressForPlatform(platform, func_name.as_ptr())
+    } else {
+        clGetExtensionFunctionAddress(func_name.as_ptr())
+    }
+}
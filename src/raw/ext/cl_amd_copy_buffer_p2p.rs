@@ -0,0 +1,17 @@
+//! `cl_amd_copy_buffer_p2p`
+//!
+//! Direct copies between buffers in different contexts, over the bus between
+//! devices rather than through host memory.
+
+use crate::raw::*;
+use libc::size_t;
+
+pub const CL_DEVICE_NUM_P2P_DEVICES_AMD: cl_device_info = 0x4088;
+pub const CL_DEVICE_P2P_DEVICES_AMD: cl_device_info = 0x4089;
+
+extension_functions! {
+    /// Functions provided by the `cl_amd_copy_buffer_p2p` extension
+    pub struct Functions {
+        fn clEnqueueCopyBufferP2PAMD(command_queue: cl_command_queue, src_buffer: cl_mem, dst_buffer: cl_mem, src_offset: size_t, dst_offset: size_t, cb: size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
+    }
+}
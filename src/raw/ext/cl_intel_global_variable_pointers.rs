@@ -0,0 +1,15 @@
+//! `cl_intel_global_variable_pointers`
+//!
+//! Querying device pointers to program scope global variables, which can then
+//! be passed to kernels using unified shared memory APIs.
+
+use crate::raw::*;
+use libc::{c_char, size_t};
+use std::ffi::c_void;
+
+extension_functions! {
+    /// Functions provided by the `cl_intel_global_variable_pointers` extension
+    pub struct Functions {
+        fn clGetDeviceGlobalVariablePointerINTEL(device: cl_device_id, program: cl_program, global_variable_name: *const c_char, global_variable_size_ret: *mut size_t, global_variable_pointer_ret: *mut *mut c_void) -> cl_int;
+    }
+}
@@ -0,0 +1,71 @@
+//! `cl_intel_unified_shared_memory`
+//!
+//! Unified shared memory (USM), a pointer-based alternative to buffers and
+//! SVM supporting host, device, and shared allocations.
+
+use crate::raw::*;
+use libc::size_t;
+use std::ffi::c_void;
+
+pub type cl_device_unified_shared_memory_capabilities_intel = cl_bitfield;
+pub type cl_mem_properties_intel = cl_bitfield;
+pub type cl_mem_alloc_flags_intel = cl_bitfield;
+pub type cl_mem_info_intel = cl_uint;
+pub type cl_unified_shared_memory_type_intel = cl_uint;
+pub type cl_mem_advice_intel = cl_uint;
+
+pub const CL_DEVICE_HOST_MEM_CAPABILITIES_INTEL: cl_device_info = 0x4190;
+pub const CL_DEVICE_DEVICE_MEM_CAPABILITIES_INTEL: cl_device_info = 0x4191;
+pub const CL_DEVICE_SINGLE_DEVICE_SHARED_MEM_CAPABILITIES_INTEL: cl_device_info = 0x4192;
+pub const CL_DEVICE_CROSS_DEVICE_SHARED_MEM_CAPABILITIES_INTEL: cl_device_info = 0x4193;
+pub const CL_DEVICE_SHARED_SYSTEM_MEM_CAPABILITIES_INTEL: cl_device_info = 0x4194;
+
+pub const CL_UNIFIED_SHARED_MEMORY_ACCESS_INTEL:
+    cl_device_unified_shared_memory_capabilities_intel = 1 << 0;
+pub const CL_UNIFIED_SHARED_MEMORY_ATOMIC_ACCESS_INTEL:
+    cl_device_unified_shared_memory_capabilities_intel = 1 << 1;
+pub const CL_UNIFIED_SHARED_MEMORY_CONCURRENT_ACCESS_INTEL:
+    cl_device_unified_shared_memory_capabilities_intel = 1 << 2;
+pub const CL_UNIFIED_SHARED_MEMORY_CONCURRENT_ATOMIC_ACCESS_INTEL:
+    cl_device_unified_shared_memory_capabilities_intel = 1 << 3;
+
+pub const CL_MEM_ALLOC_FLAGS_INTEL: cl_mem_properties_intel = 0x4195;
+
+pub const CL_MEM_ALLOC_WRITE_COMBINED_INTEL: cl_mem_alloc_flags_intel = 1 << 0;
+
+pub const CL_MEM_TYPE_UNKNOWN_INTEL: cl_unified_shared_memory_type_intel = 0x4196;
+pub const CL_MEM_TYPE_HOST_INTEL: cl_unified_shared_memory_type_intel = 0x4197;
+pub const CL_MEM_TYPE_DEVICE_INTEL: cl_unified_shared_memory_type_intel = 0x4198;
+pub const CL_MEM_TYPE_SHARED_INTEL: cl_unified_shared_memory_type_intel = 0x4199;
+
+pub const CL_MEM_ALLOC_TYPE_INTEL: cl_mem_info_intel = 0x419A;
+pub const CL_MEM_ALLOC_BASE_PTR_INTEL: cl_mem_info_intel = 0x419B;
+pub const CL_MEM_ALLOC_SIZE_INTEL: cl_mem_info_intel = 0x419C;
+pub const CL_MEM_ALLOC_DEVICE_INTEL: cl_mem_info_intel = 0x419D;
+
+pub const CL_KERNEL_EXEC_INFO_INDIRECT_HOST_ACCESS_INTEL: cl_kernel_exec_info = 0x4200;
+pub const CL_KERNEL_EXEC_INFO_INDIRECT_DEVICE_ACCESS_INTEL: cl_kernel_exec_info = 0x4201;
+pub const CL_KERNEL_EXEC_INFO_INDIRECT_SHARED_ACCESS_INTEL: cl_kernel_exec_info = 0x4202;
+pub const CL_KERNEL_EXEC_INFO_USM_PTRS_INTEL: cl_kernel_exec_info = 0x4203;
+
+pub const CL_COMMAND_MEMFILL_INTEL: cl_command_type = 0x4204;
+pub const CL_COMMAND_MEMCPY_INTEL: cl_command_type = 0x4205;
+pub const CL_COMMAND_MIGRATEMEM_INTEL: cl_command_type = 0x4206;
+pub const CL_COMMAND_MEMADVISE_INTEL: cl_command_type = 0x4207;
+
+extension_functions! {
+    /// Functions provided by the `cl_intel_unified_shared_memory` extension
+    pub struct Functions {
+        fn clHostMemAllocINTEL(context: cl_context, properties: *const cl_mem_properties_intel, size: size_t, alignment: cl_uint, errcode_ret: *mut cl_int) -> *mut c_void;
+        fn clDeviceMemAllocINTEL(context: cl_context, device: cl_device_id, properties: *const cl_mem_properties_intel, size: size_t, alignment: cl_uint, errcode_ret: *mut cl_int) -> *mut c_void;
+        fn clSharedMemAllocINTEL(context: cl_context, device: cl_device_id, properties: *const cl_mem_properties_intel, size: size_t, alignment: cl_uint, errcode_ret: *mut cl_int) -> *mut c_void;
+        fn clMemFreeINTEL(context: cl_context, ptr: *mut c_void) -> cl_int;
+        fn clMemBlockingFreeINTEL(context: cl_context, ptr: *mut c_void) -> cl_int;
+        fn clGetMemAllocInfoINTEL(context: cl_context, ptr: *const c_void, param_name: cl_mem_info_intel, param_value_size: size_t, param_value: *mut c_void, param_value_size_ret: *mut size_t) -> cl_int;
+        fn clSetKernelArgMemPointerINTEL(kernel: cl_kernel, arg_index: cl_uint, arg_value: *const c_void) -> cl_int;
+        fn clEnqueueMemFillINTEL(command_queue: cl_command_queue, dst_ptr: *mut c_void, pattern: *const c_void, pattern_size: size_t, size: size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
+        fn clEnqueueMemcpyINTEL(command_queue: cl_command_queue, blocking: cl_bool, dst_ptr: *mut c_void, src_ptr: *const c_void, size: size_t, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
+        fn clEnqueueMigrateMemINTEL(command_queue: cl_command_queue, ptr: *const c_void, size: size_t, flags: cl_mem_migration_flags, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
+        fn clEnqueueMemAdviseINTEL(command_queue: cl_command_queue, ptr: *const c_void, size: size_t, advice: cl_mem_advice_intel, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
+    }
+}
@@ -0,0 +1,45 @@
+//! `cl_intel_va_api_media_sharing`
+//!
+//! Sharing of VA-API media surfaces with OpenCL, allowing video frames to be
+//! processed by kernels without copying them through host memory.
+
+use crate::raw::*;
+use libc::c_uint;
+use std::ffi::c_void;
+
+pub type cl_va_api_device_source_intel = cl_uint;
+pub type cl_va_api_device_set_intel = cl_uint;
+
+/// A VA-API display handle (`VADisplay`)
+pub type VADisplay = *mut c_void;
+
+/// A VA-API surface ID (`VASurfaceID`)
+pub type VASurfaceID = c_uint;
+
+pub const CL_INVALID_VA_API_MEDIA_ADAPTER_INTEL: cl_int = -1098;
+pub const CL_INVALID_VA_API_MEDIA_SURFACE_INTEL: cl_int = -1099;
+pub const CL_VA_API_MEDIA_SURFACE_ALREADY_ACQUIRED_INTEL: cl_int = -1100;
+pub const CL_VA_API_MEDIA_SURFACE_NOT_ACQUIRED_INTEL: cl_int = -1101;
+
+pub const CL_VA_API_DISPLAY_INTEL: cl_va_api_device_source_intel = 0x4094;
+
+pub const CL_PREFERRED_DEVICES_FOR_VA_API_INTEL: cl_va_api_device_set_intel = 0x4095;
+pub const CL_ALL_DEVICES_FOR_VA_API_INTEL: cl_va_api_device_set_intel = 0x4096;
+
+pub const CL_CONTEXT_VA_API_DISPLAY_INTEL: cl_context_properties = 0x4097;
+
+pub const CL_MEM_VA_API_MEDIA_SURFACE_INTEL: cl_mem_info = 0x4098;
+pub const CL_IMAGE_VA_API_PLANE_INTEL: cl_image_info = 0x4099;
+
+pub const CL_COMMAND_ACQUIRE_VA_API_MEDIA_SURFACES_INTEL: cl_command_type = 0x409A;
+pub const CL_COMMAND_RELEASE_VA_API_MEDIA_SURFACES_INTEL: cl_command_type = 0x409B;
+
+extension_functions! {
+    /// Functions provided by the `cl_intel_va_api_media_sharing` extension
+    pub struct Functions {
+        fn clGetDeviceIDsFromVA_APIMediaAdapterINTEL(platform: cl_platform_id, media_adapter_type: cl_va_api_device_source_intel, media_adapter: *mut c_void, media_adapter_set: cl_va_api_device_set_intel, num_entries: cl_uint, devices: *mut cl_device_id, num_devices: *mut cl_uint) -> cl_int;
+        fn clCreateFromVA_APIMediaSurfaceINTEL(context: cl_context, flags: cl_mem_flags, surface: *mut VASurfaceID, plane: cl_uint, errcode_ret: *mut cl_int) -> cl_mem;
+        fn clEnqueueAcquireVA_APIMediaSurfacesINTEL(command_queue: cl_command_queue, num_objects: cl_uint, mem_objects: *const cl_mem, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
+        fn clEnqueueReleaseVA_APIMediaSurfacesINTEL(command_queue: cl_command_queue, num_objects: cl_uint, mem_objects: *const cl_mem, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
+    }
+}
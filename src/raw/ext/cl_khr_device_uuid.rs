@@ -0,0 +1,15 @@
+//! `cl_khr_device_uuid`
+//!
+//! Universally unique identifiers for devices and drivers, which can be
+//! matched against the identifiers reported by other APIs such as Vulkan.
+
+use crate::raw::*;
+
+pub const CL_UUID_SIZE_KHR: usize = 16;
+pub const CL_LUID_SIZE_KHR: usize = 8;
+
+pub const CL_DEVICE_UUID_KHR: cl_device_info = 0x106A;
+pub const CL_DRIVER_UUID_KHR: cl_device_info = 0x106B;
+pub const CL_DEVICE_LUID_VALID_KHR: cl_device_info = 0x106C;
+pub const CL_DEVICE_LUID_KHR: cl_device_info = 0x106D;
+pub const CL_DEVICE_NODE_MASK_KHR: cl_device_info = 0x106E;
@@ -0,0 +1,19 @@
+//! `cl_khr_egl_event`
+//!
+//! Creation of OpenCL events from EGL sync objects, allowing OpenCL commands
+//! to wait for commands submitted through other EGL client APIs.
+
+use crate::raw::*;
+use std::ffi::c_void;
+
+pub type CLeglDisplayKHR = *mut c_void;
+pub type CLeglSyncKHR = *mut c_void;
+
+pub const CL_COMMAND_EGL_FENCE_SYNC_OBJECT_KHR: cl_command_type = 0x202F;
+
+extension_functions! {
+    /// Functions provided by the `cl_khr_egl_event` extension
+    pub struct Functions {
+        fn clCreateEventFromEGLSyncKHR(context: cl_context, sync: CLeglSyncKHR, display: CLeglDisplayKHR, errcode_ret: *mut cl_int) -> cl_event;
+    }
+}
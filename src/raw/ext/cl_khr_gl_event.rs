@@ -0,0 +1,21 @@
+//! `cl_khr_gl_event`
+//!
+//! Creation of OpenCL events from OpenGL fence sync objects, allowing OpenCL
+//! commands to wait for OpenGL commands without a full `glFinish`.
+
+use crate::raw::*;
+
+opaque_type! {
+    __GLsync,
+}
+
+pub type cl_GLsync = *mut __GLsync;
+
+pub const CL_COMMAND_GL_FENCE_SYNC_OBJECT_KHR: cl_command_type = 0x200D;
+
+extension_functions! {
+    /// Functions provided by the `cl_khr_gl_event` extension
+    pub struct Functions {
+        fn clCreateEventFromGLsyncKHR(context: cl_context, sync: cl_GLsync, errcode_ret: *mut cl_int) -> cl_event;
+    }
+}
@@ -0,0 +1,39 @@
+//! `cl_khr_icd`
+//!
+//! Platform enumeration for installable client drivers (ICDs), which is how
+//! the ICD loader discovers the platforms provided by each vendor driver.
+//!
+//! Unlike other extensions, `clIcdGetPlatformIDsKHR` is used before any
+//! platform is known, so it's queried using `clGetExtensionFunctionAddress`
+//! rather than loaded for a specific platform.
+
+use crate::raw::*;
+use std::ffi::CStr;
+
+pub const CL_PLATFORM_ICD_SUFFIX_KHR: cl_platform_info = 0x0920;
+
+#[allow(non_camel_case_types)]
+pub type clIcdGetPlatformIDsKHR_fn = unsafe extern "C" fn(
+    num_entries: cl_uint,
+    platforms: *mut cl_platform_id,
+    num_platforms: *mut cl_uint,
+) -> cl_int;
+
+/// Load `clIcdGetPlatformIDsKHR`, returning `None` if it's unavailable.
+///
+/// # Safety
+///
+/// The OpenCL library must have been loaded.
+pub unsafe fn load() -> Option<clIcdGetPlatformIDsKHR_fn> {
+    let name = CStr::from_bytes_with_nul_unchecked(b"clIcdGetPlatformIDsKHR\0");
+    let addr = clGetExtensionFunctionAddress(name.as_ptr());
+
+    if addr.is_null() {
+        None
+    } else {
+        Some(std::mem::transmute::<
+            *mut std::ffi::c_void,
+            clIcdGetPlatformIDsKHR_fn,
+        >(addr))
+    }
+}
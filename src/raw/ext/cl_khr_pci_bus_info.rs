@@ -0,0 +1,16 @@
+//! `cl_khr_pci_bus_info`
+//!
+//! The PCI bus location of a device.
+
+use crate::raw::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct cl_device_pci_bus_info_khr {
+    pub pci_domain: cl_uint,
+    pub pci_bus: cl_uint,
+    pub pci_device: cl_uint,
+    pub pci_function: cl_uint,
+}
+
+pub const CL_DEVICE_PCI_BUS_INFO_KHR: cl_device_info = 0x410F;
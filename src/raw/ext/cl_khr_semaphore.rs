@@ -0,0 +1,48 @@
+//! `cl_khr_semaphore`
+//!
+//! Semaphores, which allow commands in different queues, or in other APIs, to
+//! be synchronized without events.
+
+use crate::raw::*;
+use libc::size_t;
+use std::ffi::c_void;
+
+opaque_type! {
+    _cl_semaphore_khr,
+}
+
+pub type cl_semaphore_khr = *mut _cl_semaphore_khr;
+pub type cl_semaphore_properties_khr = cl_ulong;
+pub type cl_semaphore_info_khr = cl_uint;
+pub type cl_semaphore_type_khr = cl_uint;
+pub type cl_semaphore_payload_khr = cl_ulong;
+
+pub const CL_SEMAPHORE_TYPE_BINARY_KHR: cl_semaphore_type_khr = 1;
+
+pub const CL_PLATFORM_SEMAPHORE_TYPES_KHR: cl_platform_info = 0x2036;
+pub const CL_DEVICE_SEMAPHORE_TYPES_KHR: cl_device_info = 0x204C;
+
+pub const CL_SEMAPHORE_CONTEXT_KHR: cl_semaphore_info_khr = 0x2039;
+pub const CL_SEMAPHORE_REFERENCE_COUNT_KHR: cl_semaphore_info_khr = 0x203A;
+pub const CL_SEMAPHORE_PROPERTIES_KHR: cl_semaphore_info_khr = 0x203B;
+pub const CL_SEMAPHORE_PAYLOAD_KHR: cl_semaphore_info_khr = 0x203C;
+pub const CL_SEMAPHORE_TYPE_KHR: cl_semaphore_info_khr = 0x203D;
+pub const CL_SEMAPHORE_DEVICE_HANDLE_LIST_KHR: cl_semaphore_info_khr = 0x2053;
+pub const CL_SEMAPHORE_DEVICE_HANDLE_LIST_END_KHR: cl_semaphore_properties_khr = 0;
+
+pub const CL_COMMAND_SEMAPHORE_WAIT_KHR: cl_command_type = 0x2042;
+pub const CL_COMMAND_SEMAPHORE_SIGNAL_KHR: cl_command_type = 0x2043;
+
+pub const CL_INVALID_SEMAPHORE_KHR: cl_int = -1142;
+
+extension_functions! {
+    /// Functions provided by the `cl_khr_semaphore` extension
+    pub struct Functions {
+        fn clCreateSemaphoreWithPropertiesKHR(context: cl_context, sema_props: *const cl_semaphore_properties_khr, errcode_ret: *mut cl_int) -> cl_semaphore_khr;
+        fn clEnqueueWaitSemaphoresKHR(command_queue: cl_command_queue, num_sema_objects: cl_uint, sema_objects: *const cl_semaphore_khr, sema_payload_list: *const cl_semaphore_payload_khr, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
+        fn clEnqueueSignalSemaphoresKHR(command_queue: cl_command_queue, num_sema_objects: cl_uint, sema_objects: *const cl_semaphore_khr, sema_payload_list: *const cl_semaphore_payload_khr, num_events_in_wait_list: cl_uint, event_wait_list: *const cl_event, event: *mut cl_event) -> cl_int;
+        fn clGetSemaphoreInfoKHR(sema_object: cl_semaphore_khr, param_name: cl_semaphore_info_khr, param_value_size: size_t, param_value: *mut c_void, param_value_size_ret: *mut size_t) -> cl_int;
+        fn clReleaseSemaphoreKHR(sema_object: cl_semaphore_khr) -> cl_int;
+        fn clRetainSemaphoreKHR(sema_object: cl_semaphore_khr) -> cl_int;
+    }
+}
@@ -0,0 +1,214 @@
+//! Memory accounting
+//!
+//! Bytes allocated through the safe API are tracked per context, so that
+//! memory usage can be inspected and optionally limited by a budget. Exceeding
+//! a budget results in `Error::BudgetExceeded` before the allocation reaches
+//! the driver, since running out of device memory often crashes the process
+//! rather than failing gracefully.
+//!
+//! Allocations are released from the accounting once the memory object is
+//! actually destroyed, using `clSetMemObjectDestructorCallback`, so retained
+//! and cloned handles are only counted once. This requires OpenCL 1.1+, and
+//! allocations aren't tracked at all on older systems.
+
+use crate::context::Context;
+use crate::raw::{
+    clSetMemObjectDestructorCallback, cl_context, cl_mem, OpenCLVersion, SYSTEM_OPENCL_VERSION,
+};
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct Usage {
+    used: usize,
+    budget: Option<usize>,
+    /// Whether a destructor callback has been registered to forget this
+    /// context once it's destroyed
+    watched: bool,
+}
+
+#[derive(Default)]
+struct Registry {
+    contexts: HashMap<usize, Usage>,
+    total: usize,
+    budget: Option<usize>,
+}
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: Mutex<Registry> = Default::default();
+}
+
+fn tracking_enabled() -> bool {
+    unsafe { SYSTEM_OPENCL_VERSION >= OpenCLVersion::CL11 }
+}
+
+/// Reserve `size` bytes for a new allocation in the given context, failing if
+/// this would exceed the context or global budget.
+pub(crate) fn reserve(context: cl_context, size: usize) -> Result<()> {
+    if !tracking_enabled() {
+        return Ok(());
+    }
+
+    let mut registry = REGISTRY.lock().unwrap();
+    let Registry {
+        contexts,
+        total,
+        budget,
+    } = &mut *registry;
+    let usage = contexts.entry(context as usize).or_default();
+
+    let overflow = || Error::SizeOverflow("memory accounting");
+    let new_used = usage.used.checked_add(size).ok_or_else(overflow)?;
+    let new_total = total.checked_add(size).ok_or_else(overflow)?;
+
+    for &(used, new_used, budget) in &[
+        (usage.used, new_used, usage.budget),
+        (*total, new_total, *budget),
+    ] {
+        if let Some(budget) = budget {
+            if new_used > budget {
+                return Err(Error::BudgetExceeded {
+                    requested: size,
+                    used,
+                    budget,
+                });
+            }
+        }
+    }
+
+    usage.used = new_used;
+    *total = new_total;
+    Ok(())
+}
+
+/// Release `size` bytes previously reserved in the given context
+pub(crate) fn release(context: cl_context, size: usize) {
+    if !tracking_enabled() {
+        return;
+    }
+
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.total -= size;
+
+    if let Some(usage) = registry.contexts.get_mut(&(context as usize)) {
+        usage.used -= size;
+
+        if usage.used == 0 && usage.budget.is_none() {
+            registry.contexts.remove(&(context as usize));
+        }
+    }
+}
+
+/// Forget the budget of the given context once the last wrapper for it has
+/// been dropped, on systems where its destruction can't be observed directly
+/// with a destructor callback. Contexts with live allocations are retained by
+/// them, so only contexts without any are forgotten.
+pub(crate) fn unregister_context(context: &Context) {
+    let mut registry = REGISTRY.lock().unwrap();
+    let key = context.raw() as usize;
+
+    if let Some(usage) = registry.contexts.get(&key) {
+        if usage.used == 0 && !usage.watched && matches!(context.reference_count(), Ok(1)) {
+            registry.contexts.remove(&key);
+        }
+    }
+}
+
+/// Track the given memory object, which has already been reserved with
+/// `reserve`, so that its memory is released from the accounting once the
+/// object is destroyed.
+///
+/// # Safety
+///
+/// The given memory object must be valid, and belong to the given context.
+pub(crate) unsafe fn track(context: cl_context, mem: cl_mem, size: usize) -> Result<()> {
+    if !tracking_enabled() {
+        return Ok(());
+    }
+
+    extern "C" fn destroyed(_mem: cl_mem, user_data: *mut c_void) {
+        let (context, size) = *unsafe { Box::from_raw(user_data as *mut (usize, usize)) };
+        release(context as cl_context, size);
+    }
+
+    let user_data = Box::into_raw(Box::new((context as usize, size)));
+
+    let result = wrap_result!("clSetMemObjectDestructorCallback" => clSetMemObjectDestructorCallback(
+        mem,
+        destroyed,
+        user_data as _
+    ));
+
+    if let Err(e) = result {
+        drop(Box::from_raw(user_data));
+        release(context, size);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// Get the total number of bytes currently allocated through this library,
+/// across all contexts.
+pub fn total_memory_usage() -> usize {
+    REGISTRY.lock().unwrap().total
+}
+
+/// Set a global limit on the number of bytes which may be allocated through
+/// this library across all contexts, or remove the limit with `None`.
+pub fn set_global_memory_budget(budget: Option<usize>) {
+    REGISTRY.lock().unwrap().budget = budget;
+}
+
+impl Context {
+    /// Get the number of bytes currently allocated in this context through
+    /// this library.
+    pub fn memory_usage(&self) -> usize {
+        REGISTRY
+            .lock()
+            .unwrap()
+            .contexts
+            .get(&(self.raw() as usize))
+            .map(|u| u.used)
+            .unwrap_or(0)
+    }
+
+    /// Set a limit on the number of bytes which may be allocated in this
+    /// context through this library, or remove the limit with `None`.
+    /// Allocations which would exceed the limit will fail with
+    /// `Error::BudgetExceeded`.
+    ///
+    /// The budget is forgotten once the context is destroyed. With OpenCL
+    /// 3.0+, this is detected with a destructor callback, and otherwise once
+    /// the last wrapper for the context is dropped.
+    pub fn set_memory_budget(&self, budget: Option<usize>) {
+        let mut registry = REGISTRY.lock().unwrap();
+        let key = self.raw() as usize;
+
+        match budget {
+            Some(_) => {
+                let usage = registry.contexts.entry(key).or_default();
+                usage.budget = budget;
+
+                if !usage.watched {
+                    usage.watched = self
+                        .on_destruct(move || {
+                            REGISTRY.lock().unwrap().contexts.remove(&key);
+                        })
+                        .is_ok();
+                }
+            }
+            None => {
+                if let Some(usage) = registry.contexts.get_mut(&key) {
+                    usage.budget = None;
+
+                    if usage.used == 0 && !usage.watched {
+                        registry.contexts.remove(&key);
+                    }
+                }
+            }
+        }
+    }
+}
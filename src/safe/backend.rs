@@ -0,0 +1,174 @@
+//! A backend-agnostic compute abstraction
+//!
+//! `ComputeBackend` is a deliberately minimal interface - byte buffers,
+//! kernels created from source, and dispatches over a 3D grid - which is
+//! implemented by `ComputeSession` for OpenCL. Applications which structure
+//! their compute code against the trait can implement it for other APIs (e.g.
+//! Vulkan or wgpu), and fall back to them at runtime when OpenCL isn't
+//! available, which fits naturally with this crate loading OpenCL dynamically.
+//!
+//! Since kernel languages differ between APIs, kernel source is passed through
+//! to the backend as-is - applications are expected to provide the right
+//! source for each backend they support.
+
+use crate::buffer::flags::HostReadWrite;
+use crate::buffer::Buffer;
+use crate::dependencies;
+use crate::deterministic;
+use crate::event::Event;
+use crate::kernel::UnboundKernel;
+use crate::raw::*;
+use crate::session::ComputeSession;
+use crate::{Error, Result};
+use std::ffi::CString;
+use std::mem::size_of;
+use std::ptr::{null, null_mut};
+
+/// An argument to a kernel dispatched through `ComputeBackend::dispatch`
+#[derive(Debug, Clone, Copy)]
+pub enum DispatchArg<'a, B> {
+    /// A buffer created by the backend
+    Buffer(&'a B),
+    /// A plain value, as its raw bytes
+    Bytes(&'a [u8]),
+}
+
+/// A minimal compute API, which can be implemented for different backends.
+/// See the module documentation for details.
+pub trait ComputeBackend {
+    /// A buffer of bytes in device memory
+    type Buffer;
+
+    /// A kernel which can be dispatched
+    type Kernel;
+
+    /// The error type of this backend
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Get a human-readable name for the device used by this backend
+    fn device_name(&self) -> std::result::Result<String, Self::Error>;
+
+    /// Create a buffer of the given size in bytes, with unspecified initial
+    /// contents
+    fn create_buffer(&self, size: usize) -> std::result::Result<Self::Buffer, Self::Error>;
+
+    /// Create a buffer holding a copy of the given data
+    fn create_buffer_from(&self, data: &[u8]) -> std::result::Result<Self::Buffer, Self::Error> {
+        let mut buffer = self.create_buffer(data.len())?;
+        self.write_buffer(&mut buffer, data)?;
+        Ok(buffer)
+    }
+
+    /// Copy the given data to the start of a buffer, blocking until complete
+    fn write_buffer(
+        &self,
+        buffer: &mut Self::Buffer,
+        data: &[u8],
+    ) -> std::result::Result<(), Self::Error>;
+
+    /// Copy the start of a buffer into the given slice, blocking until
+    /// complete
+    fn read_buffer(
+        &self,
+        buffer: &mut Self::Buffer,
+        dest: &mut [u8],
+    ) -> std::result::Result<(), Self::Error>;
+
+    /// Create a kernel from the given source and entry point
+    fn create_kernel(
+        &self,
+        source: &str,
+        entry_point: &str,
+    ) -> std::result::Result<Self::Kernel, Self::Error>;
+
+    /// Run a kernel with the given arguments over the given global work size,
+    /// blocking until complete
+    fn dispatch(
+        &self,
+        kernel: &mut Self::Kernel,
+        args: &[DispatchArg<'_, Self::Buffer>],
+        global_work_size: [usize; 3],
+    ) -> std::result::Result<(), Self::Error>;
+}
+
+impl ComputeBackend for ComputeSession {
+    type Buffer = Buffer<'static, HostReadWrite, u8>;
+    type Kernel = UnboundKernel;
+    type Error = Error;
+
+    fn device_name(&self) -> Result<String> {
+        Ok(self.device().name()?.to_string_lossy().into_owned())
+    }
+
+    fn create_buffer(&self, size: usize) -> Result<Self::Buffer> {
+        self.buffer(size)
+    }
+
+    fn create_buffer_from(&self, data: &[u8]) -> Result<Self::Buffer> {
+        self.buffer_from(data)
+    }
+
+    fn write_buffer(&self, buffer: &mut Self::Buffer, data: &[u8]) -> Result<()> {
+        self.queue().buffer_cmd(buffer).write(data)
+    }
+
+    fn read_buffer(&self, buffer: &mut Self::Buffer, dest: &mut [u8]) -> Result<()> {
+        self.queue().buffer_cmd(buffer).read(dest)
+    }
+
+    fn create_kernel(&self, source: &str, entry_point: &str) -> Result<Self::Kernel> {
+        let name = CString::new(entry_point).expect("entry point names must not contain NUL bytes");
+        self.kernel(source, &name)
+    }
+
+    fn dispatch(
+        &self,
+        kernel: &mut Self::Kernel,
+        args: &[DispatchArg<'_, Self::Buffer>],
+        global_work_size: [usize; 3],
+    ) -> Result<()> {
+        let mut mems = vec![];
+
+        unsafe {
+            for (index, arg) in args.iter().enumerate() {
+                let (size, value) = match arg {
+                    DispatchArg::Buffer(buffer) => {
+                        mems.push(buffer.raw());
+                        (size_of::<cl_mem>(), mems.last().unwrap() as *const _ as _)
+                    }
+                    DispatchArg::Bytes(bytes) => (bytes.len(), bytes.as_ptr() as _),
+                };
+
+                wrap_result!("clSetKernelArg" => clSetKernelArg(
+                    kernel.raw(),
+                    index as _,
+                    size,
+                    value
+                ))?;
+            }
+
+            let inferred = dependencies::wait_list(&mems);
+            let wait_list: Vec<cl_event> = inferred.iter().map(Event::raw).collect();
+            let mut event = null_mut();
+
+            wrap_result!("clEnqueueNDRangeKernel" => clEnqueueNDRangeKernel(
+                self.queue().raw(),
+                kernel.raw(),
+                3,
+                null(),
+                global_work_size.as_ptr(),
+                null(),
+                wait_list.len() as _,
+                if wait_list.is_empty() { null() } else { wait_list.as_ptr() },
+                &mut event as _
+            ))?;
+            dependencies::record_write(&mems, event);
+
+            let result = wrap_result!("clWaitForEvents" => clWaitForEvents(1, &event as _));
+            clReleaseEvent(event);
+            result?;
+        }
+
+        deterministic::sync(self.queue())
+    }
+}
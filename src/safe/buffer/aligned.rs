@@ -0,0 +1,110 @@
+use super::MemSafe;
+use crate::device::Device;
+use crate::Result;
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::fmt::{self, Debug, Formatter};
+use std::mem::{align_of, size_of};
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+/// A fixed-length, heap-allocated array with a custom alignment, for use as
+/// storage for zero-copy buffers created with `build_using_slice`.
+///
+/// Implementations can typically only avoid copying `CL_MEM_USE_HOST_PTR`
+/// buffers when the host memory is aligned to the device's base address
+/// alignment and sized in multiples of its cache line size, which the global
+/// allocator doesn't guarantee.
+pub struct AlignedVec<T: MemSafe> {
+    ptr: NonNull<T>,
+    len: usize,
+    layout: Layout,
+}
+
+unsafe impl<T: MemSafe + Send> Send for AlignedVec<T> {}
+unsafe impl<T: MemSafe + Sync> Sync for AlignedVec<T> {}
+
+impl<T: MemSafe> AlignedVec<T> {
+    /// Allocate an array of `len` copies of `value`, aligned to at least
+    /// `align` bytes, with the allocation size rounded up to a multiple of
+    /// `granularity` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` or `granularity` aren't powers of two, or the
+    /// allocation size overflows.
+    pub fn new(len: usize, value: T, align: usize, granularity: usize) -> Self {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        assert!(
+            granularity.is_power_of_two(),
+            "granularity must be a power of two"
+        );
+
+        let align = align.max(align_of::<T>());
+        let granularity = granularity.max(align);
+        let size = size_of::<T>()
+            .checked_mul(len)
+            .and_then(|s| s.checked_add(granularity - 1))
+            .expect("allocation size overflow")
+            & !(granularity - 1);
+        let layout = Layout::from_size_align(size.max(granularity), align).unwrap();
+
+        unsafe {
+            let ptr = match NonNull::new(alloc(layout) as *mut T) {
+                Some(ptr) => ptr,
+                None => handle_alloc_error(layout),
+            };
+
+            for i in 0..len {
+                ptr.as_ptr().add(i).write(value);
+            }
+
+            Self { ptr, len, layout }
+        }
+    }
+
+    /// Allocate an array of `len` copies of `value`, with the alignment and
+    /// size granularity recommended for zero-copy buffers on the given device.
+    pub fn for_device(device: Device, len: usize, value: T) -> Result<Self> {
+        // CL_DEVICE_MEM_BASE_ADDR_ALIGN is specified in bits
+        let align = (device.mem_base_addr_align()? as usize / 8).max(1);
+        let granularity = (device.global_mem_cacheline_size()? as usize).max(1);
+
+        Ok(Self::new(
+            len,
+            value,
+            align.next_power_of_two(),
+            granularity.next_power_of_two(),
+        ))
+    }
+
+    /// Get the alignment of this array in bytes
+    pub fn align(&self) -> usize {
+        self.layout.align()
+    }
+}
+
+impl<T: MemSafe> Drop for AlignedVec<T> {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr.as_ptr() as _, self.layout) }
+    }
+}
+
+impl<T: MemSafe> Deref for AlignedVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: MemSafe> DerefMut for AlignedVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: MemSafe + Debug> Debug for AlignedVec<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
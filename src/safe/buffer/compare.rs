@@ -0,0 +1,72 @@
+use super::flags::HostReadable;
+use super::{Buffer, MemSafe};
+use crate::queue::Queue;
+use crate::raw::*;
+use crate::Result;
+use std::ptr::null_mut;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+impl<'a, H: HostReadable, T: MemSafe> Buffer<'a, H, T> {
+    /// Perform a blocking read of the raw contents of this buffer
+    fn read_bytes(&self, queue: &Queue) -> Result<Vec<u8>> {
+        let mut data = vec![0u8; self.size];
+
+        if data.is_empty() {
+            return Ok(data);
+        }
+
+        unsafe {
+            wrap_result!("clEnqueueReadBuffer" => clEnqueueReadBuffer(
+                queue.raw(),
+                self.handle,
+                CL_TRUE,
+                0,
+                data.len(),
+                data.as_mut_ptr() as _,
+                0,
+                null_mut(),
+                null_mut()
+            ))?;
+        }
+
+        Ok(data)
+    }
+
+    /// Compute a 64-bit FNV-1a hash of the contents of this buffer, reading
+    /// it back using the given queue.
+    ///
+    /// The hash is computed over the raw bytes, so it's stable across runs and
+    /// platforms with the same endianness, and can be compared against hashes
+    /// of reference results recorded earlier.
+    pub fn content_hash(&self, queue: &Queue) -> Result<u64> {
+        Ok(self
+            .read_bytes(queue)?
+            .iter()
+            .fold(FNV_OFFSET_BASIS, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+            }))
+    }
+
+    /// Check whether the contents of this buffer are bitwise identical to the
+    /// contents of another buffer, reading both back using the given queue.
+    ///
+    /// Since the comparison is bitwise, floating point values such as `0.0`
+    /// and `-0.0` are considered different, while identical NaNs are equal.
+    pub fn content_eq<H2: HostReadable>(
+        &self,
+        queue: &Queue,
+        other: &Buffer<H2, T>,
+    ) -> Result<bool> {
+        if self.size != other.size {
+            return Ok(false);
+        }
+
+        if self.handle == other.handle {
+            return Ok(true);
+        }
+
+        Ok(self.read_bytes(queue)? == other.read_bytes(queue)?)
+    }
+}
@@ -0,0 +1,193 @@
+use super::flags::{HostAccess, HostReadable, HostWritable};
+use super::{Buffer, MemSafe};
+use crate::queue::Queue;
+use crate::raw::*;
+use crate::release;
+use crate::validation;
+use crate::Result;
+use std::ffi::c_void;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{null, null_mut};
+
+/// A blocking mapping of a whole buffer, which is unmapped once dropped
+struct Mapping<'b> {
+    queue: &'b Queue,
+    handle: cl_mem,
+    ptr: *mut c_void,
+}
+
+impl<'b> Mapping<'b> {
+    unsafe fn new(
+        queue: &'b Queue,
+        handle: cl_mem,
+        offset: usize,
+        size: usize,
+        flags: cl_map_flags,
+    ) -> Result<Self> {
+        validation::check_map(queue, handle, flags);
+
+        let mut err = CL_SUCCESS;
+
+        let ptr = clEnqueueMapBuffer(
+            queue.raw(),
+            handle,
+            CL_BLOCKING,
+            flags,
+            offset,
+            size,
+            0,
+            null(),
+            null_mut(),
+            &mut err as _,
+        );
+
+        wrap_result!("clEnqueueMapBuffer" => err)?;
+
+        Ok(Self { queue, handle, ptr })
+    }
+}
+
+impl Drop for Mapping<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            let mut event = null_mut();
+
+            let result = wrap_result!("clEnqueueUnmapMemObject" => clEnqueueUnmapMemObject(
+                self.queue.raw(),
+                self.handle,
+                self.ptr,
+                0,
+                null(),
+                &mut event as _
+            ))
+            .and_then(|_| {
+                let result = wrap_result!("clWaitForEvents" => clWaitForEvents(1, &event as _));
+                clReleaseEvent(event);
+                result
+            });
+
+            if let Err(e) = result {
+                release::report("mapping of OpenCL buffer", &self.handle, e);
+            }
+        }
+    }
+}
+
+/// Map `len` elements of the given buffer, starting at `offset` bytes, for
+/// reading and writing on the host, and run the given function on them before
+/// unmapping the buffer
+///
+/// # Safety
+///
+/// The handle must be a valid buffer containing elements of type `T`, which
+/// the host is allowed to read and write.
+pub(crate) unsafe fn update<T: MemSafe, R>(
+    queue: &Queue,
+    handle: cl_mem,
+    offset: usize,
+    len: usize,
+    f: impl FnOnce(&mut [T]) -> R,
+) -> Result<R> {
+    let size = len * std::mem::size_of::<T>();
+    let mapping = Mapping::new(queue, handle, offset, size, CL_MAP_READ | CL_MAP_WRITE)?;
+    Ok(f(std::slice::from_raw_parts_mut(
+        mapping.ptr as *mut T,
+        len,
+    )))
+}
+
+/// A read-only view of a buffer's contents on the host, as returned by
+/// `Buffer::host_slice`. The buffer is unmapped once this is dropped.
+pub struct HostSlice<'b, T: MemSafe> {
+    _buffer: PhantomData<&'b [T]>,
+    mapping: Mapping<'b>,
+    len: usize,
+}
+
+/// A mutable view of a buffer's contents on the host, as returned by
+/// `Buffer::host_slice_mut`. The buffer is unmapped once this is dropped,
+/// making any changes visible to the device.
+pub struct HostSliceMut<'b, T: MemSafe> {
+    _buffer: PhantomData<&'b mut [T]>,
+    mapping: Mapping<'b>,
+    len: usize,
+}
+
+impl<T: MemSafe> Deref for HostSlice<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.mapping.ptr as *const T, self.len) }
+    }
+}
+
+impl<T: MemSafe> Deref for HostSliceMut<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.mapping.ptr as *const T, self.len) }
+    }
+}
+
+impl<T: MemSafe> DerefMut for HostSliceMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.mapping.ptr as *mut T, self.len) }
+    }
+}
+
+impl<T: MemSafe + Debug> Debug for HostSlice<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: MemSafe + Debug> Debug for HostSliceMut<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, H: HostAccess, T: MemSafe> Buffer<'a, H, T> {
+    /// Map the contents of this buffer for reading on the host, blocking until
+    /// the mapping is complete.
+    ///
+    /// For buffers built with `build_using_slice` (`CL_MEM_USE_HOST_PTR`), the
+    /// mapping usually refers to the backing slice itself, so no data is
+    /// copied. The buffer is unmapped using the given queue once the returned
+    /// slice is dropped.
+    pub fn host_slice<'b>(&'b mut self, queue: &'b Queue) -> Result<HostSlice<'b, T>>
+    where
+        H: HostReadable,
+    {
+        unsafe {
+            Ok(HostSlice {
+                _buffer: PhantomData,
+                mapping: Mapping::new(queue, self.handle, 0, self.size, CL_MAP_READ)?,
+                len: self.size / std::mem::size_of::<T>(),
+            })
+        }
+    }
+
+    /// Map the contents of this buffer for reading and writing on the host,
+    /// blocking until the mapping is complete. See `host_slice` for details.
+    pub fn host_slice_mut<'b>(&'b mut self, queue: &'b Queue) -> Result<HostSliceMut<'b, T>>
+    where
+        H: HostReadable + HostWritable,
+    {
+        unsafe {
+            Ok(HostSliceMut {
+                _buffer: PhantomData,
+                mapping: Mapping::new(
+                    queue,
+                    self.handle,
+                    0,
+                    self.size,
+                    CL_MAP_READ | CL_MAP_WRITE,
+                )?,
+                len: self.size / std::mem::size_of::<T>(),
+            })
+        }
+    }
+}
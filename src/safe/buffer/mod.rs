@@ -3,16 +3,34 @@
 //! OpenCL buffers represent memory segments which can be used to store and
 //! transfer data between the host and kernels.
 
+mod aligned;
+mod compare;
 pub mod flags;
+pub(crate) mod map;
+mod rect;
 mod types;
 
+use crate::context::Context;
+use crate::dependencies;
+use crate::deterministic;
+use crate::device::DeviceTier;
+use crate::event::Event;
+use crate::labels;
+use crate::queue::Queue;
 use crate::raw::*;
+use crate::release;
 use crate::util::sealed::OclInfoInternal;
+use crate::{Error, Result};
+pub use aligned::*;
 use flags::*;
 use libc::size_t;
+pub use map::*;
+pub use rect::*;
 use std::ffi::c_void;
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::ptr::null_mut;
 pub use types::*;
 
 /// An OpenCL buffer
@@ -24,9 +42,11 @@ pub use types::*;
 /// the buffer, and provide compile-time safety when reading/writing the buffer.
 ///
 /// The type parameter `T` represents the type of data stored in this buffer.
+///
+/// Buffers set as kernel arguments are owned by the kernel until replaced, so
+/// they can't be released while the kernel still refers to them.
 // TODO: a buffer could be dropped while other OpenCL objects with implicit
-//  references to it (e.g. subbuffers, kernels, etc?) exist, violating safety
-//  guarantees?
+//  references to it (e.g. subbuffers) exist, violating safety guarantees?
 #[derive(PartialEq, Eq, Hash)]
 pub struct Buffer<'a, H: HostAccess, T: MemSafe> {
     _lifetime: PhantomData<&'a ()>,
@@ -40,9 +60,17 @@ unsafe impl<'a, H: HostAccess, T: MemSafe> Send for Buffer<'a, H, T> {}
 
 impl<'a, H: HostAccess, T: MemSafe> Drop for Buffer<'a, H, T> {
     fn drop(&mut self) {
+        if dependencies::is_enabled() && matches!(self.reference_count(), Ok(1)) {
+            dependencies::unregister_buffer(self.handle);
+        }
+
+        if labels::is_used() && matches!(self.reference_count(), Ok(1)) {
+            labels::unregister(self.handle);
+        }
+
         unsafe {
             if let Err(e) = wrap_result!("clReleaseMemObject" => clReleaseMemObject(self.handle)) {
-                log::warn!("Error releasing OpenCL mem object: {:?}: {:?}", self, e);
+                release::report("OpenCL mem object", self, e);
             }
         }
     }
@@ -50,6 +78,7 @@ impl<'a, H: HostAccess, T: MemSafe> Drop for Buffer<'a, H, T> {
 
 impl<'a, H: HostAccess, T: MemSafe> Debug for Buffer<'a, H, T> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        labels::fmt_prefix(f, self.handle)?;
         self.info_fmt(f)
     }
 }
@@ -84,6 +113,42 @@ impl<'a, H: HostAccess, T: MemSafe> Buffer<'a, H, T> {
         self.size
     }
 
+    /// Wrap the given raw buffer handle, which must refer to at least `size`
+    /// bytes, taking ownership of one reference to it
+    pub(crate) unsafe fn from_raw_parts(handle: cl_mem, size: size_t) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = T::ASSERT_NOT_ZERO_SIZED;
+
+        Self {
+            _lifetime: PhantomData,
+            _host_access: PhantomData,
+            _type: PhantomData,
+            handle,
+            size,
+        }
+    }
+
+    /// Attempt to clone this buffer, using `clRetainMemObject` to ensure the
+    /// buffer is not released while a wrapper still exists.
+    ///
+    /// The clone refers to the same underlying memory object as the original,
+    /// so writes through either wrapper (including by kernels the wrappers are
+    /// bound to) are visible through both. Commands borrowing one wrapper
+    /// mutably therefore don't guarantee exclusive access to the data.
+    pub fn try_clone(&self) -> Result<Self> {
+        unsafe {
+            wrap_result!("clRetainMemObject" => clRetainMemObject(self.handle))?;
+
+            Ok(Self {
+                _lifetime: PhantomData,
+                _host_access: PhantomData,
+                _type: PhantomData,
+                handle: self.handle,
+                size: self.size,
+            })
+        }
+    }
+
     info_funcs! {
         pub fn flags(&self) -> BufferFlagsInfo = CL_MEM_FLAGS;
         pub fn size(&self) -> size_t = CL_MEM_SIZE;
@@ -93,6 +158,188 @@ impl<'a, H: HostAccess, T: MemSafe> Buffer<'a, H, T> {
         pub fn context_raw(&self) -> cl_context = CL_MEM_CONTEXT;
         pub fn associated_memobject_raw(&self) -> cl_mem = CL_MEM_ASSOCIATED_MEMOBJECT;
         pub fn offset(&self) -> size_t = CL_MEM_OFFSET;
-        pub fn uses_svm_pointer(&self) -> bool = CL_MEM_USES_SVM_POINTER;
+    }
+
+    /// Get the context this buffer was created in
+    pub fn context(&self) -> Result<Context> {
+        let handle = self.context_raw()?;
+
+        unsafe {
+            wrap_result!("clRetainContext" => clRetainContext(handle))?;
+            Ok(Context(handle))
+        }
+    }
+
+    /// Check whether the devices in this buffer's context are likely to access
+    /// the buffer's host memory directly, rather than through a copy.
+    ///
+    /// OpenCL doesn't report whether a driver silently copies the memory of
+    /// `CL_MEM_USE_HOST_PTR` and `CL_MEM_ALLOC_HOST_PTR` buffers, so this is a
+    /// heuristic: the buffer must use host memory, and every device must be a
+    /// CPU or a GPU sharing memory with the host. For buffers built with
+    /// `build_using_slice`, the slice must also be aligned to each device's
+    /// base address alignment, and sized in multiples of its cache line size.
+    pub fn is_zero_copy(&self) -> Result<bool> {
+        let flags = self.flags()?;
+
+        if !flags.contains(BufferFlagsInfo::USE_HOST_PTR)
+            && !flags.contains(BufferFlagsInfo::ALLOC_HOST_PTR)
+        {
+            return Ok(false);
+        }
+
+        let host_ptr = self.host_ptr()? as usize;
+
+        for device in self.context()?.devices()? {
+            match device.tier()? {
+                DeviceTier::Cpu | DeviceTier::IntegratedGpu => {}
+                _ => return Ok(false),
+            }
+
+            if host_ptr != 0 {
+                let align = (device.mem_base_addr_align()? as usize / 8)
+                    .max(1)
+                    .next_power_of_two();
+                let line = (device.global_mem_cacheline_size()? as usize).max(1);
+
+                if host_ptr & (align - 1) != 0 || self.size / line * line != self.size {
+                    return Ok(false);
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Get the buffer this buffer was created from, if this is a sub-buffer.
+    /// The parent is retained with `clRetainMemObject`, so it remains valid
+    /// for as long as the returned wrapper exists.
+    ///
+    /// The element type and host accessibility of the parent aren't known, so
+    /// it's returned as a buffer of bytes without host access, which can be
+    /// reinterpreted with `cast`.
+    pub fn parent(&self) -> Result<Option<Buffer<'a, HostNoAccess, u8>>> {
+        let handle = self.associated_memobject_raw()?;
+
+        if handle.is_null() {
+            return Ok(None);
+        }
+
+        unsafe {
+            wrap_result!("clRetainMemObject" => clRetainMemObject(handle))?;
+        }
+
+        // construct the wrapper before querying the size, so the handle is
+        // still released on error
+        let mut parent = Buffer {
+            _lifetime: PhantomData,
+            _host_access: PhantomData,
+            _type: PhantomData,
+            handle,
+            size: 0,
+        };
+        parent.size = parent.size()?;
+
+        Ok(Some(parent))
+    }
+
+    /// Begin migrating this buffer to the device associated with the given
+    /// queue, returning an event which completes once the migration is done.
+    /// Requires OpenCL 1.2+.
+    ///
+    /// This is only a hint, allowing data movement to overlap with other work
+    /// before the buffer is used by a kernel on that device.
+    pub fn prefetch_to(&self, queue: &Queue) -> Result<Event> {
+        self.migrate(queue, 0)
+    }
+
+    /// Begin migrating this buffer to the host, returning an event which
+    /// completes once the migration is done. Requires OpenCL 1.2+.
+    pub fn prefetch_to_host(&self, queue: &Queue) -> Result<Event> {
+        self.migrate(queue, CL_MIGRATE_MEM_OBJECT_HOST)
+    }
+
+    fn migrate(&self, queue: &Queue, flags: cl_mem_migration_flags) -> Result<Event> {
+        unsafe {
+            check_ocl_version!("clEnqueueMigrateMemObjects" => CL12)?;
+
+            let mut event = null_mut();
+
+            wrap_result!("clEnqueueMigrateMemObjects" => clEnqueueMigrateMemObjects(
+                queue.raw(),
+                1,
+                &self.handle as _,
+                flags,
+                0,
+                null_mut(),
+                &mut event as _
+            ))?;
+
+            let event = Event(event);
+            deterministic::sync(queue)?;
+            Ok(event)
+        }
+    }
+
+    /// Reinterpret the contents of this buffer as elements of type `U`,
+    /// without creating a new memory object - e.g. to read packed data as
+    /// bytes, or to view a buffer of floats as a buffer of vectors.
+    ///
+    /// The size of the buffer, and its offset within its parent for
+    /// sub-buffers, must be multiples of the size of `U`, or
+    /// `Error::InvalidDataLength` is returned. For buffers using host memory
+    /// (e.g. built with `build_using_slice`), the memory must also be aligned
+    /// for `U`, or `Error::Misaligned` is returned.
+    pub fn cast<U: MemSafe>(self) -> Result<Buffer<'a, H, U>> {
+        let elem = size_of::<U>();
+
+        for len in [self.size, self.offset()?] {
+            if len / elem * elem != len {
+                return Err(Error::InvalidDataLength {
+                    expected: len / elem * elem,
+                    actual: len,
+                });
+            }
+        }
+
+        let address = self.host_ptr()? as usize;
+        let align = align_of::<U>();
+
+        if address & (align - 1) != 0 {
+            return Err(Error::Misaligned { address, align });
+        }
+
+        let (handle, size) = (self.handle, self.size);
+        std::mem::forget(self);
+
+        unsafe { Ok(Buffer::from_raw_parts(handle, size)) }
+    }
+
+    /// Get the offset of this sub-buffer within its parent, in elements of
+    /// type `T`. For buffers which aren't sub-buffers, this is always zero.
+    ///
+    /// Returns `Error::InvalidDataLength` if the offset in bytes isn't a
+    /// multiple of the size of `T`.
+    pub fn parent_offset(&self) -> Result<usize> {
+        let offset = self.offset()?;
+        let elem = size_of::<T>();
+
+        if offset / elem * elem != offset {
+            return Err(Error::InvalidDataLength {
+                expected: offset / elem * elem,
+                actual: offset,
+            });
+        }
+
+        Ok(offset / elem)
+    }
+}
+
+#[cfg(feature = "svm")]
+impl<'a, H: HostAccess, T: MemSafe> Buffer<'a, H, T> {
+    /// Check whether this buffer was created using a shared virtual memory
+    /// pointer. Requires OpenCL 2.0+.
+    pub fn uses_svm_pointer(&self) -> Result<bool> {
+        <Self as crate::util::OclInfo>::get_info(self, CL_MEM_USES_SVM_POINTER)
     }
 }
@@ -0,0 +1,523 @@
+use super::flags::{HostAccess, HostReadable, HostWritable};
+use super::{Buffer, MemSafe};
+use crate::deterministic;
+use crate::kernel::KernelArg;
+use crate::queue::Queue;
+use crate::raw::*;
+use crate::{Error, Result};
+use libc::size_t;
+use std::ffi::c_void;
+use std::mem::size_of;
+use std::ptr::null_mut;
+
+/// Multiply two sizes, returning `Error::SizeOverflow` if the product
+/// overflows
+fn mul(a: usize, b: usize) -> Result<usize> {
+    a.checked_mul(b)
+        .ok_or(Error::SizeOverflow("rectangular buffer layout"))
+}
+
+/// The layout of image-like data stored in a buffer, with pitches in bytes
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    dims: [usize; 3],
+    row_pitch: usize,
+    slice_pitch: usize,
+}
+
+impl Layout {
+    fn new<T: MemSafe>(
+        buffer_size: usize,
+        dims: [usize; 3],
+        row_pitch: usize,
+        slice_pitch: usize,
+    ) -> Result<Self> {
+        let row_size = mul(dims[0], size_of::<T>())?;
+        let slice_size = mul(row_pitch, dims[1])?;
+
+        assert!(
+            row_pitch >= row_size,
+            "row pitch must be at least the width of a row"
+        );
+        assert!(
+            slice_pitch >= slice_size,
+            "slice pitch must be at least the size of a slice"
+        );
+
+        let required = if dims.contains(&0) {
+            0
+        } else {
+            // the last row of the last slice only needs to hold one row
+            mul(slice_pitch, dims[2] - 1)?
+                .checked_add(slice_size - row_pitch)
+                .and_then(|s| s.checked_add(row_size))
+                .ok_or(Error::SizeOverflow("rectangular buffer layout"))?
+        };
+
+        if required > buffer_size {
+            return Err(Error::InvalidDataLength {
+                expected: required,
+                actual: buffer_size,
+            });
+        }
+
+        Ok(Self {
+            dims,
+            row_pitch,
+            slice_pitch,
+        })
+    }
+
+    /// Perform a blocking rectangular transfer between the given buffer and
+    /// tightly packed host memory, given as a pointer and number of elements
+    unsafe fn transfer<T: MemSafe>(
+        &self,
+        queue: &Queue,
+        buffer: cl_mem,
+        origin: [usize; 3],
+        region: [usize; 3],
+        (ptr, len): (*mut c_void, usize),
+        read: bool,
+    ) -> Result<()> {
+        for i in 0..3 {
+            assert!(
+                origin[i]
+                    .checked_add(region[i])
+                    .is_some_and(|end| end <= self.dims[i]),
+                "region {:?} at {:?} is out of bounds for dimensions {:?}",
+                region,
+                origin,
+                self.dims
+            );
+        }
+
+        let count = mul(mul(region[0], region[1])?, region[2])?;
+
+        if len != count {
+            return Err(Error::InvalidDataLength {
+                expected: count,
+                actual: len,
+            });
+        }
+
+        if count == 0 {
+            return Ok(());
+        }
+
+        let size = size_of::<T>();
+        let buffer_origin: [size_t; 3] = [mul(origin[0], size)?, origin[1], origin[2]];
+        let host_origin: [size_t; 3] = [0; 3];
+        let byte_region: [size_t; 3] = [mul(region[0], size)?, region[1], region[2]];
+        let host_row_pitch = byte_region[0];
+        let host_slice_pitch = mul(host_row_pitch, region[1])?;
+
+        check_ocl_version!("clEnqueueReadBufferRect" => CL11)?;
+
+        if read {
+            wrap_result!("clEnqueueReadBufferRect" => clEnqueueReadBufferRect(
+                queue.raw(),
+                buffer,
+                CL_TRUE,
+                buffer_origin.as_ptr(),
+                host_origin.as_ptr(),
+                byte_region.as_ptr(),
+                self.row_pitch,
+                self.slice_pitch,
+                host_row_pitch,
+                host_slice_pitch,
+                ptr,
+                0,
+                null_mut(),
+                null_mut()
+            ))?;
+        } else {
+            wrap_result!("clEnqueueWriteBufferRect" => clEnqueueWriteBufferRect(
+                queue.raw(),
+                buffer,
+                CL_TRUE,
+                buffer_origin.as_ptr(),
+                host_origin.as_ptr(),
+                byte_region.as_ptr(),
+                self.row_pitch,
+                self.slice_pitch,
+                host_row_pitch,
+                host_slice_pitch,
+                ptr,
+                0,
+                null_mut(),
+                null_mut()
+            ))?;
+        }
+
+        deterministic::sync(queue)
+    }
+}
+
+/// A buffer storing a 2D array of elements, with a row pitch
+///
+/// Reads and writes use the rectangular transfer functions, so regions of
+/// the array can be transferred to and from tightly packed host memory without
+/// manual index calculations. When set as a kernel argument, the underlying
+/// buffer is passed as a pointer, so kernels must account for the row pitch
+/// themselves. Requires OpenCL 1.1+.
+#[derive(Debug)]
+pub struct Buffer2d<'a, H: HostAccess, T: MemSafe> {
+    buffer: Buffer<'a, H, T>,
+    layout: Layout,
+}
+
+impl<'a, H: HostAccess, T: MemSafe> Buffer2d<'a, H, T> {
+    /// View the given buffer as a tightly packed 2D array with the given
+    /// dimensions, returning `Error::InvalidDataLength` if it's too small.
+    pub fn new(buffer: Buffer<'a, H, T>, width: usize, height: usize) -> Result<Self> {
+        Self::with_row_pitch(buffer, width, height, mul(width, size_of::<T>())?)
+    }
+
+    /// View the given buffer as a 2D array with the given dimensions and row
+    /// pitch in bytes, returning `Error::InvalidDataLength` if it's too small.
+    /// Panics if the row pitch is smaller than a row.
+    pub fn with_row_pitch(
+        buffer: Buffer<'a, H, T>,
+        width: usize,
+        height: usize,
+        row_pitch: usize,
+    ) -> Result<Self> {
+        let layout = Layout::new::<T>(
+            buffer.rust_size(),
+            [width, height, 1],
+            row_pitch,
+            mul(row_pitch, height)?,
+        )?;
+
+        Ok(Self { buffer, layout })
+    }
+
+    /// Get the width of the array, in elements
+    pub fn width(&self) -> usize {
+        self.layout.dims[0]
+    }
+
+    /// Get the height of the array, in rows
+    pub fn height(&self) -> usize {
+        self.layout.dims[1]
+    }
+
+    /// Get the distance between the start of consecutive rows, in bytes
+    pub fn row_pitch(&self) -> usize {
+        self.layout.row_pitch
+    }
+
+    /// Get a reference to the underlying buffer
+    pub fn buffer(&self) -> &Buffer<'a, H, T> {
+        &self.buffer
+    }
+
+    /// Get the underlying buffer, discarding the dimensions
+    pub fn into_inner(self) -> Buffer<'a, H, T> {
+        self.buffer
+    }
+
+    /// Perform a blocking read of the region of the given size, starting at
+    /// the given `[x, y]` origin, into a tightly packed slice.
+    pub fn read_region(
+        &mut self,
+        queue: &Queue,
+        origin: [usize; 2],
+        region: [usize; 2],
+        dest: &mut [T],
+    ) -> Result<()>
+    where
+        H: HostReadable,
+    {
+        unsafe {
+            self.layout.transfer::<T>(
+                queue,
+                self.buffer.raw(),
+                [origin[0], origin[1], 0],
+                [region[0], region[1], 1],
+                (dest.as_mut_ptr() as _, dest.len()),
+                true,
+            )
+        }
+    }
+
+    /// Perform a blocking write of a tightly packed slice into the region of
+    /// the given size, starting at the given `[x, y]` origin.
+    pub fn write_region(
+        &mut self,
+        queue: &Queue,
+        origin: [usize; 2],
+        region: [usize; 2],
+        src: &[T],
+    ) -> Result<()>
+    where
+        H: HostWritable,
+    {
+        unsafe {
+            self.layout.transfer::<T>(
+                queue,
+                self.buffer.raw(),
+                [origin[0], origin[1], 0],
+                [region[0], region[1], 1],
+                (src.as_ptr() as _, src.len()),
+                false,
+            )
+        }
+    }
+
+    /// Perform a blocking read of the whole array into a tightly packed slice
+    pub fn read(&mut self, queue: &Queue, dest: &mut [T]) -> Result<()>
+    where
+        H: HostReadable,
+    {
+        let region = [self.width(), self.height()];
+        self.read_region(queue, [0, 0], region, dest)
+    }
+
+    /// Perform a blocking write of a tightly packed slice into the whole array
+    pub fn write(&mut self, queue: &Queue, src: &[T]) -> Result<()>
+    where
+        H: HostWritable,
+    {
+        let region = [self.width(), self.height()];
+        self.write_region(queue, [0, 0], region, src)
+    }
+}
+
+/// A buffer storing a 3D array of elements, with row and slice pitches
+///
+/// See `Buffer2d` for details.
+#[derive(Debug)]
+pub struct Buffer3d<'a, H: HostAccess, T: MemSafe> {
+    buffer: Buffer<'a, H, T>,
+    layout: Layout,
+}
+
+impl<'a, H: HostAccess, T: MemSafe> Buffer3d<'a, H, T> {
+    /// View the given buffer as a tightly packed 3D array with the given
+    /// dimensions, returning `Error::InvalidDataLength` if it's too small.
+    pub fn new(
+        buffer: Buffer<'a, H, T>,
+        width: usize,
+        height: usize,
+        depth: usize,
+    ) -> Result<Self> {
+        let row_pitch = mul(width, size_of::<T>())?;
+        let slice_pitch = mul(row_pitch, height)?;
+        Self::with_pitches(buffer, width, height, depth, row_pitch, slice_pitch)
+    }
+
+    /// View the given buffer as a 3D array with the given dimensions, and row
+    /// and slice pitches in bytes, returning `Error::InvalidDataLength` if
+    /// it's too small. Panics if a pitch is smaller than a row or slice.
+    pub fn with_pitches(
+        buffer: Buffer<'a, H, T>,
+        width: usize,
+        height: usize,
+        depth: usize,
+        row_pitch: usize,
+        slice_pitch: usize,
+    ) -> Result<Self> {
+        let layout = Layout::new::<T>(
+            buffer.rust_size(),
+            [width, height, depth],
+            row_pitch,
+            slice_pitch,
+        )?;
+
+        Ok(Self { buffer, layout })
+    }
+
+    /// Get the width of the array, in elements
+    pub fn width(&self) -> usize {
+        self.layout.dims[0]
+    }
+
+    /// Get the height of the array, in rows
+    pub fn height(&self) -> usize {
+        self.layout.dims[1]
+    }
+
+    /// Get the depth of the array, in slices
+    pub fn depth(&self) -> usize {
+        self.layout.dims[2]
+    }
+
+    /// Get the distance between the start of consecutive rows, in bytes
+    pub fn row_pitch(&self) -> usize {
+        self.layout.row_pitch
+    }
+
+    /// Get the distance between the start of consecutive slices, in bytes
+    pub fn slice_pitch(&self) -> usize {
+        self.layout.slice_pitch
+    }
+
+    /// Get a reference to the underlying buffer
+    pub fn buffer(&self) -> &Buffer<'a, H, T> {
+        &self.buffer
+    }
+
+    /// Get the underlying buffer, discarding the dimensions
+    pub fn into_inner(self) -> Buffer<'a, H, T> {
+        self.buffer
+    }
+
+    /// Perform a blocking read of the region of the given size, starting at
+    /// the given `[x, y, z]` origin, into a tightly packed slice.
+    pub fn read_region(
+        &mut self,
+        queue: &Queue,
+        origin: [usize; 3],
+        region: [usize; 3],
+        dest: &mut [T],
+    ) -> Result<()>
+    where
+        H: HostReadable,
+    {
+        unsafe {
+            self.layout.transfer::<T>(
+                queue,
+                self.buffer.raw(),
+                origin,
+                region,
+                (dest.as_mut_ptr() as _, dest.len()),
+                true,
+            )
+        }
+    }
+
+    /// Perform a blocking write of a tightly packed slice into the region of
+    /// the given size, starting at the given `[x, y, z]` origin.
+    pub fn write_region(
+        &mut self,
+        queue: &Queue,
+        origin: [usize; 3],
+        region: [usize; 3],
+        src: &[T],
+    ) -> Result<()>
+    where
+        H: HostWritable,
+    {
+        unsafe {
+            self.layout.transfer::<T>(
+                queue,
+                self.buffer.raw(),
+                origin,
+                region,
+                (src.as_ptr() as _, src.len()),
+                false,
+            )
+        }
+    }
+
+    /// Perform a blocking read of the whole array into a tightly packed slice
+    pub fn read(&mut self, queue: &Queue, dest: &mut [T]) -> Result<()>
+    where
+        H: HostReadable,
+    {
+        let region = [self.width(), self.height(), self.depth()];
+        self.read_region(queue, [0; 3], region, dest)
+    }
+
+    /// Perform a blocking write of a tightly packed slice into the whole array
+    pub fn write(&mut self, queue: &Queue, src: &[T]) -> Result<()>
+    where
+        H: HostWritable,
+    {
+        let region = [self.width(), self.height(), self.depth()];
+        self.write_region(queue, [0; 3], region, src)
+    }
+}
+
+// 2D and 3D buffers are passed to kernels as pointers to their elements
+impl<H: HostAccess, T: MemSafe> KernelArg for Buffer2d<'_, H, T> {
+    type ArgType = cl_mem;
+
+    fn is_param_type_compatible(c_type: &str) -> bool {
+        Buffer::<H, T>::is_param_type_compatible(c_type)
+    }
+
+    fn as_raw_kernel_arg(&self) -> (size_t, &cl_mem) {
+        self.buffer.as_raw_kernel_arg()
+    }
+
+    fn buffer_len(&self) -> Option<usize> {
+        self.buffer.buffer_len()
+    }
+
+    fn buffer_mem(&self) -> Option<cl_mem> {
+        self.buffer.buffer_mem()
+    }
+}
+
+impl<H: HostAccess, T: MemSafe> KernelArg for Buffer3d<'_, H, T> {
+    type ArgType = cl_mem;
+
+    fn is_param_type_compatible(c_type: &str) -> bool {
+        Buffer::<H, T>::is_param_type_compatible(c_type)
+    }
+
+    fn as_raw_kernel_arg(&self) -> (size_t, &cl_mem) {
+        self.buffer.as_raw_kernel_arg()
+    }
+
+    fn buffer_len(&self) -> Option<usize> {
+        self.buffer.buffer_len()
+    }
+
+    fn buffer_mem(&self) -> Option<cl_mem> {
+        self.buffer.buffer_mem()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Layout;
+    use crate::Error;
+
+    #[test]
+    fn test_layout_fits() {
+        // 4x3x2 floats, tightly packed
+        assert!(Layout::new::<f32>(96, [4, 3, 2], 16, 48).is_ok());
+        // padded rows and slices, where the last row needn't be padded
+        assert!(Layout::new::<f32>(128 * 2 + 32 * 2 + 16, [4, 3, 3], 32, 128).is_ok());
+        // empty regions need no storage
+        assert!(Layout::new::<f32>(0, [0, 3, 2], 16, 48).is_ok());
+    }
+
+    #[test]
+    fn test_layout_too_large() {
+        match Layout::new::<f32>(95, [4, 3, 2], 16, 48) {
+            Err(Error::InvalidDataLength {
+                expected: 96,
+                actual: 95,
+            }) => {}
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_layout_overflow() {
+        assert!(matches!(
+            Layout::new::<f32>(usize::MAX, [usize::MAX, 1, 1], usize::MAX, usize::MAX),
+            Err(Error::SizeOverflow(_))
+        ));
+        assert!(matches!(
+            Layout::new::<u8>(usize::MAX, [1, 1, 3], 1, usize::MAX / 2 + 1),
+            Err(Error::SizeOverflow(_))
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "row pitch")]
+    fn test_layout_short_row_pitch() {
+        let _ = Layout::new::<f32>(96, [4, 3, 2], 15, 48);
+    }
+
+    #[test]
+    #[should_panic(expected = "slice pitch")]
+    fn test_layout_short_slice_pitch() {
+        let _ = Layout::new::<f32>(96, [4, 3, 2], 16, 47);
+    }
+}
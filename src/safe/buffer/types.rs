@@ -1,8 +1,9 @@
 use super::flags::*;
 use super::Buffer;
+use crate::accounting;
 use crate::context::Context;
 use crate::raw::*;
-use crate::Result;
+use crate::{Error, Result};
 use libc::size_t;
 use std::marker::PhantomData;
 use std::mem::{size_of, size_of_val};
@@ -15,8 +16,18 @@ use std::ptr::null_mut;
 /// for types that have the lifetime `'static`, are sized, have copy semantics,
 /// and can be freely  moved. Additionally, the type should be valid for every
 /// possible bit pattern,  but this is currently not expressible through Rust's
-/// type system.
+/// type system. Zero-sized types (including arrays of length zero) aren't
+/// supported, and are rejected at compile time when used in buffers.
 pub unsafe trait MemSafe: 'static + Sized + Copy + Unpin {
+    /// Evaluated when creating buffers of this type, failing to compile for
+    /// zero-sized types, since buffer lengths are computed by dividing by the
+    /// size of the element type.
+    #[doc(hidden)]
+    const ASSERT_NOT_ZERO_SIZED: () = assert!(
+        size_of::<Self>() != 0,
+        "zero-sized types can't be stored in buffers"
+    );
+
     /// Check whether a given OpenCL C type (e.g. `float` or `ulong`) is
     /// compatible with this Rust type. This will be used when assigning kernel
     /// arguments as a sanity check, but should not be trusted as a guarantee of
@@ -84,8 +95,40 @@ unsafe impl MemSafe for cl_double {
     }
 }
 
+/// Fixed-size arrays are compatible with OpenCL vector types with the same
+/// element type and number of components (e.g. `[f32; 4]` and `float4`).
+///
+/// Since 3-component vectors are padded to the size of 4-component vectors,
+/// `[T; 3]` isn't compatible with them, and must be accessed as a pointer to
+/// `T` (e.g. with `vload3`) instead. Arrays of length zero are zero-sized, so
+/// they're rejected when used in buffers.
+unsafe impl<T: MemSafe, const N: usize> MemSafe for [T; N] {
+    fn is_param_type_compatible(c_type: &str) -> bool {
+        match N {
+            2 | 4 | 8 | 16 => c_type
+                .strip_suffix(&N.to_string())
+                .map(T::is_param_type_compatible)
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+}
+
+/// What a `BufferBuilder` does when a buffer exceeds the practical allocation
+/// ceiling of a device in its context, as returned by
+/// `Device::suggested_max_buffer_bytes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AllocationLimit {
+    /// Attempt the allocation anyway. This is the default.
+    Ignore,
+    /// Log a warning, then attempt the allocation
+    Warn,
+    /// Return `Error::LimitExceeded` without attempting the allocation
+    Refuse,
+}
+
 /// A partially built buffer
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 #[must_use]
 pub struct BufferBuilder<
     'c,
@@ -97,6 +140,8 @@ pub struct BufferBuilder<
     _device_access: PhantomData<D>,
     _flags: PhantomData<F>,
     context: &'c Context,
+    properties: Vec<cl_mem_properties>,
+    allocation_limit: AllocationLimit,
 }
 
 impl<'c> BufferBuilder<'c> {
@@ -107,6 +152,8 @@ impl<'c> BufferBuilder<'c> {
             _device_access: PhantomData,
             _flags: PhantomData,
             context,
+            properties: vec![],
+            allocation_limit: AllocationLimit::Ignore,
         }
     }
 }
@@ -120,6 +167,8 @@ impl<'c, H: HostAccess, D: DeviceAccess, F: BufferFlags> BufferBuilder<'c, H, D,
             _device_access: PhantomData,
             _flags: PhantomData,
             context: self.context,
+            properties: self.properties,
+            allocation_limit: self.allocation_limit,
         }
     }
 
@@ -138,32 +187,133 @@ impl<'c, H: HostAccess, D: DeviceAccess, F: BufferFlags> BufferBuilder<'c, H, D,
         self.update_flags()
     }
 
-    fn build<'a, T: MemSafe>(
+    /// Add a property (e.g. from an extension) to pass to
+    /// `clCreateBufferWithProperties` when creating this buffer. Requires
+    /// OpenCL 3.0+.
+    ///
+    /// If no properties are set, `clCreateBuffer` is used instead, so
+    /// earlier OpenCL versions are still supported.
+    pub fn property(mut self, key: cl_mem_properties, value: cl_mem_properties) -> Self {
+        self.properties.extend_from_slice(&[key, value]);
+        self
+    }
+
+    /// Set what to do when the buffer is larger than the practical allocation
+    /// ceiling (`Device::suggested_max_buffer_bytes`) of any device in the
+    /// context. Buffers larger than `max_mem_alloc_size` are always refused.
+    pub fn allocation_limit(self, allocation_limit: AllocationLimit) -> Self {
+        Self {
+            allocation_limit,
+            ..self
+        }
+    }
+
+    /// Check the buffer size against the suggested maximum buffer size of
+    /// each device, as configured by `allocation_limit`
+    fn check_allocation_limit(&self, size: size_t) -> Result<()> {
+        match self.allocation_limit {
+            AllocationLimit::Ignore => Ok(()),
+            AllocationLimit::Warn => {
+                for device in self.context.devices()? {
+                    let max = device.suggested_max_buffer_bytes()?;
+
+                    if size as cl_ulong > max {
+                        log::warn!(
+                            "Allocating buffer of {} bytes, exceeding suggested maximum of {} bytes for device {}",
+                            size,
+                            max,
+                            device.name()?.to_string_lossy()
+                        );
+                    }
+                }
+
+                Ok(())
+            }
+            AllocationLimit::Refuse => {
+                self.context
+                    .check_device_limit("suggested_max_buffer_bytes", size, |d| {
+                        Ok(d.suggested_max_buffer_bytes()? as usize)
+                    })
+            }
+        }
+    }
+
+    /// Create the buffer with the given size in bytes, host pointer, and
+    /// flags in addition to those of the builder's type parameters. Buffers
+    /// whose memory isn't allocated by OpenCL (e.g. imported from another API)
+    /// should be excluded from memory accounting.
+    pub(crate) fn build<'a, T: MemSafe>(
         self,
         size: size_t,
         host_ptr: *mut T,
         extra_flags: cl_mem_flags,
+        accounted: bool,
     ) -> Result<Buffer<'a, H, T>> {
+        #[allow(clippy::let_unit_value)]
+        let () = T::ASSERT_NOT_ZERO_SIZED;
+
+        if !self.properties.is_empty() {
+            unsafe { check_ocl_version!("clCreateBufferWithProperties" => CL30)? };
+        }
+
+        self.context
+            .check_device_limit("CL_DEVICE_MAX_MEM_ALLOC_SIZE", size, |d| {
+                Ok(d.max_mem_alloc_size()? as usize)
+            })?;
+        self.check_allocation_limit(size)?;
+        if accounted {
+            accounting::reserve(self.context.raw(), size)?;
+        }
+
         unsafe {
             let mut err = CL_SUCCESS;
+            let flags = H::FLAGS | D::FLAGS | F::FLAGS | extra_flags;
 
-            let handle = clCreateBuffer(
-                self.context.raw(),
-                H::FLAGS | D::FLAGS | F::FLAGS | extra_flags,
-                size,
-                host_ptr as _,
-                &mut err as _,
-            );
+            let (handle, context) = if self.properties.is_empty() {
+                let handle = clCreateBuffer(
+                    self.context.raw(),
+                    flags,
+                    size,
+                    host_ptr as _,
+                    &mut err as _,
+                );
+
+                (handle, "clCreateBuffer")
+            } else {
+                let mut properties = self.properties.clone();
+                properties.push(0);
+
+                let handle = clCreateBufferWithProperties(
+                    self.context.raw(),
+                    properties.as_ptr(),
+                    flags,
+                    size,
+                    host_ptr as _,
+                    &mut err as _,
+                );
 
-            wrap_result!("clCreateBuffer" => err)?;
+                (handle, "clCreateBufferWithProperties")
+            };
 
-            Ok(Buffer {
+            if let Err(e) = wrap_result!(context => err) {
+                if accounted {
+                    accounting::release(self.context.raw(), size);
+                }
+                return Err(e.into());
+            }
+
+            let buffer = Buffer {
                 _lifetime: PhantomData,
                 _host_access: PhantomData,
                 _type: PhantomData,
                 handle,
                 size,
-            })
+            };
+
+            if accounted {
+                accounting::track(self.context.raw(), handle, size)?;
+            }
+            Ok(buffer)
         }
     }
 
@@ -174,13 +324,40 @@ impl<'c, H: HostAccess, D: DeviceAccess, F: BufferFlags> BufferBuilder<'c, H, D,
             size_of_val(slice),
             slice.as_ptr() as *mut _,
             CL_MEM_COPY_HOST_PTR,
+            true,
         )
     }
 
+    /// Build a buffer with `len` elements of type `T`, taking its initial
+    /// contents from the given iterator.
+    ///
+    /// The elements are written to a staging area of exactly `len` elements,
+    /// which is copied into the buffer as with `build_copying_slice`, so the
+    /// iterator doesn't need to be collected first. Only the first `len`
+    /// elements are used, and `Error::InvalidDataLength` is returned if the
+    /// iterator ends before then.
+    pub fn build_from_iter<T: MemSafe>(
+        self,
+        len: usize,
+        iter: impl IntoIterator<Item = T>,
+    ) -> Result<Buffer<'static, H, T>> {
+        let mut staging = Vec::with_capacity(len);
+        staging.extend(iter.into_iter().take(len));
+
+        if staging.len() < len {
+            return Err(Error::InvalidDataLength {
+                expected: len,
+                actual: staging.len(),
+            });
+        }
+
+        self.build_copying_slice(&staging)
+    }
+
     /// Build a buffer with space for `size` elements of type `T`. The initial
     /// contents of the buffer are unspecified.
     pub fn build_with_size<T: MemSafe>(self, size: usize) -> Result<Buffer<'static, H, T>> {
-        self.build(size_of::<T>() * size, null_mut(), 0)
+        self.build(size_of::<T>() * size, null_mut(), 0, true)
     }
 }
 
@@ -193,8 +370,59 @@ impl<'c, H: HostAccess, D: DeviceAccess> BufferBuilder<'c, H, D, NoFlags> {
     /// use. In order to safely access the underlying slice while the buffer
     /// exists, you must map the buffer to "borrow back" a reference to the
     /// slice, which should be a no-op in sane OpenCL implementations.
+    ///
+    /// Implementations may need to copy the data if the slice isn't aligned
+    /// to the base address alignment of the devices in the context, which can
+    /// be avoided by using an `AlignedVec`. In debug builds, a warning will be
+    /// logged if the slice is misaligned, and whether the buffer is likely to
+    /// be zero-copy (as determined by `Buffer::is_zero_copy`) is logged at
+    /// debug level.
     pub fn build_using_slice<T: MemSafe>(self, slice: &mut [T]) -> Result<Buffer<H, T>> {
-        self.build(size_of_val(slice), slice.as_mut_ptr(), CL_MEM_USE_HOST_PTR)
+        if cfg!(debug_assertions) {
+            self.check_host_ptr_alignment(slice.as_ptr() as usize);
+        }
+
+        let buffer = self.build(
+            size_of_val(slice),
+            slice.as_mut_ptr(),
+            CL_MEM_USE_HOST_PTR,
+            true,
+        )?;
+
+        if cfg!(debug_assertions) {
+            match buffer.is_zero_copy() {
+                Ok(true) => log::debug!("Buffer {:?} is likely zero-copy", buffer.raw()),
+                Ok(false) => log::debug!(
+                    "Buffer {:?} is likely copied by the driver, rather than using host memory directly",
+                    buffer.raw()
+                ),
+                Err(_) => {}
+            }
+        }
+
+        Ok(buffer)
+    }
+
+    fn check_host_ptr_alignment(&self, addr: usize) {
+        let devices = match self.context.devices() {
+            Ok(d) => d,
+            Err(_) => return,
+        };
+
+        for device in devices {
+            if let Ok(bits) = device.mem_base_addr_align() {
+                let align = (bits as usize / 8).max(1).next_power_of_two();
+
+                if addr & (align - 1) != 0 {
+                    log::warn!(
+                        "Host pointer {:#x} is not aligned to {} bytes as preferred by device {:?}, which may prevent zero-copy access",
+                        addr,
+                        align,
+                        device
+                    );
+                }
+            }
+        }
     }
 }
 
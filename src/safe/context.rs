@@ -3,16 +3,26 @@
 //! An OpenCL context represents a group of one or more devices from the same
 //! platform, allowing the sharing of programs and buffers.
 
+use crate::accounting;
 use crate::device::Device;
+use crate::platform::Platform;
 use crate::queue::{Queue, QueueBuilder};
-use crate::raw::{
-    clGetContextInfo, clReleaseContext, clRetainContext, cl_context, cl_context_info, cl_device_id,
-    cl_uint,
-};
+use crate::raw::*;
+use crate::release;
 use crate::util::sealed::OclInfoInternal;
-use crate::Result;
+use crate::util::{FromOclInfo, OclInfo};
+use crate::{Error, Result};
+use libc::size_t;
 use std::ffi::c_void;
 use std::fmt::{self, Debug, Formatter};
+use std::ptr::null_mut;
+
+type DestructorCallback = Box<dyn FnOnce() + Send>;
+
+extern "C" fn destructor_callback(_context: cl_context, user_data: *mut c_void) {
+    let callback = unsafe { Box::from_raw(user_data as *mut DestructorCallback) };
+    callback();
+}
 
 /// An OpenCL context
 #[derive(PartialEq, Eq, Hash)]
@@ -23,9 +33,11 @@ unsafe impl Sync for Context {}
 
 impl Drop for Context {
     fn drop(&mut self) {
+        accounting::unregister_context(self);
+
         unsafe {
             if let Err(e) = wrap_result!("clReleaseContext" => clReleaseContext(self.0)) {
-                log::warn!("Error releasing OpenCL context {:?}: {:?}", self, e);
+                release::report("OpenCL context", self, e);
             }
         }
     }
@@ -97,10 +109,297 @@ impl Context {
         pub fn reference_count(&self) -> cl_uint = CL_CONTEXT_REFERENCE_COUNT;
         pub fn num_devices(&self) -> cl_uint = CL_CONTEXT_NUM_DEVICES;
         pub fn devices_raw(&self) -> Vec<cl_device_id> = CL_CONTEXT_DEVICES;
-        // TODO: CL_CONTEXT_PROPERTIES
+        pub fn properties(&self) -> Vec<ContextProperty> = CL_CONTEXT_PROPERTIES;
     }
 
     pub fn devices(&self) -> Result<Vec<Device>> {
         Ok(self.devices_raw()?.into_iter().map(Device).collect())
     }
+
+    /// Register a callback to be called once this context has been destroyed
+    /// by the OpenCL implementation, after the last reference to it (including
+    /// implicit references held by other objects) has been released. Requires
+    /// OpenCL 3.0+.
+    ///
+    /// This allows external resources associated with the context (e.g.
+    /// caches or interop handles) to be cleaned up reliably. Callbacks are
+    /// called in the reverse order they were registered, possibly from a
+    /// thread managed by the OpenCL implementation.
+    pub fn on_destruct(&self, callback: impl FnOnce() + Send + 'static) -> Result<()> {
+        unsafe {
+            check_ocl_version!("clSetContextDestructorCallback" => CL30)?;
+
+            let user_data = Box::into_raw(Box::new(Box::new(callback) as DestructorCallback));
+
+            let result = wrap_result!("clSetContextDestructorCallback" => clSetContextDestructorCallback(
+                self.0,
+                destructor_callback,
+                user_data as _
+            ));
+
+            if result.is_err() {
+                drop(Box::from_raw(user_data));
+            }
+
+            result?;
+            Ok(())
+        }
+    }
+
+    /// Check a requested size against a device limit, for every device in
+    /// this context, returning `Error::LimitExceeded` naming the limit if any
+    /// device's limit is exceeded.
+    pub(crate) fn check_device_limit(
+        &self,
+        limit: &'static str,
+        requested: usize,
+        get: impl Fn(Device) -> Result<usize>,
+    ) -> Result<()> {
+        for device in self.devices()? {
+            let max = get(device)?;
+
+            if requested > max {
+                return Err(Error::LimitExceeded {
+                    limit,
+                    requested,
+                    max,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A property of a context, as passed to `clCreateContext` and returned by
+/// `Context::properties`. Properties used for sharing with other APIs hold
+/// the raw handle of the shared object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContextProperty {
+    /// The platform of the context (`CL_CONTEXT_PLATFORM`)
+    Platform(Platform),
+    /// Whether the application synchronizes with interop APIs itself, rather
+    /// than relying on the implementation to do so when acquiring and
+    /// releasing shared objects (`CL_CONTEXT_INTEROP_USER_SYNC`)
+    InteropUserSync(bool),
+    /// An OpenGL context to share objects with (`CL_GL_CONTEXT_KHR`)
+    GlContext(cl_context_properties),
+    /// The EGL display of the shared OpenGL context (`CL_EGL_DISPLAY_KHR`)
+    EglDisplay(cl_context_properties),
+    /// The X11 display of the shared OpenGL context (`CL_GLX_DISPLAY_KHR`)
+    GlxDisplay(cl_context_properties),
+    /// The Windows device context of the shared OpenGL context
+    /// (`CL_WGL_HDC_KHR`)
+    WglHdc(cl_context_properties),
+    /// The CGL share group of the shared OpenGL context
+    /// (`CL_CGL_SHAREGROUP_KHR`)
+    CglShareGroup(cl_context_properties),
+    /// A Direct3D 10 device to share objects with
+    /// (`CL_CONTEXT_D3D10_DEVICE_KHR`)
+    D3D10Device(cl_context_properties),
+    /// A Direct3D 11 device to share objects with
+    /// (`CL_CONTEXT_D3D11_DEVICE_KHR`)
+    D3D11Device(cl_context_properties),
+    /// Any other property, as a raw key and value
+    Other(cl_context_properties, cl_context_properties),
+}
+
+impl ContextProperty {
+    /// Encode this property as a key and value for a property list
+    pub fn encode(self) -> [cl_context_properties; 2] {
+        use ContextProperty::*;
+
+        match self {
+            Platform(platform) => [CL_CONTEXT_PLATFORM, platform.raw() as _],
+            InteropUserSync(sync) => [
+                CL_CONTEXT_INTEROP_USER_SYNC,
+                if sync { CL_TRUE } else { CL_FALSE } as _,
+            ],
+            GlContext(handle) => [CL_GL_CONTEXT_KHR, handle],
+            EglDisplay(handle) => [CL_EGL_DISPLAY_KHR, handle],
+            GlxDisplay(handle) => [CL_GLX_DISPLAY_KHR, handle],
+            WglHdc(handle) => [CL_WGL_HDC_KHR, handle],
+            CglShareGroup(handle) => [CL_CGL_SHAREGROUP_KHR, handle],
+            D3D10Device(handle) => [CL_CONTEXT_D3D10_DEVICE_KHR, handle],
+            D3D11Device(handle) => [CL_CONTEXT_D3D11_DEVICE_KHR, handle],
+            Other(key, value) => [key, value],
+        }
+    }
+
+    /// Decode a property from a key and value of a property list
+    pub fn decode(key: cl_context_properties, value: cl_context_properties) -> Self {
+        use ContextProperty::*;
+
+        match key {
+            CL_CONTEXT_PLATFORM => Platform(crate::platform::Platform(value as _)),
+            CL_CONTEXT_INTEROP_USER_SYNC => InteropUserSync(value as cl_bool != CL_FALSE),
+            CL_GL_CONTEXT_KHR => GlContext(value),
+            CL_EGL_DISPLAY_KHR => EglDisplay(value),
+            CL_GLX_DISPLAY_KHR => GlxDisplay(value),
+            CL_WGL_HDC_KHR => WglHdc(value),
+            CL_CGL_SHAREGROUP_KHR => CglShareGroup(value),
+            CL_CONTEXT_D3D10_DEVICE_KHR => D3D10Device(value),
+            CL_CONTEXT_D3D11_DEVICE_KHR => D3D11Device(value),
+            key => Other(key, value),
+        }
+    }
+
+    /// Encode a zero-terminated property list
+    fn encode_list(properties: &[Self]) -> Vec<cl_context_properties> {
+        properties
+            .iter()
+            .flat_map(|p| p.encode().to_vec())
+            .chain(Some(0))
+            .collect()
+    }
+}
+
+impl FromOclInfo for Vec<ContextProperty> {
+    fn read<T: OclInfo>(from: &T, param_name: T::Param) -> Result<Self> {
+        let raw = Vec::<size_t>::read(from, param_name)?;
+
+        // the list is terminated by a zero key, and may be empty if the
+        // context was created without properties
+        Ok(raw
+            .chunks_exact(2)
+            .take_while(|pair| pair[0] != 0)
+            .map(|pair| ContextProperty::decode(pair[0] as _, pair[1] as _))
+            .collect())
+    }
+}
+
+/// A partially built context
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ContextBuilder<'d> {
+    devices: &'d [Device],
+    properties: Vec<ContextProperty>,
+}
+
+impl<'d> ContextBuilder<'d> {
+    /// Begin building a new context containing the given devices, which must
+    /// all belong to the same platform
+    pub fn new(devices: &'d [Device]) -> Self {
+        assert!(
+            !devices.is_empty(),
+            "contexts must contain at least one device"
+        );
+
+        Self {
+            devices,
+            properties: vec![],
+        }
+    }
+
+    /// Set whether the application synchronizes with interop APIs itself
+    pub fn interop_user_sync(mut self, sync: bool) -> Self {
+        self.properties.push(ContextProperty::InteropUserSync(sync));
+        self
+    }
+
+    /// Add a property to pass to `clCreateContext` when creating this
+    /// context. If no `Platform` property is added, the platform of the
+    /// first device is used.
+    ///
+    /// # Safety
+    ///
+    /// Raw handles held by the property (e.g. an OpenGL context or Direct3D
+    /// device) must be valid, and must remain valid for as long as the
+    /// context exists.
+    pub unsafe fn property(mut self, property: ContextProperty) -> Self {
+        self.properties.push(property);
+        self
+    }
+
+    /// Build the context, calling `clCreateContext`
+    pub fn build(self) -> Result<Context> {
+        let mut properties = self.properties;
+
+        if !properties
+            .iter()
+            .any(|p| matches!(p, ContextProperty::Platform(_)))
+        {
+            properties.insert(0, ContextProperty::Platform(self.devices[0].platform()?));
+        }
+
+        let props = ContextProperty::encode_list(&properties);
+        let ids: Vec<cl_device_id> = self.devices.iter().map(|d| d.raw()).collect();
+
+        unsafe {
+            let mut err = CL_SUCCESS;
+            let id = clCreateContext(
+                props.as_ptr(),
+                ids.len() as _,
+                ids.as_ptr(),
+                None,
+                null_mut(),
+                &mut err as _,
+            );
+            wrap_result!("clCreateContext" => err)?;
+            Ok(Context(id))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContextProperty;
+    use crate::platform::Platform;
+    use crate::raw::*;
+
+    fn round_trip(property: ContextProperty) -> ContextProperty {
+        let [key, value] = property.encode();
+        ContextProperty::decode(key, value)
+    }
+
+    #[test]
+    fn test_property_round_trip() {
+        let properties = [
+            ContextProperty::Platform(unsafe { Platform::from_raw(0x1000 as _) }),
+            ContextProperty::InteropUserSync(true),
+            ContextProperty::InteropUserSync(false),
+            ContextProperty::GlContext(1),
+            ContextProperty::EglDisplay(2),
+            ContextProperty::GlxDisplay(3),
+            ContextProperty::WglHdc(4),
+            ContextProperty::CglShareGroup(5),
+            ContextProperty::D3D10Device(6),
+            ContextProperty::D3D11Device(7),
+            ContextProperty::Other(0x7fff, 8),
+        ];
+
+        for property in properties {
+            assert_eq!(round_trip(property), property);
+        }
+    }
+
+    #[test]
+    fn test_property_encoding() {
+        assert_eq!(
+            ContextProperty::InteropUserSync(true).encode(),
+            [CL_CONTEXT_INTEROP_USER_SYNC, CL_TRUE as _]
+        );
+        assert_eq!(
+            ContextProperty::decode(CL_GL_CONTEXT_KHR, 42),
+            ContextProperty::GlContext(42)
+        );
+        // any non-zero value is true
+        assert_eq!(
+            ContextProperty::decode(CL_CONTEXT_INTEROP_USER_SYNC, 2),
+            ContextProperty::InteropUserSync(true)
+        );
+    }
+
+    #[test]
+    fn test_encode_list() {
+        let list = ContextProperty::encode_list(&[
+            ContextProperty::InteropUserSync(false),
+            ContextProperty::Other(0x7fff, 8),
+        ]);
+        assert_eq!(
+            list,
+            [CL_CONTEXT_INTEROP_USER_SYNC, CL_FALSE as _, 0x7fff, 8, 0]
+        );
+        assert_eq!(ContextProperty::encode_list(&[]), [0]);
+    }
 }
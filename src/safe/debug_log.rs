@@ -0,0 +1,191 @@
+//! Kernel debug logging
+//!
+//! A `DebugLog` is a small ring buffer in host-accessible (pinned) memory,
+//! which kernels append fixed-size debug records to with atomic operations.
+//! The host reads and decodes the records once the kernels have finished,
+//! which is a practical alternative to `printf` on drivers where it's missing
+//! or broken.
+//!
+//! Kernels use the helpers in `DebugLog::SOURCE`, which should be added to
+//! their program (e.g. with `ProgramBuilder::add_source`), and take the log
+//! buffer as a `__global uint *` argument:
+//!
+//! ```c
+//! __kernel void step(__global uint *log, __global float *data) {
+//!     size_t i = get_global_id(0);
+//!     ocl_debug_logf(log, 1, i, (float4)(data[i], 0, 0, 0));
+//! }
+//! ```
+//!
+//! Each record holds a tag, an index (e.g. the global ID), and four 32-bit
+//! values. When more records are written than the log can hold, the oldest
+//! are overwritten, and the number of records lost is reported.
+
+use crate::buffer::flags::HostReadWrite;
+use crate::buffer::Buffer;
+use crate::queue::Queue;
+use crate::raw::cl_uint;
+use crate::Result;
+use std::fmt::{self, Display, Formatter};
+
+/// The number of words in the log header: the number of records written, and
+/// the capacity
+const HEADER_WORDS: usize = 2;
+
+/// The number of words in each record: the tag, the index, and four values
+const RECORD_WORDS: usize = 6;
+
+/// A record written to a `DebugLog` by a kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DebugRecord {
+    tag: cl_uint,
+    index: cl_uint,
+    values: [cl_uint; 4],
+}
+
+impl DebugRecord {
+    /// Get the tag of this record, identifying where it was written
+    pub fn tag(&self) -> cl_uint {
+        self.tag
+    }
+
+    /// Get the index of this record, usually the global ID of the work item
+    /// which wrote it
+    pub fn index(&self) -> cl_uint {
+        self.index
+    }
+
+    /// Get the values of this record, as written with `ocl_debug_log`
+    pub fn values(&self) -> [cl_uint; 4] {
+        self.values
+    }
+
+    /// Get the values of this record as floats, as written with
+    /// `ocl_debug_logf`
+    pub fn values_f32(&self) -> [f32; 4] {
+        self.values.map(f32::from_bits)
+    }
+}
+
+impl Display for DebugRecord {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[tag {}] #{}: {:?} ({:?})",
+            self.tag,
+            self.index,
+            self.values,
+            self.values_f32()
+        )
+    }
+}
+
+/// A ring buffer which kernels append debug records to. See the module
+/// documentation for details.
+#[derive(Debug)]
+pub struct DebugLog {
+    buffer: Buffer<'static, HostReadWrite, cl_uint>,
+    capacity: usize,
+}
+
+impl DebugLog {
+    /// OpenCL C helpers for writing to a debug log:
+    ///
+    /// - `ocl_debug_log(log, tag, index, values)` appends a record with the
+    ///   given `uint` tag and index, and `uint4` values
+    /// - `ocl_debug_logf(log, tag, index, values)` appends a record with
+    ///   `float4` values
+    pub const SOURCE: &'static str = "
+void ocl_debug_log(__global uint *log, uint tag, uint index, uint4 values) {
+    uint slot = atomic_inc((volatile __global uint *)log) % log[1];
+    __global uint *record = log + 2 + slot * 6;
+    record[0] = tag;
+    record[1] = index;
+    vstore4(values, 0, record + 2);
+}
+
+#define ocl_debug_logf(log, tag, index, values) \\
+    ocl_debug_log(log, tag, index, as_uint4(values))
+";
+
+    /// Create an empty debug log in the context of the given queue, which can
+    /// hold up to `capacity` records
+    pub fn new(queue: &Queue, capacity: usize) -> Result<Self> {
+        assert!(capacity > 0, "debug log capacity must be nonzero");
+
+        let buffer = queue
+            .context()?
+            .buffer_builder()
+            .host_access::<HostReadWrite>()
+            .alloc_host_ptr()
+            .build_with_size(HEADER_WORDS + capacity * RECORD_WORDS)?;
+
+        let mut log = Self { buffer, capacity };
+        log.clear(queue)?;
+        Ok(log)
+    }
+
+    /// Get the maximum number of records this log can hold
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get a new reference to the log buffer, to be passed to a kernel
+    pub fn kernel_arg(&self) -> Result<Buffer<'static, HostReadWrite, cl_uint>> {
+        self.buffer.try_clone()
+    }
+
+    /// Discard all records in the log, blocking until complete
+    pub fn clear(&mut self, queue: &Queue) -> Result<()> {
+        queue
+            .buffer_cmd(&mut self.buffer)
+            .write(&[0, self.capacity as cl_uint])
+    }
+
+    /// Read the records in the log, oldest first, along with the number of
+    /// records which were overwritten. Blocks until complete, but doesn't wait
+    /// for kernels which may still be writing to the log.
+    pub fn read(&mut self, queue: &Queue) -> Result<(Vec<DebugRecord>, usize)> {
+        let mut words = vec![0; HEADER_WORDS + self.capacity * RECORD_WORDS];
+        queue.buffer_cmd(&mut self.buffer).read(&mut words)?;
+
+        let written = words[0] as usize;
+        let len = written.min(self.capacity);
+        let first = if written > self.capacity {
+            written % self.capacity
+        } else {
+            0
+        };
+
+        let records = (0..len)
+            .map(|i| {
+                let start = HEADER_WORDS + (first + i) % self.capacity * RECORD_WORDS;
+                let record = &words[start..start + RECORD_WORDS];
+
+                DebugRecord {
+                    tag: record[0],
+                    index: record[1],
+                    values: [record[2], record[3], record[4], record[5]],
+                }
+            })
+            .collect();
+
+        Ok((records, written - len))
+    }
+
+    /// Read the records in the log, forward them to the `log` crate (at debug
+    /// level), and clear the log
+    pub fn flush(&mut self, queue: &Queue) -> Result<()> {
+        let (records, lost) = self.read(queue)?;
+
+        if lost > 0 {
+            log::warn!("{} kernel debug records were overwritten", lost);
+        }
+
+        for record in records {
+            log::debug!("{}", record);
+        }
+
+        self.clear(queue)
+    }
+}
@@ -0,0 +1,94 @@
+//! Automatic dependency inference
+//!
+//! When dependency inference is enabled, the event of the last kernel launch
+//! which may have written each buffer is recorded, and subsequent commands
+//! enqueued through the safe API which use the buffer wait for it, on any
+//! queue in the same context. This gives correct ordering by default for work
+//! which may still be running when the command that started it returns (e.g.
+//! kernels left running by `exec_ndrange_timeout`), and for commands on
+//! out-of-order or multiple queues.
+//!
+//! Kernel launches add the recorded events to their wait lists. Buffer
+//! commands, which block until completion anyway, wait for the recorded events
+//! on the host before they're enqueued. Since the access qualifiers of kernel
+//! arguments aren't known, every buffer passed to a kernel is assumed to be
+//! written by it.
+//!
+//! Dependency inference is disabled by default, and can be toggled at runtime
+//! with `set_enabled`.
+
+use crate::event::Event;
+use crate::raw::{clRetainEvent, cl_event, cl_mem, CL_SUCCESS};
+use crate::Result;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+    /// The event of the last kernel launch which may have written each buffer,
+    /// keyed by raw handle
+    static ref LAST_WRITES: Mutex<HashMap<usize, Event>> = Default::default();
+}
+
+/// Enable or disable dependency inference at runtime. Disabling it forgets
+/// all recorded events.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+
+    if !enabled {
+        LAST_WRITES.lock().unwrap().clear();
+    }
+}
+
+/// Check whether dependency inference is currently enabled
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Get the recorded write events for the given buffers, which a command using
+/// them must wait for
+pub(crate) fn wait_list(mems: &[cl_mem]) -> Vec<Event> {
+    if !is_enabled() || mems.is_empty() {
+        return vec![];
+    }
+
+    let writes = LAST_WRITES.lock().unwrap();
+
+    mems.iter()
+        .filter_map(|&m| writes.get(&(m as usize)))
+        .filter_map(|e| e.try_clone().ok())
+        .collect()
+}
+
+/// Block until the recorded write events for the given buffers have completed
+pub(crate) fn wait_for_writes(mems: &[cl_mem]) -> Result<()> {
+    let events = wait_list(mems);
+    Event::wait_all(&events.iter().collect::<Vec<_>>())
+}
+
+/// Record the given event as the last write to the given buffers
+pub(crate) fn record_write(mems: &[cl_mem], event: cl_event) {
+    if !is_enabled() || event.is_null() {
+        return;
+    }
+
+    let mut writes = LAST_WRITES.lock().unwrap();
+
+    for &mem in mems {
+        unsafe {
+            if clRetainEvent(event) == CL_SUCCESS {
+                writes.insert(mem as usize, Event(event));
+            }
+        }
+    }
+}
+
+/// Forget the recorded write event for the given buffer, once the last wrapper
+/// for it has been dropped
+pub(crate) fn unregister_buffer(mem: cl_mem) {
+    if is_enabled() {
+        LAST_WRITES.lock().unwrap().remove(&(mem as usize));
+    }
+}
@@ -0,0 +1,82 @@
+//! Deterministic mode
+//!
+//! When deterministic mode is enabled, `clFinish` is called on a command
+//! queue after every command enqueued on it through the safe API, and command
+//! queues are built without out-of-order execution. Every command has
+//! therefore completed before the next one is submitted, which helps to tell
+//! whether misbehavior is caused by missing synchronization in user code or
+//! by a driver bug, without editing every call site.
+//!
+//! This is a debugging aid, and has a significant performance cost.
+//! Deterministic mode is disabled by default, unless the `deterministic-mode`
+//! feature is set, and can be toggled globally at runtime with `set_enabled`,
+//! or for individual queues with `Queue::set_deterministic`.
+
+use crate::queue::Queue;
+use crate::raw::{clFinish, cl_command_queue};
+use crate::Result;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static ENABLED: AtomicBool = AtomicBool::new(cfg!(feature = "deterministic-mode"));
+
+lazy_static::lazy_static! {
+    /// Command queues with deterministic mode enabled individually, keyed by
+    /// raw handle
+    static ref QUEUES: Mutex<HashSet<usize>> = Default::default();
+}
+
+/// Enable or disable deterministic mode for all queues at runtime
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Check whether deterministic mode is currently enabled for all queues
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Forget the per-queue setting for the given command queue, once the last
+/// wrapper for it has been dropped
+pub(crate) fn unregister_queue(queue: cl_command_queue) {
+    QUEUES.lock().unwrap().remove(&(queue as usize));
+}
+
+/// Block until all commands on the given queue have completed, if
+/// deterministic mode is enabled for it. Called after every command enqueued
+/// through the safe API.
+pub(crate) fn sync(queue: &Queue) -> Result<()> {
+    if queue.is_deterministic() {
+        unsafe {
+            wrap_result!("clFinish" => clFinish(queue.raw()))?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Queue {
+    /// Enable or disable deterministic mode for this queue, regardless of
+    /// whether it's enabled globally. The setting is shared by all wrappers
+    /// for the same queue.
+    ///
+    /// Out-of-order execution can't be disabled for an existing queue, but
+    /// since each command completes before the next is submitted, commands
+    /// are still serialized.
+    pub fn set_deterministic(&self, enabled: bool) {
+        let mut queues = QUEUES.lock().unwrap();
+
+        if enabled {
+            queues.insert(self.0 as usize);
+        } else {
+            queues.remove(&(self.0 as usize));
+        }
+    }
+
+    /// Check whether deterministic mode is enabled for this queue, either
+    /// globally or individually
+    pub fn is_deterministic(&self) -> bool {
+        is_enabled() || QUEUES.lock().unwrap().contains(&(self.0 as usize))
+    }
+}
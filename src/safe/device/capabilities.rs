@@ -0,0 +1,83 @@
+use super::{Device, DeviceType, FPConfig, LocalMemType};
+use crate::raw::{cl_uint, cl_ulong};
+use crate::Result;
+use libc::size_t;
+
+/// A snapshot of commonly needed information about a device, as returned by
+/// `Device::snapshot`.
+///
+/// All fields are queried once when the snapshot is taken, so reading them
+/// doesn't require any further OpenCL calls and can't fail.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DeviceCapabilities {
+    pub device: Device,
+    pub name: String,
+    pub vendor: String,
+    pub vendor_id: cl_uint,
+    pub version: String,
+    pub driver_version: String,
+    pub device_type: DeviceType,
+    pub available: bool,
+    pub max_compute_units: cl_uint,
+    pub max_clock_frequency: cl_uint,
+    pub global_mem_size: cl_ulong,
+    pub max_mem_alloc_size: cl_ulong,
+    pub local_mem_type: LocalMemType,
+    pub local_mem_size: cl_ulong,
+    pub max_constant_buffer_size: cl_ulong,
+    pub max_work_group_size: size_t,
+    pub max_work_item_sizes: Vec<size_t>,
+    pub mem_base_addr_align: cl_uint,
+    pub image_support: bool,
+    pub single_fp_config: FPConfig,
+    pub double_fp_config: FPConfig,
+    pub extensions: Vec<String>,
+}
+
+impl DeviceCapabilities {
+    /// Check whether the given extension is supported by the device
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|e| e == name)
+    }
+
+    /// Check whether the device supports double precision floating point
+    pub fn supports_fp64(&self) -> bool {
+        self.double_fp_config.raw() != 0
+    }
+}
+
+impl Device {
+    /// Query commonly needed information about this device all at once,
+    /// returning a snapshot which is cheap to clone and share.
+    pub fn snapshot(self) -> Result<DeviceCapabilities> {
+        Ok(DeviceCapabilities {
+            device: self,
+            name: self.name()?.to_string_lossy().into_owned(),
+            vendor: self.vendor()?.to_string_lossy().into_owned(),
+            vendor_id: self.device_vendor_id()?,
+            version: self.version()?.to_string_lossy().into_owned(),
+            driver_version: self.driver_version()?.to_string_lossy().into_owned(),
+            device_type: self.device_type()?,
+            available: self.available()?,
+            max_compute_units: self.max_compute_units()?,
+            max_clock_frequency: self.max_clock_frequency()?,
+            global_mem_size: self.global_mem_size()?,
+            max_mem_alloc_size: self.max_mem_alloc_size()?,
+            local_mem_type: self.local_mem_type()?,
+            local_mem_size: self.local_mem_size()?,
+            max_constant_buffer_size: self.max_constant_buffer_size()?,
+            max_work_group_size: self.max_work_group_size()?,
+            max_work_item_sizes: self.max_work_item_sizes()?,
+            mem_base_addr_align: self.mem_base_addr_align()?,
+            image_support: self.image_support()?,
+            single_fp_config: self.single_fp_config()?,
+            double_fp_config: self.double_fp_config()?,
+            extensions: self
+                .extensions()?
+                .to_string_lossy()
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+        })
+    }
+}
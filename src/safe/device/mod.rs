@@ -3,26 +3,35 @@
 //! An OpenCL device is a physical or logical device which can be used to
 //! execute OpenCL kernels.
 
+mod capabilities;
 mod types;
 
-use crate::context::Context;
+use crate::context::{Context, ContextBuilder};
 use crate::platform::Platform;
 use crate::queue::QueueProperties;
 use crate::raw::{
-    clCreateContext, clGetDeviceInfo, cl_device_id, cl_device_info, cl_platform_id, cl_uint,
-    cl_ulong, CL_CONTEXT_PLATFORM, CL_SUCCESS,
+    clGetDeviceInfo, cl_device_id, cl_device_info, cl_platform_id, cl_uint, cl_ulong,
+    CL_DEVICE_GENERIC_ADDRESS_SPACE_SUPPORT, CL_DEVICE_NON_UNIFORM_WORK_GROUP_SUPPORT,
+    CL_DEVICE_NOT_FOUND, CL_DEVICE_PIPE_SUPPORT, CL_DEVICE_PREFERRED_WORK_GROUP_SIZE_MULTIPLE,
+    CL_DEVICE_WORK_GROUP_COLLECTIVE_FUNCTIONS_SUPPORT,
 };
 use crate::util::sealed::OclInfoInternal;
-use crate::Result;
+use crate::util::OclInfo;
+use crate::version::{find_version, AtLeast, Cl30, NameVersion, NumericVersion, Versioned};
+use crate::{Error, Result};
 use libc::size_t;
 use std::ffi::CString;
 
+pub use capabilities::*;
 use std::ffi::c_void;
 use std::fmt::{self, Debug, Formatter};
 use std::hash::Hash;
-use std::ptr::null_mut;
 pub use types::*;
 
+/// PCIe vendor IDs reported by `CL_DEVICE_VENDOR_ID`, for vendor quirks
+const VENDOR_ID_AMD: cl_uint = 0x1002;
+const VENDOR_ID_INTEL: cl_uint = 0x8086;
+
 /// An OpenCL device
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Device(pub(crate) cl_device_id);
@@ -61,20 +70,7 @@ impl Device {
     /// Create a new context containing only this device, with no custom
     /// properties set.
     pub fn create_context(self) -> Result<Context> {
-        unsafe {
-            let mut props = [CL_CONTEXT_PLATFORM, self.platform()?.0 as _, 0];
-            let mut err = CL_SUCCESS;
-            let id = clCreateContext(
-                props.as_mut_ptr(),
-                1,
-                &self.0 as *const _,
-                None,
-                null_mut(),
-                &mut err as _,
-            );
-            wrap_result!("clCreateContext" => err)?;
-            Ok(Context(id))
-        }
+        ContextBuilder::new(&[self]).build()
     }
 
     /// Get the raw handle for this device
@@ -149,9 +145,11 @@ impl Device {
         pub fn local_mem_type(&self) -> LocalMemType = CL_DEVICE_LOCAL_MEM_TYPE;
         pub fn local_mem_size(&self) -> cl_ulong = CL_DEVICE_LOCAL_MEM_SIZE;
         pub fn error_correction_support(&self) -> bool = CL_DEVICE_ERROR_CORRECTION_SUPPORT;
+        pub fn host_unified_memory(&self) -> bool = CL_DEVICE_HOST_UNIFIED_MEMORY;
         pub fn profiling_timer_resolution(&self) -> size_t = CL_DEVICE_PROFILING_TIMER_RESOLUTION;
         pub fn endian_little(&self) -> bool = CL_DEVICE_ENDIAN_LITTLE;
         pub fn available(&self) -> bool = CL_DEVICE_AVAILABLE;
+        pub fn compiler_available(&self) -> bool = CL_DEVICE_COMPILER_AVAILABLE;
         pub fn linker_available(&self) -> bool = CL_DEVICE_LINKER_AVAILABLE;
         pub fn execution_capabilities(&self) -> ExecutionCapabilities = CL_DEVICE_EXECUTION_CAPABILITIES;
         pub fn queue_on_host_properties(&self) -> QueueProperties = CL_DEVICE_QUEUE_ON_HOST_PROPERTIES;
@@ -177,12 +175,17 @@ impl Device {
         pub fn partition_affinity_domain(&self) -> AffinityDomain = CL_DEVICE_PARTITION_AFFINITY_DOMAIN;
         // TODO: CL_DEVICE_PARTITION_TYPE
         pub fn reference_count(&self) -> cl_uint = CL_DEVICE_REFERENCE_COUNT;
-        pub fn svm_capabilities(&self) -> SVMCapabilities = CL_DEVICE_SVM_CAPABILITIES;
         pub fn preferred_platform_atomic_alignment(&self) -> cl_uint = CL_DEVICE_PREFERRED_PLATFORM_ATOMIC_ALIGNMENT;
         pub fn preferred_global_atomic_alignment(&self) -> cl_uint = CL_DEVICE_PREFERRED_GLOBAL_ATOMIC_ALIGNMENT;
         pub fn preferred_local_atomic_alignment(&self) -> cl_uint = CL_DEVICE_PREFERRED_LOCAL_ATOMIC_ALIGNMENT;
         pub fn max_num_sub_groups(&self) -> cl_uint = CL_DEVICE_MAX_NUM_SUB_GROUPS;
         pub fn sub_group_independent_forward_progress(&self) -> bool = CL_DEVICE_SUB_GROUP_INDEPENDENT_FORWARD_PROGRESS;
+        pub fn numeric_version(&self) -> NumericVersion = CL_DEVICE_NUMERIC_VERSION;
+        pub fn extensions_with_version(&self) -> Vec<NameVersion> = CL_DEVICE_EXTENSIONS_WITH_VERSION;
+        pub fn ils_with_version(&self) -> Vec<NameVersion> = CL_DEVICE_ILS_WITH_VERSION;
+        pub fn built_in_kernels_with_version(&self) -> Vec<NameVersion> = CL_DEVICE_BUILT_IN_KERNELS_WITH_VERSION;
+        pub fn opencl_c_all_versions(&self) -> Vec<NameVersion> = CL_DEVICE_OPENCL_C_ALL_VERSIONS;
+        pub fn opencl_c_features(&self) -> Vec<NameVersion> = CL_DEVICE_OPENCL_C_FEATURES;
     }
 
     pub fn platform(self) -> Result<Platform> {
@@ -193,4 +196,357 @@ impl Device {
         self.parent_device_raw()
             .map(|id| if id.is_null() { None } else { Some(Device(id)) })
     }
+
+    /// Check whether the given extension is supported by this device.
+    pub fn has_extension(self, name: &str) -> Result<bool> {
+        Ok(self
+            .extensions()?
+            .to_string_lossy()
+            .split_whitespace()
+            .any(|e| e == name))
+    }
+
+    /// Get the version of the given extension supported by this device, or
+    /// `None` if it isn't supported. Requires OpenCL 3.0+.
+    pub fn extension_version(self, name: &str) -> Result<Option<NumericVersion>> {
+        Ok(find_version(&self.extensions_with_version()?, name))
+    }
+
+    /// Check whether the given optional OpenCL C feature (e.g.
+    /// `__opencl_c_fp64`) is supported by this device. Requires OpenCL 3.0+.
+    pub fn has_opencl_c_feature(self, name: &str) -> Result<bool> {
+        Ok(find_version(&self.opencl_c_features()?, name).is_some())
+    }
+
+    /// Get a coarse classification of this device.
+    ///
+    /// The tier is derived from the device profile and type, and for GPUs,
+    /// from whether memory is shared with the host. If that isn't reported
+    /// (`CL_DEVICE_HOST_UNIFIED_MEMORY` is deprecated since OpenCL 2.0), GPUs
+    /// with few compute units and under 2 GiB of global memory are considered
+    /// integrated.
+    pub fn tier(self) -> Result<DeviceTier> {
+        if self.profile()?.to_bytes() == b"EMBEDDED_PROFILE" {
+            return Ok(DeviceTier::Embedded);
+        }
+
+        let typ = self.device_type()?;
+
+        if typ.contains(DeviceType::CPU) {
+            Ok(DeviceTier::Cpu)
+        } else if typ.contains(DeviceType::GPU) {
+            let integrated = match self.host_unified_memory() {
+                Ok(unified) => unified,
+                Err(_) => self.max_compute_units()? < 16 && self.global_mem_size()? < 2 << 30,
+            };
+
+            Ok(if integrated {
+                DeviceTier::IntegratedGpu
+            } else {
+                DeviceTier::DiscreteGpu
+            })
+        } else {
+            Ok(DeviceTier::Accelerator)
+        }
+    }
+
+    /// Get a practical ceiling for the size in bytes of a single buffer on
+    /// this device, which is often lower than `max_mem_alloc_size` suggests.
+    ///
+    /// This is `max_mem_alloc_size`, limited to a share of `global_mem_size`
+    /// leaving room for other allocations - 7/8 of dedicated memory, or half
+    /// of memory shared with the host (for CPUs and integrated GPUs) - and
+    /// adjusted for known vendor quirks:
+    ///
+    /// - AMD GPUs limit allocations to 1/4 of global memory by default (unless
+    ///   the `GPU_MAX_ALLOC_PERCENT` environment variable is set), which older
+    ///   drivers don't reflect in `max_mem_alloc_size`
+    /// - Intel GPUs require extension flags for buffers of 4 GiB or more
+    pub fn suggested_max_buffer_bytes(self) -> Result<cl_ulong> {
+        let global = self.global_mem_size()?;
+        let mut max = self.max_mem_alloc_size()?;
+
+        max = match self.tier()? {
+            DeviceTier::Cpu | DeviceTier::IntegratedGpu => max.min(global / 2),
+            _ => max.min(global - global / 8),
+        };
+
+        if self.device_type()?.contains(DeviceType::GPU) {
+            match self.device_vendor_id()? {
+                VENDOR_ID_AMD if std::env::var_os("GPU_MAX_ALLOC_PERCENT").is_none() => {
+                    max = max.min(global / 4);
+                }
+                VENDOR_ID_INTEL => max = max.min((4 << 30) - 1),
+                _ => {}
+            }
+        }
+
+        Ok(max)
+    }
+
+    /// Get the size in bytes of chunks to split `total_bytes` of data into,
+    /// so that each chunk fits in a buffer of at most
+    /// `suggested_max_buffer_bytes`. The data is split into as few chunks as
+    /// possible, of equal size rounded up to the device's base address
+    /// alignment (so chunks can also be used as sub-buffers), with the last
+    /// chunk holding the remainder.
+    pub fn suggested_chunk_bytes(self, total_bytes: cl_ulong) -> Result<cl_ulong> {
+        let max = self.suggested_max_buffer_bytes()?.max(1);
+        let align = (self.mem_base_addr_align()? as cl_ulong / 8).max(1);
+
+        let chunks = total_bytes.div_ceil(max).max(1);
+        let aligned = total_bytes.div_ceil(chunks).div_ceil(align) * align;
+
+        // rounding up may exceed the maximum, in which case round down instead
+        Ok(if aligned > max {
+            (max / align * align).max(align)
+        } else {
+            aligned
+        })
+    }
+
+    /// Check whether this device supports work group sizes which don't evenly
+    /// divide the global work size.
+    ///
+    /// Non-uniform work groups are always supported by OpenCL 2.x devices, and
+    /// are optional for OpenCL 3.0+ devices, where support is queried with
+    /// `CL_DEVICE_NON_UNIFORM_WORK_GROUP_SUPPORT`. Kernels using them must also
+    /// be built with `-cl-std=CL2.0` or newer.
+    pub fn supports_non_uniform_work_groups(self) -> Result<bool> {
+        // CL_DEVICE_VERSION is formatted as "OpenCL <major>.<minor> <vendor info>"
+        let major = self
+            .version()?
+            .to_string_lossy()
+            .split_whitespace()
+            .nth(1)
+            .and_then(|v| v.split('.').next()?.parse::<u32>().ok())
+            .unwrap_or(1);
+
+        match major {
+            0 | 1 => Ok(false),
+            2 => Ok(true),
+            _ => self.get_info(CL_DEVICE_NON_UNIFORM_WORK_GROUP_SUPPORT),
+        }
+    }
+}
+
+#[cfg(feature = "svm")]
+impl Device {
+    /// Get the shared virtual memory capabilities of this device. Requires
+    /// OpenCL 2.0+.
+    pub fn svm_capabilities(&self) -> Result<SVMCapabilities> {
+        self.get_info(crate::raw::CL_DEVICE_SVM_CAPABILITIES)
+    }
+}
+
+impl<V: AtLeast<Cl30>> Versioned<'_, Device, V> {
+    /// Check whether the device supports pipes
+    pub fn pipe_support(&self) -> Result<bool> {
+        self.handle().get_info(CL_DEVICE_PIPE_SUPPORT)
+    }
+
+    /// Check whether the device supports work group sizes which don't evenly
+    /// divide the global work size
+    pub fn non_uniform_work_group_support(&self) -> Result<bool> {
+        self.handle()
+            .get_info(CL_DEVICE_NON_UNIFORM_WORK_GROUP_SUPPORT)
+    }
+
+    /// Check whether the device supports work group collective functions,
+    /// such as `work_group_reduce_add`
+    pub fn work_group_collective_functions_support(&self) -> Result<bool> {
+        self.handle()
+            .get_info(CL_DEVICE_WORK_GROUP_COLLECTIVE_FUNCTIONS_SUPPORT)
+    }
+
+    /// Check whether the device supports the generic address space
+    pub fn generic_address_space_support(&self) -> Result<bool> {
+        self.handle()
+            .get_info(CL_DEVICE_GENERIC_ADDRESS_SPACE_SUPPORT)
+    }
+
+    /// Get the preferred multiple of work group size for kernels on the
+    /// device, as a performance hint
+    pub fn preferred_work_group_size_multiple(&self) -> Result<size_t> {
+        self.handle()
+            .get_info(CL_DEVICE_PREFERRED_WORK_GROUP_SIZE_MULTIPLE)
+    }
+}
+
+/// Get all devices from all platforms available on this system, paired with
+/// the platform providing them.
+///
+/// Platforms which don't provide any devices are skipped. The returned
+/// iterator provides adapters to further filter the devices, e.g.
+/// `all_devices()?.gpus().with_extension("cl_khr_fp64")`.
+pub fn all_devices() -> Result<AllDevices> {
+    let mut devices = vec![];
+
+    for platform in Platform::get_platforms()? {
+        match platform.get_devices(DeviceType::ALL) {
+            Ok(d) => devices.extend(d.into_iter().map(|d| (platform, d))),
+            Err(Error::ApiError(e)) if e.code() == CL_DEVICE_NOT_FOUND => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(AllDevices {
+        devices: devices.into_iter(),
+    })
+}
+
+/// An iterator over `(Platform, Device)` pairs, as returned by `all_devices`
+#[derive(Debug, Clone)]
+pub struct AllDevices {
+    devices: std::vec::IntoIter<(Platform, Device)>,
+}
+
+impl Iterator for AllDevices {
+    type Item = (Platform, Device);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.devices.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.devices.size_hint()
+    }
+}
+
+impl ExactSizeIterator for AllDevices {}
+
+impl AllDevices {
+    pub(crate) fn retain(self, mut f: impl FnMut(Device) -> Result<bool>) -> Self {
+        let devices: Vec<_> = self
+            .devices
+            .filter(|&(_, d)| match f(d) {
+                Ok(keep) => keep,
+                Err(e) => {
+                    log::warn!("Error querying device {:?}: {:?}", d, e);
+                    false
+                }
+            })
+            .collect();
+
+        Self {
+            devices: devices.into_iter(),
+        }
+    }
+
+    /// Only keep devices matching any of the given device types. Devices whose
+    /// type can't be queried are skipped.
+    pub fn of_type(self, typ: DeviceType) -> Self {
+        self.retain(|d| Ok((d.device_type()? & typ).raw() != 0))
+    }
+
+    /// Only keep GPU devices
+    pub fn gpus(self) -> Self {
+        self.of_type(DeviceType::GPU)
+    }
+
+    /// Only keep CPU devices
+    pub fn cpus(self) -> Self {
+        self.of_type(DeviceType::CPU)
+    }
+
+    /// Only keep accelerator devices
+    pub fn accelerators(self) -> Self {
+        self.of_type(DeviceType::ACCELERATOR)
+    }
+
+    /// Only keep devices supporting the given extension. Devices whose
+    /// extensions can't be queried are skipped.
+    pub fn with_extension(self, name: &str) -> Self {
+        self.retain(|d| d.has_extension(name))
+    }
+
+    /// Group devices which appear to be the same physical device exposed by
+    /// multiple platforms, e.g. by both a vendor driver and a generic
+    /// implementation like PoCL or rusticl.
+    ///
+    /// Devices are considered duplicates if they have the same vendor ID,
+    /// device type, and name (ignoring case and any parenthesized driver
+    /// details). This is only a heuristic - identical cards in the same system
+    /// will also be grouped together. Within each group, devices from vendor
+    /// platforms are listed before those from generic implementations. Devices
+    /// which can't be queried are skipped.
+    pub fn group_duplicates(self) -> Vec<Vec<(Platform, Device)>> {
+        let mut groups: Vec<(DuplicateKey, Vec<(Platform, Device)>)> = vec![];
+
+        for (platform, device) in self.devices {
+            let key = match DuplicateKey::new(device) {
+                Ok(k) => k,
+                Err(e) => {
+                    log::warn!("Error querying device {:?}: {:?}", device, e);
+                    continue;
+                }
+            };
+
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, group)) => group.push((platform, device)),
+                None => groups.push((key, vec![(platform, device)])),
+            }
+        }
+
+        groups
+            .into_iter()
+            .map(|(_, mut group)| {
+                group.sort_by_key(|&(p, _)| is_generic_platform(p));
+                group
+            })
+            .collect()
+    }
+
+    /// Remove devices which appear to be duplicates of another device, as
+    /// determined by `group_duplicates`, keeping the device from a vendor
+    /// platform where possible.
+    pub fn dedup(self) -> Self {
+        let devices: Vec<_> = self
+            .group_duplicates()
+            .into_iter()
+            .map(|group| group[0])
+            .collect();
+
+        Self {
+            devices: devices.into_iter(),
+        }
+    }
+}
+
+/// Identifying properties of a device, used to detect duplicates
+#[derive(PartialEq, Eq)]
+struct DuplicateKey {
+    vendor_id: cl_uint,
+    device_type: DeviceType,
+    name: String,
+}
+
+impl DuplicateKey {
+    fn new(device: Device) -> Result<Self> {
+        let name = device.name()?.to_string_lossy().to_lowercase();
+        let name = match name.find('(') {
+            Some(i) => name[..i].trim().to_string(),
+            None => name.trim().to_string(),
+        };
+
+        Ok(Self {
+            vendor_id: device.device_vendor_id()?,
+            device_type: device.device_type()?,
+            name,
+        })
+    }
+}
+
+/// Check whether a platform is a generic implementation which may expose the
+/// same devices as a vendor platform
+fn is_generic_platform(platform: Platform) -> bool {
+    const GENERIC: &[&str] = &["portable computing language", "rusticl", "clover"];
+
+    match platform.name() {
+        Ok(name) => {
+            let name = name.to_string_lossy().to_lowercase();
+            GENERIC.iter().any(|g| name.contains(g))
+        }
+        Err(_) => true,
+    }
 }
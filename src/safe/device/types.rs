@@ -31,6 +31,24 @@ bitfield! {
     }
 }
 
+/// A coarse classification of a device, as returned by `Device::tier`, which
+/// can be used to pick sensible defaults (e.g. work sizes, or whether to enable
+/// expensive features) without inspecting individual device properties.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceTier {
+    /// A device supporting only the OpenCL embedded profile, such as a mobile
+    /// or low-power GPU
+    Embedded,
+    /// A GPU sharing memory with the host
+    IntegratedGpu,
+    /// A GPU with its own dedicated memory
+    DiscreteGpu,
+    /// A CPU
+    Cpu,
+    /// A dedicated accelerator (e.g. an FPGA or DSP), or a custom device
+    Accelerator,
+}
+
 flag_enum! {
     /// Type of cache memory supported by a device
     pub enum MemCacheType(cl_device_mem_cache_type) {
@@ -73,6 +91,7 @@ bitfield! {
     }
 }
 
+#[cfg(feature = "svm")]
 bitfield! {
     /// OpenCL device SVM capabilities
     pub struct SVMCapabilities(cl_device_svm_capabilities) {
@@ -1,4 +1,4 @@
-use crate::raw::{cl_int, cl_uint, error_name, OpenCLVersion};
+use crate::raw::*;
 use std::convert::Infallible;
 use std::fmt::{self, Debug, Display, Formatter};
 
@@ -24,6 +24,49 @@ impl ApiError {
     pub fn context(&self) -> &'static str {
         self.context
     }
+
+    /// Get the category of this error, based on its error code
+    pub fn kind(&self) -> ErrorKind {
+        match self.code {
+            CL_MEM_OBJECT_ALLOCATION_FAILURE
+            | CL_OUT_OF_RESOURCES
+            | CL_OUT_OF_HOST_MEMORY
+            | CL_MAX_SIZE_RESTRICTION_EXCEEDED => ErrorKind::ResourceExhausted,
+            CL_DEVICE_NOT_FOUND
+            | CL_PLATFORM_NOT_FOUND_KHR
+            | CL_COMPILER_NOT_AVAILABLE
+            | CL_PROFILING_INFO_NOT_AVAILABLE
+            | CL_IMAGE_FORMAT_NOT_SUPPORTED
+            | CL_LINKER_NOT_AVAILABLE
+            | CL_KERNEL_ARG_INFO_NOT_AVAILABLE => ErrorKind::UnsupportedOperation,
+            CL_MEM_COPY_OVERLAP
+            | CL_IMAGE_FORMAT_MISMATCH
+            | CL_BUILD_PROGRAM_FAILURE
+            | CL_MISALIGNED_SUB_BUFFER_OFFSET
+            | CL_COMPILE_PROGRAM_FAILURE
+            | CL_LINK_PROGRAM_FAILURE => ErrorKind::InvalidUsage,
+            CL_INVALID_SPEC_ID..=CL_INVALID_VALUE => ErrorKind::InvalidUsage,
+            _ => ErrorKind::DriverFailure,
+        }
+    }
+}
+
+/// A broad category of error, for deciding how to handle or report an error
+/// without matching on individual error codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The API was used incorrectly, e.g. with invalid arguments, or a kernel
+    /// failed to compile. Retrying won't help without changing the inputs.
+    InvalidUsage,
+    /// The host or device ran out of memory or other resources. Retrying may
+    /// succeed once resources have been freed.
+    ResourceExhausted,
+    /// The driver failed or behaved unexpectedly, e.g. a device became
+    /// unavailable or returned an unknown error code.
+    DriverFailure,
+    /// The operation isn't supported by the system OpenCL library, platform,
+    /// or device.
+    UnsupportedOperation,
 }
 
 impl Debug for ApiError {
@@ -69,6 +112,92 @@ pub enum Error {
         actual: OpenCLVersion,
         context: &'static str,
     },
+
+    /// Extension not supported by the platform or device
+    #[error("Extension {0} is not supported")]
+    UnsupportedExtension(&'static str),
+
+    /// Allocation would exceed a memory budget
+    #[error("Allocation of {requested} bytes would exceed memory budget of {budget} bytes ({used} bytes in use)")]
+    BudgetExceeded {
+        requested: usize,
+        used: usize,
+        budget: usize,
+    },
+
+    /// Timed out waiting for a command to complete
+    #[error("Timed out after {0:?} waiting for command to complete")]
+    Timeout(std::time::Duration),
+
+    /// No kernel argument with the given name
+    #[error("Kernel has no argument named {0:?}")]
+    UnknownKernelArg(String),
+
+    /// A size exceeds a device limit
+    #[error("Requested size {requested} exceeds device limit {limit} of {max}")]
+    LimitExceeded {
+        limit: &'static str,
+        requested: usize,
+        max: usize,
+    },
+
+    /// Building a program failed, with the build log from each device and
+    /// the diagnostics parsed from it
+    #[error("{error}, build log:\n{log}")]
+    BuildFailed {
+        error: ApiError,
+        log: String,
+        diagnostics: Vec<crate::program::BuildDiagnostic>,
+    },
+
+    /// No suitable image format is supported for the given source data
+    #[error("No supported image format for {0} data")]
+    UnsupportedImageFormat(&'static str),
+
+    /// An address isn't aligned as required for a type
+    #[error("Address {address:#x} is not aligned to {align} bytes")]
+    Misaligned { address: usize, align: usize },
+
+    /// A kernel argument can't be set at the given index
+    #[error("Cannot set kernel argument #{index}: {reason}")]
+    InvalidArgIndex {
+        index: cl_uint,
+        reason: &'static str,
+    },
+
+    /// A size calculation overflowed
+    #[error("Size overflow in {0}")]
+    SizeOverflow(&'static str),
+
+    /// A recorded kernel launch can't be replayed
+    #[error("Invalid launch record: {0}")]
+    InvalidLaunchRecord(&'static str),
+}
+
+impl Error {
+    /// Get the category of this error
+    ///
+    /// Timeouts are treated as driver failures, since a command which doesn't
+    /// complete typically indicates a hung device.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::ApiError(e) => e.kind(),
+            Error::InvalidFlag { .. } => ErrorKind::DriverFailure,
+            Error::InvalidDataLength { .. } => ErrorKind::InvalidUsage,
+            Error::UnsupportedVersion { .. } => ErrorKind::UnsupportedOperation,
+            Error::UnsupportedExtension(_) => ErrorKind::UnsupportedOperation,
+            Error::BudgetExceeded { .. } => ErrorKind::ResourceExhausted,
+            Error::Timeout(_) => ErrorKind::DriverFailure,
+            Error::UnknownKernelArg(_) => ErrorKind::InvalidUsage,
+            Error::UnsupportedImageFormat(_) => ErrorKind::UnsupportedOperation,
+            Error::LimitExceeded { .. } => ErrorKind::InvalidUsage,
+            Error::BuildFailed { error, .. } => error.kind(),
+            Error::Misaligned { .. } => ErrorKind::InvalidUsage,
+            Error::InvalidArgIndex { .. } => ErrorKind::InvalidUsage,
+            Error::SizeOverflow(_) => ErrorKind::InvalidUsage,
+            Error::InvalidLaunchRecord(_) => ErrorKind::InvalidUsage,
+        }
+    }
 }
 
 impl Debug for Error {
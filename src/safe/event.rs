@@ -0,0 +1,219 @@
+//! Events
+//!
+//! OpenCL events represent the execution status of commands, and can be used
+//! to wait for commands to complete or to synchronize with other APIs.
+
+use crate::raw::{
+    clGetEventInfo, clGetEventProfilingInfo, clReleaseEvent, clRetainEvent, clSetEventCallback,
+    clWaitForEvents, cl_command_queue, cl_command_type, cl_context, cl_event, cl_event_info,
+    cl_int, cl_profiling_info, cl_uint, cl_ulong, CL_COMPLETE, CL_PROFILING_COMMAND_END,
+    CL_PROFILING_COMMAND_START, CL_QUEUED, CL_RUNNING, CL_SUBMITTED,
+};
+use crate::release;
+use crate::util::sealed::OclInfoInternal;
+use crate::{ApiError, Error, Result};
+use std::ffi::c_void;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+type Callback = Box<dyn FnOnce(cl_int) + Send>;
+
+extern "C" fn event_callback(_event: cl_event, status: cl_int, user_data: *mut c_void) {
+    let callback = unsafe { Box::from_raw(user_data as *mut Callback) };
+    callback(status);
+}
+
+/// An OpenCL event
+#[derive(PartialEq, Eq, Hash)]
+pub struct Event(pub(crate) cl_event);
+
+unsafe impl Send for Event {}
+unsafe impl Sync for Event {}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = wrap_result!("clReleaseEvent" => clReleaseEvent(self.0)) {
+                release::report("OpenCL event", self, e);
+            }
+        }
+    }
+}
+
+impl Debug for Event {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.info_fmt(f)
+    }
+}
+
+impl OclInfoInternal for Event {
+    type Param = cl_event_info;
+    const DEBUG_CONTEXT: &'static str = "clGetEventInfo";
+
+    unsafe fn raw_info_internal(
+        &self,
+        param_name: Self::Param,
+        param_value_size: usize,
+        param_value: *mut c_void,
+        param_value_size_ret: *mut usize,
+    ) -> i32 {
+        clGetEventInfo(
+            self.0,
+            param_name,
+            param_value_size,
+            param_value,
+            param_value_size_ret,
+        )
+    }
+}
+
+flag_enum! {
+    /// Execution status of the command associated with an event
+    pub enum ExecutionStatus(cl_int) {
+        Queued = CL_QUEUED,
+        Submitted = CL_SUBMITTED,
+        Running = CL_RUNNING,
+        Complete = CL_COMPLETE,
+    }
+}
+
+impl Event {
+    /// Attempt to clone this event, using `clRetainEvent` to ensure the event
+    /// is not released while a wrapper still exists.
+    pub fn try_clone(&self) -> Result<Self> {
+        unsafe {
+            wrap_result!("clRetainEvent" => clRetainEvent(self.0))?;
+            Ok(Self(self.0))
+        }
+    }
+
+    /// Get the raw handle for this event. Note that this handle is only a raw
+    /// pointer and does not use RAII to ensure validity, so you must manually
+    /// make sure that it's not released while still in use.
+    pub fn raw(&self) -> cl_event {
+        self.0
+    }
+
+    /// Wrap the given raw event handle
+    ///
+    /// # Safety
+    ///
+    /// If the given handle is not a valid OpenCL event, behavior is undefined.
+    /// Additionally, the reference count must stay above zero until the wrapper
+    /// is dropped (which will implicitly release the handle and decrement the
+    /// reference count).
+    pub unsafe fn from_raw(handle: cl_event) -> Self {
+        Self(handle)
+    }
+
+    info_funcs! {
+        pub fn command_queue_raw(&self) -> cl_command_queue = CL_EVENT_COMMAND_QUEUE;
+        pub fn context_raw(&self) -> cl_context = CL_EVENT_CONTEXT;
+        pub fn command_type(&self) -> cl_command_type = CL_EVENT_COMMAND_TYPE;
+        pub fn execution_status(&self) -> ExecutionStatus = CL_EVENT_COMMAND_EXECUTION_STATUS;
+        pub fn reference_count(&self) -> cl_uint = CL_EVENT_REFERENCE_COUNT;
+    }
+
+    /// Block until the command associated with this event has completed.
+    pub fn wait(&self) -> Result<()> {
+        Self::wait_all(&[self])
+    }
+
+    /// Block until the commands associated with all of the given events have
+    /// completed.
+    pub fn wait_all(events: &[&Event]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let raw: Vec<cl_event> = events.iter().map(|e| e.0).collect();
+
+        unsafe {
+            wrap_result!("clWaitForEvents" => clWaitForEvents(raw.len() as _, raw.as_ptr()))?;
+            Ok(())
+        }
+    }
+
+    /// Register a callback to be called once the command associated with this
+    /// event has completed, or terminated abnormally. The callback receives
+    /// the final execution status of the command, which is either `CL_COMPLETE`
+    /// or a negative error code. Requires OpenCL 1.1+.
+    ///
+    /// The callback may be called from a thread managed by the OpenCL
+    /// implementation, and should return quickly.
+    pub fn on_complete(&self, callback: impl FnOnce(cl_int) + Send + 'static) -> Result<()> {
+        unsafe {
+            check_ocl_version!("clSetEventCallback" => CL11)?;
+
+            let user_data = Box::into_raw(Box::new(Box::new(callback) as Callback));
+
+            let result = wrap_result!("clSetEventCallback" => clSetEventCallback(
+                self.0,
+                CL_COMPLETE,
+                event_callback,
+                user_data as _
+            ));
+
+            if result.is_err() {
+                drop(Box::from_raw(user_data));
+            }
+
+            result?;
+            Ok(())
+        }
+    }
+
+    /// Block until the command associated with this event has completed, or
+    /// until the given timeout has elapsed, in which case `Error::Timeout` is
+    /// returned and the command is left running. Requires OpenCL 1.1+.
+    ///
+    /// The command must have been submitted to the device (e.g. by flushing its
+    /// queue), or this will always time out.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<()> {
+        let state = Arc::new((Mutex::new(None), Condvar::new()));
+        let callback_state = state.clone();
+
+        self.on_complete(move |status| {
+            let (lock, cvar) = &*callback_state;
+            *lock.lock().unwrap() = Some(status);
+            cvar.notify_all();
+        })?;
+
+        let (lock, cvar) = &*state;
+        let (status, _) = cvar
+            .wait_timeout_while(lock.lock().unwrap(), timeout, |s| s.is_none())
+            .unwrap();
+
+        match *status {
+            None => Err(Error::Timeout(timeout)),
+            Some(code) if code < 0 => Err(ApiError::new(code, "clSetEventCallback").into()),
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn profiling_info(&self, param_name: cl_profiling_info) -> Result<cl_ulong> {
+        let mut value: cl_ulong = 0;
+
+        unsafe {
+            wrap_result!("clGetEventProfilingInfo" => clGetEventProfilingInfo(
+                self.0,
+                param_name,
+                std::mem::size_of::<cl_ulong>(),
+                &mut value as *mut _ as _,
+                std::ptr::null_mut()
+            ))?;
+        }
+
+        Ok(value)
+    }
+
+    /// Get the time taken by the device to execute the command associated
+    /// with this event. The command must have completed, and its queue must
+    /// have been created with `QueueProperties::PROFILING_ENABLE`.
+    pub fn profiling_duration(&self) -> Result<Duration> {
+        let start = self.profiling_info(CL_PROFILING_COMMAND_START)?;
+        let end = self.profiling_info(CL_PROFILING_COMMAND_END)?;
+        Ok(Duration::from_nanos(end.saturating_sub(start)))
+    }
+}
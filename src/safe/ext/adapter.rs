@@ -0,0 +1,114 @@
+//! Device selection by display adapter
+//!
+//! The `cl_khr_device_uuid` and `cl_khr_pci_bus_info` extensions report
+//! identifiers for the physical device behind an OpenCL device, which can be
+//! matched against the identifiers reported by graphics APIs - e.g.
+//! `VkPhysicalDeviceIDProperties::deviceUUID`/`deviceLUID` in Vulkan, or
+//! `DXGI_ADAPTER_DESC::AdapterLuid` in DXGI. This allows picking the OpenCL
+//! device driving a particular window or display, which matters on systems
+//! with multiple GPUs such as hybrid laptops.
+
+use crate::device::{AllDevices, Device};
+use crate::raw::ext::cl_khr_device_uuid::*;
+use crate::raw::ext::cl_khr_pci_bus_info::*;
+use crate::raw::{cl_device_info, cl_uint};
+use crate::util::OclInfo;
+use crate::{Error, Result};
+use std::convert::TryInto;
+
+/// An identifier for a physical display adapter, as reported by a graphics
+/// API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AdapterId {
+    /// A device UUID, as reported by `cl_khr_device_uuid` or Vulkan
+    Uuid([u8; CL_UUID_SIZE_KHR]),
+    /// A locally unique identifier (Windows only), as reported by
+    /// `cl_khr_device_uuid`, Vulkan, or DXGI
+    Luid([u8; CL_LUID_SIZE_KHR]),
+    /// A PCI bus location, as reported by `cl_khr_pci_bus_info` or
+    /// `VK_EXT_pci_bus_info`
+    Pci(cl_device_pci_bus_info_khr),
+}
+
+fn read_array<const N: usize>(device: Device, param: cl_device_info) -> Result<[u8; N]> {
+    let data = device.get_info_raw(param)?;
+    let len = data.len();
+
+    data.try_into().map_err(|_| Error::InvalidDataLength {
+        expected: N,
+        actual: len,
+    })
+}
+
+impl Device {
+    /// Get the UUID of this device. Requires the `cl_khr_device_uuid`
+    /// extension.
+    pub fn uuid(self) -> Result<[u8; CL_UUID_SIZE_KHR]> {
+        read_array(self, CL_DEVICE_UUID_KHR)
+    }
+
+    /// Get the UUID of the driver for this device. Requires the
+    /// `cl_khr_device_uuid` extension.
+    pub fn driver_uuid(self) -> Result<[u8; CL_UUID_SIZE_KHR]> {
+        read_array(self, CL_DRIVER_UUID_KHR)
+    }
+
+    /// Get the locally unique identifier of this device, if it has one. LUIDs
+    /// are generally only available on Windows. Requires the
+    /// `cl_khr_device_uuid` extension.
+    pub fn luid(self) -> Result<Option<[u8; CL_LUID_SIZE_KHR]>> {
+        if self.get_info::<bool>(CL_DEVICE_LUID_VALID_KHR)? {
+            read_array(self, CL_DEVICE_LUID_KHR).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Get the node mask of this device, identifying it within a linked
+    /// device adapter. Only meaningful if the device has a LUID. Requires the
+    /// `cl_khr_device_uuid` extension.
+    pub fn node_mask(self) -> Result<cl_uint> {
+        self.get_info(CL_DEVICE_NODE_MASK_KHR)
+    }
+
+    /// Get the PCI bus location of this device. Requires the
+    /// `cl_khr_pci_bus_info` extension.
+    pub fn pci_bus_info(self) -> Result<cl_device_pci_bus_info_khr> {
+        let data: [u8; std::mem::size_of::<cl_device_pci_bus_info_khr>()] =
+            read_array(self, CL_DEVICE_PCI_BUS_INFO_KHR)?;
+        let field = |i: usize| cl_uint::from_ne_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+
+        Ok(cl_device_pci_bus_info_khr {
+            pci_domain: field(0),
+            pci_bus: field(1),
+            pci_device: field(2),
+            pci_function: field(3),
+        })
+    }
+
+    /// Check whether this device is the given display adapter. Devices which
+    /// don't support the extension needed to check the identifier never
+    /// match.
+    pub fn is_adapter(self, id: &AdapterId) -> Result<bool> {
+        match id {
+            AdapterId::Uuid(uuid) if self.has_extension("cl_khr_device_uuid")? => {
+                Ok(self.uuid()? == *uuid)
+            }
+            AdapterId::Luid(luid) if self.has_extension("cl_khr_device_uuid")? => {
+                Ok(self.luid()?.as_ref() == Some(luid))
+            }
+            AdapterId::Pci(pci) if self.has_extension("cl_khr_pci_bus_info")? => {
+                Ok(self.pci_bus_info()? == *pci)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+impl AllDevices {
+    /// Only keep devices which are the given display adapter, as determined
+    /// by `Device::is_adapter`. Devices which can't be queried are skipped.
+    pub fn for_adapter(self, id: &AdapterId) -> Self {
+        self.retain(|d| d.is_adapter(id))
+    }
+}
@@ -0,0 +1,262 @@
+//! Command buffers
+//!
+//! The `cl_khr_command_buffer` extension allows a sequence of commands to be
+//! recorded once and then enqueued any number of times, avoiding the CPU
+//! overhead of setting up each command again. This is useful for fixed
+//! sequences of commands which are executed repeatedly, such as per-frame
+//! processing.
+//!
+//! Commands are recorded into a `CommandBufferKhr`, which must be finalized
+//! with `finalize` before it can be enqueued. Commands within a command buffer
+//! are ordered using sync points rather than events.
+
+use crate::event::Event;
+use crate::ext::load_functions;
+use crate::kernel::{Kernel, KernelArgList, KernelInfo};
+use crate::platform::Platform;
+use crate::queue::{Queue, WorkDims};
+use crate::raw::ext::cl_khr_command_buffer::*;
+use crate::raw::*;
+use crate::release;
+use crate::util::sealed::OclInfoInternal;
+use crate::util::OclInfo;
+use crate::Result;
+use std::ffi::c_void;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::ptr::{null, null_mut};
+
+/// The `cl_khr_command_buffer` extension, loaded for a specific platform
+#[derive(Debug, Clone, Copy)]
+pub struct CommandBuffers {
+    platform: Platform,
+    functions: Functions,
+}
+
+impl Platform {
+    /// Load the `cl_khr_command_buffer` extension for this platform, returning
+    /// `Error::UnsupportedExtension` if it isn't available.
+    pub fn command_buffers(self) -> Result<CommandBuffers> {
+        Ok(CommandBuffers {
+            platform: self,
+            functions: load_functions(self, "cl_khr_command_buffer", Functions::load)?,
+        })
+    }
+}
+
+impl CommandBuffers {
+    /// Get the platform this extension was loaded for
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Get the raw function table for this extension
+    pub fn functions(&self) -> &Functions {
+        &self.functions
+    }
+
+    /// Create a new, empty command buffer recording commands for the given
+    /// queue.
+    pub fn create<'a>(&self, queue: &Queue) -> Result<CommandBufferKhr<'a>> {
+        let queue = queue.try_clone()?;
+
+        unsafe {
+            let mut err = CL_SUCCESS;
+
+            let handle = (self.functions.clCreateCommandBufferKHR)(
+                1,
+                &queue.raw() as _,
+                null(),
+                &mut err as _,
+            );
+
+            wrap_result!("clCreateCommandBufferKHR" => err)?;
+
+            Ok(CommandBufferKhr {
+                _lifetime: PhantomData,
+                handle,
+                queue,
+                functions: self.functions,
+            })
+        }
+    }
+}
+
+/// A point within a command buffer which later commands in the same command
+/// buffer can wait for, as returned when recording a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SyncPoint(cl_sync_point_khr);
+
+impl SyncPoint {
+    /// Get the raw value of this sync point
+    pub fn raw(self) -> cl_sync_point_khr {
+        self.0
+    }
+}
+
+/// A recorded sequence of commands, which may be enqueued repeatedly once
+/// finalized.
+///
+/// Kernels recorded into the command buffer are borrowed for its lifetime,
+/// so that their arguments remain valid for as long as it can be enqueued.
+pub struct CommandBufferKhr<'a> {
+    _lifetime: PhantomData<&'a ()>,
+    handle: cl_command_buffer_khr,
+    queue: Queue,
+    functions: Functions,
+}
+
+unsafe impl Send for CommandBufferKhr<'_> {}
+
+impl Drop for CommandBufferKhr<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = wrap_result!("clReleaseCommandBufferKHR" => (self.functions.clReleaseCommandBufferKHR)(self.handle))
+            {
+                release::report("OpenCL command buffer", self, e);
+            }
+        }
+    }
+}
+
+impl Debug for CommandBufferKhr<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(&tynm::type_name::<Self>())
+            .field("handle", &self.handle)
+            .field("CL_COMMAND_BUFFER_STATE_KHR", &self.state())
+            .finish()
+    }
+}
+
+impl OclInfoInternal for CommandBufferKhr<'_> {
+    type Param = cl_command_buffer_info_khr;
+    const DEBUG_CONTEXT: &'static str = "clGetCommandBufferInfoKHR";
+
+    unsafe fn raw_info_internal(
+        &self,
+        param_name: Self::Param,
+        param_value_size: usize,
+        param_value: *mut c_void,
+        param_value_size_ret: *mut usize,
+    ) -> i32 {
+        (self.functions.clGetCommandBufferInfoKHR)(
+            self.handle,
+            param_name,
+            param_value_size,
+            param_value,
+            param_value_size_ret,
+        )
+    }
+}
+
+impl<'a> CommandBufferKhr<'a> {
+    /// Get the raw handle for this command buffer. Note that this handle is
+    /// only a raw pointer and does not use RAII to ensure validity, so you
+    /// must manually make sure that it's not released while still in use.
+    pub fn raw(&self) -> cl_command_buffer_khr {
+        self.handle
+    }
+
+    /// Get the queue this command buffer records commands for
+    pub fn queue(&self) -> &Queue {
+        &self.queue
+    }
+
+    /// Get the state of this command buffer, one of the
+    /// `CL_COMMAND_BUFFER_STATE_*_KHR` constants
+    pub fn state(&self) -> Result<cl_command_buffer_state_khr> {
+        self.get_info(CL_COMMAND_BUFFER_STATE_KHR)
+    }
+
+    /// Get the reference count of this command buffer
+    pub fn reference_count(&self) -> Result<cl_uint> {
+        self.get_info(CL_COMMAND_BUFFER_REFERENCE_COUNT_KHR)
+    }
+
+    /// Record execution of the given kernel with its current arguments, after
+    /// the commands at the given sync points have completed.
+    ///
+    /// Changes to the kernel's arguments after recording have no effect on the
+    /// recorded command.
+    pub fn record_ndrange<T: KernelArgList, W: WorkDims>(
+        &mut self,
+        kernel: &'a Kernel<T>,
+        global_work_size: W,
+        local_work_size: Option<W>,
+        wait_for: &[SyncPoint],
+    ) -> Result<SyncPoint> {
+        let mut sync_point = 0;
+
+        unsafe {
+            wrap_result!("clCommandNDRangeKernelKHR" => (self.functions.clCommandNDRangeKernelKHR)(
+                self.handle,
+                null_mut(),
+                null(),
+                kernel.as_unbound().raw(),
+                W::NUM_WORK_DIMS,
+                null(),
+                global_work_size.as_ptr(),
+                local_work_size.as_ref().map(|l| l.as_ptr()).unwrap_or(null()),
+                wait_for.len() as _,
+                if wait_for.is_empty() { null() } else { wait_for.as_ptr() as _ },
+                &mut sync_point as _,
+                null_mut()
+            ))?;
+        }
+
+        Ok(SyncPoint(sync_point))
+    }
+
+    /// Record a barrier which completes once the commands at the given sync
+    /// points, or all previously recorded commands if none are given, have
+    /// completed.
+    pub fn record_barrier(&mut self, wait_for: &[SyncPoint]) -> Result<SyncPoint> {
+        let mut sync_point = 0;
+
+        unsafe {
+            wrap_result!("clCommandBarrierWithWaitListKHR" => (self.functions.clCommandBarrierWithWaitListKHR)(
+                self.handle,
+                null_mut(),
+                null(),
+                wait_for.len() as _,
+                if wait_for.is_empty() { null() } else { wait_for.as_ptr() as _ },
+                &mut sync_point as _,
+                null_mut()
+            ))?;
+        }
+
+        Ok(SyncPoint(sync_point))
+    }
+
+    /// Finish recording this command buffer, so that it can be enqueued. No
+    /// further commands may be recorded afterwards.
+    pub fn finalize(&mut self) -> Result<()> {
+        unsafe {
+            wrap_result!("clFinalizeCommandBufferKHR" => (self.functions.clFinalizeCommandBufferKHR)(self.handle))?;
+        }
+
+        Ok(())
+    }
+
+    /// Enqueue the recorded commands on this command buffer's queue, returning
+    /// an event which completes once they have all completed.
+    ///
+    /// Unless the device supports simultaneous use, the command buffer may
+    /// not be enqueued again until the previous enqueue has completed.
+    pub fn enqueue(&mut self) -> Result<Event> {
+        let mut event = null_mut();
+
+        unsafe {
+            wrap_result!("clEnqueueCommandBufferKHR" => (self.functions.clEnqueueCommandBufferKHR)(
+                0,
+                null_mut(),
+                self.handle,
+                0,
+                null(),
+                &mut event as _
+            ))?;
+        }
+
+        Ok(Event(event))
+    }
+}
@@ -0,0 +1,183 @@
+//! External memory import
+//!
+//! The `cl_khr_external_memory` extension allows memory allocated by other
+//! APIs, such as Vulkan, to be imported as OpenCL buffers, so that it can be
+//! processed by kernels without copying. Imported buffers must be acquired
+//! with `ExternalMemory::acquire` before they're used by OpenCL commands, and
+//! released with `ExternalMemory::release` before the other API uses them
+//! again.
+//!
+//! Importing requires OpenCL 3.0, as well as the platform extension for the
+//! relevant handle type (e.g. `cl_khr_external_memory_opaque_fd`).
+
+use crate::buffer::flags::{DeviceAccess, HostAccess, NoFlags};
+use crate::buffer::{Buffer, BufferBuilder, MemSafe};
+use crate::event::Event;
+use crate::ext::load_functions;
+use crate::platform::Platform;
+use crate::queue::Queue;
+use crate::raw::ext::cl_khr_external_memory::*;
+use crate::raw::*;
+use crate::{Error, Result};
+use std::ffi::c_void;
+use std::mem::size_of;
+use std::os::raw::c_int;
+use std::ptr::{null, null_mut};
+
+/// A handle to memory allocated outside of OpenCL, which can be imported as
+/// an OpenCL buffer.
+///
+/// The handle must refer to an allocation at least as large as the imported
+/// buffer. Ownership of file descriptors is transferred to OpenCL by a
+/// successful import, while Win32 handles remain owned by the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExternalMemoryHandle {
+    /// An opaque POSIX file descriptor (`cl_khr_external_memory_opaque_fd`)
+    OpaqueFd(c_int),
+    /// A Linux dma-buf file descriptor (`cl_khr_external_memory_dma_buf`)
+    DmaBuf(c_int),
+    /// An opaque Win32 NT handle (`cl_khr_external_memory_win32`)
+    OpaqueWin32(*mut c_void),
+    /// An opaque Win32 KMT handle (`cl_khr_external_memory_win32`)
+    OpaqueWin32Kmt(*mut c_void),
+}
+
+impl ExternalMemoryHandle {
+    /// Get the `CL_EXTERNAL_MEMORY_HANDLE_*_KHR` type of this handle
+    pub fn handle_type(&self) -> cl_external_memory_handle_type_khr {
+        match self {
+            Self::OpaqueFd(_) => CL_EXTERNAL_MEMORY_HANDLE_OPAQUE_FD_KHR,
+            Self::DmaBuf(_) => CL_EXTERNAL_MEMORY_HANDLE_DMA_BUF_KHR,
+            Self::OpaqueWin32(_) => CL_EXTERNAL_MEMORY_HANDLE_OPAQUE_WIN32_KHR,
+            Self::OpaqueWin32Kmt(_) => CL_EXTERNAL_MEMORY_HANDLE_OPAQUE_WIN32_KMT_KHR,
+        }
+    }
+
+    fn as_property(&self) -> cl_mem_properties {
+        match *self {
+            Self::OpaqueFd(fd) | Self::DmaBuf(fd) => fd as cl_mem_properties,
+            Self::OpaqueWin32(h) | Self::OpaqueWin32Kmt(h) => h as usize as cl_mem_properties,
+        }
+    }
+}
+
+/// The `cl_khr_external_memory` extension, loaded for a specific platform
+#[derive(Debug, Clone, Copy)]
+pub struct ExternalMemory {
+    platform: Platform,
+    functions: Functions,
+}
+
+impl Platform {
+    /// Load the `cl_khr_external_memory` extension for this platform,
+    /// returning `Error::UnsupportedExtension` if it isn't available.
+    pub fn external_memory(self) -> Result<ExternalMemory> {
+        Ok(ExternalMemory {
+            platform: self,
+            functions: load_functions(self, "cl_khr_external_memory", Functions::load)?,
+        })
+    }
+}
+
+impl<'c, H: HostAccess, D: DeviceAccess> BufferBuilder<'c, H, D, NoFlags> {
+    /// Build a buffer with space for `size` elements of type `T`, importing
+    /// its memory from the given external handle. Requires OpenCL 3.0+ and the
+    /// `cl_khr_external_memory` extension.
+    ///
+    /// # Safety
+    ///
+    /// The handle must be valid, and refer to an allocation of at least
+    /// `size` elements of type `T`. The buffer must be acquired before use,
+    /// and the allocation must not be accessed by other APIs while acquired.
+    pub unsafe fn build_importing<T: MemSafe>(
+        self,
+        handle: ExternalMemoryHandle,
+        size: usize,
+    ) -> Result<Buffer<'static, H, T>> {
+        let size = size_of::<T>()
+            .checked_mul(size)
+            .ok_or(Error::SizeOverflow("imported buffer size"))?;
+
+        // the memory is allocated by another API, so it isn't accounted for
+        self.property(
+            handle.handle_type() as cl_mem_properties,
+            handle.as_property(),
+        )
+        .build(size, null_mut(), 0, false)
+    }
+}
+
+impl ExternalMemory {
+    /// Get the platform this extension was loaded for
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Get the raw function table for this extension
+    pub fn functions(&self) -> &Functions {
+        &self.functions
+    }
+
+    unsafe fn enqueue<H: HostAccess, T: MemSafe>(
+        context: &'static str,
+        func: unsafe extern "C" fn(
+            cl_command_queue,
+            cl_uint,
+            *const cl_mem,
+            cl_uint,
+            *const cl_event,
+            *mut cl_event,
+        ) -> cl_int,
+        queue: &Queue,
+        buffers: &[&Buffer<H, T>],
+    ) -> Result<Event> {
+        let handles: Vec<cl_mem> = buffers.iter().map(|b| b.raw()).collect();
+        let mut event = null_mut();
+
+        wrap_result!(context => func(
+            queue.raw(),
+            handles.len() as _,
+            handles.as_ptr(),
+            0,
+            null(),
+            &mut event as _
+        ))?;
+
+        Ok(Event(event))
+    }
+
+    /// Enqueue a command to acquire the given imported buffers for use by
+    /// subsequent OpenCL commands in the queue.
+    pub fn acquire<H: HostAccess, T: MemSafe>(
+        &self,
+        queue: &Queue,
+        buffers: &[&Buffer<H, T>],
+    ) -> Result<Event> {
+        unsafe {
+            Self::enqueue(
+                "clEnqueueAcquireExternalMemObjectsKHR",
+                self.functions.clEnqueueAcquireExternalMemObjectsKHR,
+                queue,
+                buffers,
+            )
+        }
+    }
+
+    /// Enqueue a command to release the given imported buffers once
+    /// previous commands in the queue have completed, so that they may be
+    /// used by other APIs again.
+    pub fn release<H: HostAccess, T: MemSafe>(
+        &self,
+        queue: &Queue,
+        buffers: &[&Buffer<H, T>],
+    ) -> Result<Event> {
+        unsafe {
+            Self::enqueue(
+                "clEnqueueReleaseExternalMemObjectsKHR",
+                self.functions.clEnqueueReleaseExternalMemObjectsKHR,
+                queue,
+                buffers,
+            )
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! OpenGL and EGL event interop
+//!
+//! The `cl_khr_gl_event` and `cl_khr_egl_event` extensions allow OpenCL events
+//! to be created from OpenGL and EGL sync objects. The resulting events can be
+//! waited on like any other event, allowing pipelines mixing OpenCL with
+//! OpenGL or EGL to synchronize without stalling on `glFinish`/`clFinish`.
+
+use crate::context::Context;
+use crate::event::Event;
+use crate::ext::load_functions;
+use crate::platform::Platform;
+use crate::raw::ext::cl_khr_egl_event::{self, CLeglDisplayKHR, CLeglSyncKHR};
+use crate::raw::ext::cl_khr_gl_event::{self, cl_GLsync};
+use crate::raw::CL_SUCCESS;
+use crate::Result;
+
+/// The `cl_khr_gl_event` extension, loaded for a specific platform
+#[derive(Debug, Clone, Copy)]
+pub struct GlSyncInterop {
+    platform: Platform,
+    functions: cl_khr_gl_event::Functions,
+}
+
+/// The `cl_khr_egl_event` extension, loaded for a specific platform
+#[derive(Debug, Clone, Copy)]
+pub struct EglSyncInterop {
+    platform: Platform,
+    functions: cl_khr_egl_event::Functions,
+}
+
+impl Platform {
+    /// Load the `cl_khr_gl_event` extension for this platform, returning
+    /// `Error::UnsupportedExtension` if it isn't available.
+    pub fn gl_sync_interop(self) -> Result<GlSyncInterop> {
+        Ok(GlSyncInterop {
+            platform: self,
+            functions: load_functions(self, "cl_khr_gl_event", cl_khr_gl_event::Functions::load)?,
+        })
+    }
+
+    /// Load the `cl_khr_egl_event` extension for this platform, returning
+    /// `Error::UnsupportedExtension` if it isn't available.
+    pub fn egl_sync_interop(self) -> Result<EglSyncInterop> {
+        Ok(EglSyncInterop {
+            platform: self,
+            functions: load_functions(self, "cl_khr_egl_event", cl_khr_egl_event::Functions::load)?,
+        })
+    }
+}
+
+impl GlSyncInterop {
+    /// Get the platform this extension was loaded for
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Get the raw function table for this extension
+    pub fn functions(&self) -> &cl_khr_gl_event::Functions {
+        &self.functions
+    }
+
+    /// Create an event from an OpenGL fence sync object, which will complete
+    /// once the fence has been signaled.
+    ///
+    /// # Safety
+    ///
+    /// The given sync object must be a valid fence sync object, created by an
+    /// OpenGL context which shares with the given OpenCL context.
+    pub unsafe fn create_event(&self, context: &Context, sync: cl_GLsync) -> Result<Event> {
+        let mut err = CL_SUCCESS;
+        let event = (self.functions.clCreateEventFromGLsyncKHR)(context.raw(), sync, &mut err as _);
+        wrap_result!("clCreateEventFromGLsyncKHR" => err)?;
+        Ok(Event(event))
+    }
+}
+
+impl EglSyncInterop {
+    /// Get the platform this extension was loaded for
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Get the raw function table for this extension
+    pub fn functions(&self) -> &cl_khr_egl_event::Functions {
+        &self.functions
+    }
+
+    /// Create an event from an EGL sync object, which will complete once the
+    /// sync object has been signaled.
+    ///
+    /// # Safety
+    ///
+    /// The given sync object must be a valid EGL sync object belonging to the
+    /// given EGL display.
+    pub unsafe fn create_event(
+        &self,
+        context: &Context,
+        sync: CLeglSyncKHR,
+        display: CLeglDisplayKHR,
+    ) -> Result<Event> {
+        let mut err = CL_SUCCESS;
+        let event = (self.functions.clCreateEventFromEGLSyncKHR)(
+            context.raw(),
+            sync,
+            display,
+            &mut err as _,
+        );
+        wrap_result!("clCreateEventFromEGLSyncKHR" => err)?;
+        Ok(Event(event))
+    }
+}
@@ -0,0 +1,144 @@
+//! Program scope global variable pointers
+//!
+//! The `cl_intel_global_variable_pointers` extension allows device pointers to
+//! program scope global variables to be queried, so that persistent device-side
+//! state can be passed to kernels from other programs. The pointers are passed
+//! to kernels using the `cl_intel_unified_shared_memory` extension, which must
+//! also be supported.
+
+use crate::buffer::MemSafe;
+use crate::device::Device;
+use crate::ext::load_functions;
+use crate::kernel::KernelArg;
+use crate::program::Program;
+use crate::raw::ext::cl_intel_global_variable_pointers::Functions;
+use crate::raw::ext::cl_intel_unified_shared_memory;
+use crate::raw::{cl_kernel, cl_uint};
+use crate::{Error, Result};
+use libc::size_t;
+use std::ffi::CStr;
+use std::fmt::{self, Debug, Formatter};
+use std::mem::size_of;
+use std::ptr::null_mut;
+
+/// A device pointer to a program scope global variable holding `len` elements
+/// of type `T`, as returned by `Program::global_variable_pointer`.
+///
+/// The program is retained for as long as the pointer exists, so the variable
+/// remains valid.
+pub struct GlobalVariablePointer<T: MemSafe> {
+    _program: Program,
+    usm: cl_intel_unified_shared_memory::Functions,
+    ptr: *mut T,
+    len: usize,
+}
+
+unsafe impl<T: MemSafe> Send for GlobalVariablePointer<T> {}
+
+impl<T: MemSafe> Debug for GlobalVariablePointer<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(&tynm::type_name::<Self>())
+            .field("ptr", &self.ptr)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<T: MemSafe> GlobalVariablePointer<T> {
+    /// Get the raw device pointer to the variable. Note that this pointer may
+    /// not be dereferenced by the host.
+    pub fn raw(&self) -> *mut T {
+        self.ptr
+    }
+
+    /// Get the number of elements of type `T` in the variable
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the variable is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Program {
+    /// Get a device pointer to the program scope global variable with the
+    /// given name, for the given device.
+    ///
+    /// The size of the variable must be a multiple of the size of `T`, or
+    /// `Error::InvalidDataLength` will be returned. Requires the platform to
+    /// support both the `cl_intel_global_variable_pointers` and
+    /// `cl_intel_unified_shared_memory` extensions.
+    pub fn global_variable_pointer<T: MemSafe>(
+        &self,
+        device: Device,
+        name: &CStr,
+    ) -> Result<GlobalVariablePointer<T>> {
+        #[allow(clippy::let_unit_value)]
+        let () = T::ASSERT_NOT_ZERO_SIZED;
+
+        let platform = device.platform()?;
+        let functions = load_functions(
+            platform,
+            "cl_intel_global_variable_pointers",
+            Functions::load,
+        )?;
+        let usm = *platform.unified_shared_memory()?.functions();
+
+        let mut size: size_t = 0;
+        let mut ptr = null_mut();
+
+        unsafe {
+            wrap_result!("clGetDeviceGlobalVariablePointerINTEL" => (functions.clGetDeviceGlobalVariablePointerINTEL)(
+                device.raw(),
+                self.raw(),
+                name.as_ptr(),
+                &mut size as _,
+                &mut ptr as _
+            ))?;
+        }
+
+        let len = size / size_of::<T>();
+
+        if !size.is_multiple_of(size_of::<T>()) {
+            return Err(Error::InvalidDataLength {
+                expected: len * size_of::<T>(),
+                actual: size,
+            });
+        }
+
+        Ok(GlobalVariablePointer {
+            _program: self.try_clone()?,
+            usm,
+            ptr: ptr as _,
+            len,
+        })
+    }
+}
+
+// global variable pointers can be used as pointer kernel args
+impl<T: MemSafe> KernelArg for GlobalVariablePointer<T> {
+    type ArgType = *mut T;
+
+    fn is_param_type_compatible(c_type: &str) -> bool {
+        c_type
+            .rsplit_once('*')
+            .map(|(base, _)| T::is_param_type_compatible(base))
+            .unwrap_or(false)
+    }
+
+    fn as_raw_kernel_arg(&self) -> (size_t, &*mut T) {
+        (size_of::<*mut T>(), &self.ptr)
+    }
+
+    unsafe fn set_raw_kernel_arg(&self, kernel: cl_kernel, index: cl_uint) -> Result<()> {
+        wrap_result!("clSetKernelArgMemPointerINTEL" => (self.usm.clSetKernelArgMemPointerINTEL)(
+            kernel,
+            index,
+            self.ptr as _
+        ))?;
+
+        Ok(())
+    }
+}
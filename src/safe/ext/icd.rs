@@ -0,0 +1,68 @@
+//! ICD platform enumeration
+//!
+//! When the crate is pointed at a vendor driver (an installable client
+//! driver, or ICD) rather than the ICD loader, `clGetPlatformIDs` may fail with
+//! `CL_PLATFORM_NOT_FOUND_KHR`, since ICDs only expose their platforms through
+//! the `cl_khr_icd` extension. The functions here enumerate platforms directly
+//! using `clIcdGetPlatformIDsKHR` instead.
+
+use crate::platform::Platform;
+use crate::raw::ext::cl_khr_icd::*;
+use crate::raw::CL_PLATFORM_NOT_FOUND_KHR;
+use crate::util::OclInfo;
+use crate::{Error, Result};
+use std::ffi::CString;
+use std::ptr::null_mut;
+
+impl Platform {
+    /// Get a list of the platforms provided by the loaded driver using
+    /// `clIcdGetPlatformIDsKHR`, bypassing the ICD loader. Returns
+    /// `Error::UnsupportedExtension` if the driver isn't an ICD.
+    pub fn get_icd_platforms() -> Result<Vec<Platform>> {
+        unsafe {
+            let get_platform_ids = load().ok_or(Error::UnsupportedExtension("cl_khr_icd"))?;
+            let mut num_platforms = 0u32;
+
+            match wrap_result!("clIcdGetPlatformIDsKHR" => get_platform_ids(
+                0,
+                null_mut(),
+                &mut num_platforms as _
+            )) {
+                Err(e) if e.code() == CL_PLATFORM_NOT_FOUND_KHR => return Ok(vec![]),
+                r => r?,
+            }
+
+            if num_platforms == 0 {
+                return Ok(vec![]);
+            }
+
+            let mut ids = vec![null_mut(); num_platforms as usize];
+
+            wrap_result!("clIcdGetPlatformIDsKHR" => get_platform_ids(
+                num_platforms,
+                ids.as_mut_ptr(),
+                &mut num_platforms as _,
+            ))?;
+
+            Ok(ids.into_iter().map(Platform).collect())
+        }
+    }
+
+    /// Get a list of OpenCL platforms, falling back to `get_icd_platforms` if
+    /// `clGetPlatformIDs` fails with `CL_PLATFORM_NOT_FOUND_KHR`.
+    pub fn get_platforms_or_icd() -> Result<Vec<Platform>> {
+        match Self::get_platforms() {
+            Err(Error::ApiError(e)) if e.code() == CL_PLATFORM_NOT_FOUND_KHR => {
+                Self::get_icd_platforms()
+            }
+            r => r,
+        }
+    }
+
+    /// Get the suffix used by this platform to identify its extension
+    /// functions, e.g. `"INTEL"` or `"AMD"`. Requires the `cl_khr_icd`
+    /// extension.
+    pub fn icd_suffix(self) -> Result<CString> {
+        self.get_info(CL_PLATFORM_ICD_SUFFIX_KHR)
+    }
+}
@@ -0,0 +1,49 @@
+//! Extensions
+//!
+//! Safe wrappers for optional OpenCL extensions. Each extension is only
+//! available with the corresponding feature flag set, and must also be
+//! supported by the platform at runtime.
+
+#[cfg(feature = "khr-device-uuid")]
+pub mod adapter;
+#[cfg(feature = "khr-command-buffer")]
+pub mod command_buffer;
+#[cfg(feature = "khr-external-memory")]
+pub mod external_memory;
+#[cfg(feature = "gl-interop")]
+pub mod gl_event;
+#[cfg(feature = "intel-global-variable-pointers")]
+pub mod global_variable;
+#[cfg(feature = "khr-icd")]
+pub mod icd;
+#[cfg(feature = "khr-semaphore")]
+pub mod semaphore;
+#[cfg(feature = "intel-usm")]
+pub mod usm;
+#[cfg(feature = "va-interop")]
+pub mod va_api;
+
+/// Check that the given platform supports an extension, and load its function
+/// table, returning `Error::UnsupportedExtension` if either step fails.
+#[cfg(any(
+    feature = "khr-command-buffer",
+    feature = "khr-external-memory",
+    feature = "gl-interop",
+    feature = "intel-global-variable-pointers",
+    feature = "khr-semaphore",
+    feature = "intel-usm",
+    feature = "va-interop"
+))]
+pub(crate) fn load_functions<F>(
+    platform: crate::platform::Platform,
+    name: &'static str,
+    load: unsafe fn(crate::raw::cl_platform_id) -> Option<F>,
+) -> crate::Result<F> {
+    use crate::Error;
+
+    if !platform.has_extension(name)? {
+        return Err(Error::UnsupportedExtension(name));
+    }
+
+    unsafe { load(platform.raw()) }.ok_or(Error::UnsupportedExtension(name))
+}
@@ -0,0 +1,231 @@
+//! Semaphores
+//!
+//! The `cl_khr_semaphore` extension provides semaphores, which can be signalled
+//! by commands in one queue and waited on by commands in another. Unlike
+//! events, a semaphore can be waited on before the command which signals it
+//! has been enqueued, and can be reused, which allows synchronization
+//! patterns spanning queues (or, with further extensions, other APIs) that
+//! events can't express.
+
+use crate::context::Context;
+use crate::event::Event;
+use crate::ext::load_functions;
+use crate::platform::Platform;
+use crate::queue::Queue;
+use crate::raw::ext::cl_khr_semaphore::*;
+use crate::raw::*;
+use crate::release;
+use crate::util::sealed::OclInfoInternal;
+use crate::util::OclInfo;
+use crate::Result;
+use std::ffi::c_void;
+use std::fmt::{self, Debug, Formatter};
+use std::ptr::{null, null_mut};
+
+/// The `cl_khr_semaphore` extension, loaded for a specific platform
+#[derive(Debug, Clone, Copy)]
+pub struct Semaphores {
+    platform: Platform,
+    functions: Functions,
+}
+
+impl Platform {
+    /// Load the `cl_khr_semaphore` extension for this platform, returning
+    /// `Error::UnsupportedExtension` if it isn't available.
+    pub fn semaphores(self) -> Result<Semaphores> {
+        Ok(Semaphores {
+            platform: self,
+            functions: load_functions(self, "cl_khr_semaphore", Functions::load)?,
+        })
+    }
+}
+
+impl Semaphores {
+    /// Get the platform this extension was loaded for
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Get the raw function table for this extension
+    pub fn functions(&self) -> &Functions {
+        &self.functions
+    }
+
+    /// Create a new binary semaphore in the given context, which is initially
+    /// unsignalled.
+    pub fn create_binary(&self, context: &Context) -> Result<SemaphoreKhr> {
+        let properties = [
+            CL_SEMAPHORE_TYPE_KHR as cl_semaphore_properties_khr,
+            CL_SEMAPHORE_TYPE_BINARY_KHR as cl_semaphore_properties_khr,
+            0,
+        ];
+
+        unsafe {
+            let mut err = CL_SUCCESS;
+
+            let handle = (self.functions.clCreateSemaphoreWithPropertiesKHR)(
+                context.raw(),
+                properties.as_ptr(),
+                &mut err as _,
+            );
+
+            wrap_result!("clCreateSemaphoreWithPropertiesKHR" => err)?;
+
+            Ok(SemaphoreKhr {
+                handle,
+                functions: self.functions,
+            })
+        }
+    }
+}
+
+/// An OpenCL semaphore
+pub struct SemaphoreKhr {
+    handle: cl_semaphore_khr,
+    functions: Functions,
+}
+
+unsafe impl Send for SemaphoreKhr {}
+unsafe impl Sync for SemaphoreKhr {}
+
+impl Drop for SemaphoreKhr {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = wrap_result!("clReleaseSemaphoreKHR" => (self.functions.clReleaseSemaphoreKHR)(self.handle))
+            {
+                release::report("OpenCL semaphore", self, e);
+            }
+        }
+    }
+}
+
+impl Debug for SemaphoreKhr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(&tynm::type_name::<Self>())
+            .field("handle", &self.handle)
+            .field("CL_SEMAPHORE_TYPE_KHR", &self.semaphore_type())
+            .field("CL_SEMAPHORE_REFERENCE_COUNT_KHR", &self.reference_count())
+            .finish()
+    }
+}
+
+impl OclInfoInternal for SemaphoreKhr {
+    type Param = cl_semaphore_info_khr;
+    const DEBUG_CONTEXT: &'static str = "clGetSemaphoreInfoKHR";
+
+    unsafe fn raw_info_internal(
+        &self,
+        param_name: Self::Param,
+        param_value_size: usize,
+        param_value: *mut c_void,
+        param_value_size_ret: *mut usize,
+    ) -> i32 {
+        (self.functions.clGetSemaphoreInfoKHR)(
+            self.handle,
+            param_name,
+            param_value_size,
+            param_value,
+            param_value_size_ret,
+        )
+    }
+}
+
+impl SemaphoreKhr {
+    /// Attempt to clone this semaphore, using `clRetainSemaphoreKHR` to ensure
+    /// the semaphore is not released while a wrapper still exists.
+    pub fn try_clone(&self) -> Result<Self> {
+        unsafe {
+            wrap_result!("clRetainSemaphoreKHR" => (self.functions.clRetainSemaphoreKHR)(self.handle))?;
+        }
+
+        Ok(Self {
+            handle: self.handle,
+            functions: self.functions,
+        })
+    }
+
+    /// Get the raw handle for this semaphore. Note that this handle is only a
+    /// raw pointer and does not use RAII to ensure validity, so you must
+    /// manually make sure that it's not released while still in use.
+    pub fn raw(&self) -> cl_semaphore_khr {
+        self.handle
+    }
+
+    /// Get the raw handle of the context this semaphore belongs to
+    pub fn context_raw(&self) -> Result<cl_context> {
+        self.get_info(CL_SEMAPHORE_CONTEXT_KHR)
+    }
+
+    /// Get the reference count of this semaphore
+    pub fn reference_count(&self) -> Result<cl_uint> {
+        self.get_info(CL_SEMAPHORE_REFERENCE_COUNT_KHR)
+    }
+
+    /// Get the type of this semaphore, one of the `CL_SEMAPHORE_TYPE_*_KHR`
+    /// constants
+    pub fn semaphore_type(&self) -> Result<cl_semaphore_type_khr> {
+        self.get_info(CL_SEMAPHORE_TYPE_KHR)
+    }
+
+    /// Check whether this semaphore is currently signalled
+    pub fn is_signalled(&self) -> Result<bool> {
+        self.get_info::<cl_ulong>(CL_SEMAPHORE_PAYLOAD_KHR)
+            .map(|p| p != 0)
+    }
+
+    fn enqueue(
+        &self,
+        context: &'static str,
+        func: unsafe extern "C" fn(
+            cl_command_queue,
+            cl_uint,
+            *const cl_semaphore_khr,
+            *const cl_semaphore_payload_khr,
+            cl_uint,
+            *const cl_event,
+            *mut cl_event,
+        ) -> cl_int,
+        queue: &Queue,
+        wait_for: &[Event],
+    ) -> Result<Event> {
+        let wait_list: Vec<cl_event> = wait_for.iter().map(|e| e.raw()).collect();
+        let mut event = null_mut();
+
+        unsafe {
+            wrap_result!(context => func(
+                queue.raw(),
+                1,
+                &self.handle as _,
+                null(),
+                wait_list.len() as _,
+                if wait_list.is_empty() { null() } else { wait_list.as_ptr() },
+                &mut event as _
+            ))?;
+        }
+
+        Ok(Event(event))
+    }
+
+    /// Enqueue a command to signal this semaphore once the given events, and
+    /// all previous commands in the queue, have completed.
+    pub fn signal(&self, queue: &Queue, wait_for: &[Event]) -> Result<Event> {
+        self.enqueue(
+            "clEnqueueSignalSemaphoresKHR",
+            self.functions.clEnqueueSignalSemaphoresKHR,
+            queue,
+            wait_for,
+        )
+    }
+
+    /// Enqueue a command to wait for this semaphore to be signalled, so that
+    /// subsequent commands in the queue don't begin until it has been. Waiting
+    /// resets a binary semaphore to the unsignalled state.
+    pub fn wait(&self, queue: &Queue, wait_for: &[Event]) -> Result<Event> {
+        self.enqueue(
+            "clEnqueueWaitSemaphoresKHR",
+            self.functions.clEnqueueWaitSemaphoresKHR,
+            queue,
+            wait_for,
+        )
+    }
+}
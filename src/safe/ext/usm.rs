@@ -0,0 +1,367 @@
+//! Unified shared memory
+//!
+//! The `cl_intel_unified_shared_memory` extension provides pointer-based
+//! allocations as an alternative to buffers and SVM. Allocations come in three
+//! kinds, encoded by a type parameter:
+//!
+//! - `UsmHost` allocations live in host memory, and are accessible by the host
+//!   and all devices in the context.
+//! - `UsmDevice` allocations are owned by a single device, and can't be
+//!   accessed directly by the host.
+//! - `UsmShared` allocations migrate between the host and a device as needed,
+//!   and are accessible by both.
+
+use crate::buffer::MemSafe;
+use crate::context::Context;
+use crate::device::Device;
+use crate::ext::load_functions;
+use crate::kernel::KernelArg;
+use crate::platform::Platform;
+use crate::queue::Queue;
+use crate::raw::ext::cl_intel_unified_shared_memory::*;
+use crate::raw::*;
+use crate::release;
+use crate::{Error, Result};
+use libc::size_t;
+use std::ffi::c_void;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of, size_of_val};
+use std::ptr::null_mut;
+
+mod sealed {
+    pub trait UsmKindInternal {}
+}
+
+/// A trait denoting the kind of a USM allocation.
+pub trait UsmKind: sealed::UsmKindInternal {}
+
+/// A trait denoting a USM allocation that may be accessed directly by the
+/// host.
+pub trait UsmHostAccessible: UsmKind {}
+
+/// A host USM allocation.
+pub struct UsmHost;
+
+/// A device USM allocation.
+pub struct UsmDevice;
+
+/// A shared USM allocation.
+pub struct UsmShared;
+
+impl sealed::UsmKindInternal for UsmHost {}
+impl sealed::UsmKindInternal for UsmDevice {}
+impl sealed::UsmKindInternal for UsmShared {}
+
+impl UsmKind for UsmHost {}
+impl UsmHostAccessible for UsmHost {}
+
+impl UsmKind for UsmDevice {}
+
+impl UsmKind for UsmShared {}
+impl UsmHostAccessible for UsmShared {}
+
+/// The `cl_intel_unified_shared_memory` extension, loaded for a specific
+/// platform
+#[derive(Debug, Clone, Copy)]
+pub struct UnifiedSharedMemory {
+    platform: Platform,
+    functions: Functions,
+}
+
+impl Platform {
+    /// Load the `cl_intel_unified_shared_memory` extension for this platform,
+    /// returning `Error::UnsupportedExtension` if it isn't available.
+    pub fn unified_shared_memory(self) -> Result<UnifiedSharedMemory> {
+        Ok(UnifiedSharedMemory {
+            platform: self,
+            functions: load_functions(self, "cl_intel_unified_shared_memory", Functions::load)?,
+        })
+    }
+}
+
+/// Get the size in bytes of an allocation of `len` elements of type `T`
+fn alloc_size<T>(len: usize) -> Result<usize> {
+    size_of::<T>()
+        .checked_mul(len)
+        .ok_or(Error::SizeOverflow("USM allocation size"))
+}
+
+impl UnifiedSharedMemory {
+    /// Get the platform this extension was loaded for
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Get the raw function table for this extension
+    pub fn functions(&self) -> &Functions {
+        &self.functions
+    }
+
+    fn wrap<K: UsmKind, T: MemSafe>(
+        &self,
+        context: &Context,
+        ptr: *mut c_void,
+        len: usize,
+    ) -> Result<UsmAllocation<K, T>> {
+        Ok(UsmAllocation {
+            _kind: PhantomData,
+            _type: PhantomData,
+            context: context.try_clone()?,
+            functions: self.functions,
+            ptr: ptr as _,
+            len,
+        })
+    }
+
+    /// Allocate host memory for `len` elements of type `T`, initialized to
+    /// zero.
+    pub fn alloc_host<T: MemSafe>(
+        &self,
+        context: &Context,
+        len: usize,
+    ) -> Result<UsmAllocation<UsmHost, T>> {
+        unsafe {
+            let mut err = CL_SUCCESS;
+
+            let ptr = (self.functions.clHostMemAllocINTEL)(
+                context.raw(),
+                null_mut(),
+                alloc_size::<T>(len)?,
+                align_of::<T>() as _,
+                &mut err as _,
+            );
+
+            wrap_result!("clHostMemAllocINTEL" => err)?;
+            self.wrap(context, ptr, len).map(UsmAllocation::zeroed)
+        }
+    }
+
+    /// Allocate memory owned by the given device for `len` elements of type
+    /// `T`. The initial contents of the allocation are unspecified.
+    pub fn alloc_device<T: MemSafe>(
+        &self,
+        context: &Context,
+        device: Device,
+        len: usize,
+    ) -> Result<UsmAllocation<UsmDevice, T>> {
+        unsafe {
+            let mut err = CL_SUCCESS;
+
+            let ptr = (self.functions.clDeviceMemAllocINTEL)(
+                context.raw(),
+                device.raw(),
+                null_mut(),
+                alloc_size::<T>(len)?,
+                align_of::<T>() as _,
+                &mut err as _,
+            );
+
+            wrap_result!("clDeviceMemAllocINTEL" => err)?;
+            self.wrap(context, ptr, len)
+        }
+    }
+
+    /// Allocate shared memory for `len` elements of type `T`, optionally
+    /// associated with a specific device, initialized to zero.
+    pub fn alloc_shared<T: MemSafe>(
+        &self,
+        context: &Context,
+        device: Option<Device>,
+        len: usize,
+    ) -> Result<UsmAllocation<UsmShared, T>> {
+        unsafe {
+            let mut err = CL_SUCCESS;
+
+            let ptr = (self.functions.clSharedMemAllocINTEL)(
+                context.raw(),
+                device.map(Device::raw).unwrap_or(null_mut()),
+                null_mut(),
+                alloc_size::<T>(len)?,
+                align_of::<T>() as _,
+                &mut err as _,
+            );
+
+            wrap_result!("clSharedMemAllocINTEL" => err)?;
+            self.wrap(context, ptr, len).map(UsmAllocation::zeroed)
+        }
+    }
+}
+
+/// A USM allocation holding `len` elements of type `T`.
+///
+/// The type parameter `K` indicates the kind of allocation, and determines
+/// whether the allocation may be accessed directly by the host.
+///
+/// The allocation keeps a reference to its context, and is freed with
+/// `clMemBlockingFreeINTEL` when dropped, so that it's never freed while
+/// commands using it are still executing.
+pub struct UsmAllocation<K: UsmKind, T: MemSafe> {
+    _kind: PhantomData<K>,
+    _type: PhantomData<T>,
+    context: Context,
+    functions: Functions,
+    ptr: *mut T,
+    len: usize,
+}
+
+unsafe impl<K: UsmKind, T: MemSafe> Send for UsmAllocation<K, T> {}
+
+impl<K: UsmKind, T: MemSafe> Drop for UsmAllocation<K, T> {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = wrap_result!("clMemBlockingFreeINTEL" => (self.functions.clMemBlockingFreeINTEL)(self.context.raw(), self.ptr as _))
+            {
+                release::report("USM allocation", self, e);
+            }
+        }
+    }
+}
+
+impl<K: UsmKind, T: MemSafe> Debug for UsmAllocation<K, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(&tynm::type_name::<Self>())
+            .field("ptr", &self.ptr)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<K: UsmKind, T: MemSafe> UsmAllocation<K, T> {
+    /// Get the raw pointer to this allocation. Note that this pointer may not
+    /// be dereferenced by the host unless the allocation is host-accessible.
+    pub fn raw(&self) -> *mut T {
+        self.ptr
+    }
+
+    /// Get the number of elements in this allocation
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether this allocation is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the context this allocation belongs to
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    unsafe fn memcpy(
+        &self,
+        queue: &Queue,
+        dst: *mut c_void,
+        src: *const c_void,
+        size: size_t,
+    ) -> Result<()> {
+        wrap_result!("clEnqueueMemcpyINTEL" => (self.functions.clEnqueueMemcpyINTEL)(
+            queue.raw(),
+            CL_TRUE,
+            dst,
+            src,
+            size,
+            0,
+            null_mut(),
+            null_mut()
+        ))?;
+
+        Ok(())
+    }
+
+    /// Perform a blocking copy from this allocation into the given slice.
+    pub fn read(&self, queue: &Queue, dest: &mut [T]) -> Result<()> {
+        assert!(
+            dest.len() <= self.len,
+            "cannot read {} elements from USM allocation of length {}",
+            dest.len(),
+            self.len
+        );
+
+        unsafe {
+            self.memcpy(
+                queue,
+                dest.as_mut_ptr() as _,
+                self.ptr as _,
+                size_of_val(dest),
+            )
+        }
+    }
+
+    /// Perform a blocking copy from the given slice into this allocation.
+    pub fn write(&mut self, queue: &Queue, src: &[T]) -> Result<()> {
+        assert!(
+            src.len() <= self.len,
+            "cannot write {} elements to USM allocation of length {}",
+            src.len(),
+            self.len
+        );
+
+        unsafe { self.memcpy(queue, self.ptr as _, src.as_ptr() as _, size_of_val(src)) }
+    }
+
+    /// Perform a blocking copy from another USM allocation into this one.
+    pub fn copy_from<K2: UsmKind>(
+        &mut self,
+        queue: &Queue,
+        src: &UsmAllocation<K2, T>,
+    ) -> Result<()> {
+        assert!(
+            src.len <= self.len,
+            "cannot copy {} elements to USM allocation of length {}",
+            src.len,
+            self.len
+        );
+
+        unsafe { self.memcpy(queue, self.ptr as _, src.ptr as _, size_of::<T>() * src.len) }
+    }
+}
+
+impl<K: UsmHostAccessible, T: MemSafe> UsmAllocation<K, T> {
+    /// Zero the contents of a new allocation from the host, so that it's
+    /// never read uninitialized through `as_slice`
+    fn zeroed(self) -> Self {
+        if self.len > 0 {
+            unsafe { std::ptr::write_bytes(self.ptr, 0, self.len) };
+        }
+
+        self
+    }
+
+    /// Get a slice referencing the contents of this allocation.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    /// Get a mutable slice referencing the contents of this allocation.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+// allocations can be used as pointer kernel args
+impl<K: UsmKind, T: MemSafe> KernelArg for UsmAllocation<K, T> {
+    type ArgType = *mut T;
+
+    fn is_param_type_compatible(c_type: &str) -> bool {
+        c_type
+            .rsplit_once('*')
+            .map(|(base, _)| T::is_param_type_compatible(base))
+            .unwrap_or(false)
+    }
+
+    fn as_raw_kernel_arg(&self) -> (size_t, &*mut T) {
+        (size_of::<*mut T>(), &self.ptr)
+    }
+
+    unsafe fn set_raw_kernel_arg(&self, kernel: cl_kernel, index: cl_uint) -> Result<()> {
+        wrap_result!("clSetKernelArgMemPointerINTEL" => (self.functions.clSetKernelArgMemPointerINTEL)(
+            kernel,
+            index,
+            self.ptr as _
+        ))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+//! VA-API media sharing
+//!
+//! The `cl_intel_va_api_media_sharing` extension allows VA-API surfaces to be
+//! used as OpenCL images, enabling zero-copy video frame processing on Intel
+//! GPUs. Surfaces must be acquired on a command queue before kernels can use
+//! them, and released again before VA-API can use them.
+
+use crate::buffer::flags::DeviceAccess;
+use crate::context::Context;
+use crate::device::Device;
+use crate::ext::load_functions;
+use crate::kernel::KernelArg;
+use crate::platform::Platform;
+use crate::queue::Queue;
+use crate::raw::ext::cl_intel_va_api_media_sharing::*;
+use crate::raw::*;
+use crate::release;
+use crate::util::sealed::OclInfoInternal;
+use crate::util::OclInfo;
+use crate::Result;
+use libc::size_t;
+use std::ffi::c_void;
+use std::fmt::{self, Debug, Formatter};
+use std::mem::size_of;
+use std::ptr::null_mut;
+
+flag_enum! {
+    /// The set of devices to return when querying devices for a VA-API display
+    pub enum VaApiDeviceSet(cl_va_api_device_set_intel) {
+        /// Devices which are preferred for sharing with the display
+        Preferred = CL_PREFERRED_DEVICES_FOR_VA_API_INTEL,
+        /// All devices which can share with the display
+        All = CL_ALL_DEVICES_FOR_VA_API_INTEL,
+    }
+}
+
+/// The `cl_intel_va_api_media_sharing` extension, loaded for a specific
+/// platform
+#[derive(Debug, Clone, Copy)]
+pub struct VaApiMediaSharing {
+    platform: Platform,
+    functions: Functions,
+}
+
+impl Platform {
+    /// Load the `cl_intel_va_api_media_sharing` extension for this platform,
+    /// returning `Error::UnsupportedExtension` if it isn't available.
+    pub fn va_api_media_sharing(self) -> Result<VaApiMediaSharing> {
+        Ok(VaApiMediaSharing {
+            platform: self,
+            functions: load_functions(self, "cl_intel_va_api_media_sharing", Functions::load)?,
+        })
+    }
+}
+
+impl VaApiMediaSharing {
+    /// Get the platform this extension was loaded for
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Get the raw function table for this extension
+    pub fn functions(&self) -> &Functions {
+        &self.functions
+    }
+
+    /// Get a list of OpenCL devices which can share surfaces with the given
+    /// VA-API display.
+    ///
+    /// # Safety
+    ///
+    /// The given display must be a valid VA-API display handle.
+    pub unsafe fn get_devices(
+        &self,
+        display: VADisplay,
+        set: VaApiDeviceSet,
+    ) -> Result<Vec<Device>> {
+        let mut num_devices = 0u32;
+
+        wrap_result!("clGetDeviceIDsFromVA_APIMediaAdapterINTEL" => (self.functions.clGetDeviceIDsFromVA_APIMediaAdapterINTEL)(
+            self.platform.raw(),
+            CL_VA_API_DISPLAY_INTEL,
+            display,
+            set.raw(),
+            0,
+            null_mut(),
+            &mut num_devices as _
+        ))?;
+
+        if num_devices == 0 {
+            return Ok(vec![]);
+        }
+
+        let mut ids = vec![null_mut(); num_devices as usize];
+
+        wrap_result!("clGetDeviceIDsFromVA_APIMediaAdapterINTEL" => (self.functions.clGetDeviceIDsFromVA_APIMediaAdapterINTEL)(
+            self.platform.raw(),
+            CL_VA_API_DISPLAY_INTEL,
+            display,
+            set.raw(),
+            num_devices,
+            ids.as_mut_ptr(),
+            &mut num_devices as _
+        ))?;
+
+        Ok(ids.into_iter().map(Device).collect())
+    }
+
+    /// Create a new context containing the given devices, which can share
+    /// surfaces with the given VA-API display.
+    ///
+    /// # Safety
+    ///
+    /// The given display must be a valid VA-API display handle, and must
+    /// remain valid for as long as the context exists.
+    pub unsafe fn create_context(&self, display: VADisplay, devices: &[Device]) -> Result<Context> {
+        let props = [
+            CL_CONTEXT_PLATFORM,
+            self.platform.raw() as _,
+            CL_CONTEXT_VA_API_DISPLAY_INTEL,
+            display as _,
+            0,
+        ];
+        let ids: Vec<cl_device_id> = devices.iter().map(|d| d.raw()).collect();
+        let mut err = CL_SUCCESS;
+
+        let id = clCreateContext(
+            props.as_ptr(),
+            ids.len() as _,
+            ids.as_ptr(),
+            None,
+            null_mut(),
+            &mut err as _,
+        );
+
+        wrap_result!("clCreateContext" => err)?;
+        Ok(Context(id))
+    }
+
+    /// Create an OpenCL image from a plane of a VA-API surface. The device
+    /// accessibility of the image is specified by the type parameter `D`.
+    ///
+    /// # Safety
+    ///
+    /// The given surface must be a valid surface of the VA-API display used to
+    /// create the context, and must remain valid for as long as the returned
+    /// image exists.
+    pub unsafe fn create_surface<D: DeviceAccess>(
+        &self,
+        context: &Context,
+        surface: VASurfaceID,
+        plane: cl_uint,
+    ) -> Result<VaApiMediaSurface> {
+        let mut surface = Box::new(surface);
+        let mut err = CL_SUCCESS;
+
+        let handle = (self.functions.clCreateFromVA_APIMediaSurfaceINTEL)(
+            context.raw(),
+            D::FLAGS,
+            &mut *surface as _,
+            plane,
+            &mut err as _,
+        );
+
+        wrap_result!("clCreateFromVA_APIMediaSurfaceINTEL" => err)?;
+
+        Ok(VaApiMediaSurface {
+            handle,
+            _surface: surface,
+        })
+    }
+
+    /// Acquire the given surfaces for use by OpenCL, blocking until
+    /// completion.
+    pub fn acquire(&self, queue: &Queue, surfaces: &[&VaApiMediaSurface]) -> Result<()> {
+        unsafe {
+            self.enqueue(
+                "clEnqueueAcquireVA_APIMediaSurfacesINTEL",
+                self.functions.clEnqueueAcquireVA_APIMediaSurfacesINTEL,
+                queue,
+                surfaces,
+            )
+        }
+    }
+
+    /// Release the given surfaces so that they can be used by VA-API again,
+    /// blocking until completion.
+    pub fn release(&self, queue: &Queue, surfaces: &[&VaApiMediaSurface]) -> Result<()> {
+        unsafe {
+            self.enqueue(
+                "clEnqueueReleaseVA_APIMediaSurfacesINTEL",
+                self.functions.clEnqueueReleaseVA_APIMediaSurfacesINTEL,
+                queue,
+                surfaces,
+            )
+        }
+    }
+
+    unsafe fn enqueue(
+        &self,
+        context: &'static str,
+        func: unsafe extern "C" fn(
+            cl_command_queue,
+            cl_uint,
+            *const cl_mem,
+            cl_uint,
+            *const cl_event,
+            *mut cl_event,
+        ) -> cl_int,
+        queue: &Queue,
+        surfaces: &[&VaApiMediaSurface],
+    ) -> Result<()> {
+        let handles: Vec<cl_mem> = surfaces.iter().map(|s| s.handle).collect();
+        let mut event = null_mut();
+
+        wrap_result!(context => func(
+            queue.raw(),
+            handles.len() as _,
+            handles.as_ptr(),
+            0,
+            null_mut(),
+            &mut event as _
+        ))?;
+
+        let result = wrap_result!("clWaitForEvents" => clWaitForEvents(1, &event as _));
+        wrap_result!("clReleaseEvent" => clReleaseEvent(event))?;
+        result?;
+
+        Ok(())
+    }
+}
+
+/// An OpenCL image created from a plane of a VA-API surface
+#[derive(PartialEq, Eq, Hash)]
+pub struct VaApiMediaSurface {
+    handle: cl_mem,
+    _surface: Box<VASurfaceID>,
+}
+
+unsafe impl Send for VaApiMediaSurface {}
+
+impl Drop for VaApiMediaSurface {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = wrap_result!("clReleaseMemObject" => clReleaseMemObject(self.handle)) {
+                release::report("VA-API media surface", self, e);
+            }
+        }
+    }
+}
+
+impl Debug for VaApiMediaSurface {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.info_fmt(f)
+    }
+}
+
+impl OclInfoInternal for VaApiMediaSurface {
+    type Param = cl_mem_info;
+    const DEBUG_CONTEXT: &'static str = "clGetMemObjectInfo";
+
+    unsafe fn raw_info_internal(
+        &self,
+        param_name: Self::Param,
+        param_value_size: usize,
+        param_value: *mut c_void,
+        param_value_size_ret: *mut usize,
+    ) -> i32 {
+        clGetMemObjectInfo(
+            self.handle,
+            param_name,
+            param_value_size,
+            param_value,
+            param_value_size_ret,
+        )
+    }
+}
+
+impl VaApiMediaSurface {
+    /// Get the raw handle for this image. Note that this handle is only a raw
+    /// pointer and does not use RAII to ensure validity, so you must manually
+    /// make sure that it's not released while still in use.
+    pub fn raw(&self) -> cl_mem {
+        self.handle
+    }
+
+    info_funcs! {
+        pub fn size(&self) -> size_t = CL_MEM_SIZE;
+        pub fn reference_count(&self) -> cl_uint = CL_MEM_REFERENCE_COUNT;
+        pub fn context_raw(&self) -> cl_context = CL_MEM_CONTEXT;
+    }
+
+    /// Get a pointer to the VA-API surface ID this image was created from
+    pub fn surface_raw(&self) -> Result<*mut VASurfaceID> {
+        self.get_info(CL_MEM_VA_API_MEDIA_SURFACE_INTEL)
+    }
+}
+
+// surfaces can be used as image kernel args
+impl KernelArg for VaApiMediaSurface {
+    type ArgType = cl_mem;
+
+    fn is_param_type_compatible(c_type: &str) -> bool {
+        c_type == "image2d_t"
+    }
+
+    fn as_raw_kernel_arg(&self) -> (size_t, &cl_mem) {
+        (size_of::<cl_mem>(), &self.handle)
+    }
+}
@@ -0,0 +1,161 @@
+use super::{ChannelOrder, ChannelType, ImageFormat, ImageType};
+use crate::buffer::BufferFlagsInfo;
+use crate::context::Context;
+use crate::{Error, Result};
+
+/// Image data converted on the host to a format supported by a context, as
+/// returned by `Context::convert_rgba8` and `Context::convert_gray_f32`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertedImage {
+    /// The format of the converted data
+    pub format: ImageFormat,
+    /// The converted pixel data, tightly packed in native byte order
+    pub data: Vec<u8>,
+}
+
+/// A candidate format, and a function encoding a single source pixel in it
+type Candidate<P> = (ImageFormat, fn(&P, &mut Vec<u8>));
+
+const fn format(order: ChannelOrder, ty: ChannelType) -> ImageFormat {
+    ImageFormat::new(order, ty)
+}
+
+/// RGBA8 candidates, in order of preference
+const RGBA8_CANDIDATES: &[Candidate<[u8; 4]>] = &[
+    (
+        format(ChannelOrder::Rgba, ChannelType::UnormInt8),
+        |&[r, g, b, a], out| out.extend_from_slice(&[r, g, b, a]),
+    ),
+    (
+        format(ChannelOrder::Bgra, ChannelType::UnormInt8),
+        |&[r, g, b, a], out| out.extend_from_slice(&[b, g, r, a]),
+    ),
+    (
+        format(ChannelOrder::Argb, ChannelType::UnormInt8),
+        |&[r, g, b, a], out| out.extend_from_slice(&[a, r, g, b]),
+    ),
+    (
+        format(ChannelOrder::Abgr, ChannelType::UnormInt8),
+        |&[r, g, b, a], out| out.extend_from_slice(&[a, b, g, r]),
+    ),
+    (
+        format(ChannelOrder::Rgba, ChannelType::UnormInt16),
+        |p, out| {
+            for &c in p {
+                out.extend_from_slice(&(c as u16 * 257).to_ne_bytes());
+            }
+        },
+    ),
+    (format(ChannelOrder::Rgba, ChannelType::Float), |p, out| {
+        for &c in p {
+            out.extend_from_slice(&(c as f32 / 255.0).to_ne_bytes());
+        }
+    }),
+];
+
+/// Grayscale f32 candidates, in order of preference
+const GRAY_F32_CANDIDATES: &[Candidate<f32>] = &[
+    (format(ChannelOrder::R, ChannelType::Float), |&v, out| {
+        out.extend_from_slice(&v.to_ne_bytes())
+    }),
+    (
+        format(ChannelOrder::Luminance, ChannelType::Float),
+        |&v, out| out.extend_from_slice(&v.to_ne_bytes()),
+    ),
+    (
+        format(ChannelOrder::Intensity, ChannelType::Float),
+        |&v, out| out.extend_from_slice(&v.to_ne_bytes()),
+    ),
+    (
+        format(ChannelOrder::R, ChannelType::UnormInt16),
+        |&v, out| out.extend_from_slice(&unorm16(v).to_ne_bytes()),
+    ),
+    (
+        format(ChannelOrder::R, ChannelType::UnormInt8),
+        |&v, out| out.push(unorm8(v)),
+    ),
+    (format(ChannelOrder::Rgba, ChannelType::Float), |&v, out| {
+        for c in &[v, v, v, 1.0] {
+            out.extend_from_slice(&c.to_ne_bytes());
+        }
+    }),
+    (
+        format(ChannelOrder::Rgba, ChannelType::UnormInt8),
+        |&v, out| {
+            let v = unorm8(v);
+            out.extend_from_slice(&[v, v, v, 255]);
+        },
+    ),
+];
+
+fn unorm8(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn unorm16(v: f32) -> u16 {
+    (v.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+impl Context {
+    fn convert<P>(
+        &self,
+        flags: BufferFlagsInfo,
+        image_type: ImageType,
+        pixels: &[P],
+        candidates: &[Candidate<P>],
+        source: &'static str,
+    ) -> Result<ConvertedImage> {
+        let supported = self.supported_image_formats(flags, image_type)?;
+
+        let &(format, encode) = candidates
+            .iter()
+            .find(|(f, _)| supported.contains(f))
+            .ok_or(Error::UnsupportedImageFormat(source))?;
+
+        let mut data = Vec::with_capacity(pixels.len() * format.pixel_size());
+
+        for pixel in pixels {
+            encode(pixel, &mut data);
+        }
+
+        Ok(ConvertedImage { format, data })
+    }
+
+    /// Convert RGBA pixels with 8 bits per channel to the closest format
+    /// supported by this context for images of the given type and flags,
+    /// reordering or widening channels on the host if `CL_RGBA` /
+    /// `CL_UNORM_INT8` isn't supported.
+    ///
+    /// Returns `Error::UnsupportedImageFormat` if no suitable format is
+    /// supported.
+    pub fn convert_rgba8(
+        &self,
+        flags: BufferFlagsInfo,
+        image_type: ImageType,
+        pixels: &[[u8; 4]],
+    ) -> Result<ConvertedImage> {
+        self.convert(flags, image_type, pixels, RGBA8_CANDIDATES, "RGBA8")
+    }
+
+    /// Convert single-channel `f32` pixels to the closest format supported by
+    /// this context for images of the given type and flags, quantizing values
+    /// (clamped to `0.0..=1.0`) or replicating them across channels on the
+    /// host if `CL_R` / `CL_FLOAT` isn't supported.
+    ///
+    /// Returns `Error::UnsupportedImageFormat` if no suitable format is
+    /// supported.
+    pub fn convert_gray_f32(
+        &self,
+        flags: BufferFlagsInfo,
+        image_type: ImageType,
+        pixels: &[f32],
+    ) -> Result<ConvertedImage> {
+        self.convert(
+            flags,
+            image_type,
+            pixels,
+            GRAY_F32_CANDIDATES,
+            "grayscale f32",
+        )
+    }
+}
@@ -0,0 +1,274 @@
+//! Images
+//!
+//! OpenCL images are memory objects with a specific format and dimensions,
+//! which kernels access through samplers rather than as plain arrays. Image
+//! format support varies widely between devices, so the supported formats
+//! should be queried with `Context::supported_image_formats` before creating
+//! images.
+
+// some channel order constants (e.g. `CL_sRGB`) aren't upper case
+#![allow(non_upper_case_globals)]
+
+mod convert;
+
+use crate::buffer::BufferFlagsInfo;
+use crate::context::Context;
+use crate::raw::*;
+use crate::Result;
+pub use convert::*;
+use std::ptr::null_mut;
+
+flag_enum! {
+    /// OpenCL image channel order
+    pub enum ChannelOrder(cl_channel_order) {
+        R = CL_R,
+        A = CL_A,
+        Rg = CL_RG,
+        Ra = CL_RA,
+        Rgb = CL_RGB,
+        Rgba = CL_RGBA,
+        Bgra = CL_BGRA,
+        Argb = CL_ARGB,
+        Intensity = CL_INTENSITY,
+        Luminance = CL_LUMINANCE,
+        Rx = CL_Rx,
+        Rgx = CL_RGx,
+        Rgbx = CL_RGBx,
+        Depth = CL_DEPTH,
+        DepthStencil = CL_DEPTH_STENCIL,
+        Srgb = CL_sRGB,
+        Srgbx = CL_sRGBx,
+        Srgba = CL_sRGBA,
+        Sbgra = CL_sBGRA,
+        Abgr = CL_ABGR
+    }
+}
+
+flag_enum! {
+    /// OpenCL image channel data type
+    pub enum ChannelType(cl_channel_type) {
+        SnormInt8 = CL_SNORM_INT8,
+        SnormInt16 = CL_SNORM_INT16,
+        UnormInt8 = CL_UNORM_INT8,
+        UnormInt16 = CL_UNORM_INT16,
+        UnormShort565 = CL_UNORM_SHORT_565,
+        UnormShort555 = CL_UNORM_SHORT_555,
+        UnormInt101010 = CL_UNORM_INT_101010,
+        SignedInt8 = CL_SIGNED_INT8,
+        SignedInt16 = CL_SIGNED_INT16,
+        SignedInt32 = CL_SIGNED_INT32,
+        UnsignedInt8 = CL_UNSIGNED_INT8,
+        UnsignedInt16 = CL_UNSIGNED_INT16,
+        UnsignedInt32 = CL_UNSIGNED_INT32,
+        HalfFloat = CL_HALF_FLOAT,
+        Float = CL_FLOAT,
+        UnormInt24 = CL_UNORM_INT24,
+        UnormInt101010_2 = CL_UNORM_INT_101010_2
+    }
+}
+
+flag_enum! {
+    /// OpenCL image type
+    pub enum ImageType(cl_mem_object_type) {
+        Image1D = CL_MEM_OBJECT_IMAGE1D,
+        Image1DArray = CL_MEM_OBJECT_IMAGE1D_ARRAY,
+        Image1DBuffer = CL_MEM_OBJECT_IMAGE1D_BUFFER,
+        Image2D = CL_MEM_OBJECT_IMAGE2D,
+        Image2DArray = CL_MEM_OBJECT_IMAGE2D_ARRAY,
+        Image3D = CL_MEM_OBJECT_IMAGE3D
+    }
+}
+
+impl ChannelOrder {
+    /// Get the number of channels in each pixel, including padding channels
+    pub fn num_channels(self) -> usize {
+        match self {
+            Self::R | Self::A | Self::Intensity | Self::Luminance => 1,
+            Self::Depth | Self::DepthStencil => 1,
+            Self::Rg | Self::Ra | Self::Rx => 2,
+            Self::Rgb | Self::Rgx | Self::Srgb => 3,
+            Self::Rgba | Self::Bgra | Self::Argb | Self::Abgr | Self::Rgbx => 4,
+            Self::Srgbx | Self::Srgba | Self::Sbgra => 4,
+        }
+    }
+}
+
+impl ChannelType {
+    /// Get the size of each channel in bytes, or `None` for packed types whose
+    /// channels aren't individually addressable
+    pub fn channel_size(self) -> Option<usize> {
+        match self {
+            Self::SnormInt8 | Self::UnormInt8 | Self::SignedInt8 | Self::UnsignedInt8 => Some(1),
+            Self::SnormInt16 | Self::UnormInt16 | Self::SignedInt16 | Self::UnsignedInt16 => {
+                Some(2)
+            }
+            Self::HalfFloat => Some(2),
+            Self::SignedInt32 | Self::UnsignedInt32 | Self::Float => Some(4),
+            Self::UnormShort565
+            | Self::UnormShort555
+            | Self::UnormInt101010
+            | Self::UnormInt101010_2
+            | Self::UnormInt24 => None,
+        }
+    }
+}
+
+/// An OpenCL image format, consisting of a channel order and data type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageFormat {
+    pub channel_order: ChannelOrder,
+    pub channel_type: ChannelType,
+}
+
+impl ImageFormat {
+    /// Create a new image format
+    pub const fn new(channel_order: ChannelOrder, channel_type: ChannelType) -> Self {
+        Self {
+            channel_order,
+            channel_type,
+        }
+    }
+
+    /// Convert a raw image format, returning `None` if the channel order or
+    /// data type isn't known (e.g. it's defined by an extension).
+    pub fn from_raw(format: cl_image_format) -> Option<Self> {
+        Some(Self {
+            channel_order: ChannelOrder::from_raw(format.image_channel_order)?,
+            channel_type: ChannelType::from_raw(format.image_channel_data_type)?,
+        })
+    }
+
+    /// Get the size of each pixel in this format, in bytes
+    pub fn pixel_size(self) -> usize {
+        match self.channel_type {
+            ChannelType::UnormShort565 | ChannelType::UnormShort555 => 2,
+            ChannelType::UnormInt101010 | ChannelType::UnormInt101010_2 => 4,
+            ChannelType::UnormInt24 => 4,
+            ty => self.channel_order.num_channels() * ty.channel_size().unwrap(),
+        }
+    }
+
+    /// Get the raw form of this image format
+    pub const fn raw(self) -> cl_image_format {
+        cl_image_format {
+            image_channel_order: self.channel_order.raw(),
+            image_channel_data_type: self.channel_type.raw(),
+        }
+    }
+}
+
+impl Context {
+    /// Check that an image of the given type, format, and size (width, height,
+    /// and depth) would be within the limits of every device in this context,
+    /// returning `Error::LimitExceeded` naming the violated limit otherwise.
+    ///
+    /// Unused dimensions should be 1, and `array_size` is only checked for
+    /// array image types. This allows descriptive errors to be reported before
+    /// attempting to create an image, rather than `CL_INVALID_IMAGE_SIZE`.
+    pub fn validate_image_size(
+        &self,
+        image_type: ImageType,
+        format: ImageFormat,
+        [width, height, depth]: [usize; 3],
+        array_size: usize,
+    ) -> Result<()> {
+        match image_type {
+            ImageType::Image1D | ImageType::Image1DArray => {
+                self.check_device_limit("CL_DEVICE_IMAGE2D_MAX_WIDTH", width, |d| {
+                    d.image2d_max_width()
+                })?;
+            }
+            ImageType::Image1DBuffer => {
+                self.check_device_limit("CL_DEVICE_IMAGE_MAX_BUFFER_SIZE", width, |d| {
+                    d.image_max_buffer_size()
+                })?;
+            }
+            ImageType::Image2D | ImageType::Image2DArray => {
+                self.check_device_limit("CL_DEVICE_IMAGE2D_MAX_WIDTH", width, |d| {
+                    d.image2d_max_width()
+                })?;
+                self.check_device_limit("CL_DEVICE_IMAGE2D_MAX_HEIGHT", height, |d| {
+                    d.image2d_max_height()
+                })?;
+            }
+            ImageType::Image3D => {
+                self.check_device_limit("CL_DEVICE_IMAGE3D_MAX_WIDTH", width, |d| {
+                    d.image3d_max_width()
+                })?;
+                self.check_device_limit("CL_DEVICE_IMAGE3D_MAX_HEIGHT", height, |d| {
+                    d.image3d_max_height()
+                })?;
+                self.check_device_limit("CL_DEVICE_IMAGE3D_MAX_DEPTH", depth, |d| {
+                    d.image3d_max_depth()
+                })?;
+            }
+        }
+
+        let layers = match image_type {
+            ImageType::Image1DArray | ImageType::Image2DArray => {
+                self.check_device_limit("CL_DEVICE_IMAGE_MAX_ARRAY_SIZE", array_size, |d| {
+                    d.image_max_array_size()
+                })?;
+                array_size
+            }
+            _ => 1,
+        };
+
+        let size = [width, height, depth, layers, format.pixel_size()]
+            .iter()
+            .try_fold(1usize, |acc, &n| acc.checked_mul(n))
+            .unwrap_or(usize::MAX);
+
+        self.check_device_limit("CL_DEVICE_MAX_MEM_ALLOC_SIZE", size, |d| {
+            Ok(d.max_mem_alloc_size()? as usize)
+        })
+    }
+
+    /// Get the image formats supported by all devices in this context for
+    /// images of the given type, created with the given flags.
+    ///
+    /// Formats with a channel order or data type defined by an extension are
+    /// omitted.
+    pub fn supported_image_formats(
+        &self,
+        flags: BufferFlagsInfo,
+        image_type: ImageType,
+    ) -> Result<Vec<ImageFormat>> {
+        unsafe {
+            let mut num_formats = 0;
+
+            wrap_result!("clGetSupportedImageFormats" => clGetSupportedImageFormats(
+                self.raw(),
+                flags.raw(),
+                image_type.raw(),
+                0,
+                null_mut(),
+                &mut num_formats as _
+            ))?;
+
+            let mut formats = vec![
+                cl_image_format {
+                    image_channel_order: 0,
+                    image_channel_data_type: 0,
+                };
+                num_formats as usize
+            ];
+
+            wrap_result!("clGetSupportedImageFormats" => clGetSupportedImageFormats(
+                self.raw(),
+                flags.raw(),
+                image_type.raw(),
+                num_formats,
+                formats.as_mut_ptr(),
+                &mut num_formats as _
+            ))?;
+
+            formats.truncate(num_formats as usize);
+
+            Ok(formats
+                .into_iter()
+                .filter_map(ImageFormat::from_raw)
+                .collect())
+        }
+    }
+}
@@ -2,12 +2,21 @@
 
 mod types;
 
-use crate::program::Program;
+use crate::buffer::flags::HostAccess;
+use crate::buffer::{Buffer, MemSafe};
+use crate::device::Device;
+use crate::labels;
+use crate::program::{tracking, Program};
 use crate::raw::*;
+use crate::release;
+use crate::strict;
 use crate::util::sealed::OclInfoInternal;
-use crate::Result;
+use crate::version::{AtLeast, Cl21, Versioned};
+use crate::{Error, Result};
+use libc::size_t;
 use std::ffi::{c_void, CStr};
 use std::fmt::{self, Debug, Formatter};
+use std::mem::size_of;
 use std::pin::Pin;
 pub use types::*;
 
@@ -22,15 +31,41 @@ unsafe impl Send for UnboundKernel {}
 pub struct Kernel<T: KernelArgList> {
     kernel: UnboundKernel,
     args: T::Bound,
+    trailing: Vec<TrailingBuffer>,
+}
+
+/// A buffer bound after the argument list by `bind_arguments_with_buffers`,
+/// retained by the kernel
+#[derive(PartialEq, Eq, Hash)]
+struct TrailingBuffer {
+    handle: cl_mem,
+    /// The length of the buffer in elements
+    len: usize,
+}
+
+impl Drop for TrailingBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = wrap_result!("clReleaseMemObject" => clReleaseMemObject(self.handle)) {
+                release::report("OpenCL mem object", &self.handle, e);
+            }
+        }
+    }
 }
 
 unsafe impl<T: KernelArgList> Send for Kernel<T> {}
 
 impl Drop for UnboundKernel {
     fn drop(&mut self) {
+        tracking::unregister_kernel(self.0);
+
+        if labels::is_used() && matches!(self.reference_count(), Ok(1)) {
+            labels::unregister(self.0);
+        }
+
         unsafe {
             if let Err(e) = wrap_result!("clReleaseKernel" => clReleaseKernel(self.0)) {
-                log::warn!("Error releasing OpenCL kernel: {:?}: {:?}", self, e);
+                release::report("OpenCL kernel", self, e);
             }
         }
     }
@@ -38,12 +73,14 @@ impl Drop for UnboundKernel {
 
 impl Debug for UnboundKernel {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        labels::fmt_prefix(f, self.0)?;
         self.info_fmt(f)
     }
 }
 
 impl<T: KernelArgList> Debug for Kernel<T> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        labels::fmt_prefix(f, self.kernel.0)?;
         self.info_fmt(f)
     }
 }
@@ -110,14 +147,19 @@ impl UnboundKernel {
     /// types appear to be incompatible, as determined by
     /// `KernelArg::is_param_type_compatible`, it will panic. If this behavior
     /// isn't desired, `bind_arguments_unchecked` can be used instead.
+    ///
+    /// With the `unchecked-release-args` feature enabled, these checks are
+    /// only performed in debug builds.
     pub fn bind_arguments<T: KernelArgList>(self, arguments: T) -> Result<Kernel<T>> {
-        let required = self.num_args()? as usize;
-        let supplied = T::NUM_ARGS;
-        assert_eq!(
-            required, supplied,
-            "kernel arity mismatch - kernel requires {} arguments, but {} were specified",
-            required, supplied
-        );
+        if types::ARG_CHECKS {
+            let required = self.num_args()? as usize;
+            let supplied = T::NUM_ARGS;
+            assert_eq!(
+                required, supplied,
+                "kernel arity mismatch - kernel requires {} arguments, but {} were specified",
+                required, supplied
+            );
+        }
         arguments.bind(self, true)
     }
 
@@ -133,12 +175,104 @@ impl UnboundKernel {
         arguments.bind(self, false)
     }
 
+    /// Bind arguments to this kernel, followed by a variable number of buffers
+    /// of the same type, which are bound to the arguments immediately after
+    /// those in the argument list. This is useful for e.g. generated kernels
+    /// taking a varying number of input buffers.
+    ///
+    /// Type checks are performed for all arguments as with `bind_arguments`,
+    /// and the kernel must take exactly as many arguments as are given in
+    /// total. The buffers are retained by the kernel, so they can't be
+    /// released while it refers to them, but can't be replaced or retrieved
+    /// through `arguments`.
+    pub fn bind_arguments_with_buffers<T: KernelArgList, H: HostAccess, B: MemSafe>(
+        self,
+        arguments: T,
+        buffers: &[Buffer<'static, H, B>],
+    ) -> Result<Kernel<T>> {
+        if types::ARG_CHECKS {
+            let required = self.num_args()? as usize;
+            let supplied = T::NUM_ARGS + buffers.len();
+            assert_eq!(
+                required, supplied,
+                "kernel arity mismatch - kernel requires {} arguments, but {} were specified",
+                required, supplied
+            );
+        }
+
+        let mut kernel = arguments.bind(self, true)?;
+
+        for (i, buffer) in buffers.iter().enumerate() {
+            let index = (T::NUM_ARGS + i) as cl_uint;
+            types::check_arg_type::<Buffer<H, B>>(&kernel.kernel, index);
+
+            unsafe {
+                wrap_result!("clRetainMemObject" => clRetainMemObject(buffer.raw()))?;
+                kernel.trailing.push(TrailingBuffer {
+                    handle: buffer.raw(),
+                    len: buffer.rust_size() / size_of::<B>(),
+                });
+                buffer.set_raw_kernel_arg(kernel.kernel.0, index)?;
+            }
+        }
+
+        Ok(kernel)
+    }
+
+    /// Attempt to clone this kernel, using `clCloneKernel` to create a new
+    /// kernel object. Requires OpenCL 2.1+.
+    ///
+    /// Unlike the `try_clone` methods of other types, this doesn't retain the
+    /// existing kernel, since kernel arguments are stored in the kernel object
+    /// and two wrappers sharing a handle could overwrite each other's bound
+    /// arguments. The clone has a copy of any arguments set at the time of
+    /// cloning, but is otherwise independent.
+    pub fn try_clone(&self) -> Result<Self> {
+        unsafe {
+            check_ocl_version!("clCloneKernel" => CL21)?;
+            self.clone_unchecked()
+        }
+    }
+
+    unsafe fn clone_unchecked(&self) -> Result<Self> {
+        let mut err = CL_SUCCESS;
+        let kernel = clCloneKernel(self.0, &mut err as _);
+        wrap_result!("clCloneKernel" => err)?;
+        tracking::register_clone(self.0, kernel);
+        labels::register_new(kernel);
+        Ok(Self(kernel))
+    }
+
     pub fn raw(&self) -> cl_kernel {
         self.0
     }
+
+    /// Get the maximum work group size which can be used to execute this
+    /// kernel on the given device, based on its resource usage.
+    pub fn work_group_size(&self, Device(device): Device) -> Result<size_t> {
+        let mut size: size_t = 0;
+
+        unsafe {
+            wrap_result!("clGetKernelWorkGroupInfo" => clGetKernelWorkGroupInfo(
+                self.0,
+                device,
+                CL_KERNEL_WORK_GROUP_SIZE,
+                size_of::<size_t>(),
+                &mut size as *mut _ as _,
+                std::ptr::null_mut()
+            ))?;
+        }
+
+        Ok(size)
+    }
 }
 
 impl<T: KernelArgList> Kernel<T> {
+    /// Get the total number of arguments bound to this kernel
+    pub(crate) fn num_bound_args(&self) -> usize {
+        T::NUM_ARGS + self.trailing.len()
+    }
+
     /// Get mutable references to the arguments of this kernel.
     ///
     /// For safety reasons, the bound arguments are pinned, and must not be
@@ -153,6 +287,83 @@ impl<T: KernelArgList> Kernel<T> {
             BindProjectInternal::project(bound)
         }
     }
+
+    /// Run the given closure with mutable accessors for the arguments of this
+    /// kernel, as a tuple of `ArgMut` (or a single `ArgMut` for kernels with
+    /// one argument).
+    ///
+    /// This is an alternative to `arguments` which doesn't expose `Pin`, and
+    /// is easier to use from generic code. Since the accessors mutably borrow
+    /// the kernel, it can't be launched while arguments are being updated.
+    pub fn with_arguments<'a, R>(
+        &'a mut self,
+        f: impl FnOnce(<T::Bound as BindProject<'a>>::Accessors) -> R,
+    ) -> R
+    where
+        T::Bound: BindProject<'a>,
+    {
+        unsafe {
+            use sealed::BindProjectInternal;
+            let bound: Pin<&'a mut T::Bound> = Pin::new_unchecked(&mut self.args);
+            f(BindProjectInternal::accessors(bound))
+        }
+    }
+
+    /// Set the value of a single scalar argument by index, performing a type
+    /// check as with `UnboundKernel::bind_arguments`.
+    ///
+    /// This is a cheaper alternative to projecting the bound arguments when
+    /// only a few scalars need to change between launches. The argument at
+    /// this index must have been bound as a value of type `A`, and the bound
+    /// value is updated, so `Bound::get` returns the new value. Returns
+    /// `Error::InvalidArgIndex` if the index is out of range or refers to an
+    /// argument of another type.
+    pub fn set_arg<A: MemSafe>(&mut self, index: cl_uint, value: A) -> Result<()> {
+        strict::check(self, CL_KERNEL_REFERENCE_COUNT, "kernel");
+
+        if index >= self.num_args()? {
+            return Err(Error::InvalidArgIndex {
+                index,
+                reason: "index out of range",
+            });
+        }
+
+        types::check_arg_type::<A>(&self.kernel, index);
+
+        let kernel = self.kernel.0;
+
+        match T::value_mut(self, index as usize).and_then(|v| v.downcast_mut::<A>()) {
+            Some(bound) => {
+                unsafe { value.set_raw_kernel_arg(kernel, index)? };
+                *bound = value;
+                Ok(())
+            }
+            None => Err(Error::InvalidArgIndex {
+                index,
+                reason: "argument is not bound to a value of the given type",
+            }),
+        }
+    }
+
+    /// Set the value of a single scalar argument by name. See `set_arg` for
+    /// details.
+    ///
+    /// Argument names are only available with OpenCL 1.2+, and only if the
+    /// program was built with the `-cl-kernel-arg-info` option.
+    pub fn set_arg_named<A: MemSafe>(&mut self, name: &str, value: A) -> Result<()> {
+        let index = self
+            .arg_index(name)?
+            .ok_or_else(|| Error::UnknownKernelArg(name.to_string()))?;
+        self.set_arg(index, value)
+    }
+}
+
+impl<V: AtLeast<Cl21>> Versioned<'_, UnboundKernel, V> {
+    /// Clone this kernel using `clCloneKernel`, as with
+    /// `UnboundKernel::try_clone`, without checking the OpenCL version.
+    pub fn try_clone(&self) -> Result<UnboundKernel> {
+        unsafe { self.handle().clone_unchecked() }
+    }
 }
 
 impl Program {
@@ -162,6 +373,8 @@ impl Program {
             let mut err = CL_SUCCESS;
             let kernel = clCreateKernel(self.raw(), name.as_ptr(), &mut err as _);
             wrap_result!("clCreateKernel" => err)?;
+            tracking::register_kernel(self.raw(), kernel, name);
+            labels::register_new(kernel);
             Ok(UnboundKernel(kernel))
         }
     }
@@ -4,8 +4,9 @@ use crate::buffer::sealed::AsBufferInternal;
 use crate::buffer::{AsBuffer, Buffer, MemSafe};
 use crate::raw::*;
 use crate::util::sealed::OclInfoInternal;
-use crate::Result;
+use crate::{Error, Result};
 use libc::size_t;
+use std::any::Any;
 use std::ffi::c_void;
 use std::ffi::CString;
 use std::fmt::{self, Debug, Formatter};
@@ -24,12 +25,42 @@ pub(crate) mod sealed {
         fn bind(self, kernel: UnboundKernel, type_checks: bool) -> Result<Kernel<Self>>
         where
             Self: Sized + KernelArgList;
+
+        fn buffer_lens(kernel: &Kernel<Self>) -> Vec<Option<usize>>
+        where
+            Self: Sized + KernelArgList;
+
+        fn buffer_mems(kernel: &Kernel<Self>) -> Vec<crate::raw::cl_mem>
+        where
+            Self: Sized + KernelArgList;
+
+        fn raw_args(kernel: &Kernel<Self>) -> Vec<RawArg>
+        where
+            Self: Sized + KernelArgList;
+
+        fn value_mut(kernel: &mut Kernel<Self>, index: usize) -> Option<&mut dyn std::any::Any>
+        where
+            Self: Sized + KernelArgList;
+    }
+
+    /// The raw value of a bound kernel argument
+    pub enum RawArg {
+        /// A plain value, as its raw bytes
+        Value(Vec<u8>),
+        /// A memory object
+        Mem(crate::raw::cl_mem),
+        /// A local memory allocation of the given size in bytes
+        Local(usize),
     }
 
     pub trait BindProjectInternal<'a> {
         fn project(self: Pin<&'a mut Self>) -> Self::Projected
         where
             Self: BindProject<'a>;
+
+        fn accessors(self: Pin<&'a mut Self>) -> Self::Accessors
+        where
+            Self: BindProject<'a>;
     }
 }
 
@@ -62,6 +93,29 @@ pub trait KernelInfo: OclInfoInternal<Param = cl_kernel_info> + Sized {
             idx,
         }
     }
+
+    /// Find the index of the argument with the given name, or `None` if this
+    /// kernel has no such argument. Requires OpenCL 1.2+, and the program must
+    /// have been built with `-cl-kernel-arg-info`.
+    fn arg_index(&self, name: &str) -> Result<Option<cl_uint>> {
+        let version = unsafe { SYSTEM_OPENCL_VERSION };
+
+        if version < OpenCLVersion::CL12 {
+            return Err(Error::UnsupportedVersion {
+                expected: OpenCLVersion::CL12,
+                actual: version,
+                context: "clGetKernelArgInfo",
+            });
+        }
+
+        for idx in 0..self.num_args()? {
+            if self.arg_info(idx).arg_name()?.to_bytes() == name.as_bytes() {
+                return Ok(Some(idx));
+            }
+        }
+
+        Ok(None)
+    }
 }
 
 /// A type providing access to information about a single kernel argument.
@@ -149,9 +203,64 @@ pub trait KernelArg {
     /// compatible with this kernel argument type.
     fn is_param_type_compatible(c_type: &str) -> bool;
 
+    /// Check whether a parameter in the given address space is compatible
+    /// with this kernel argument type. This is only checked if the
+    /// implementation reports address qualifiers, and accepts any address
+    /// space by default.
+    fn is_address_qualifier_compatible(_qualifier: ArgAddressQualifier) -> bool {
+        true
+    }
+
     /// Get the data of this kernel argument, as a size and value to be passed
     /// to `clSetKernelArg`
     fn as_raw_kernel_arg(&self) -> (size_t, &Self::ArgType);
+
+    /// Get the number of elements in this argument, if it's a buffer. This is
+    /// used by the validation layer to check buffer sizes against work sizes.
+    fn buffer_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// Get the memory object of this argument, if it's a buffer. This is used
+    /// to infer dependencies between commands using the same buffer.
+    fn buffer_mem(&self) -> Option<cl_mem> {
+        None
+    }
+
+    /// Get the size in bytes of this argument, if it's a local memory
+    /// allocation, in which case `as_raw_kernel_arg` returns a size of zero
+    /// and doesn't point to valid data.
+    fn local_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Get a mutable reference to this argument as a plain value, if it is
+    /// one. This is used by `Kernel::set_arg` to update bound values.
+    #[doc(hidden)]
+    fn as_value_mut(&mut self) -> Option<&mut dyn Any> {
+        None
+    }
+
+    /// Set this value as the argument at the given index of a kernel. By
+    /// default, this calls `clSetKernelArg` with the data returned by
+    /// `as_raw_kernel_arg`, but it may be overridden for arguments that must be
+    /// set through other means (e.g. extension functions).
+    ///
+    /// # Safety
+    ///
+    /// The given kernel handle must be valid.
+    unsafe fn set_raw_kernel_arg(&self, kernel: cl_kernel, index: cl_uint) -> Result<()> {
+        let (size, ptr) = self.as_raw_kernel_arg();
+
+        wrap_result!("clSetKernelArg" => clSetKernelArg(
+            kernel,
+            index,
+            size,
+            ptr as *const _ as _
+        ))?;
+
+        Ok(())
+    }
 }
 
 // values can be used as individual kernel args
@@ -165,6 +274,10 @@ impl<T: MemSafe> KernelArg for T {
     fn as_raw_kernel_arg(&self) -> (size_t, &T) {
         (size_of_val(self), &self)
     }
+
+    fn as_value_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
 }
 
 // buffers can be used as individual kernel args
@@ -182,10 +295,156 @@ impl<H: HostAccess, T: MemSafe> KernelArg for Buffer<'_, H, T> {
     fn as_raw_kernel_arg(&self) -> (size_t, &cl_mem) {
         (size_of::<cl_mem>(), &self.handle)
     }
+
+    fn buffer_len(&self) -> Option<usize> {
+        Some(self.rust_size() / size_of::<T>())
+    }
+
+    fn buffer_mem(&self) -> Option<cl_mem> {
+        Some(self.handle)
+    }
+}
+
+/// A raw memory object handle, which can be used as a kernel argument. This
+/// allows memory objects created outside of the safe API (e.g. through interop
+/// or vendor extensions) to be bound with `bind_arguments`.
+///
+/// The handle isn't retained or released, and its contents aren't checked, so
+/// it's only compatible with pointer, image, and pipe parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RawMemArg(cl_mem);
+
+impl RawMemArg {
+    /// Wrap a raw memory object handle as a kernel argument
+    ///
+    /// # Safety
+    ///
+    /// The handle must be a valid memory object in the context of any kernel
+    /// it's bound to, and must remain valid for as long as it's bound. Its
+    /// contents must be valid for the kernel parameter it's bound to.
+    pub unsafe fn new(mem: cl_mem) -> Self {
+        Self(mem)
+    }
+
+    /// Get the raw memory object handle
+    pub fn raw(&self) -> cl_mem {
+        self.0
+    }
+}
+
+impl KernelArg for RawMemArg {
+    type ArgType = cl_mem;
+
+    fn is_param_type_compatible(c_type: &str) -> bool {
+        c_type.ends_with('*') || c_type.starts_with("image") || c_type.starts_with("pipe")
+    }
+
+    fn as_raw_kernel_arg(&self) -> (size_t, &cl_mem) {
+        (size_of::<cl_mem>(), &self.0)
+    }
+
+    fn buffer_mem(&self) -> Option<cl_mem> {
+        Some(self.0)
+    }
+}
+
+/// A `__local` memory allocation of the given size in bytes, which can be used
+/// as a kernel argument for `__local` pointer parameters. Local memory has no
+/// contents visible to the host, so only the size is passed to the kernel.
+///
+/// The right size usually depends on the local work size, so it can also be
+/// computed for each launch with `KernelCmd::scale_local_alloc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LocalSize(pub usize);
+
+impl KernelArg for LocalSize {
+    type ArgType = ();
+
+    fn is_param_type_compatible(c_type: &str) -> bool {
+        c_type.ends_with('*')
+    }
+
+    fn is_address_qualifier_compatible(qualifier: ArgAddressQualifier) -> bool {
+        qualifier == ArgAddressQualifier::Local
+    }
+
+    fn as_raw_kernel_arg(&self) -> (size_t, &()) {
+        (0, &())
+    }
+
+    fn local_size(&self) -> Option<usize> {
+        Some(self.0)
+    }
+
+    unsafe fn set_raw_kernel_arg(&self, kernel: cl_kernel, index: cl_uint) -> Result<()> {
+        wrap_result!("clSetKernelArg" => clSetKernelArg(kernel, index, self.0, std::ptr::null()))?;
+        Ok(())
+    }
+}
+
+/// Whether kernel argument arity and type checks are performed. These are
+/// compiled out of release builds when the `unchecked-release-args` feature is
+/// enabled.
+pub(crate) const ARG_CHECKS: bool = !cfg!(all(
+    feature = "unchecked-release-args",
+    not(debug_assertions)
+));
+
+/// Check that the given kernel argument type is compatible with the argument
+/// at the given index, panicking if it isn't. Does nothing if OpenCL 1.2+
+/// features aren't available, or if argument checks are disabled.
+pub(crate) fn check_arg_type<K: KernelArg>(kernel: &UnboundKernel, index: cl_uint) {
+    if !ARG_CHECKS || unsafe { SYSTEM_OPENCL_VERSION } < OpenCLVersion::CL12 {
+        return;
+    }
+
+    let arg_info = kernel.arg_info(index);
+
+    match arg_info.type_name() {
+        Ok(s) if K::is_param_type_compatible(&s.to_string_lossy()) => {}
+        Ok(s) => {
+            panic!(
+                "Kernel argument type mismatch - OpenCL type {:?} is not compatible with {} for argument #{} ({:?}) of kernel {:?}",
+                s,
+                type_name::<K>(),
+                index,
+                arg_info,
+                kernel
+            );
+        }
+        Err(e) => {
+            log::warn!(
+                "Could not check type of argument #{} ({:?}) of kernel {:?}: {:?}",
+                index,
+                arg_info,
+                kernel,
+                e
+            );
+            return;
+        }
+    }
+
+    match arg_info.address_qualifier() {
+        Ok(q) if !K::is_address_qualifier_compatible(q) => {
+            panic!(
+                "Kernel argument address space mismatch - {:?} parameter is not compatible with {} for argument #{} ({:?}) of kernel {:?}",
+                q,
+                type_name::<K>(),
+                index,
+                arg_info,
+                kernel
+            );
+        }
+        _ => {}
+    }
 }
 
 /// A "bound" kernel argument which already has a value set, but can be updated.
 ///
+/// The kernel owns the values of its bound arguments, so that e.g. a buffer
+/// can't be dropped while it's still set as an argument of the kernel. Values
+/// which borrow host memory encode that lifetime in the kernel's type.
+///
 /// # Safety
 ///
 /// This type should only be used behind an immutable reference, as returned by
@@ -207,41 +466,11 @@ impl<K: KernelArg> Bound<K> {
         type_check: bool,
     ) -> Result<Self> {
         unsafe {
-            let (size, ptr) = value.as_raw_kernel_arg();
-
-            if type_check && SYSTEM_OPENCL_VERSION >= OpenCLVersion::CL12 {
-                let arg_info = kernel.arg_info(index);
-
-                match arg_info.type_name() {
-                    Ok(s) if K::is_param_type_compatible(&s.to_string_lossy()) => {}
-                    Ok(s) => {
-                        panic!(
-                            "Kernel argument type mismatch - OpenCL type {:?} is not compatible with {} for argument #{} ({:?}) of kernel {:?}",
-                            s,
-                            type_name::<K>(),
-                            index,
-                            arg_info,
-                            kernel
-                        );
-                    }
-                    Err(e) => {
-                        log::warn!(
-                            "Could not check type of argument #{} ({:?}) of kernel {:?}: {:?}",
-                            index,
-                            arg_info,
-                            kernel,
-                            e
-                        );
-                    }
-                }
+            if type_check {
+                check_arg_type::<K>(kernel, index);
             }
 
-            wrap_result!("clSetKernelArg" => clSetKernelArg(
-                kernel.0,
-                index,
-                size,
-                ptr as *const _ as _
-            ))?;
+            value.set_raw_kernel_arg(kernel.0, index)?;
 
             Ok(Self {
                 _pinned: PhantomPinned,
@@ -261,14 +490,7 @@ impl<K: KernelArg> Bound<K> {
     /// the original value if successful.
     pub fn replace(self: Pin<&mut Self>, value: K) -> Result<K> {
         unsafe {
-            let (size, ptr) = value.as_raw_kernel_arg();
-
-            wrap_result!("clSetKernelArg" => clSetKernelArg(
-                self.kernel,
-                self.index,
-                size,
-                ptr as *const _ as _
-            ))?;
+            value.set_raw_kernel_arg(self.kernel, self.index)?;
 
             Ok(std::mem::replace(
                 &mut self.get_unchecked_mut().value,
@@ -277,20 +499,53 @@ impl<K: KernelArg> Bound<K> {
         }
     }
 
-    /// Set this argument to a new value.
+    /// Set this argument to a new value, dropping the original value.
+    ///
+    /// The new value is kept alive by the kernel for as long as it's bound, so
+    /// buffers can't be released while the kernel still refers to them.
     pub fn set(self: Pin<&mut Self>, value: K) -> Result<()> {
-        unsafe {
-            let (size, ptr) = value.as_raw_kernel_arg();
+        self.replace(value).map(drop)
+    }
+}
 
-            wrap_result!("clSetKernelArg" => clSetKernelArg(
-                self.kernel,
-                self.index,
-                size,
-                ptr as *const _ as _
-            ))?;
+/// A mutable accessor for a single bound kernel argument, as passed to the
+/// closure of `Kernel::with_arguments`.
+///
+/// This offers the same operations as a pinned `Bound` argument, without
+/// exposing `Pin` - the argument stays pinned internally. The accessor
+/// mutably borrows the kernel, so the kernel can't be launched while it's
+/// alive, even if it's returned from the closure.
+pub struct ArgMut<'a, A: KernelArg>(Pin<&'a mut Bound<A>>);
+
+impl<A: KernelArg> ArgMut<'_, A> {
+    /// Get the index of this argument
+    pub fn index(&self) -> cl_uint {
+        self.0.index
+    }
 
-            Ok(())
-        }
+    /// Get a reference to the current value of this argument
+    pub fn get(&self) -> &A {
+        self.0.get()
+    }
+
+    /// Replace the current value of this argument with a new value, returning
+    /// the original value if successful.
+    pub fn replace(&mut self, value: A) -> Result<A> {
+        self.0.as_mut().replace(value)
+    }
+
+    /// Set this argument to a new value, dropping the original value.
+    pub fn set(&mut self, value: A) -> Result<()> {
+        self.0.as_mut().set(value)
+    }
+}
+
+impl<A: KernelArg + Debug> Debug for ArgMut<'_, A> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("ArgMut")
+            .field("index", &self.index())
+            .field("value", self.get())
+            .finish()
     }
 }
 
@@ -299,6 +554,10 @@ impl<K: KernelArg> Bound<K> {
 /// destructuring and individual use.
 pub trait BindProject<'a>: sealed::BindProjectInternal<'a> {
     type Projected: 'a;
+
+    /// The argument list as `ArgMut` accessors, as passed to the closure of
+    /// `Kernel::with_arguments`
+    type Accessors: 'a;
 }
 
 /// A trait implemented by types that can be used as a complete set of kernel
@@ -322,23 +581,41 @@ kernel_arg_list_tuples! {
     (A, B, C, D, E, F),
     (A, B, C, D, E, F, G),
     (A, B, C, D, E, F, G, H),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y),
+    #[cfg(feature = "kernel-tuples")]
     (A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z),
 }
 
@@ -351,3 +628,11 @@ impl<'a, H: HostAccess, T: MemSafe> AsBufferInternal<'a, H, T>
 }
 
 impl<'a, H: HostAccess, T: MemSafe> AsBuffer<'a, H, T> for Pin<&mut Bound<Buffer<'a, H, T>>> {}
+
+impl<'a, H: HostAccess, T: MemSafe> AsBufferInternal<'a, H, T> for ArgMut<'_, Buffer<'a, H, T>> {
+    fn as_buffer(&mut self) -> &Buffer<'a, H, T> {
+        self.get()
+    }
+}
+
+impl<'a, H: HostAccess, T: MemSafe> AsBuffer<'a, H, T> for ArgMut<'_, Buffer<'a, H, T>> {}
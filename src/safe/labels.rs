@@ -0,0 +1,155 @@
+//! Object labels
+//!
+//! Buffers, programs, kernels, and command queues can be given a label with
+//! `set_label`, to tell them apart in diagnostics - labels are included in
+//! their `Debug` output (and so in errors reported when releasing them), and
+//! in command journals. OpenCL has no standard mechanism for naming objects,
+//! so labels are only stored on the host.
+//!
+//! Labels are shared by all wrappers for the same object. Buffer labels are
+//! forgotten once the buffer is destroyed, and other labels once the last
+//! wrapper is dropped - or, for programs and kernels which may be kept alive
+//! by implicit references, once a new object reuses the same handle.
+
+use crate::buffer::flags::HostAccess;
+use crate::buffer::{Buffer, MemSafe};
+use crate::kernel::{Kernel, KernelArgList, KernelInfo, UnboundKernel};
+use crate::program::Program;
+use crate::queue::Queue;
+use crate::raw::{clSetMemObjectDestructorCallback, cl_mem, OpenCLVersion, SYSTEM_OPENCL_VERSION};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::fmt::{self, Formatter};
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+lazy_static::lazy_static! {
+    /// Labels of OpenCL objects, keyed by raw handle
+    static ref LABELS: Mutex<HashMap<usize, String>> = Default::default();
+}
+
+/// Whether any label has been set, to avoid locking the map (and querying
+/// reference counts on release) when labels aren't used
+static USED: AtomicBool = AtomicBool::new(false);
+
+fn set<P>(handle: *mut P, label: &str) {
+    USED.store(true, Ordering::Relaxed);
+    LABELS
+        .lock()
+        .unwrap()
+        .insert(handle as usize, label.to_string());
+}
+
+/// Get the label of the object with the given raw handle, if any
+pub(crate) fn get<P>(handle: *mut P) -> Option<String> {
+    if !is_used() {
+        return None;
+    }
+
+    LABELS.lock().unwrap().get(&(handle as usize)).cloned()
+}
+
+/// Check whether any label has been set
+pub(crate) fn is_used() -> bool {
+    USED.load(Ordering::Relaxed)
+}
+
+/// Forget the label of the object with the given raw handle, once the last
+/// wrapper for it has been dropped
+pub(crate) fn unregister<P>(handle: *mut P) {
+    if is_used() {
+        LABELS.lock().unwrap().remove(&(handle as usize));
+    }
+}
+
+/// Forget any label left behind for the given raw handle of a newly created
+/// object. Labels of programs and kernels may outlive them, since their
+/// reference counts include implicit references (e.g. from kernels to their
+/// program), so a new object may reuse the address of a labelled one.
+pub(crate) fn register_new<P>(handle: *mut P) {
+    unregister(handle);
+}
+
+extern "C" fn buffer_destroyed(mem: cl_mem, _user_data: *mut c_void) {
+    unregister(mem);
+}
+
+/// Write the label of the object with the given raw handle, if any, as a
+/// prefix for its `Debug` output
+pub(crate) fn fmt_prefix<P>(f: &mut Formatter, handle: *mut P) -> fmt::Result {
+    match get(handle) {
+        Some(label) => write!(f, "{:?} ", label),
+        None => Ok(()),
+    }
+}
+
+impl<H: HostAccess, T: MemSafe> Buffer<'_, H, T> {
+    /// Set a label for this buffer, to identify it in diagnostics
+    pub fn set_label(&self, label: &str) {
+        let labelled = get(self.raw()).is_some();
+        set(self.raw(), label);
+
+        // forget the label once the memory object is actually destroyed,
+        // which may be after the last wrapper is dropped
+        if !labelled && unsafe { SYSTEM_OPENCL_VERSION } >= OpenCLVersion::CL11 {
+            unsafe {
+                clSetMemObjectDestructorCallback(self.raw(), buffer_destroyed, null_mut());
+            }
+        }
+    }
+
+    /// Get the label of this buffer, if set
+    pub fn label(&self) -> Option<String> {
+        get(self.raw())
+    }
+}
+
+impl Program {
+    /// Set a label for this program, to identify it in diagnostics
+    pub fn set_label(&self, label: &str) {
+        set(self.raw(), label);
+    }
+
+    /// Get the label of this program, if set
+    pub fn label(&self) -> Option<String> {
+        get(self.raw())
+    }
+}
+
+impl UnboundKernel {
+    /// Set a label for this kernel, to identify it in diagnostics. The label
+    /// is kept when arguments are bound.
+    pub fn set_label(&self, label: &str) {
+        set(self.raw(), label);
+    }
+
+    /// Get the label of this kernel, if set
+    pub fn label(&self) -> Option<String> {
+        get(self.raw())
+    }
+}
+
+impl<T: KernelArgList> Kernel<T> {
+    /// Set a label for this kernel, to identify it in diagnostics
+    pub fn set_label(&self, label: &str) {
+        self.as_unbound().set_label(label);
+    }
+
+    /// Get the label of this kernel, if set
+    pub fn label(&self) -> Option<String> {
+        self.as_unbound().label()
+    }
+}
+
+impl Queue {
+    /// Set a label for this command queue, to identify it in diagnostics
+    pub fn set_label(&self, label: &str) {
+        set(self.raw(), label);
+    }
+
+    /// Get the label of this command queue, if set
+    pub fn label(&self) -> Option<String> {
+        get(self.raw())
+    }
+}
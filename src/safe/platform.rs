@@ -6,11 +6,12 @@
 
 use crate::device::{Device, DeviceType};
 use crate::raw::{
-    clGetDeviceIDs, clGetPlatformIDs, clGetPlatformInfo, clUnloadPlatformCompiler, cl_platform_id,
-    cl_platform_info, cl_ulong,
+    api, clGetDeviceIDs, clGetPlatformIDs, clGetPlatformInfo, cl_platform_id, cl_platform_info,
+    cl_ulong, OpenCLVersion, SYSTEM_OPENCL_VERSION,
 };
 use crate::util::sealed::OclInfoInternal;
-use crate::Result;
+use crate::version::{find_version, NameVersion, NumericVersion};
+use crate::{Error, Result};
 use std::ffi::c_void;
 use std::ffi::CString;
 use std::fmt::{self, Debug, Formatter};
@@ -132,13 +133,48 @@ impl Platform {
         pub fn vendor(&self) -> CString = CL_PLATFORM_VENDOR;
         pub fn extensions(&self) -> CString = CL_PLATFORM_EXTENSIONS;
         pub fn host_timer_resolution(&self) -> cl_ulong = CL_PLATFORM_HOST_TIMER_RESOLUTION;
+        pub fn numeric_version(&self) -> NumericVersion = CL_PLATFORM_NUMERIC_VERSION;
+        pub fn extensions_with_version(&self) -> Vec<NameVersion> = CL_PLATFORM_EXTENSIONS_WITH_VERSION;
+    }
+
+    /// Check whether the given extension is supported by this platform.
+    pub fn has_extension(self, name: &str) -> Result<bool> {
+        Ok(self
+            .extensions()?
+            .to_string_lossy()
+            .split_whitespace()
+            .any(|e| e == name))
+    }
+
+    /// Get the version of the given extension supported by this platform, or
+    /// `None` if it isn't supported. Requires OpenCL 3.0+.
+    pub fn extension_version(self, name: &str) -> Result<Option<NumericVersion>> {
+        Ok(find_version(&self.extensions_with_version()?, name))
     }
 
     /// Unload the OpenCL C program compiler for this platform.
+    ///
+    /// This uses `clUnloadPlatformCompiler` if available, falling back to the
+    /// legacy `clUnloadCompiler` (which unloads the compiler for all
+    /// platforms) on older systems. If neither function is available,
+    /// `Error::UnsupportedVersion` is returned.
     pub fn unload_compiler(self) -> Result<()> {
+        let api = api();
+
         unsafe {
-            wrap_result!("clUnloadPlatformCompiler" => clUnloadPlatformCompiler(self.raw()))?;
-            Ok(())
+            if let Some(unload) = api.clUnloadPlatformCompiler {
+                wrap_result!("clUnloadPlatformCompiler" => unload(self.raw()))?;
+            } else if let Some(unload) = api.clUnloadCompiler {
+                wrap_result!("clUnloadCompiler" => unload())?;
+            } else {
+                return Err(Error::UnsupportedVersion {
+                    expected: OpenCLVersion::CL12,
+                    actual: SYSTEM_OPENCL_VERSION,
+                    context: "clUnloadPlatformCompiler",
+                });
+            }
         }
+
+        Ok(())
     }
 }
@@ -0,0 +1,250 @@
+use super::Scalar;
+use crate::buffer::flags::HostAccess;
+use crate::buffer::Buffer;
+use crate::context::Context;
+use crate::deterministic;
+use crate::event::Event;
+use crate::program::{Program, ProgramBuilder};
+use crate::queue::Queue;
+use crate::raw::*;
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::mem::size_of;
+use std::ptr::{null, null_mut};
+use std::sync::Mutex;
+
+lazy_static::lazy_static! {
+    /// Compiled element-wise programs, keyed by raw context handle and
+    /// generated source
+    static ref PROGRAMS: Mutex<HashMap<(usize, String), Program>> = Default::default();
+}
+
+/// Release all cached element-wise programs, along with the contexts they
+/// retain.
+pub(super) fn clear_map_cache() {
+    PROGRAMS.lock().unwrap().clear();
+}
+
+mod sealed {
+    pub trait MapArgInternal {}
+}
+
+/// A value which can be bound to a name in an element-wise kernel
+///
+/// Scalars are passed by value, shared buffer references are bound as
+/// `__global const` pointers, and mutable buffer references are bound as
+/// `__global` pointers.
+pub trait MapArg: sealed::MapArgInternal {
+    /// Get the OpenCL C parameter declaration for this argument
+    fn declaration(&self, name: &str) -> String;
+
+    /// Get the number of elements in this argument, if it's a buffer
+    fn buffer_len(&self) -> Option<usize>;
+
+    /// Set this argument on the given raw kernel
+    ///
+    /// # Safety
+    ///
+    /// The kernel must be a valid kernel whose parameter at the given index
+    /// was declared using `declaration`.
+    unsafe fn set(&self, kernel: cl_kernel, index: cl_uint) -> Result<()>;
+}
+
+impl<T: Scalar> sealed::MapArgInternal for T {}
+
+impl<T: Scalar> MapArg for T {
+    fn declaration(&self, name: &str) -> String {
+        format!("const {} {}", T::C_TYPE, name)
+    }
+
+    fn buffer_len(&self) -> Option<usize> {
+        None
+    }
+
+    unsafe fn set(&self, kernel: cl_kernel, index: cl_uint) -> Result<()> {
+        wrap_result!("clSetKernelArg" => clSetKernelArg(kernel, index, size_of::<T>(), self as *const T as _))?;
+        Ok(())
+    }
+}
+
+impl<H: HostAccess, T: Scalar> sealed::MapArgInternal for &Buffer<'_, H, T> {}
+
+impl<H: HostAccess, T: Scalar> MapArg for &Buffer<'_, H, T> {
+    fn declaration(&self, name: &str) -> String {
+        format!("__global const {} *{}", T::C_TYPE, name)
+    }
+
+    fn buffer_len(&self) -> Option<usize> {
+        Some(self.rust_size() / size_of::<T>())
+    }
+
+    unsafe fn set(&self, kernel: cl_kernel, index: cl_uint) -> Result<()> {
+        wrap_result!("clSetKernelArg" => clSetKernelArg(kernel, index, size_of::<cl_mem>(), &self.handle as *const cl_mem as _))?;
+        Ok(())
+    }
+}
+
+impl<H: HostAccess, T: Scalar> sealed::MapArgInternal for &mut Buffer<'_, H, T> {}
+
+impl<H: HostAccess, T: Scalar> MapArg for &mut Buffer<'_, H, T> {
+    fn declaration(&self, name: &str) -> String {
+        format!("__global {} *{}", T::C_TYPE, name)
+    }
+
+    fn buffer_len(&self) -> Option<usize> {
+        Some(self.rust_size() / size_of::<T>())
+    }
+
+    unsafe fn set(&self, kernel: cl_kernel, index: cl_uint) -> Result<()> {
+        wrap_result!("clSetKernelArg" => clSetKernelArg(kernel, index, size_of::<cl_mem>(), &self.handle as *const cl_mem as _))?;
+        Ok(())
+    }
+}
+
+/// An element-wise kernel, generated from a snippet of OpenCL C executed once
+/// for each index `i`, as created by `ocl_map!`.
+///
+/// The generated kernel is compiled the first time it's executed in each
+/// context, and cached until `clear_cache` is called.
+#[must_use]
+pub struct MapKernel<'a> {
+    body: &'a str,
+    args: Vec<(&'a str, Box<dyn MapArg + 'a>)>,
+    global_work_size: Option<usize>,
+}
+
+impl<'a> MapKernel<'a> {
+    /// Begin building an element-wise kernel with the given body
+    pub fn new(body: &'a str) -> Self {
+        Self {
+            body,
+            args: vec![],
+            global_work_size: None,
+        }
+    }
+
+    /// Bind a scalar or buffer to the given name
+    pub fn arg(mut self, name: &'a str, value: impl MapArg + 'a) -> Self {
+        self.args.push((name, Box::new(value)));
+        self
+    }
+
+    /// Set the number of indices to execute the snippet for. By default, this
+    /// is the length of the shortest bound buffer.
+    pub fn global_work_size(self, global_work_size: usize) -> Self {
+        Self {
+            global_work_size: Some(global_work_size),
+            ..self
+        }
+    }
+
+    /// Get the OpenCL C source of the generated kernel
+    pub fn source(&self) -> String {
+        let params: Vec<String> = self
+            .args
+            .iter()
+            .map(|(name, arg)| arg.declaration(name))
+            .collect();
+
+        format!(
+            "__kernel void ocl_map({}) {{\n    size_t i = get_global_id(0);\n    {};\n}}\n",
+            params.join(", "),
+            self.body
+        )
+    }
+
+    fn program(&self, context: &Context) -> Result<Program> {
+        let source = self.source();
+        let key = (context.raw() as usize, source);
+
+        if let Some(program) = PROGRAMS.lock().unwrap().get(&key) {
+            return program.try_clone();
+        }
+
+        // as with primitives, the cache isn't locked while building
+        let program = ProgramBuilder::with_source(context, &key.1).build()?;
+
+        PROGRAMS
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert(program)
+            .try_clone()
+    }
+
+    /// Compile (if necessary) and execute the kernel, blocking until
+    /// completion.
+    ///
+    /// Returns `Error::InvalidDataLength` if no global work size was set and
+    /// no buffers are bound.
+    pub fn exec(self, queue: &Queue) -> Result<()> {
+        let global_work_size = match self.global_work_size {
+            Some(size) => size,
+            None => self
+                .args
+                .iter()
+                .filter_map(|(_, arg)| arg.buffer_len())
+                .min()
+                .ok_or(Error::InvalidDataLength {
+                    expected: 1,
+                    actual: 0,
+                })?,
+        };
+
+        if global_work_size == 0 {
+            return Ok(());
+        }
+
+        let program = self.program(&queue.context()?)?;
+        let kernel = program.create_kernel(CStr::from_bytes_with_nul(b"ocl_map\0").unwrap())?;
+
+        unsafe {
+            for (i, (_, arg)) in self.args.iter().enumerate() {
+                arg.set(kernel.raw(), i as _)?;
+            }
+
+            let mut event = null_mut();
+
+            wrap_result!("clEnqueueNDRangeKernel" => clEnqueueNDRangeKernel(
+                queue.raw(),
+                kernel.raw(),
+                1,
+                null(),
+                &global_work_size as _,
+                null(),
+                0,
+                null(),
+                &mut event as _
+            ))?;
+
+            Event(event).wait()?;
+        }
+
+        deterministic::sync(queue)
+    }
+}
+
+/// Generate, compile, and execute an element-wise kernel from a snippet of
+/// OpenCL C, blocking until completion.
+///
+/// The snippet is executed once for each index `i`, up to the length of the
+/// shortest bound buffer. Each binding is a name followed by a scalar, a
+/// shared buffer reference (read-only in the kernel), or a mutable buffer
+/// reference. Compiled kernels are cached per context.
+///
+/// ```no_run
+/// # use dynamic_ocl::{buffer::Buffer, buffer::flags::HostReadWrite, queue::Queue};
+/// # fn f(queue: &Queue, a: &Buffer<HostReadWrite, f32>, b: &Buffer<HostReadWrite, f32>, c: &mut Buffer<HostReadWrite, f32>) -> dynamic_ocl::Result<()> {
+/// dynamic_ocl::ocl_map!(queue, "c[i] = a[i] * b[i] + k", a = a, b = b, c = c, k = 2.0f32)?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! ocl_map {
+    ( $queue:expr, $body:expr $( , $name:ident = $value:expr )* $(,)? ) => {
+        $crate::primitives::MapKernel::new($body)
+            $( .arg(stringify!($name), $value) )*
+            .exec($queue)
+    };
+}
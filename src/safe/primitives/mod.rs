@@ -0,0 +1,480 @@
+//! Reduction and scan primitives
+//!
+//! Prebuilt kernels for reducing a buffer to a single value, and computing
+//! prefix sums (scans) of a buffer, using `Queue::reduce` and `Queue::scan`.
+//! The kernels are written in terms of a binary operation (`Op`), and are
+//! compiled on demand for each combination of context, scalar type, and
+//! operation, then cached until `clear_cache` is called.
+//!
+//! Simple element-wise kernels can also be generated from a snippet of OpenCL
+//! C using `ocl_map!` (or `MapKernel`), and are cached in the same way.
+//!
+//! Work group sizes for reductions and scans are chosen as the largest power
+//! of two supported by the compiled kernel on the queue's device, up to 256.
+
+use crate::buffer::flags::{HostAccess, HostNoAccess};
+use crate::buffer::{Buffer, MemSafe};
+use crate::context::Context;
+use crate::deterministic;
+use crate::event::Event;
+use crate::kernel::UnboundKernel;
+use crate::program::{Program, ProgramBuilder};
+use crate::queue::Queue;
+use crate::raw::*;
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr};
+use std::mem::size_of;
+use std::ptr::{null, null_mut};
+use std::sync::Mutex;
+
+mod map;
+
+pub use map::*;
+
+/// The largest work group size used by the primitive kernels
+const MAX_LOCAL_SIZE: usize = 256;
+
+const SOURCE: &str = r#"
+#ifdef cl_khr_fp64
+#pragma OPENCL EXTENSION cl_khr_fp64 : enable
+#endif
+
+__kernel void reduce(
+    __global const T *in,
+    __global T *out,
+    const ulong n,
+    __local T *scratch
+) {
+    size_t lid = get_local_id(0);
+    size_t stride = get_global_size(0);
+    T acc = IDENTITY;
+
+    for (size_t i = get_global_id(0); i < n; i += stride) {
+        acc = OP(acc, in[i]);
+    }
+
+    scratch[lid] = acc;
+    barrier(CLK_LOCAL_MEM_FENCE);
+
+    for (size_t s = get_local_size(0) / 2; s > 0; s >>= 1) {
+        if (lid < s) {
+            scratch[lid] = OP(scratch[lid], scratch[lid + s]);
+        }
+        barrier(CLK_LOCAL_MEM_FENCE);
+    }
+
+    if (lid == 0) {
+        out[get_group_id(0)] = scratch[0];
+    }
+}
+
+__kernel void scan_block(
+    __global const T *in,
+    __global T *out,
+    __global T *sums,
+    const ulong n,
+    const int exclusive,
+    __local T *scratch
+) {
+    size_t gid = get_global_id(0);
+    size_t lid = get_local_id(0);
+    size_t lsz = get_local_size(0);
+
+    scratch[lid] = gid < n ? in[gid] : IDENTITY;
+    barrier(CLK_LOCAL_MEM_FENCE);
+
+    for (size_t offset = 1; offset < lsz; offset <<= 1) {
+        T v = lid >= offset ? scratch[lid - offset] : IDENTITY;
+        barrier(CLK_LOCAL_MEM_FENCE);
+        scratch[lid] = OP(scratch[lid], v);
+        barrier(CLK_LOCAL_MEM_FENCE);
+    }
+
+    if (gid < n) {
+        out[gid] = exclusive ? (lid > 0 ? scratch[lid - 1] : IDENTITY) : scratch[lid];
+    }
+
+    if (lid == lsz - 1) {
+        sums[get_group_id(0)] = scratch[lid];
+    }
+}
+
+__kernel void scan_add(
+    __global T *out,
+    __global const T *offsets,
+    const ulong n
+) {
+    size_t gid = get_global_id(0);
+    size_t group = get_group_id(0);
+
+    if (gid < n && group > 0) {
+        out[gid] = OP(offsets[group - 1], out[gid]);
+    }
+}
+"#;
+
+mod sealed {
+    pub trait ScalarInternal {}
+}
+
+/// A scalar type supported by the primitive kernels
+pub trait Scalar: MemSafe + sealed::ScalarInternal {
+    /// The name of this type in OpenCL C
+    const C_TYPE: &'static str;
+    /// An OpenCL C expression for the smallest value of this type
+    const MIN: &'static str;
+    /// An OpenCL C expression for the largest value of this type
+    const MAX: &'static str;
+}
+
+macro_rules! scalars {
+    ( $( $ty:ty = $c_type:literal, $min:literal, $max:literal; )* ) => {
+        $(
+            impl sealed::ScalarInternal for $ty {}
+
+            impl Scalar for $ty {
+                const C_TYPE: &'static str = $c_type;
+                const MIN: &'static str = $min;
+                const MAX: &'static str = $max;
+            }
+        )*
+    };
+}
+
+scalars! {
+    cl_char = "char", "CHAR_MIN", "CHAR_MAX";
+    cl_uchar = "uchar", "0", "UCHAR_MAX";
+    cl_short = "short", "SHRT_MIN", "SHRT_MAX";
+    cl_ushort = "ushort", "0", "USHRT_MAX";
+    cl_int = "int", "INT_MIN", "INT_MAX";
+    cl_uint = "uint", "0", "UINT_MAX";
+    cl_long = "long", "LONG_MIN", "LONG_MAX";
+    cl_ulong = "ulong", "0", "ULONG_MAX";
+    cl_float = "float", "-INFINITY", "INFINITY";
+    cl_double = "double", "-INFINITY", "INFINITY";
+}
+
+/// A binary operation used by the primitive kernels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Op {
+    fn definition(self) -> &'static str {
+        match self {
+            Self::Sum => "-D OP(a,b)=((a)+(b))",
+            Self::Min => "-D OP(a,b)=min(a,b)",
+            Self::Max => "-D OP(a,b)=max(a,b)",
+        }
+    }
+
+    fn identity<T: Scalar>(self) -> &'static str {
+        match self {
+            Self::Sum => "0",
+            Self::Min => T::MAX,
+            Self::Max => T::MIN,
+        }
+    }
+}
+
+/// Whether a scan includes each element in its own result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScanKind {
+    /// Each output element combines all input elements up to and including
+    /// the corresponding input element
+    Inclusive,
+    /// Each output element combines all input elements before the
+    /// corresponding input element, so the first output element is the
+    /// identity of the operation
+    Exclusive,
+}
+
+lazy_static::lazy_static! {
+    /// Compiled programs, keyed by raw context handle, scalar type, and
+    /// operation. Each program retains its context, so handles can't be
+    /// reused while cached.
+    static ref PROGRAMS: Mutex<HashMap<(usize, &'static str, Op), Program>> = Default::default();
+}
+
+/// Release all cached primitive and element-wise programs, along with the
+/// contexts they retain. Programs will be recompiled the next time they're
+/// needed.
+pub fn clear_cache() {
+    PROGRAMS.lock().unwrap().clear();
+    map::clear_map_cache();
+}
+
+fn program<T: Scalar>(context: &Context, op: Op) -> Result<Program> {
+    let key = (context.raw() as usize, T::C_TYPE, op);
+
+    if let Some(program) = PROGRAMS.lock().unwrap().get(&key) {
+        return program.try_clone();
+    }
+
+    // the cache isn't locked while building, so that builds in other contexts
+    // aren't blocked, and the program may have been cached concurrently
+    let program = ProgramBuilder::with_source(context, &SOURCE)
+        .opt(format!("-D T={}", T::C_TYPE))
+        .opt(format!(
+            "-D IDENTITY=(({}){})",
+            T::C_TYPE,
+            op.identity::<T>()
+        ))
+        .opt(op.definition())
+        .build()?;
+
+    PROGRAMS
+        .lock()
+        .unwrap()
+        .entry(key)
+        .or_insert(program)
+        .try_clone()
+}
+
+/// Choose a power of two work group size for the given kernel
+fn local_size(queue: &Queue, kernel: &UnboundKernel) -> Result<usize> {
+    let max = kernel
+        .work_group_size(queue.device()?)?
+        .clamp(1, MAX_LOCAL_SIZE);
+
+    Ok(1 << (usize::BITS - 1 - max.leading_zeros()))
+}
+
+/// A kernel argument, set with `clSetKernelArg`
+enum Arg {
+    Mem(cl_mem),
+    Ulong(cl_ulong),
+    Int(cl_int),
+    Local(usize),
+}
+
+fn enqueue(
+    queue: &Queue,
+    kernel: &UnboundKernel,
+    args: &[Arg],
+    global_work_size: usize,
+    local_work_size: usize,
+    wait_for: Option<&Event>,
+) -> Result<Event> {
+    unsafe {
+        for (i, arg) in args.iter().enumerate() {
+            let (size, value): (usize, *const c_void) = match arg {
+                Arg::Mem(m) => (size_of::<cl_mem>(), m as *const _ as _),
+                Arg::Ulong(v) => (size_of::<cl_ulong>(), v as *const _ as _),
+                Arg::Int(v) => (size_of::<cl_int>(), v as *const _ as _),
+                Arg::Local(size) => (*size, null()),
+            };
+
+            wrap_result!("clSetKernelArg" => clSetKernelArg(kernel.raw(), i as _, size, value))?;
+        }
+
+        let wait_list = wait_for.map(|e| e.raw());
+        let mut event = null_mut();
+
+        wrap_result!("clEnqueueNDRangeKernel" => clEnqueueNDRangeKernel(
+            queue.raw(),
+            kernel.raw(),
+            1,
+            null(),
+            &global_work_size as _,
+            &local_work_size as _,
+            wait_list.is_some() as _,
+            wait_list.as_ref().map(|e| e as *const _).unwrap_or(null()),
+            &mut event as _
+        ))?;
+
+        let event = Event(event);
+        deterministic::sync(queue)?;
+        Ok(event)
+    }
+}
+
+fn temp_buffer<T: Scalar>(
+    context: &Context,
+    len: usize,
+) -> Result<Buffer<'static, HostNoAccess, T>> {
+    context
+        .buffer_builder()
+        .host_access::<HostNoAccess>()
+        .build_with_size(len)
+}
+
+/// Scan `n` elements of `input` into `output`, which may be the same buffer,
+/// recursively scanning the totals of each work group.
+#[allow(clippy::too_many_arguments)]
+fn scan_pass<T: Scalar>(
+    queue: &Queue,
+    context: &Context,
+    program: &Program,
+    input: cl_mem,
+    output: cl_mem,
+    n: usize,
+    exclusive: bool,
+    wait_for: Option<&Event>,
+) -> Result<Event> {
+    let block = program.create_kernel(CStr::from_bytes_with_nul(b"scan_block\0").unwrap())?;
+    let local = local_size(queue, &block)?;
+    let groups = n.div_ceil(local);
+    let sums = temp_buffer::<T>(context, groups)?;
+
+    let scanned = enqueue(
+        queue,
+        &block,
+        &[
+            Arg::Mem(input),
+            Arg::Mem(output),
+            Arg::Mem(sums.raw()),
+            Arg::Ulong(n as _),
+            Arg::Int(exclusive as _),
+            Arg::Local(local * size_of::<T>()),
+        ],
+        groups * local,
+        local,
+        wait_for,
+    )?;
+
+    if groups == 1 {
+        return Ok(scanned);
+    }
+
+    let sums_scanned = scan_pass::<T>(
+        queue,
+        context,
+        program,
+        sums.raw(),
+        sums.raw(),
+        groups,
+        false,
+        Some(&scanned),
+    )?;
+
+    let add = program.create_kernel(CStr::from_bytes_with_nul(b"scan_add\0").unwrap())?;
+
+    // the temporary buffer is only released by OpenCL once the commands using
+    // it have completed, so it's fine to drop it here
+    enqueue(
+        queue,
+        &add,
+        &[Arg::Mem(output), Arg::Mem(sums.raw()), Arg::Ulong(n as _)],
+        groups * local,
+        local,
+        Some(&sums_scanned),
+    )
+}
+
+impl Queue {
+    /// Reduce the contents of the given buffer to a single value using the
+    /// given operation, blocking until completion.
+    ///
+    /// Returns `Error::InvalidDataLength` if the buffer is empty.
+    pub fn reduce<H: HostAccess, T: Scalar>(&self, buffer: &Buffer<H, T>, op: Op) -> Result<T> {
+        let n = buffer.rust_size() / size_of::<T>();
+
+        if n == 0 {
+            return Err(Error::InvalidDataLength {
+                expected: size_of::<T>(),
+                actual: 0,
+            });
+        }
+
+        let context = self.context()?;
+        let program = program::<T>(&context, op)?;
+        let kernel = program.create_kernel(CStr::from_bytes_with_nul(b"reduce\0").unwrap())?;
+        let local = local_size(self, &kernel)?;
+        let groups = n.div_ceil(local).min(local);
+
+        let partials = temp_buffer::<T>(&context, groups)?;
+        let result = temp_buffer::<T>(&context, 1)?;
+
+        let first = enqueue(
+            self,
+            &kernel,
+            &[
+                Arg::Mem(buffer.raw()),
+                Arg::Mem(partials.raw()),
+                Arg::Ulong(n as _),
+                Arg::Local(local * size_of::<T>()),
+            ],
+            groups * local,
+            local,
+            None,
+        )?;
+
+        let second = enqueue(
+            self,
+            &kernel,
+            &[
+                Arg::Mem(partials.raw()),
+                Arg::Mem(result.raw()),
+                Arg::Ulong(groups as _),
+                Arg::Local(local * size_of::<T>()),
+            ],
+            local,
+            local,
+            Some(&first),
+        )?;
+
+        unsafe {
+            let mut value = std::mem::MaybeUninit::<T>::uninit();
+
+            wrap_result!("clEnqueueReadBuffer" => clEnqueueReadBuffer(
+                self.raw(),
+                result.raw(),
+                CL_BLOCKING,
+                0,
+                size_of::<T>(),
+                value.as_mut_ptr() as _,
+                1,
+                &second.raw() as _,
+                null_mut()
+            ))?;
+
+            Ok(value.assume_init())
+        }
+    }
+
+    /// Compute the inclusive or exclusive scan (generalized prefix sum) of the
+    /// given buffer using the given operation, writing the results to the
+    /// output buffer and blocking until completion.
+    ///
+    /// Returns `Error::InvalidDataLength` if the buffers have different sizes.
+    pub fn scan<H: HostAccess, H2: HostAccess, T: Scalar>(
+        &self,
+        input: &Buffer<H, T>,
+        output: &mut Buffer<H2, T>,
+        op: Op,
+        kind: ScanKind,
+    ) -> Result<()> {
+        if input.rust_size() != output.rust_size() {
+            return Err(Error::InvalidDataLength {
+                expected: input.rust_size(),
+                actual: output.rust_size(),
+            });
+        }
+
+        let n = input.rust_size() / size_of::<T>();
+
+        if n == 0 {
+            return Ok(());
+        }
+
+        let context = self.context()?;
+        let program = program::<T>(&context, op)?;
+
+        scan_pass::<T>(
+            self,
+            &context,
+            &program,
+            input.raw(),
+            output.raw(),
+            n,
+            kind == ScanKind::Exclusive,
+            None,
+        )?
+        .wait()
+    }
+}
@@ -0,0 +1,207 @@
+use super::{Program, ProgramBuilder};
+use crate::context::Context;
+use crate::kernel::UnboundKernel;
+use crate::Result;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CStr;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+/// A set of preprocessor defines used to specialize a program, as passed to
+/// `SpecializedProgramCache::get`
+pub type Defines = BTreeMap<String, String>;
+
+/// A cache of programs compiled from a single source file, with one program
+/// for each unique set of preprocessor defines.
+///
+/// This allows kernels to be specialized by compile-time constants (e.g.
+/// `BLOCK_SIZE=64`), while only compiling each specialization once.
+pub struct SpecializedProgramCache {
+    context: Context,
+    source: String,
+    options: Option<String>,
+    programs: Mutex<HashMap<Defines, Program>>,
+}
+
+impl Debug for SpecializedProgramCache {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(&tynm::type_name::<Self>())
+            .field("context", &self.context)
+            .field("options", &self.options)
+            .field("specializations", &self.programs.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl SpecializedProgramCache {
+    /// Create a new, empty cache for programs built from the given source in
+    /// the given context
+    pub fn new(context: &Context, source: impl Into<String>) -> Result<Self> {
+        Ok(Self {
+            context: context.try_clone()?,
+            source: source.into(),
+            options: None,
+            programs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Set additional compiler options used when building every
+    /// specialization
+    pub fn options(self, options: impl Into<String>) -> Self {
+        Self {
+            options: Some(options.into()),
+            ..self
+        }
+    }
+
+    /// Get the program specialized with the given defines, compiling it if it
+    /// hasn't been compiled yet. The order of the defines doesn't matter.
+    pub fn get<N: Into<String>, V: Display>(
+        &self,
+        defines: impl IntoIterator<Item = (N, V)>,
+    ) -> Result<Program> {
+        let defines: Defines = defines
+            .into_iter()
+            .map(|(n, v)| (n.into(), v.to_string()))
+            .collect();
+
+        if let Some(program) = self.programs.lock().unwrap().get(&defines) {
+            return program.try_clone();
+        }
+
+        // the cache isn't locked while building, so that other
+        // specializations can be built concurrently, and this one may have
+        // been cached in the meantime
+        let mut builder = ProgramBuilder::with_source(&self.context, &self.source);
+
+        if let Some(options) = &self.options {
+            builder.opt(options.as_str());
+        }
+
+        for (name, value) in &defines {
+            builder.define(name, value);
+        }
+
+        let program = builder.build()?;
+
+        self.programs
+            .lock()
+            .unwrap()
+            .entry(defines)
+            .or_insert(program)
+            .try_clone()
+    }
+
+    /// Get the number of specializations which have been compiled
+    pub fn len(&self) -> usize {
+        self.programs.lock().unwrap().len()
+    }
+
+    /// Check whether no specializations have been compiled yet
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Release all compiled specializations
+    pub fn clear(&self) {
+        self.programs.lock().unwrap().clear();
+    }
+}
+
+/// A program cached by `Context::get_or_build_program`
+struct CachedProgram {
+    source: String,
+    options: String,
+    program: Program,
+}
+
+lazy_static::lazy_static! {
+    /// Programs built by `Context::get_or_build_program`, keyed by raw context
+    /// handle and a hash of the source and options. Each program retains its
+    /// context, so handles can't be reused while cached.
+    static ref REGISTRY: Mutex<HashMap<(usize, u64), Vec<CachedProgram>>> = Default::default();
+}
+
+impl Context {
+    /// Get a program built from the given source and compiler options in this
+    /// context, building it only if an identical program hasn't already been
+    /// built by this function.
+    ///
+    /// Programs are cached for the lifetime of the process, or until
+    /// `clear_program_cache` is called, and retain the context while cached.
+    pub fn get_or_build_program(&self, source: &str, options: &str) -> Result<Program> {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        options.hash(&mut hasher);
+        let key = (self.raw() as usize, hasher.finish());
+
+        let find = |entries: &[CachedProgram]| {
+            entries
+                .iter()
+                .find(|c| c.source == source && c.options == options)
+                .map(|c| c.program.try_clone())
+        };
+
+        if let Some(program) = REGISTRY.lock().unwrap().get(&key).and_then(|e| find(e)) {
+            return program;
+        }
+
+        // the registry isn't locked while building, so that other programs
+        // can be built concurrently, and this one may have been cached in the
+        // meantime
+        let mut builder = ProgramBuilder::with_source(self, &source);
+
+        if !options.is_empty() {
+            builder.opt(options);
+        }
+
+        let program = builder.build()?;
+
+        let mut registry = REGISTRY.lock().unwrap();
+        let entries = registry.entry(key).or_default();
+
+        if let Some(cached) = find(entries) {
+            return cached;
+        }
+
+        entries.push(CachedProgram {
+            source: source.to_string(),
+            options: options.to_string(),
+            program: program.try_clone()?,
+        });
+
+        Ok(program)
+    }
+
+    /// Get a program with `get_or_build_program`, and create the kernel with
+    /// the given name from it. This collapses the usual build-and-create steps
+    /// into one call for simple cases.
+    ///
+    /// If the build fails, the returned `Error::BuildFailed` includes the
+    /// build log.
+    pub fn kernel_from_source(
+        &self,
+        source: &str,
+        name: &CStr,
+        options: &str,
+    ) -> Result<UnboundKernel> {
+        self.get_or_build_program(source, options)?
+            .create_kernel(name)
+    }
+
+    /// Release all programs cached for this context by
+    /// `get_or_build_program`
+    pub fn clear_program_cache(&self) {
+        REGISTRY
+            .lock()
+            .unwrap()
+            .retain(|&(context, _), _| context != self.raw() as usize);
+    }
+}
+
+/// Release all programs cached by `get_or_build_program`, for every context
+pub(crate) fn clear_registry() {
+    REGISTRY.lock().unwrap().clear();
+}
@@ -0,0 +1,145 @@
+//! Program constants
+//!
+//! OpenCL C has no equivalent of SPIR-V specialization constants, but the
+//! same effect can be had by defining preprocessor macros when building a
+//! program. Constants declared with `ProgramBuilder::constant` are passed to
+//! the compiler as `-D` options, formatted as typed OpenCL C literals (e.g.
+//! `1920u` for a `u32`), and recorded on the built program so they can be
+//! inspected with `Program::constants`.
+
+use std::fmt::{self, Display, Formatter};
+
+/// The value of a program constant, along with its OpenCL C type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstantValue {
+    Bool(bool),
+    Int(i32),
+    UInt(u32),
+    Long(i64),
+    ULong(u64),
+    Float(f32),
+    /// A double precision value, requiring device support for `double`
+    Double(f64),
+}
+
+impl ConstantValue {
+    /// Get the name of the OpenCL C type of this constant
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ConstantValue::Bool(_) => "bool",
+            ConstantValue::Int(_) => "int",
+            ConstantValue::UInt(_) => "uint",
+            ConstantValue::Long(_) => "long",
+            ConstantValue::ULong(_) => "ulong",
+            ConstantValue::Float(_) => "float",
+            ConstantValue::Double(_) => "double",
+        }
+    }
+}
+
+/// Format a float as an OpenCL C literal with the given suffix
+fn fmt_float(f: &mut Formatter, value: f64, debug: &dyn fmt::Debug, suffix: &str) -> fmt::Result {
+    if value.is_nan() {
+        write!(f, "NAN")
+    } else if value.is_infinite() {
+        write!(f, "({}INFINITY)", if value < 0.0 { "-" } else { "" })
+    } else if value.is_sign_negative() {
+        write!(f, "({:?}{})", debug, suffix)
+    } else {
+        write!(f, "{:?}{}", debug, suffix)
+    }
+}
+
+/// Formats the value as an OpenCL C literal of the corresponding type.
+/// Negative values are parenthesized so they can be safely substituted into
+/// expressions.
+impl Display for ConstantValue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            ConstantValue::Bool(v) => write!(f, "{}", v as u8),
+            ConstantValue::Int(i32::MIN) => write!(f, "({}-1)", i32::MIN + 1),
+            ConstantValue::Int(v) if v < 0 => write!(f, "({})", v),
+            ConstantValue::Int(v) => write!(f, "{}", v),
+            ConstantValue::UInt(v) => write!(f, "{}u", v),
+            ConstantValue::Long(i64::MIN) => write!(f, "({}l-1l)", i64::MIN + 1),
+            ConstantValue::Long(v) if v < 0 => write!(f, "({}l)", v),
+            ConstantValue::Long(v) => write!(f, "{}l", v),
+            ConstantValue::ULong(v) => write!(f, "{}ul", v),
+            ConstantValue::Float(v) => fmt_float(f, v as f64, &v, "f"),
+            ConstantValue::Double(v) => fmt_float(f, v, &v, ""),
+        }
+    }
+}
+
+macro_rules! constant_from {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$ty> for ConstantValue {
+                fn from(value: $ty) -> Self {
+                    ConstantValue::$variant(value)
+                }
+            }
+        )*
+    };
+}
+
+constant_from! {
+    bool => Bool,
+    i32 => Int,
+    u32 => UInt,
+    i64 => Long,
+    u64 => ULong,
+    f32 => Float,
+    f64 => Double,
+}
+
+/// Check that the given name is a valid OpenCL C identifier
+pub(super) fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid_name, ConstantValue};
+
+    fn literal(value: impl Into<ConstantValue>) -> String {
+        value.into().to_string()
+    }
+
+    #[test]
+    fn test_integer_literals() {
+        assert_eq!(literal(true), "1");
+        assert_eq!(literal(false), "0");
+        assert_eq!(literal(42), "42");
+        assert_eq!(literal(-42), "(-42)");
+        assert_eq!(literal(i32::MIN), "(-2147483647-1)");
+        assert_eq!(literal(1920u32), "1920u");
+        assert_eq!(literal(-42i64), "(-42l)");
+        assert_eq!(literal(i64::MIN), "(-9223372036854775807l-1l)");
+        assert_eq!(literal(u64::MAX), "18446744073709551615ul");
+    }
+
+    #[test]
+    fn test_float_literals() {
+        assert_eq!(literal(1.5f32), "1.5f");
+        assert_eq!(literal(-1.5f32), "(-1.5f)");
+        assert_eq!(literal(-0.0f32), "(-0.0f)");
+        assert_eq!(literal(2.0f64), "2.0");
+        assert_eq!(literal(f32::NAN), "NAN");
+        assert_eq!(literal(f64::NAN), "NAN");
+        assert_eq!(literal(f32::INFINITY), "(INFINITY)");
+        assert_eq!(literal(f64::NEG_INFINITY), "(-INFINITY)");
+    }
+
+    #[test]
+    fn test_valid_names() {
+        assert!(is_valid_name("TILE_SIZE"));
+        assert!(is_valid_name("_x1"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("1x"));
+        assert!(!is_valid_name("a-b"));
+    }
+}
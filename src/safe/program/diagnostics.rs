@@ -0,0 +1,239 @@
+use std::fmt::{self, Display, Formatter};
+
+/// The severity of a build diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuildSeverity {
+    /// A fatal or non-fatal compiler error
+    Error,
+    /// A compiler warning
+    Warning,
+    /// A note attached to a previous error or warning
+    Note,
+}
+
+/// The source a build diagnostic refers to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BuildSource {
+    /// A source fragment passed to the program builder, by index. Programs
+    /// built from a single source have one fragment.
+    Fragment(usize),
+    /// A virtual header registered with `ProgramBuilder::header`, by name
+    Header(String),
+    /// Another file, as named in the build log (e.g. `<built-in>` or a system
+    /// header)
+    Other(String),
+}
+
+/// A diagnostic parsed from a build log, with its location mapped back to
+/// the source fragment or virtual header it refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildDiagnostic {
+    severity: BuildSeverity,
+    source: BuildSource,
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl BuildDiagnostic {
+    /// Get the severity of this diagnostic
+    pub fn severity(&self) -> BuildSeverity {
+        self.severity
+    }
+
+    /// Get the source this diagnostic refers to
+    pub fn source(&self) -> &BuildSource {
+        &self.source
+    }
+
+    /// Get the line number this diagnostic refers to, starting from 1 at the
+    /// beginning of its source
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Get the column number this diagnostic refers to, starting from 1
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Get the message reported by the compiler
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for BuildDiagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match &self.source {
+            BuildSource::Fragment(i) => write!(f, "<fragment {}>", i)?,
+            BuildSource::Header(name) | BuildSource::Other(name) => write!(f, "{}", name)?,
+        }
+
+        write!(
+            f,
+            ":{}:{}: {:?}: {}",
+            self.line, self.column, self.severity, self.message
+        )
+    }
+}
+
+/// Split a line of the form `file:line:column: severity: message`, as printed
+/// by clang-based compilers. The file name may itself contain colons.
+fn split_line(line: &str) -> Option<(&str, usize, usize, &str, &str)> {
+    let mut start = 0;
+
+    while let Some(i) = line[start..].find(':').map(|i| start + i) {
+        let mut parts = line[i + 1..].splitn(4, ':');
+
+        if let (Some(l), Some(c), Some(severity), Some(message)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        {
+            if let (Ok(l), Ok(c)) = (l.parse(), c.parse()) {
+                return Some((&line[..i], l, c, severity.trim(), message.trim()));
+            }
+        }
+
+        start = i + 1;
+    }
+
+    None
+}
+
+/// Parse the diagnostics in a build log, mapping line numbers in the main
+/// source back to the fragments it was built from, and file names back to the
+/// given virtual header names. Lines which aren't diagnostics are skipped, as
+/// are duplicates reported by multiple devices.
+///
+/// Vendors name the main source differently (e.g. `<source>`, `<kernel>`, or
+/// a temporary file), so any file in angle brackets other than `<built-in>`,
+/// or any file named like a temporary compiler input, is assumed to be the
+/// main source.
+pub(super) fn parse(log: &str, fragments: &[&[u8]], headers: &[&str]) -> Vec<BuildDiagnostic> {
+    // the first line of each fragment in the concatenated source
+    let starts: Vec<usize> = fragments
+        .iter()
+        .scan(1, |line, fragment| {
+            let start = *line;
+            *line += fragment.iter().filter(|&&b| b == b'\n').count();
+            Some(start)
+        })
+        .collect();
+
+    let mut diagnostics = vec![];
+
+    for (file, line, column, severity, message) in log.lines().filter_map(split_line) {
+        let severity = match severity {
+            "error" | "fatal error" => BuildSeverity::Error,
+            "warning" => BuildSeverity::Warning,
+            "note" => BuildSeverity::Note,
+            _ => continue,
+        };
+
+        let base = file.rsplit(['/', '\\']).next().unwrap();
+
+        let (source, line) = if let Some(header) = headers
+            .iter()
+            .find(|&&h| h == base || file.ends_with(&format!("/{}", h)))
+        {
+            (BuildSource::Header(header.to_string()), line)
+        } else if (file.starts_with('<') && file != "<built-in>")
+            || base.starts_with("CompileSource")
+            || base.starts_with("input.cl")
+        {
+            match starts.iter().rposition(|&s| s <= line) {
+                Some(i) => (BuildSource::Fragment(i), line - starts[i] + 1),
+                None => (BuildSource::Other(file.to_string()), line),
+            }
+        } else {
+            (BuildSource::Other(file.to_string()), line)
+        };
+
+        let diagnostic = BuildDiagnostic {
+            severity,
+            source,
+            line,
+            column,
+            message: message.to_string(),
+        };
+
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, split_line, BuildSeverity, BuildSource};
+
+    #[test]
+    fn test_split_line() {
+        assert_eq!(
+            split_line("<source>:3:14: error: use of undeclared identifier 'x'"),
+            Some((
+                "<source>",
+                3,
+                14,
+                "error",
+                "use of undeclared identifier 'x'"
+            ))
+        );
+        assert_eq!(
+            split_line("C:\\tmp\\input.cl:1:2: warning: unused"),
+            Some(("C:\\tmp\\input.cl", 1, 2, "warning", "unused"))
+        );
+        assert_eq!(split_line("1 error generated."), None);
+    }
+
+    #[test]
+    fn test_map_fragments() {
+        let fragments: [&[u8]; 2] = [b"#define N 4\n", b"kernel void f() {\n  x;\n}\n"];
+        let log = "<kernel>:3:3: error: use of undeclared identifier 'x'\n\
+                   <kernel>:1:9: note: macro defined here\n\
+                   1 error generated.\n";
+
+        let diagnostics = parse(log, &fragments, &[]);
+        assert_eq!(diagnostics.len(), 2);
+
+        assert_eq!(diagnostics[0].severity(), BuildSeverity::Error);
+        assert_eq!(diagnostics[0].source(), &BuildSource::Fragment(1));
+        assert_eq!(diagnostics[0].line(), 2);
+        assert_eq!(diagnostics[0].column(), 3);
+        assert_eq!(diagnostics[0].message(), "use of undeclared identifier 'x'");
+
+        assert_eq!(diagnostics[1].severity(), BuildSeverity::Note);
+        assert_eq!(diagnostics[1].source(), &BuildSource::Fragment(0));
+        assert_eq!(diagnostics[1].line(), 1);
+    }
+
+    #[test]
+    fn test_map_headers_and_other_files() {
+        let log = "/tmp/headers/util.h:5:1: fatal error: expected ';'\n\
+                   <built-in>:1:1: warning: redefined\n\
+                   /tmp/CompileSource.cl:2:1: error: bad\n";
+
+        let diagnostics = parse(log, &[b"\n\n"], &["util.h"]);
+        let sources: Vec<_> = diagnostics.iter().map(|d| d.source().clone()).collect();
+        assert_eq!(
+            sources,
+            [
+                BuildSource::Header("util.h".into()),
+                BuildSource::Other("<built-in>".into()),
+                BuildSource::Fragment(0),
+            ]
+        );
+        assert_eq!(diagnostics[0].severity(), BuildSeverity::Error);
+    }
+
+    #[test]
+    fn test_skip_duplicates_and_unknown_severities() {
+        let log = "<source>:1:1: error: bad\n\
+                   <source>:1:1: remark: ignored\n\
+                   <source>:1:1: error: bad\n";
+
+        assert_eq!(parse(log, &[b"\n"], &[]).len(), 1);
+    }
+}
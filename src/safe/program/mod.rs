@@ -3,40 +3,91 @@
 //! In OpenCL, a `Program` exports kernels that can be executed to perform work
 //! on specialized hardware.
 
+pub(crate) mod cache;
+mod constants;
+mod diagnostics;
+pub mod tracking;
 mod types;
 
 use crate::context::Context;
 use crate::device::Device;
+use crate::labels;
 use crate::raw::{
-    clGetProgramBuildInfo, clGetProgramInfo, clReleaseProgram, clRetainProgram, cl_context,
-    cl_device_id, cl_program, cl_program_build_info, cl_program_info, cl_uint,
+    clCreateProgramWithBuiltInKernels, clGetProgramBuildInfo, clGetProgramInfo, clReleaseProgram,
+    clRetainProgram, cl_context, cl_device_id, cl_program, cl_program_build_info, cl_program_info,
+    cl_uint, CL_SUCCESS,
 };
+use crate::release;
 use crate::util::sealed::OclInfoInternal;
+use crate::version::{AtLeast, Cl12, Versioned};
 use crate::Result;
+pub use cache::*;
+pub use constants::ConstantValue;
+pub use diagnostics::*;
 use libc::size_t;
 use std::ffi::{c_void, CString};
 use std::fmt::{self, Debug, Formatter};
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
-use std::mem::ManuallyDrop;
+use std::sync::Arc;
 pub use types::*;
 
 /// An OpenCL program
-#[derive(Debug, PartialEq, Eq, Hash)]
-pub struct Program(pub(crate) cl_program);
+pub struct Program(pub(crate) cl_program, pub(crate) Option<Constants>);
+
+/// The constants a program was built with, shared by clones of its wrapper
+pub(crate) type Constants = Arc<[(String, ConstantValue)]>;
+
+impl PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Program {}
+
+impl Hash for Program {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
 
 unsafe impl Send for Program {}
 unsafe impl Sync for Program {}
 
 impl Drop for Program {
     fn drop(&mut self) {
+        if tracking::is_enabled() {
+            let alive = self.kernels_alive();
+
+            if !alive.is_empty() && matches!(self.reference_count(), Ok(1)) {
+                log::warn!(
+                    "OpenCL program {:?} released with kernels still alive: {:?}",
+                    self.0,
+                    alive
+                );
+            }
+        }
+
+        if labels::is_used() && matches!(self.reference_count(), Ok(1)) {
+            labels::unregister(self.0);
+        }
+
         unsafe {
             if let Err(e) = wrap_result!("clReleaseProgram" => clReleaseProgram(self.0)) {
-                log::warn!("Error releasing OpenCL program: {:?}: {:?}", self, e);
+                release::report("OpenCL program", self, e);
             }
         }
     }
 }
 
+impl Debug for Program {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        labels::fmt_prefix(f, self.0)?;
+        f.debug_tuple("Program").field(&self.0).finish()
+    }
+}
+
 impl OclInfoInternal for Program {
     type Param = cl_program_info;
     const DEBUG_CONTEXT: &'static str = "clGetProgramInfo";
@@ -59,15 +110,84 @@ impl OclInfoInternal for Program {
 }
 
 impl Program {
+    /// Create a program from the given built-in kernels of the given devices.
+    /// Requires OpenCL 1.2+.
+    ///
+    /// The available kernel names can be found with `Device::built_in_kernels`.
+    /// Programs created this way are already executable, and don't need to be
+    /// built - kernels can be created from them immediately.
+    pub fn with_built_in_kernels(
+        ctx: &Context,
+        devices: &[Device],
+        names: &[&str],
+    ) -> Result<Self> {
+        unsafe {
+            check_ocl_version!("clCreateProgramWithBuiltInKernels" => CL12)?;
+            Self::with_built_in_kernels_unchecked(ctx, devices, names)
+        }
+    }
+
+    unsafe fn with_built_in_kernels_unchecked(
+        ctx: &Context,
+        devices: &[Device],
+        names: &[&str],
+    ) -> Result<Self> {
+        assert!(
+            names.iter().all(|n| !n.contains(';')),
+            "built-in kernel names must not contain semicolons"
+        );
+
+        let names = CString::new(names.join(";")).unwrap();
+        let ids: Vec<cl_device_id> = devices.iter().map(|d| d.raw()).collect();
+
+        let mut err = CL_SUCCESS;
+        let program = clCreateProgramWithBuiltInKernels(
+            ctx.raw(),
+            ids.len() as _,
+            ids.as_ptr(),
+            names.as_ptr(),
+            &mut err as _,
+        );
+        wrap_result!("clCreateProgramWithBuiltInKernels" => err)?;
+        labels::register_new(program);
+        Ok(Self(program, None))
+    }
+
     /// Attempt to clone this program, using `clRetainProgram` to ensure the
     /// program is not released while a wrapper still exists.
     pub fn try_clone(&self) -> Result<Self> {
         unsafe {
             wrap_result!("clRetainProgram" => clRetainProgram(self.0))?;
-            Ok(Self(self.0))
+            Ok(Self(self.0, self.1.clone()))
         }
     }
 
+    /// Get the names of the kernels created from this program which are still
+    /// alive. Only kernels created while kernel tracking is enabled are
+    /// included - see the `tracking` module.
+    pub fn kernels_alive(&self) -> Vec<CString> {
+        tracking::kernels_alive(self.0)
+    }
+
+    /// Get the constants this program was built with, declared with
+    /// `ProgramBuilder::constant`, in the order they were declared.
+    ///
+    /// Constants are recorded by the wrapper returned by
+    /// `ProgramBuilder::build`, and shared with its clones from `try_clone`,
+    /// so they aren't available from wrappers created from a raw handle.
+    pub fn constants(&self) -> &[(String, ConstantValue)] {
+        self.1.as_deref().unwrap_or_default()
+    }
+
+    /// Get the value of the constant with the given name this program was
+    /// built with, if any
+    pub fn constant(&self, name: &str) -> Option<ConstantValue> {
+        self.constants()
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|&(_, v)| v)
+    }
+
     /// Get the raw handle for this program. Note that this handle is only a raw
     /// pointer and does not use RAII to ensure validity, so you must manually
     /// make sure that it's not released while still in use.
@@ -79,6 +199,7 @@ impl Program {
         pub fn reference_count(&self) -> cl_uint = CL_PROGRAM_REFERENCE_COUNT;
         pub fn context_raw(&self) -> cl_context = CL_PROGRAM_CONTEXT;
         pub fn num_devices(&self) -> cl_uint = CL_PROGRAM_NUM_DEVICES;
+        pub fn devices_raw(&self) -> Vec<cl_device_id> = CL_PROGRAM_DEVICES;
         pub fn source(&self) -> CString = CL_PROGRAM_SOURCE;
         pub fn il(&self) -> Vec<u8> = CL_PROGRAM_IL;
         pub fn binary_sizes(&self) -> Vec<size_t> = CL_PROGRAM_BINARY_SIZES;
@@ -89,13 +210,16 @@ impl Program {
         pub fn scope_global_dtors_present(&self) -> bool = CL_PROGRAM_SCOPE_GLOBAL_DTORS_PRESENT;
     }
 
+    /// Get the devices this program is associated with
+    pub fn devices(&self) -> Result<Vec<Device>> {
+        Ok(self.devices_raw()?.into_iter().map(Device).collect())
+    }
+
     /// Get program build info for a given device
     pub fn build_info(&self, Device(device): Device) -> Result<ProgramBuildInfo> {
-        let context = ManuallyDrop::new(Context(self.context_raw()?));
-
         assert!(
-            context.devices_raw()?.contains(&device),
-            "program context does not include given device"
+            self.devices_raw()?.contains(&device),
+            "program is not associated with given device"
         );
 
         Ok(ProgramBuildInfo {
@@ -106,6 +230,14 @@ impl Program {
     }
 }
 
+impl<V: AtLeast<Cl12>> Versioned<'_, Context, V> {
+    /// Create a program from built-in kernels, as with
+    /// `Program::with_built_in_kernels`, without checking the OpenCL version.
+    pub fn built_in_kernel_program(&self, devices: &[Device], names: &[&str]) -> Result<Program> {
+        unsafe { Program::with_built_in_kernels_unchecked(self.handle(), devices, names) }
+    }
+}
+
 pub struct ProgramBuildInfo<'a> {
     device: cl_device_id,
     program: cl_program,
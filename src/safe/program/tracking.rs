@@ -0,0 +1,71 @@
+//! Kernel tracking
+//!
+//! OpenCL allows a program to be released while kernels created from it are
+//! still alive, in which case the program is only destroyed once the last of
+//! them is released. When kernel tracking is enabled, kernels created with
+//! `Program::create_kernel` are recorded against their program, so that the
+//! kernels keeping a program alive can be listed with `Program::kernels_alive`,
+//! and dropping the last wrapper for a program logs any which remain.
+//!
+//! Tracking is disabled by default, and only applies to kernels created while
+//! it's enabled.
+
+use crate::raw::{cl_kernel, cl_program};
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+    /// Tracked kernels, keyed by raw kernel handle, along with the raw handle
+    /// of their program and their name
+    static ref KERNELS: Mutex<HashMap<usize, (usize, CString)>> = Default::default();
+}
+
+/// Enable or disable kernel tracking at runtime
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Check whether kernel tracking is currently enabled
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Record a new kernel created from the given program, if tracking is enabled
+pub(crate) fn register_kernel(program: cl_program, kernel: cl_kernel, name: &CStr) {
+    if is_enabled() {
+        KERNELS
+            .lock()
+            .unwrap()
+            .insert(kernel as usize, (program as usize, name.to_owned()));
+    }
+}
+
+/// Record a clone of a tracked kernel, created with `clCloneKernel`
+pub(crate) fn register_clone(source: cl_kernel, kernel: cl_kernel) {
+    let mut kernels = KERNELS.lock().unwrap();
+
+    if let Some(entry) = kernels.get(&(source as usize)).cloned() {
+        kernels.insert(kernel as usize, entry);
+    }
+}
+
+/// Record that the given kernel has been released
+pub(crate) fn unregister_kernel(kernel: cl_kernel) {
+    KERNELS.lock().unwrap().remove(&(kernel as usize));
+}
+
+/// Get the names of the tracked kernels created from the given program which
+/// are still alive
+pub(super) fn kernels_alive(program: cl_program) -> Vec<CString> {
+    KERNELS
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|(p, _)| *p == program as usize)
+        .map(|(_, name)| name.clone())
+        .collect()
+}
@@ -1,16 +1,20 @@
 use crate::context::Context;
-use crate::program::Program;
+use crate::labels;
+use crate::program::{constants, diagnostics, ConstantValue, Program};
 use crate::raw::{
-    clBuildProgram, clCreateProgramWithSource, cl_build_status, cl_int, cl_program,
-    cl_program_binary_type, CL_BUILD_ERROR, CL_BUILD_IN_PROGRESS, CL_BUILD_NONE, CL_BUILD_SUCCESS,
-    CL_PROGRAM_BINARY_TYPE_COMPILED_OBJECT, CL_PROGRAM_BINARY_TYPE_EXECUTABLE,
-    CL_PROGRAM_BINARY_TYPE_LIBRARY, CL_PROGRAM_BINARY_TYPE_NONE, CL_SUCCESS,
+    clBuildProgram, clCompileProgram, clCreateProgramWithSource, clLinkProgram, cl_build_status,
+    cl_int, cl_program, cl_program_binary_type, CL_BUILD_ERROR, CL_BUILD_IN_PROGRESS,
+    CL_BUILD_NONE, CL_BUILD_SUCCESS, CL_PROGRAM_BINARY_TYPE_COMPILED_OBJECT,
+    CL_PROGRAM_BINARY_TYPE_EXECUTABLE, CL_PROGRAM_BINARY_TYPE_LIBRARY, CL_PROGRAM_BINARY_TYPE_NONE,
+    CL_SUCCESS,
 };
-use crate::Result;
+use crate::{ApiError, Error, Result};
 use sealed::ProgramBuilderTypeInternal;
 use std::borrow::Cow;
 use std::ffi::CString;
-use std::ptr::null_mut;
+use std::fmt::Display;
+use std::os::raw::c_char;
+use std::ptr::{null, null_mut};
 
 mod sealed {
     use super::ProgramBuilder;
@@ -23,6 +27,10 @@ mod sealed {
         unsafe fn create_program(builder: &ProgramBuilder<Self>, err: *mut cl_int) -> cl_program
         where
             Self: Sized + ProgramBuilderType;
+
+        /// Get the source fragments the program is built from, to map build
+        /// diagnostics back to them
+        fn fragments(&self) -> Vec<&[u8]>;
     }
 }
 
@@ -35,6 +43,10 @@ pub trait ProgramBuilderType: sealed::ProgramBuilderTypeInternal {}
 pub enum FromSource<'a> {
     /// Build the program from a single source file
     Single(&'a [u8]),
+    /// Build the program from multiple source fragments, passed to the
+    /// compiler together so that line numbers in build logs refer to each
+    /// fragment
+    Multiple(Cow<'a, [&'a [u8]]>),
 }
 
 impl<'a> ProgramBuilderTypeInternal for FromSource<'a> {
@@ -49,6 +61,25 @@ impl<'a> ProgramBuilderTypeInternal for FromSource<'a> {
                 [src.len()].as_ptr(),
                 err,
             ),
+            FromSource::Multiple(ref srcs) => {
+                let ptrs: Vec<_> = srcs.iter().map(|s| s.as_ptr()).collect();
+                let lens: Vec<_> = srcs.iter().map(|s| s.len()).collect();
+
+                clCreateProgramWithSource(
+                    builder.ctx.raw(),
+                    srcs.len() as _,
+                    ptrs.as_ptr() as _,
+                    lens.as_ptr(),
+                    err,
+                )
+            }
+        }
+    }
+
+    fn fragments(&self) -> Vec<&[u8]> {
+        match self {
+            FromSource::Single(src) => vec![src],
+            FromSource::Multiple(srcs) => srcs.to_vec(),
         }
     }
 }
@@ -61,6 +92,8 @@ pub struct ProgramBuilder<'a, T: ProgramBuilderType> {
     ctx: &'a Context,
     ty: T,
     opts: Option<Cow<'a, str>>,
+    headers: Vec<(CString, &'a [u8])>,
+    constants: Vec<(String, ConstantValue)>,
 }
 
 impl<'a> ProgramBuilder<'a, FromSource<'a>> {
@@ -70,8 +103,45 @@ impl<'a> ProgramBuilder<'a, FromSource<'a>> {
             ctx,
             ty: FromSource::Single(src.as_ref()),
             opts: None,
+            headers: vec![],
+            constants: vec![],
         }
     }
+
+    /// Begin building a program with multiple source fragments
+    pub fn with_sources(ctx: &'a Context, srcs: &'a [&'a [u8]]) -> Self {
+        Self {
+            ctx,
+            ty: FromSource::Multiple(Cow::Borrowed(srcs)),
+            opts: None,
+            headers: vec![],
+            constants: vec![],
+        }
+    }
+
+    /// Append a source fragment to be compiled along with the existing
+    /// sources
+    pub fn add_source(&mut self, src: &'a impl AsRef<[u8]>) -> &mut Self {
+        match &mut self.ty {
+            FromSource::Single(old) => {
+                self.ty = FromSource::Multiple(Cow::Owned(vec![*old, src.as_ref()]))
+            }
+            FromSource::Multiple(srcs) => srcs.to_mut().push(src.as_ref()),
+        }
+        self
+    }
+
+    /// Register a virtual header with the given name and contents, which can
+    /// be included by the sources (e.g. `#include "common.cl"`) without
+    /// existing on disk. Requires OpenCL 1.2+.
+    ///
+    /// Programs with headers are compiled and linked separately rather than
+    /// built, so compiler options are only passed to the compiler.
+    pub fn header(&mut self, name: &str, contents: &'a impl AsRef<[u8]>) -> &mut Self {
+        let name = CString::new(name).expect("header names must not contain NUL bytes");
+        self.headers.push((name, contents.as_ref()));
+        self
+    }
 }
 
 impl<'a, T: ProgramBuilderType> ProgramBuilder<'a, T> {
@@ -87,33 +157,207 @@ impl<'a, T: ProgramBuilderType> ProgramBuilder<'a, T> {
         self
     }
 
+    /// Define a preprocessor macro with the given value, equivalent to the
+    /// compiler option `-D name=value`
+    pub fn define(&mut self, name: &str, value: impl Display) -> &mut Self {
+        self.opt(format!("-D {}={}", name, value))
+    }
+
+    /// Declare a named compile-time constant, defined as a preprocessor macro
+    /// expanding to an OpenCL C literal of the corresponding type (e.g.
+    /// `constant("WIDTH", 1920u32)` is equivalent to `-D WIDTH=1920u`).
+    /// Declaring a constant again replaces its previous value.
+    ///
+    /// Unlike `define`, constants are recorded against the built program, and
+    /// can be retrieved with `Program::constants`.
+    pub fn constant(&mut self, name: &str, value: impl Into<ConstantValue>) -> &mut Self {
+        assert!(
+            constants::is_valid_name(name),
+            "constant name {:?} is not a valid identifier",
+            name
+        );
+
+        let value = value.into();
+
+        match self.constants.iter_mut().find(|(n, _)| n == name) {
+            Some((_, old)) => *old = value,
+            None => self.constants.push((name.to_string(), value)),
+        }
+
+        self
+    }
+
     /// Build the program
     pub fn build(&self) -> Result<Program> {
+        let mut program = self.build_program()?;
+
+        if !self.constants.is_empty() {
+            program.1 = Some(self.constants.clone().into());
+        }
+
+        Ok(program)
+    }
+
+    /// Get the compiler options, including definitions of any declared
+    /// constants
+    fn options(&self) -> Option<CString> {
+        let mut opts = self.opts.as_deref().unwrap_or_default().to_string();
+
+        for (name, value) in &self.constants {
+            if !opts.is_empty() {
+                opts += " ";
+            }
+
+            opts += &format!("-D {}={}", name, value);
+        }
+
+        if opts.is_empty() {
+            None
+        } else {
+            Some(CString::new(opts).unwrap())
+        }
+    }
+
+    fn build_program(&self) -> Result<Program> {
         unsafe {
             let mut err = CL_SUCCESS;
 
             let program = T::create_program(self, &mut err as _);
             wrap_result!(T::CONTEXT => std::mem::replace(&mut err, CL_SUCCESS))?;
+            labels::register_new(program);
+            let program = Program(program, None);
 
-            let opts = self
-                .opts
-                .as_ref()
-                .map(|o| CString::new(o.as_bytes()).unwrap());
+            let opts = self.options();
 
-            wrap_result!("clBuildProgram" => clBuildProgram(
-                program,
+            if !self.headers.is_empty() {
+                return self.compile_and_link(program, opts);
+            }
+
+            let result = wrap_result!("clBuildProgram" => clBuildProgram(
+                program.raw(),
                 0,
                 null_mut(),
-                opts.map(|o| o.as_ptr()).unwrap_or(null_mut()),
+                opts.as_ref().map(|o| o.as_ptr()).unwrap_or(null_mut()),
                 None,
                 null_mut()
-            ))?;
+            ));
+
+            match result {
+                Ok(()) => Ok(program),
+                Err(error) => Err(self.build_failed(error, &program)),
+            }
+        }
+    }
+
+    /// Compile the given program with the registered headers, and link it
+    /// into an executable program
+    unsafe fn compile_and_link(&self, program: Program, opts: Option<CString>) -> Result<Program> {
+        check_ocl_version!("clCompileProgram" => CL12)?;
+
+        let mut headers = Vec::with_capacity(self.headers.len());
+
+        for (_, contents) in &self.headers {
+            let mut err = CL_SUCCESS;
+            let header = clCreateProgramWithSource(
+                self.ctx.raw(),
+                1,
+                [contents.as_ptr()].as_ptr() as _,
+                [contents.len()].as_ptr(),
+                &mut err,
+            );
+            wrap_result!("clCreateProgramWithSource" => err)?;
+            headers.push(Program(header, None));
+        }
+
+        let raw_headers: Vec<cl_program> = headers.iter().map(|h| h.raw()).collect();
+        let names: Vec<*const c_char> = self.headers.iter().map(|(n, _)| n.as_ptr()).collect();
 
-            Ok(Program(program))
+        let result = wrap_result!("clCompileProgram" => clCompileProgram(
+            program.raw(),
+            0,
+            null(),
+            opts.as_ref().map(|o| o.as_ptr()).unwrap_or(null()),
+            raw_headers.len() as _,
+            raw_headers.as_ptr(),
+            names.as_ptr(),
+            None,
+            null_mut()
+        ));
+
+        if let Err(error) = result {
+            return Err(self.build_failed(error, &program));
+        }
+
+        let mut err = CL_SUCCESS;
+        let linked = clLinkProgram(
+            self.ctx.raw(),
+            0,
+            null(),
+            null(),
+            1,
+            &program.raw(),
+            None,
+            null_mut(),
+            &mut err,
+        );
+
+        match wrap_result!("clLinkProgram" => err) {
+            Ok(()) => {
+                labels::register_new(linked);
+                Ok(Program(linked, None))
+            }
+            Err(error) => {
+                // the linked program may still be returned, with the link log
+                if linked.is_null() {
+                    Err(self.build_failed(error, &program))
+                } else {
+                    Err(self.build_failed(error, &Program(linked, None)))
+                }
+            }
+        }
+    }
+
+    /// Create an `Error::BuildFailed` for the given error, with the build log
+    /// of the given program and the diagnostics parsed from it
+    fn build_failed(&self, error: ApiError, program: &Program) -> Error {
+        let log = build_log(program);
+        let headers: Vec<&str> = self
+            .headers
+            .iter()
+            .map(|(n, _)| n.to_str().unwrap())
+            .collect();
+        let diagnostics = diagnostics::parse(&log, &self.ty.fragments(), &headers);
+
+        Error::BuildFailed {
+            error,
+            log,
+            diagnostics,
         }
     }
 }
 
+/// Get the build logs of the given program for each of its devices, prefixed
+/// with the device name. Logs which can't be retrieved are skipped.
+fn build_log(program: &Program) -> String {
+    let mut log = String::new();
+
+    for device in program.devices().unwrap_or_default() {
+        let device_log = match program.build_info(device).and_then(|i| i.log()) {
+            Ok(l) if !l.as_bytes().is_empty() => l,
+            _ => continue,
+        };
+
+        let name = device
+            .name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        log += &format!("[{}]\n{}\n", name, device_log.to_string_lossy().trim_end());
+    }
+
+    log
+}
+
 flag_enum! {
     pub enum ProgramBuildStatus(cl_build_status) {
         None = CL_BUILD_NONE,
@@ -0,0 +1,108 @@
+use super::QueueProperties;
+use crate::device::Device;
+use crate::kernel::KernelArg;
+use crate::raw::*;
+use crate::release;
+use crate::util::sealed::OclInfoInternal;
+use crate::Result;
+use libc::size_t;
+use std::ffi::c_void;
+use std::fmt::{self, Debug, Formatter};
+use std::mem::size_of;
+
+/// An OpenCL device-side command queue, created with the `ON_DEVICE` property.
+///
+/// Device queues can only be enqueued to by kernels running on the device, so
+/// unlike `Queue`, this type doesn't provide any host-side commands. It can be
+/// passed to kernels as a `queue_t` argument.
+#[derive(PartialEq, Eq, Hash)]
+pub struct DeviceQueue(pub(crate) cl_command_queue);
+
+unsafe impl Send for DeviceQueue {}
+
+impl Drop for DeviceQueue {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = wrap_result!("clReleaseCommandQueue" => clReleaseCommandQueue(self.0)) {
+                release::report("OpenCL device queue", self, e);
+            }
+        }
+    }
+}
+
+impl Debug for DeviceQueue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.info_fmt(f)
+    }
+}
+
+impl OclInfoInternal for DeviceQueue {
+    type Param = cl_command_queue_info;
+    const DEBUG_CONTEXT: &'static str = "clGetCommandQueueInfo";
+
+    unsafe fn raw_info_internal(
+        &self,
+        param_name: Self::Param,
+        param_value_size: usize,
+        param_value: *mut c_void,
+        param_value_size_ret: *mut usize,
+    ) -> i32 {
+        clGetCommandQueueInfo(
+            self.0,
+            param_name,
+            param_value_size,
+            param_value,
+            param_value_size_ret,
+        )
+    }
+}
+
+impl DeviceQueue {
+    /// Attempt to clone this queue, using `clRetainCommandQueue` to ensure the
+    /// queue is not released while a wrapper still exists.
+    pub fn try_clone(&self) -> Result<Self> {
+        unsafe {
+            wrap_result!("clRetainCommandQueue" => clRetainCommandQueue(self.0))?;
+            Ok(Self(self.0))
+        }
+    }
+
+    /// Get the raw handle for this queue. Note that this handle is only a raw
+    /// pointer and does not use RAII to ensure validity, so you must manually
+    /// make sure that it's not released while still in use.
+    pub fn raw(&self) -> cl_command_queue {
+        self.0
+    }
+
+    info_funcs! {
+        pub fn context_raw(&self) -> cl_context = CL_QUEUE_CONTEXT;
+        pub fn device_raw(&self) -> cl_device_id = CL_QUEUE_DEVICE;
+        pub fn reference_count(&self) -> cl_uint = CL_QUEUE_REFERENCE_COUNT;
+        pub fn properties(&self) -> QueueProperties = CL_QUEUE_PROPERTIES;
+        pub fn size(&self) -> cl_uint = CL_QUEUE_SIZE;
+    }
+
+    pub fn device(&self) -> Result<Device> {
+        self.device_raw().map(Device)
+    }
+
+    /// Check whether this is the default device queue for its device
+    pub fn is_default(&self) -> Result<bool> {
+        Ok(self
+            .properties()?
+            .contains(QueueProperties::ON_DEVICE_DEFAULT))
+    }
+}
+
+// device queues can be used as queue_t kernel args
+impl KernelArg for DeviceQueue {
+    type ArgType = cl_command_queue;
+
+    fn is_param_type_compatible(c_type: &str) -> bool {
+        c_type == "queue_t"
+    }
+
+    fn as_raw_kernel_arg(&self) -> (size_t, &cl_command_queue) {
+        (size_of::<cl_command_queue>(), &self.0)
+    }
+}
@@ -0,0 +1,275 @@
+//! Overlapping compute and transfers
+//!
+//! A `DualQueue` owns two command queues on the same device - one for kernels
+//! and one for buffer transfers - so that copies can run concurrently with
+//! kernels on devices with separate copy engines. Work is submitted through a
+//! `Pipeline`, which enqueues commands without blocking and chains them with
+//! events automatically: each command waits for the previous command using
+//! any of the same buffers, on either queue. For example, with two sets of
+//! buffers used alternately, uploading the input of one batch overlaps with
+//! computing the previous batch:
+//!
+//! ```no_run
+//! # use dynamic_ocl::buffer::Buffer;
+//! # use dynamic_ocl::buffer::flags::HostReadWrite;
+//! # use dynamic_ocl::kernel::Kernel;
+//! # use dynamic_ocl::queue::DualQueue;
+//! # type Buf = Buffer<'static, HostReadWrite, f32>;
+//! # fn f(
+//! #     queues: &DualQueue,
+//! #     inputs: &[Buf; 2],
+//! #     outputs: &[Buf; 2],
+//! #     kernels: &mut [Kernel<(Buf, Buf)>; 2],
+//! #     batches: &[Vec<f32>],
+//! #     results: &mut [Vec<f32>],
+//! # ) -> dynamic_ocl::Result<()> {
+//! // kernels[i] is bound to inputs[i] and outputs[i]
+//! queues.pipeline(|pipeline| {
+//!     for (i, (batch, result)) in batches.iter().zip(results.iter_mut()).enumerate() {
+//!         pipeline.upload(&inputs[i % 2], batch)?;
+//!         pipeline.compute(&mut kernels[i % 2], batch.len())?;
+//!         pipeline.download(&outputs[i % 2], result)?;
+//!     }
+//!
+//!     Ok(())
+//! })
+//! # }
+//! ```
+//!
+//! Since the commands run asynchronously, the buffers and host memory they use
+//! must outlive the call to `DualQueue::pipeline`, which blocks until all
+//! commands have completed before returning (or unwinding).
+
+use super::types::{enqueue_ndrange, NdRange};
+use super::{journal, Queue, WorkDims};
+use crate::buffer::flags::{HostReadable, HostWritable};
+use crate::buffer::{Buffer, MemSafe};
+use crate::context::Context;
+use crate::deterministic;
+use crate::device::Device;
+use crate::event::Event;
+use crate::kernel::{Kernel, KernelArgList};
+use crate::labels;
+use crate::raw::*;
+use crate::release;
+use crate::Result;
+use std::collections::HashMap;
+use std::mem::size_of_val;
+use std::ptr::{null, null_mut};
+
+/// A pair of command queues on the same device, for kernels and transfers
+/// respectively. See the module documentation for details.
+#[derive(Debug)]
+pub struct DualQueue {
+    compute: Queue,
+    transfer: Queue,
+}
+
+impl DualQueue {
+    /// Create a compute queue and a transfer queue on the given device
+    pub fn new(context: &Context, device: Device) -> Result<Self> {
+        Ok(Self {
+            compute: context.create_queue(device)?,
+            transfer: context.create_queue(device)?,
+        })
+    }
+
+    /// Get the queue used for kernels
+    pub fn compute(&self) -> &Queue {
+        &self.compute
+    }
+
+    /// Get the queue used for buffer transfers
+    pub fn transfer(&self) -> &Queue {
+        &self.transfer
+    }
+
+    /// Submit work to these queues through a pipeline passed to the given
+    /// function, then block until all of the submitted commands have
+    /// completed. The commands are also waited for if the function panics.
+    ///
+    /// Errors from the function are returned in preference to errors while
+    /// waiting, which are logged instead.
+    pub fn pipeline<'env, R>(
+        &'env self,
+        f: impl FnOnce(&mut Pipeline<'env>) -> Result<R>,
+    ) -> Result<R> {
+        let mut pipeline = Pipeline {
+            queues: self,
+            last_use: HashMap::new(),
+            pending: vec![],
+        };
+
+        let result = f(&mut pipeline);
+        let waited = pipeline.wait();
+
+        match (result, waited) {
+            (Ok(r), Ok(())) => Ok(r),
+            (Ok(_), Err(e)) => Err(e),
+            (Err(e), waited) => {
+                if let Err(w) = waited {
+                    release::report("pipeline", self, w);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// A sequence of transfers and kernel launches on a `DualQueue`, chained with
+/// events automatically, as passed to the function given to
+/// `DualQueue::pipeline`. Host memory and buffers used by commands are borrowed
+/// for `'env`, which outlives the call to `DualQueue::pipeline`.
+pub struct Pipeline<'env> {
+    queues: &'env DualQueue,
+    /// The event of the last command using each buffer, keyed by raw handle
+    last_use: HashMap<usize, Event>,
+    /// The events of all commands enqueued so far
+    pending: Vec<Event>,
+}
+
+impl<'env> Pipeline<'env> {
+    /// Get the events which a command using the given buffers must wait for
+    fn wait_list(&self, mems: &[cl_mem]) -> Vec<cl_event> {
+        mems.iter()
+            .filter_map(|&m| self.last_use.get(&(m as usize)))
+            .map(Event::raw)
+            .collect()
+    }
+
+    /// Record the event of a command using the given buffers
+    fn record(&mut self, mems: &[cl_mem], event: Event) -> Result<()> {
+        for &mem in mems {
+            self.last_use.insert(mem as usize, event.try_clone()?);
+        }
+
+        self.pending.push(event);
+        Ok(())
+    }
+
+    /// Copy the given data to the start of a buffer on the transfer queue,
+    /// once previous commands using the buffer have completed
+    pub fn upload<H: HostWritable, T: MemSafe>(
+        &mut self,
+        buffer: &'env Buffer<'_, H, T>,
+        data: &'env [T],
+    ) -> Result<&mut Self> {
+        let queue = &self.queues.transfer;
+        let mems = [buffer.raw()];
+        let wait_list = self.wait_list(&mems);
+
+        unsafe {
+            let mut event = null_mut();
+
+            let result = wrap_result!("clEnqueueWriteBuffer" => clEnqueueWriteBuffer(
+                queue.raw(),
+                buffer.raw(),
+                CL_FALSE,
+                0,
+                size_of_val(data),
+                data.as_ptr() as _,
+                wait_list.len() as _,
+                if wait_list.is_empty() { null() } else { wait_list.as_ptr() },
+                &mut event as _
+            ));
+            journal::record(
+                queue,
+                "write buffer",
+                || (None, labels::get(buffer.raw()), vec![size_of_val(data)]),
+                event,
+                result,
+            )?;
+
+            self.record(&mems, Event(event))?;
+        }
+
+        deterministic::sync(queue)?;
+        Ok(self)
+    }
+
+    /// Run a kernel with the given global work size on the compute queue,
+    /// once previous commands using any of its buffer arguments have
+    /// completed.
+    ///
+    /// The arguments are captured when the kernel is enqueued, so the kernel
+    /// may be updated and launched again in the same pipeline.
+    pub fn compute<T: KernelArgList, W: WorkDims>(
+        &mut self,
+        kernel: &mut Kernel<T>,
+        global_work_size: W,
+    ) -> Result<&mut Self> {
+        let queue = &self.queues.compute;
+        let sizes = NdRange::new(queue, kernel, &global_work_size, None, None, false)?;
+
+        let mems = T::buffer_mems(kernel);
+        let wait_list = self.wait_list(&mems);
+        let event =
+            unsafe { enqueue_ndrange(queue, kernel, "ndrange kernel", &sizes, &wait_list)? };
+        self.record(&mems, event)?;
+
+        deterministic::sync(queue)?;
+        Ok(self)
+    }
+
+    /// Copy the start of a buffer into the given slice on the transfer queue,
+    /// once previous commands using the buffer have completed
+    pub fn download<H: HostReadable, T: MemSafe>(
+        &mut self,
+        buffer: &'env Buffer<'_, H, T>,
+        dest: &'env mut [T],
+    ) -> Result<&mut Self> {
+        let queue = &self.queues.transfer;
+        let mems = [buffer.raw()];
+        let wait_list = self.wait_list(&mems);
+        let size = size_of_val(dest);
+
+        unsafe {
+            let mut event = null_mut();
+
+            let result = wrap_result!("clEnqueueReadBuffer" => clEnqueueReadBuffer(
+                queue.raw(),
+                buffer.raw(),
+                CL_FALSE,
+                0,
+                size,
+                dest.as_mut_ptr() as _,
+                wait_list.len() as _,
+                if wait_list.is_empty() { null() } else { wait_list.as_ptr() },
+                &mut event as _
+            ));
+            journal::record(
+                queue,
+                "read buffer",
+                || (None, labels::get(buffer.raw()), vec![size]),
+                event,
+                result,
+            )?;
+
+            self.record(&mems, Event(event))?;
+        }
+
+        deterministic::sync(queue)?;
+        Ok(self)
+    }
+
+    /// Block until all commands in the pipeline have completed
+    fn wait(&mut self) -> Result<()> {
+        self.queues.compute.flush()?;
+        self.queues.transfer.flush()?;
+
+        let result = Event::wait_all(&self.pending.iter().collect::<Vec<_>>());
+        self.pending.clear();
+        self.last_use.clear();
+        result
+    }
+}
+
+impl Drop for Pipeline<'_> {
+    /// Block until all commands have completed, if the function passed to
+    /// `DualQueue::pipeline` panicked
+    fn drop(&mut self) {
+        if let Err(e) = self.wait() {
+            release::report("pipeline", &self.queues, e);
+        }
+    }
+}
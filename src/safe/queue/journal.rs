@@ -0,0 +1,228 @@
+//! Command journals
+//!
+//! When enabled for a command queue, the journal records the most recent
+//! commands enqueued on it through the safe API, along with the events
+//! created for them, so that the commands in flight when a driver returned an
+//! error (e.g. `CL_OUT_OF_RESOURCES`) or hung can be reconstructed. Journals
+//! are dumped to the log automatically when an enqueue fails.
+
+use super::Queue;
+use crate::event::{Event, ExecutionStatus};
+use crate::raw::{clRetainEvent, cl_command_queue, cl_event, cl_int, CL_SUCCESS};
+use crate::{ApiError, Result};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+struct Journal {
+    capacity: usize,
+    entries: VecDeque<JournalEntry>,
+}
+
+lazy_static::lazy_static! {
+    /// Command journals for each command queue, keyed by raw handle
+    static ref JOURNALS: Mutex<HashMap<usize, Journal>> = Default::default();
+}
+
+/// The number of queues with journals enabled, to avoid locking the map when
+/// no journals are enabled
+static ENABLED: AtomicUsize = AtomicUsize::new(0);
+
+/// A command recorded in a queue's journal
+pub struct JournalEntry {
+    command: &'static str,
+    kernel: Option<String>,
+    label: Option<String>,
+    size: Vec<usize>,
+    enqueued: Instant,
+    error: Option<cl_int>,
+    event: Option<Event>,
+}
+
+impl JournalEntry {
+    /// Get a description of the type of command (e.g. `read buffer`)
+    pub fn command(&self) -> &'static str {
+        self.command
+    }
+
+    /// Get the name of the kernel executed by this command, if any
+    pub fn kernel(&self) -> Option<&str> {
+        self.kernel.as_deref()
+    }
+
+    /// Get the label of the kernel or buffer used by this command, if set
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Get the size of the command - the number of bytes transferred for
+    /// buffer commands, or the global work size for kernel commands
+    pub fn size(&self) -> &[usize] {
+        &self.size
+    }
+
+    /// Get the time at which the command was enqueued
+    pub fn enqueued(&self) -> Instant {
+        self.enqueued
+    }
+
+    /// Get the error code returned when enqueueing the command, if it failed
+    pub fn error(&self) -> Option<cl_int> {
+        self.error
+    }
+
+    /// Get the current execution status of the command, or `None` if no
+    /// event was created for it (e.g. for blocking transfers)
+    pub fn status(&self) -> Result<Option<ExecutionStatus>> {
+        self.event
+            .as_ref()
+            .map(|e| e.execution_status())
+            .transpose()
+    }
+
+    fn try_clone(&self) -> Result<Self> {
+        Ok(Self {
+            command: self.command,
+            kernel: self.kernel.clone(),
+            label: self.label.clone(),
+            size: self.size.clone(),
+            enqueued: self.enqueued,
+            error: self.error,
+            event: self.event.as_ref().map(Event::try_clone).transpose()?,
+        })
+    }
+}
+
+impl Debug for JournalEntry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("JournalEntry")
+            .field("command", &self.command)
+            .field("kernel", &self.kernel)
+            .field("label", &self.label)
+            .field("size", &self.size)
+            .field("enqueued", &self.enqueued)
+            .field("error", &self.error)
+            .field("status", &self.status())
+            .finish()
+    }
+}
+
+/// Forget the journal for the given command queue, once the last wrapper for
+/// it has been dropped
+pub(super) fn unregister_queue(queue: cl_command_queue) {
+    if JOURNALS.lock().unwrap().remove(&(queue as usize)).is_some() {
+        ENABLED.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Record a command in the journal for the given queue, if enabled, given the
+/// event created for the command (or null) and the result of enqueueing it.
+/// Details of the command (the kernel name, label, and size) are only computed
+/// if the journal is enabled. If enqueueing the command failed, the journal is
+/// dumped to the log.
+pub(crate) fn record(
+    queue: &Queue,
+    command: &'static str,
+    details: impl FnOnce() -> (Option<String>, Option<String>, Vec<usize>),
+    event: cl_event,
+    result: std::result::Result<(), ApiError>,
+) -> std::result::Result<(), ApiError> {
+    if ENABLED.load(Ordering::Relaxed) == 0 {
+        return result;
+    }
+
+    let mut journals = JOURNALS.lock().unwrap();
+    let journal = match journals.get_mut(&(queue.raw() as usize)) {
+        Some(journal) => journal,
+        None => return result,
+    };
+
+    let event = unsafe {
+        if !event.is_null() && clRetainEvent(event) == CL_SUCCESS {
+            Some(Event(event))
+        } else {
+            None
+        }
+    };
+
+    let (kernel, label, size) = details();
+
+    if journal.entries.len() == journal.capacity {
+        journal.entries.pop_front();
+    }
+
+    journal.entries.push_back(JournalEntry {
+        command,
+        kernel,
+        label,
+        size,
+        enqueued: Instant::now(),
+        error: result.as_ref().err().map(ApiError::code),
+        event,
+    });
+
+    if let Err(e) = &result {
+        log::warn!("Error enqueueing {} command: {:?}", command, e);
+        dump(queue, journal);
+    }
+
+    result
+}
+
+fn dump(queue: &Queue, journal: &Journal) {
+    log::warn!("Recent commands on {:?}:", queue);
+
+    for entry in &journal.entries {
+        log::warn!("    {:?}", entry);
+    }
+}
+
+impl Queue {
+    /// Enable the command journal for this queue, recording the most recent
+    /// `capacity` commands enqueued through the safe API. The journal is
+    /// shared by all wrappers for the same queue. Enabling the journal again
+    /// clears it.
+    pub fn enable_journal(&self, capacity: usize) {
+        assert!(capacity > 0, "journal capacity must be nonzero");
+
+        let journal = Journal {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        };
+
+        if JOURNALS
+            .lock()
+            .unwrap()
+            .insert(self.0 as usize, journal)
+            .is_none()
+        {
+            ENABLED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Disable the command journal for this queue, discarding its entries
+    pub fn disable_journal(&self) {
+        unregister_queue(self.0);
+    }
+
+    /// Get the commands recorded in the journal for this queue, from oldest to
+    /// newest, or `None` if the journal isn't enabled.
+    pub fn journal(&self) -> Result<Option<Vec<JournalEntry>>> {
+        JOURNALS
+            .lock()
+            .unwrap()
+            .get(&(self.0 as usize))
+            .map(|j| j.entries.iter().map(JournalEntry::try_clone).collect())
+            .transpose()
+    }
+
+    /// Log the commands recorded in the journal for this queue at warning
+    /// level, if enabled
+    pub fn dump_journal(&self) {
+        if let Some(journal) = JOURNALS.lock().unwrap().get(&(self.0 as usize)) {
+            dump(self, journal);
+        }
+    }
+}
@@ -3,31 +3,66 @@
 //! In OpenCL, command queues are used to perform operations that involve state,
 //! like interacting with buffers and executing kernels.
 
+mod device;
+mod dual;
+pub(crate) mod journal;
+mod p2p;
+#[cfg(unix)]
+mod printf;
+pub(crate) mod temporaries;
+pub(crate) mod throttle;
 mod types;
 
 use crate::buffer::flags::HostAccess;
 use crate::buffer::{AsBuffer, MemSafe};
+use crate::context::Context;
+use crate::deterministic;
 use crate::device::Device;
 use crate::kernel::{Kernel, KernelArgList};
+use crate::labels;
 use crate::raw::*;
+use crate::release;
+use crate::shutdown;
+use crate::strict;
 use crate::util::sealed::OclInfoInternal;
 use crate::Result;
+pub use device::*;
+pub use dual::*;
+pub use journal::JournalEntry;
+pub use p2p::*;
 use std::ffi::c_void;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
+pub use temporaries::Arena;
+pub use throttle::{Governor, Pressure};
 pub use types::*;
 
 /// An OpenCL command queue
+///
+/// Commands can be enqueued through a shared reference, since OpenCL 1.1+
+/// command queues are thread-safe, so a queue can be shared between threads
+/// (e.g. in an `Arc`) to submit work concurrently. Commands from different
+/// threads are executed in the order they're enqueued, so synchronization
+/// between dependent commands is still up to the caller.
 #[derive(PartialEq, Eq, Hash)]
 pub struct Queue(pub(crate) cl_command_queue);
 
 unsafe impl Send for Queue {}
+unsafe impl Sync for Queue {}
 
 impl Drop for Queue {
     fn drop(&mut self) {
+        if shutdown::unregister_queue(self.0) {
+            temporaries::release_pool(self.0);
+            deterministic::unregister_queue(self.0);
+            journal::unregister_queue(self.0);
+            throttle::unregister_queue(self.0);
+            labels::unregister(self.0);
+        }
+
         unsafe {
             if let Err(e) = wrap_result!("clReleaseCommandQueue" => clReleaseCommandQueue(self.0)) {
-                log::warn!("Error releasing OpenCL command queue {:?}: {:?}", self, e);
+                release::report("OpenCL command queue", self, e);
             }
         }
     }
@@ -35,6 +70,7 @@ impl Drop for Queue {
 
 impl Debug for Queue {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        labels::fmt_prefix(f, self.0)?;
         self.info_fmt(f)
     }
 }
@@ -61,10 +97,17 @@ impl OclInfoInternal for Queue {
 }
 
 impl Queue {
+    /// Wrap the given handle, registering it so that it can be finished by
+    /// `shutdown`.
+    pub(crate) fn new(handle: cl_command_queue) -> Self {
+        shutdown::register_queue(handle);
+        Self(handle)
+    }
+
     pub fn try_clone(&self) -> Result<Self> {
         unsafe {
             wrap_result!("clRetainCommandQueue" => clRetainCommandQueue(self.0))?;
-            Ok(Self(self.0))
+            Ok(Self::new(self.0))
         }
     }
 
@@ -84,7 +127,7 @@ impl Queue {
     /// is dropped (which will implicitly release the handle and decrement the
     /// reference count).
     pub unsafe fn from_raw(handle: cl_command_queue) -> Self {
-        Self(handle)
+        Self::new(handle)
     }
 
     info_funcs! {
@@ -92,40 +135,104 @@ impl Queue {
         pub fn device_raw(&self) -> cl_device_id = CL_QUEUE_DEVICE;
         pub fn reference_count(&self) -> cl_uint = CL_QUEUE_REFERENCE_COUNT;
         pub fn properties(&self) -> QueueProperties = CL_QUEUE_PROPERTIES;
-        pub fn size(&self) -> cl_uint = CL_QUEUE_SIZE;
+        fn size_raw(&self) -> cl_uint = CL_QUEUE_SIZE;
         pub fn device_default_raw(&self) -> cl_command_queue = CL_QUEUE_DEVICE_DEFAULT;
     }
 
+    /// Check whether this is a device-side queue. Queues created through the
+    /// safe API are always host-side queues, but queues wrapped with
+    /// `from_raw` may not be.
+    pub fn is_on_device(&self) -> Result<bool> {
+        Ok(self.properties()?.contains(QueueProperties::ON_DEVICE))
+    }
+
+    /// Get the size of this queue, or `None` for host-side queues, which don't
+    /// have a size.
+    pub fn size(&self) -> Result<Option<cl_uint>> {
+        if self.is_on_device()? {
+            self.size_raw().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn device(&self) -> Result<Device> {
         self.device_raw().map(Device)
     }
 
-    pub fn device_default(&self) -> Result<Queue> {
-        self.device_default_raw().map(Queue)
+    /// Get the context this queue was created in
+    pub fn context(&self) -> Result<Context> {
+        let handle = self.context_raw()?;
+
+        unsafe {
+            wrap_result!("clRetainContext" => clRetainContext(handle))?;
+            Ok(Context(handle))
+        }
+    }
+
+    /// Get the default device-side queue for this queue's device, if one has
+    /// been created.
+    pub fn device_default(&self) -> Result<Option<DeviceQueue>> {
+        let handle = self.device_default_raw()?;
+
+        if handle.is_null() {
+            return Ok(None);
+        }
+
+        unsafe {
+            wrap_result!("clRetainCommandQueue" => clRetainCommandQueue(handle))?;
+            Ok(Some(DeviceQueue(handle)))
+        }
+    }
+
+    /// Issue all previously queued commands to the device, without waiting
+    /// for them to complete.
+    pub fn flush(&self) -> Result<()> {
+        unsafe {
+            wrap_result!("clFlush" => clFlush(self.0))?;
+            Ok(())
+        }
+    }
+
+    /// Block until all previously queued commands have completed.
+    pub fn finish(&self) -> Result<()> {
+        unsafe {
+            wrap_result!("clFinish" => clFinish(self.0))?;
+            Ok(())
+        }
     }
 
     /// Begin a new buffer command
     pub fn buffer_cmd<'q, 'a, H: HostAccess, T: MemSafe>(
-        &'q mut self,
+        &'q self,
         buffer: &'q mut dyn AsBuffer<'a, H, T>,
     ) -> BufferCmd<'q, 'a, H, T> {
+        strict::check(self, CL_QUEUE_REFERENCE_COUNT, "command queue");
+        strict::check(buffer.as_buffer(), CL_MEM_REFERENCE_COUNT, "buffer");
+
         BufferCmd {
             queue: self,
             buffer,
             offset: None,
+            progress: None,
         }
     }
 
     /// Begin a new kernel execution command
     pub fn kernel_cmd<'q, T: KernelArgList, W: WorkDims>(
-        &'q mut self,
+        &'q self,
         kernel: &'q mut Kernel<T>,
     ) -> KernelCmd<'q, T, W> {
+        strict::check(self, CL_QUEUE_REFERENCE_COUNT, "command queue");
+        strict::check(kernel, CL_KERNEL_REFERENCE_COUNT, "kernel");
+
         KernelCmd {
             queue: self,
             kernel,
             global_work_offset: None,
             local_work_size: None,
+            round_global_work_size: false,
+            events: vec![],
         }
     }
 }
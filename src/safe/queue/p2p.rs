@@ -0,0 +1,245 @@
+//! Copies between contexts
+//!
+//! Buffers can't be shared between contexts, so on multi-GPU systems where
+//! each device lives in its own context, data has to be copied between them
+//! explicitly. `copy_between_contexts` uses a direct peer-to-peer copy where
+//! both devices support one (AMD's `cl_amd_copy_buffer_p2p`, with the
+//! `amd-p2p` feature enabled), and otherwise bounces the data through pinned
+//! host memory in chunks, overlapping reads from the source buffer with writes
+//! to the destination buffer.
+
+use super::{journal, Queue};
+use crate::buffer::flags::{HostReadWrite, HostReadable, HostWritable};
+use crate::buffer::{Buffer, MemSafe};
+use crate::dependencies;
+use crate::event::Event;
+use crate::labels;
+use crate::raw::*;
+use crate::Result;
+use std::ptr::{null, null_mut};
+
+/// The size of each chunk copied through host memory, with two chunks in
+/// flight at a time
+const CHUNK_SIZE: usize = 8 << 20;
+
+/// The method used by `copy_between_contexts`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CopyPath {
+    /// A direct copy between devices, using a vendor extension
+    PeerToPeer,
+    /// A copy through pinned host memory
+    HostBounce,
+}
+
+/// Copy the contents of a buffer into the start of a buffer in another
+/// context, using the best available method, and blocking until complete.
+/// The source queue must be in the context of the source buffer, and likewise
+/// for the destination. See the module documentation for details.
+///
+/// Panics if the destination buffer is smaller than the source buffer.
+pub fn copy_between_contexts<H1: HostReadable, H2: HostWritable, T: MemSafe>(
+    src: &Buffer<'_, H1, T>,
+    src_queue: &Queue,
+    dst: &mut Buffer<'_, H2, T>,
+    dst_queue: &Queue,
+) -> Result<CopyPath> {
+    let size = src.rust_size();
+    assert!(
+        dst.rust_size() >= size,
+        "destination buffer of {} bytes is smaller than source buffer of {} bytes",
+        dst.rust_size(),
+        size
+    );
+
+    dependencies::wait_for_writes(&[src.raw(), dst.raw()])?;
+
+    #[cfg(feature = "amd-p2p")]
+    {
+        if copy_p2p(src.raw(), src_queue, dst.raw(), dst_queue, size)? {
+            return Ok(CopyPath::PeerToPeer);
+        }
+    }
+
+    copy_host_bounce(src.raw(), src_queue, dst.raw(), dst_queue, size)?;
+    Ok(CopyPath::HostBounce)
+}
+
+/// Copy between buffers with `cl_amd_copy_buffer_p2p`, returning `false`
+/// without copying if either device doesn't support it
+#[cfg(feature = "amd-p2p")]
+fn copy_p2p(
+    src: cl_mem,
+    src_queue: &Queue,
+    dst: cl_mem,
+    dst_queue: &Queue,
+    size: usize,
+) -> Result<bool> {
+    use crate::raw::ext::cl_amd_copy_buffer_p2p::Functions;
+
+    const EXTENSION: &str = "cl_amd_copy_buffer_p2p";
+
+    let src_device = src_queue.device()?;
+    if !src_device.has_extension(EXTENSION)? || !dst_queue.device()?.has_extension(EXTENSION)? {
+        return Ok(false);
+    }
+
+    let functions = match unsafe { Functions::load(src_device.platform()?.raw()) } {
+        Some(functions) => functions,
+        None => return Ok(false),
+    };
+
+    unsafe {
+        let mut event = null_mut();
+
+        let result = wrap_result!("clEnqueueCopyBufferP2PAMD" => (functions.clEnqueueCopyBufferP2PAMD)(
+            src_queue.raw(),
+            src,
+            dst,
+            0,
+            0,
+            size,
+            0,
+            null(),
+            &mut event as _
+        ));
+        journal::record(
+            src_queue,
+            "copy buffer p2p",
+            || (None, labels::get(src), vec![size]),
+            event,
+            result,
+        )?;
+
+        let result = wrap_result!("clWaitForEvents" => clWaitForEvents(1, &event as _));
+        clReleaseEvent(event);
+        result?;
+    }
+
+    Ok(true)
+}
+
+/// Copy between buffers through a pinned staging buffer in the source
+/// context, with two chunks in flight so that reading one chunk overlaps with
+/// writing the previous one
+fn copy_host_bounce(
+    src: cl_mem,
+    src_queue: &Queue,
+    dst: cl_mem,
+    dst_queue: &Queue,
+    size: usize,
+) -> Result<()> {
+    if size == 0 {
+        return Ok(());
+    }
+
+    let chunk = CHUNK_SIZE.min(size);
+    let staging: Buffer<'static, HostReadWrite, u8> = src_queue
+        .context()?
+        .buffer_builder()
+        .host_access::<HostReadWrite>()
+        .alloc_host_ptr()
+        .build_with_size(chunk * 2)?;
+
+    unsafe {
+        let mut err = CL_SUCCESS;
+        let host = clEnqueueMapBuffer(
+            src_queue.raw(),
+            staging.raw(),
+            CL_BLOCKING,
+            CL_MAP_READ | CL_MAP_WRITE,
+            0,
+            chunk * 2,
+            0,
+            null(),
+            null_mut(),
+            &mut err as _,
+        ) as *mut u8;
+        wrap_result!("clEnqueueMapBuffer" => err)?;
+
+        let mut writes: [Option<Event>; 2] = [None, None];
+
+        let result = (|| -> Result<()> {
+            for (i, offset) in (0..size).step_by(chunk).enumerate() {
+                let len = chunk.min(size - offset);
+                let slot = i & 1;
+                let ptr = host.add(slot * chunk);
+
+                // the staging slot can only be reused once its last write
+                // has completed
+                if let Some(write) = writes[slot].take() {
+                    write.wait()?;
+                }
+
+                let mut event = null_mut();
+                let result = wrap_result!("clEnqueueReadBuffer" => clEnqueueReadBuffer(
+                    src_queue.raw(),
+                    src,
+                    CL_BLOCKING,
+                    offset,
+                    len,
+                    ptr as _,
+                    0,
+                    null(),
+                    &mut event as _
+                ));
+                journal::record(
+                    src_queue,
+                    "read buffer",
+                    || (None, labels::get(src), vec![len]),
+                    event,
+                    result,
+                )?;
+                clReleaseEvent(event);
+
+                // events can't be waited on across contexts, so the write is
+                // only enqueued once the read has completed on the host
+                let mut event = null_mut();
+                let result = wrap_result!("clEnqueueWriteBuffer" => clEnqueueWriteBuffer(
+                    dst_queue.raw(),
+                    dst,
+                    CL_NON_BLOCKING,
+                    offset,
+                    len,
+                    ptr as _,
+                    0,
+                    null(),
+                    &mut event as _
+                ));
+                journal::record(
+                    dst_queue,
+                    "write buffer",
+                    || (None, labels::get(dst), vec![len]),
+                    event,
+                    result,
+                )?;
+                writes[slot] = Some(Event(event));
+                dst_queue.flush()?;
+            }
+
+            Ok(())
+        })();
+
+        // pending writes must complete before the staging memory is unmapped,
+        // even if the copy failed
+        let pending: Vec<&Event> = writes.iter().flatten().collect();
+        let waited = Event::wait_all(&pending);
+
+        let mut event = null_mut();
+        wrap_result!("clEnqueueUnmapMemObject" => clEnqueueUnmapMemObject(
+            src_queue.raw(),
+            staging.raw(),
+            host as _,
+            0,
+            null(),
+            &mut event as _
+        ))?;
+        let unmapped = wrap_result!("clWaitForEvents" => clWaitForEvents(1, &event as _));
+        clReleaseEvent(event);
+
+        result?;
+        waited?;
+        unmapped?;
+    }
+
+    Ok(())
+}
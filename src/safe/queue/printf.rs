@@ -0,0 +1,84 @@
+//! Capturing of kernel `printf` output
+//!
+//! OpenCL implementations write `printf` output from kernels directly to the
+//! process's standard output. To capture it, standard output is temporarily
+//! redirected to a pipe, which is drained by a background thread so that large
+//! amounts of output can't block the implementation.
+
+use libc::{c_int, STDOUT_FILENO};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::ptr::null_mut;
+use std::thread::{self, JoinHandle};
+
+/// An active redirection of standard output
+pub(super) struct PrintfCapture {
+    saved_fd: c_int,
+    reader: JoinHandle<io::Result<Vec<u8>>>,
+}
+
+impl PrintfCapture {
+    /// Begin redirecting standard output, reserving `capacity` bytes for the
+    /// captured output.
+    pub fn start(capacity: usize) -> io::Result<Self> {
+        io::stdout().flush()?;
+
+        unsafe {
+            libc::fflush(null_mut());
+
+            let mut fds = [0; 2];
+            if libc::pipe(fds.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let [read_fd, write_fd] = fds;
+
+            let saved_fd = libc::dup(STDOUT_FILENO);
+            if saved_fd < 0 || libc::dup2(write_fd, STDOUT_FILENO) < 0 {
+                let err = io::Error::last_os_error();
+                if saved_fd >= 0 {
+                    libc::close(saved_fd);
+                }
+                libc::close(read_fd);
+                libc::close(write_fd);
+                return Err(err);
+            }
+
+            // standard output now holds the only write end of the pipe
+            libc::close(write_fd);
+
+            let mut pipe = File::from_raw_fd(read_fd);
+            let reader = thread::spawn(move || {
+                let mut buf = Vec::with_capacity(capacity);
+                pipe.read_to_end(&mut buf)?;
+                Ok(buf)
+            });
+
+            Ok(Self { saved_fd, reader })
+        }
+    }
+
+    /// Restore standard output, returning everything written while it was
+    /// redirected.
+    pub fn finish(self) -> io::Result<Vec<u8>> {
+        let _ = io::stdout().flush();
+
+        unsafe {
+            libc::fflush(null_mut());
+
+            // replacing standard output closes the write end of the pipe,
+            // allowing the reader to finish
+            let restored = libc::dup2(self.saved_fd, STDOUT_FILENO);
+            let err = io::Error::last_os_error();
+            libc::close(self.saved_fd);
+
+            if restored < 0 {
+                return Err(err);
+            }
+        }
+
+        self.reader
+            .join()
+            .unwrap_or_else(|_| panic!("printf capture thread panicked"))
+    }
+}
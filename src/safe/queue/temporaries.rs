@@ -0,0 +1,111 @@
+//! Scoped temporary buffers
+//!
+//! Scratch buffers handed out by an `Arena` are taken from a pool kept for
+//! each command queue, and returned to the pool once the arena is dropped, so
+//! that loops which need the same scratch space each iteration don't allocate
+//! and release it every time. Pooled buffers are released once the last
+//! wrapper for their queue is dropped, or by `Queue::release_temporaries`.
+
+use super::Queue;
+use crate::buffer::flags::HostNoAccess;
+use crate::buffer::{Buffer, MemSafe};
+use crate::context::Context;
+use crate::raw::{clRetainMemObject, cl_command_queue};
+use crate::Result;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::Mutex;
+
+type Storage = Buffer<'static, HostNoAccess, u8>;
+
+lazy_static::lazy_static! {
+    /// Unused scratch buffers for each command queue, keyed by raw handle
+    static ref POOLS: Mutex<HashMap<usize, Vec<Storage>>> = Default::default();
+}
+
+/// Release all pooled scratch buffers for the given command queue
+pub(super) fn release_pool(queue: cl_command_queue) {
+    POOLS.lock().unwrap().remove(&(queue as usize));
+}
+
+/// Release all pooled scratch buffers for every command queue
+pub(crate) fn release_all_pools() {
+    POOLS.lock().unwrap().clear();
+}
+
+/// A source of scratch buffers for the duration of `Queue::with_temporaries`
+///
+/// Buffers handed out by the arena can't outlive it, and their storage is
+/// returned to the queue's pool once the arena is dropped. Since their
+/// contents may be overwritten by later users of the pool, they should only
+/// be used by commands enqueued on the same in-order queue.
+pub struct Arena {
+    queue: usize,
+    context: Context,
+    taken: RefCell<Vec<Storage>>,
+}
+
+impl Arena {
+    /// Get a scratch buffer with space for `len` elements of type `T`,
+    /// reusing pooled storage of the same size if available. The initial
+    /// contents of the buffer are unspecified.
+    pub fn buffer<T: MemSafe>(&self, len: usize) -> Result<Buffer<'_, HostNoAccess, T>> {
+        let size = len * size_of::<T>();
+
+        let pooled = POOLS.lock().unwrap().get_mut(&self.queue).and_then(|pool| {
+            pool.iter()
+                .position(|b| b.rust_size() == size)
+                .map(|i| pool.swap_remove(i))
+        });
+
+        let storage = match pooled {
+            Some(storage) => storage,
+            None => self
+                .context
+                .buffer_builder()
+                .host_access::<HostNoAccess>()
+                .build_with_size::<u8>(size)?,
+        };
+
+        unsafe {
+            let retained = wrap_result!("clRetainMemObject" => clRetainMemObject(storage.raw()));
+            let handle = storage.raw();
+            self.taken.borrow_mut().push(storage);
+            retained?;
+
+            Ok(Buffer::from_raw_parts(handle, size))
+        }
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        POOLS
+            .lock()
+            .unwrap()
+            .entry(self.queue)
+            .or_default()
+            .append(self.taken.get_mut());
+    }
+}
+
+impl Queue {
+    /// Run the given function with an arena providing scratch buffers, which
+    /// are returned to a pool kept for this queue once the function returns,
+    /// and reused by later calls.
+    pub fn with_temporaries<R>(&self, f: impl FnOnce(&Queue, &Arena) -> R) -> Result<R> {
+        let arena = Arena {
+            queue: self.0 as usize,
+            context: self.context()?,
+            taken: RefCell::new(vec![]),
+        };
+
+        Ok(f(self, &arena))
+    }
+
+    /// Release all pooled scratch buffers for this queue
+    pub fn release_temporaries(&self) {
+        release_pool(self.0);
+    }
+}
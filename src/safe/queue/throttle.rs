@@ -0,0 +1,207 @@
+//! Thermal throttling
+//!
+//! A `Governor` attached to a command queue slows down work submitted through
+//! the safe API when a user-provided callback reports thermal (or power)
+//! pressure, which keeps long-running compute jobs from overheating laptops or
+//! draining their batteries. Under pressure, kernel launches are delayed, and
+//! chunked transfers use smaller chunks.
+//!
+//! Neither core OpenCL nor the Intel and AMD device attribute query
+//! extensions report thermal state, so pressure must be measured by the
+//! application, e.g. from platform sensors or battery status.
+
+use super::Queue;
+use crate::raw::cl_command_queue;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The thermal pressure reported by a governor's callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Pressure {
+    /// No throttling is needed
+    Nominal,
+    /// Work should be slowed down slightly
+    Moderate,
+    /// Work should be slowed down significantly
+    Serious,
+    /// Work should be slowed down as much as possible
+    Critical,
+}
+
+impl Pressure {
+    fn index(self) -> usize {
+        match self {
+            Pressure::Nominal => 0,
+            Pressure::Moderate => 1,
+            Pressure::Serious => 2,
+            Pressure::Critical => 3,
+        }
+    }
+}
+
+type Callback = dyn Fn() -> Pressure + Send + Sync;
+
+/// A policy for throttling work submitted to a command queue under thermal
+/// pressure. See the module documentation for details.
+pub struct Governor {
+    callback: Box<Callback>,
+    interval: Duration,
+    delays: [Duration; 4],
+    divisors: [usize; 4],
+    sample: Mutex<Option<(Instant, Pressure)>>,
+}
+
+impl Debug for Governor {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Governor")
+            .field("interval", &self.interval)
+            .field("delays", &self.delays)
+            .field("divisors", &self.divisors)
+            .finish()
+    }
+}
+
+impl Governor {
+    /// Create a governor using the given callback to measure thermal
+    /// pressure.
+    ///
+    /// By default, the callback is called at most once per second, kernel
+    /// launches are delayed by 1ms, 10ms, or 100ms under moderate, serious, or
+    /// critical pressure respectively, and transfer chunks are reduced to
+    /// 1/2, 1/4, or 1/8 of their size.
+    pub fn new(callback: impl Fn() -> Pressure + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Box::new(callback),
+            interval: Duration::from_secs(1),
+            delays: [
+                Duration::from_millis(0),
+                Duration::from_millis(1),
+                Duration::from_millis(10),
+                Duration::from_millis(100),
+            ],
+            divisors: [1, 2, 4, 8],
+            sample: Mutex::new(None),
+        }
+    }
+
+    /// Set the minimum interval between calls to the callback. Pressure
+    /// reported by the callback is reused until the interval has elapsed.
+    pub fn interval(self, interval: Duration) -> Self {
+        Self { interval, ..self }
+    }
+
+    /// Set the delay before each kernel launch under the given pressure
+    pub fn delay(mut self, pressure: Pressure, delay: Duration) -> Self {
+        self.delays[pressure.index()] = delay;
+        self
+    }
+
+    /// Set the factor by which batch sizes are divided under the given
+    /// pressure
+    pub fn batch_divisor(mut self, pressure: Pressure, divisor: usize) -> Self {
+        assert!(divisor > 0, "batch divisor must be nonzero");
+        self.divisors[pressure.index()] = divisor;
+        self
+    }
+
+    /// Get the current pressure, calling the callback if the last sample is
+    /// older than the interval
+    pub fn pressure(&self) -> Pressure {
+        let mut sample = self.sample.lock().unwrap();
+
+        match *sample {
+            Some((time, pressure)) if time.elapsed() < self.interval => pressure,
+            _ => {
+                let pressure = (self.callback)();
+                *sample = Some((Instant::now(), pressure));
+                pressure
+            }
+        }
+    }
+
+    /// Sleep for the delay configured for the current pressure
+    pub fn pace(&self) {
+        let delay = self.delays[self.pressure().index()];
+
+        if delay > Duration::from_millis(0) {
+            std::thread::sleep(delay);
+        }
+    }
+
+    /// Reduce the given batch size according to the current pressure. The
+    /// result is always at least 1.
+    pub fn batch_size(&self, size: usize) -> usize {
+        (size / self.divisors[self.pressure().index()]).max(1)
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Governors attached to each command queue, keyed by raw handle
+    static ref GOVERNORS: Mutex<HashMap<usize, Arc<Governor>>> = Default::default();
+}
+
+/// The number of queues with governors, to avoid locking the map when no
+/// governors are attached
+static ENABLED: AtomicUsize = AtomicUsize::new(0);
+
+fn governor(queue: &Queue) -> Option<Arc<Governor>> {
+    if ENABLED.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+
+    GOVERNORS.lock().unwrap().get(&(queue.0 as usize)).cloned()
+}
+
+/// Delay a kernel launch on the given queue according to its governor, if any
+pub(crate) fn pace(queue: &Queue) {
+    if let Some(governor) = governor(queue) {
+        governor.pace();
+    }
+}
+
+/// Reduce a batch size for the given queue according to its governor, if any.
+/// A size of zero is left unchanged, so that it can still be rejected.
+pub(crate) fn batch_size(queue: &Queue, size: usize) -> usize {
+    match governor(queue) {
+        Some(governor) if size > 0 => governor.batch_size(size),
+        _ => size,
+    }
+}
+
+/// Detach the governor from the given command queue, once the last wrapper for
+/// it has been dropped
+pub(super) fn unregister_queue(queue: cl_command_queue) {
+    if GOVERNORS
+        .lock()
+        .unwrap()
+        .remove(&(queue as usize))
+        .is_some()
+    {
+        ENABLED.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Queue {
+    /// Attach a governor to this queue, throttling kernel launches and chunked
+    /// transfers enqueued through the safe API under thermal pressure. The
+    /// governor is shared by all wrappers for the same queue, and replaces any
+    /// existing governor.
+    pub fn set_governor(&self, governor: Arc<Governor>) {
+        if GOVERNORS
+            .lock()
+            .unwrap()
+            .insert(self.0 as usize, governor)
+            .is_none()
+        {
+            ENABLED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Detach the governor from this queue, if any
+    pub fn clear_governor(&self) {
+        unregister_queue(self.0);
+    }
+}
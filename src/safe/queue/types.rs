@@ -1,15 +1,25 @@
 use crate::buffer::flags::HostAccess;
-use crate::buffer::MemSafe;
+use crate::buffer::{map, MemSafe};
 use crate::context::Context;
+use crate::dependencies;
+use crate::deterministic;
 use crate::device::Device;
-use crate::kernel::{Kernel, KernelArgList, KernelInfo};
-use crate::queue::Queue;
+use crate::event::Event;
+use crate::kernel::{Kernel, KernelArg, KernelArgList, KernelInfo, LocalSize};
+use crate::labels;
+use crate::queue::{journal, throttle, DeviceQueue, Queue};
 use crate::raw::*;
+use crate::replay::LaunchRecord;
 use crate::safe::buffer::flags::{HostReadable, HostWritable};
 use crate::safe::buffer::AsBuffer;
-use crate::Result;
-use std::mem::size_of_val;
+use crate::validation;
+use crate::{Error, Result};
+use std::ffi::c_void;
+use std::mem::{size_of, size_of_val};
+use std::ops::Range;
 use std::ptr::{null, null_mut};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 bitfield! {
     /// Special command queue properties
@@ -21,14 +31,20 @@ bitfield! {
     }
 }
 
+impl QueueProperties {
+    /// A queue properties bitfield with no fields set
+    pub const EMPTY: QueueProperties = QueueProperties(0);
+}
+
 /// A partially built command queue
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 #[must_use]
 pub struct QueueBuilder<'c, 'd> {
     context: &'c Context,
     device: &'d Device,
     properties: Option<QueueProperties>,
     size: Option<cl_uint>,
+    extra_properties: Vec<cl_queue_properties>,
 }
 
 impl<'c, 'd> QueueBuilder<'c, 'd> {
@@ -39,6 +55,7 @@ impl<'c, 'd> QueueBuilder<'c, 'd> {
             device,
             properties: None,
             size: None,
+            extra_properties: vec![],
         }
     }
 
@@ -58,55 +75,119 @@ impl<'c, 'd> QueueBuilder<'c, 'd> {
         }
     }
 
-    /// Build the command queue, calling either `clCreateCommandQueue` or
-    /// `clCreateCommandQueueWithProperties` depending on builder parameters and
-    /// system OpenCL version.
+    /// Add a property (e.g. from an extension) to pass to
+    /// `clCreateCommandQueueWithProperties` when creating this queue, in
+    /// addition to the properties and size set with `properties` and `size`.
+    /// Requires OpenCL 2.0+.
+    pub fn property(mut self, key: cl_queue_properties, value: cl_queue_properties) -> Self {
+        self.extra_properties.extend_from_slice(&[key, value]);
+        self
+    }
+
+    /// Get the zero-terminated property list for
+    /// `clCreateCommandQueueWithProperties`
+    fn raw_properties(&self, props: QueueProperties) -> Vec<cl_queue_properties> {
+        let mut raw_props = vec![CL_QUEUE_PROPERTIES as cl_queue_properties, props.raw()];
+        if let Some(size) = self.size {
+            raw_props.extend_from_slice(&[CL_QUEUE_SIZE as _, size as _]);
+        }
+        raw_props.extend_from_slice(&self.extra_properties);
+        raw_props.push(0);
+        raw_props
+    }
+
+    /// Create a command queue with the given property list, calling
+    /// `clCreateCommandQueueWithProperties`
+    unsafe fn create_with_properties(
+        &self,
+        raw_props: &[cl_queue_properties],
+    ) -> Result<cl_command_queue> {
+        check_ocl_version!("clCreateCommandQueueWithProperties" => CL20)?;
+
+        let mut err = CL_SUCCESS;
+
+        let queue = clCreateCommandQueueWithProperties(
+            self.context.raw(),
+            self.device.raw(),
+            raw_props.as_ptr(),
+            &mut err as _,
+        );
+
+        wrap_result!("clCreateCommandQueueWithProperties" => err)?;
+        Ok(queue)
+    }
+
+    /// Build the command queue, calling `clCreateCommandQueueWithProperties`
+    /// on OpenCL 2.0+, or the deprecated `clCreateCommandQueue` otherwise.
+    /// Properties added with `property` require OpenCL 2.0+.
+    ///
+    /// Device-side queues must be built with `build_on_device` instead, so
+    /// this will panic if the `ON_DEVICE` property or a queue size is set.
+    /// If deterministic mode is enabled globally, the
+    /// `OUT_OF_ORDER_EXEC_MODE_ENABLE` property is ignored.
     pub fn build(self) -> Result<Queue> {
+        let mut props = self.properties.unwrap_or(QueueProperties::EMPTY);
+
+        if deterministic::is_enabled() {
+            props = QueueProperties(props.0 & !CL_QUEUE_OUT_OF_ORDER_EXEC_MODE_ENABLE);
+        }
+
+        assert!(
+            !props.contains(QueueProperties::ON_DEVICE),
+            "queues with property ON_DEVICE must be built with build_on_device"
+        );
+        assert!(
+            self.size.is_none(),
+            "cannot set queue size unless queue property ON_DEVICE is set"
+        );
+
         unsafe {
+            if SYSTEM_OPENCL_VERSION >= OpenCLVersion::CL20 || !self.extra_properties.is_empty() {
+                let queue = self.create_with_properties(&self.raw_properties(props))?;
+                return Ok(Queue::new(queue));
+            }
+
             let mut err = CL_SUCCESS;
 
-            let queue = match (self.properties, self.size) {
-                (Some(props), Some(size)) if props.contains(QueueProperties::ON_DEVICE) => {
-                    check_ocl_version!("clCreateCommandQueueWithProperties" => CL20)?;
+            let queue = clCreateCommandQueue(
+                self.context.raw(),
+                self.device.raw(),
+                props.raw(),
+                &mut err as _,
+            );
 
-                    let props = [
-                        CL_QUEUE_PROPERTIES as _,
-                        props.raw(),
-                        CL_QUEUE_SIZE as _,
-                        size as _,
-                        0,
-                    ];
+            wrap_result!("clCreateCommandQueue" => err)?;
+            Ok(Queue::new(queue))
+        }
+    }
 
-                    clCreateCommandQueueWithProperties(
-                        self.context.raw(),
-                        self.device.raw(),
-                        props.as_ptr(),
-                        &mut err as _,
-                    )
-                }
-                (_, Some(_)) => {
-                    panic!("cannot set queue size unless queue property ON_DEVICE is set")
-                }
-                (props, None) => clCreateCommandQueue(
-                    self.context.raw(),
-                    self.device.raw(),
-                    props.map(|p| p.raw()).unwrap_or_default(),
-                    &mut err as _,
-                ),
-            };
+    /// Build a device-side command queue, calling
+    /// `clCreateCommandQueueWithProperties`. The `ON_DEVICE` and
+    /// `OUT_OF_ORDER_EXEC_MODE_ENABLE` properties are always set, as required
+    /// for device queues. Requires OpenCL 2.0+.
+    pub fn build_on_device(self) -> Result<DeviceQueue> {
+        let props = self.properties.unwrap_or(QueueProperties::EMPTY)
+            | QueueProperties::ON_DEVICE
+            | QueueProperties::OUT_OF_ORDER_EXEC_MODE_ENABLE;
 
-            wrap_result!("clCreateCommandQueue" => err)?;
-            Ok(Queue(queue))
+        unsafe {
+            let queue = self.create_with_properties(&self.raw_properties(props))?;
+            Ok(DeviceQueue(queue))
         }
     }
 }
 
+/// A callback reporting the progress of a transfer, as set by
+/// `BufferCmd::on_progress`
+type Progress<'p> = Box<dyn FnMut(usize, usize) + 'p>;
+
 /// A partially built command to interact with a buffer
 #[must_use]
 pub struct BufferCmd<'q, 'a, H: HostAccess, T: MemSafe> {
     pub(super) queue: &'q Queue,
     pub(super) buffer: &'q mut dyn AsBuffer<'a, H, T>,
     pub(super) offset: Option<usize>,
+    pub(super) progress: Option<Progress<'q>>,
 }
 
 impl<'q, 'a, H: HostAccess, T: MemSafe> BufferCmd<'q, 'a, H, T> {
@@ -120,13 +201,31 @@ impl<'q, 'a, H: HostAccess, T: MemSafe> BufferCmd<'q, 'a, H, T> {
         }
     }
 
+    /// Set a callback to report the progress of chunked and multi-range
+    /// transfers, which is called with the number of bytes transferred so far
+    /// and the total number of bytes each time a chunk completes. This allows
+    /// progress to be shown for large transfers (e.g. `write_chunked`), which
+    /// otherwise block without feedback until complete.
+    ///
+    /// Other commands don't report progress.
+    pub fn on_progress(self, progress: impl FnMut(usize, usize) + 'q) -> Self {
+        Self {
+            progress: Some(Box::new(progress)),
+            ..self
+        }
+    }
+
     /// Perform a blocking read of the buffer into the given slice.
     pub fn read(self, dest: &mut [T]) -> Result<()>
     where
         H: HostReadable,
     {
+        dependencies::wait_for_writes(&[self.buffer.as_buffer().raw()])?;
+
+        let size = size_of_val(dest);
+
         unsafe {
-            wrap_result!("clEnqueueReadBuffer" => clEnqueueReadBuffer(
+            let result = wrap_result!("clEnqueueReadBuffer" => clEnqueueReadBuffer(
                 self.queue.raw(),
                 self.buffer.as_buffer().raw(),
                 CL_TRUE,
@@ -136,10 +235,17 @@ impl<'q, 'a, H: HostAccess, T: MemSafe> BufferCmd<'q, 'a, H, T> {
                 0,
                 null_mut(),
                 null_mut()
-            ))?;
-
-            Ok(())
+            ));
+            journal::record(
+                self.queue,
+                "read buffer",
+                || (None, labels::get(self.buffer.as_buffer().raw()), vec![size]),
+                null_mut(),
+                result,
+            )?;
         }
+
+        deterministic::sync(self.queue)
     }
 
     /// Perform a blocking write of the buffer into the given slice.
@@ -147,8 +253,10 @@ impl<'q, 'a, H: HostAccess, T: MemSafe> BufferCmd<'q, 'a, H, T> {
     where
         H: HostWritable,
     {
+        dependencies::wait_for_writes(&[self.buffer.as_buffer().raw()])?;
+
         unsafe {
-            wrap_result!("clEnqueueWriteBuffer" => clEnqueueWriteBuffer(
+            let result = wrap_result!("clEnqueueWriteBuffer" => clEnqueueWriteBuffer(
                 self.queue.raw(),
                 self.buffer.as_buffer().raw(),
                 CL_TRUE,
@@ -158,36 +266,630 @@ impl<'q, 'a, H: HostAccess, T: MemSafe> BufferCmd<'q, 'a, H, T> {
                 0,
                 null_mut(),
                 null_mut(),
-            ))?;
-
-            Ok(())
+            ));
+            journal::record(
+                self.queue,
+                "write buffer",
+                || {
+                    (
+                        None,
+                        labels::get(self.buffer.as_buffer().raw()),
+                        vec![size_of_val(src)],
+                    )
+                },
+                null_mut(),
+                result,
+            )?;
         }
+
+        deterministic::sync(self.queue)
     }
 
-    /// Fill the buffer with the given pattern, blocking until completion.
-    pub fn fill(self, pattern: &T) -> Result<()> {
-        unsafe {
+    /// Map the buffer (from the offset, if set, to the end) for reading and
+    /// writing on the host, run the given function on its contents, then
+    /// unmap it so that any changes are visible to the device. Blocks until
+    /// the buffer has been mapped and unmapped.
+    ///
+    /// This replaces reading the buffer into a temporary `Vec`, modifying it,
+    /// and writing it back, and avoids copying the data entirely if the buffer
+    /// is accessible from the host.
+    pub fn update<R>(self, f: impl FnOnce(&mut [T]) -> R) -> Result<R>
+    where
+        H: HostReadable + HostWritable,
+    {
+        dependencies::wait_for_writes(&[self.buffer.as_buffer().raw()])?;
+
+        let buffer = self.buffer.as_buffer();
+        let offset = self.offset.unwrap_or(0);
+        let len = buffer.rust_size().saturating_sub(offset) / size_of::<T>();
+
+        let result = unsafe { map::update(self.queue, buffer.raw(), offset, len, f)? };
+        deterministic::sync(self.queue)?;
+        Ok(result)
+    }
+
+    /// Perform a read of the buffer into the given slice, blocking until
+    /// completion or until the given timeout has elapsed. Requires OpenCL 1.1+.
+    ///
+    /// On timeout, `Error::Timeout` is returned and the read is left running.
+    /// To make this safe, the data is read into a temporary allocation which is
+    /// kept alive until the read completes, and only copied into `dest` if the
+    /// read completes in time.
+    pub fn read_timeout(self, dest: &mut [T], timeout: Duration) -> Result<()>
+    where
+        H: HostReadable,
+        T: Send,
+    {
+        dependencies::wait_for_writes(&[self.buffer.as_buffer().raw()])?;
+
+        let len = dest.len();
+        let staging = Arc::new(Mutex::new(Vec::<T>::with_capacity(len)));
+        let ptr = staging.lock().unwrap().as_mut_ptr();
+
+        let event = unsafe {
             let mut event = null_mut();
 
-            wrap_result!("clEnqueueFillBuffer" => clEnqueueFillBuffer(
+            let result = wrap_result!("clEnqueueReadBuffer" => clEnqueueReadBuffer(
                 self.queue.raw(),
                 self.buffer.as_buffer().raw(),
-                pattern as *const _ as _,
-                size_of_val(pattern),
+                CL_FALSE,
                 self.offset.unwrap_or(0),
-                self.buffer.as_buffer().rust_size(),
+                size_of_val(dest),
+                ptr as _,
                 0,
                 null_mut(),
                 &mut event as _
-            ))?;
+            ));
+            journal::record(
+                self.queue,
+                "read buffer",
+                || {
+                    (
+                        None,
+                        labels::get(self.buffer.as_buffer().raw()),
+                        vec![size_of_val(dest)],
+                    )
+                },
+                event,
+                result,
+            )?;
 
-            wrap_result!("clWaitForEvents" => clWaitForEvents(1, &event as _))?;
+            Event(event)
+        };
 
-            Ok(())
+        // keep the temporary allocation alive until the read has completed
+        let keep_alive = staging.clone();
+        if let Err(e) = event.on_complete(move |_| drop(keep_alive)) {
+            // the allocation can't be released safely without the callback
+            event.wait()?;
+            return Err(e);
+        }
+
+        unsafe {
+            wrap_result!("clFlush" => clFlush(self.queue.raw()))?;
+        }
+
+        event.wait_timeout(timeout)?;
+
+        let mut staging = staging.lock().unwrap();
+        unsafe { staging.set_len(len) };
+        dest.copy_from_slice(&staging);
+
+        Ok(())
+    }
+
+    /// Perform a chunked read of the buffer into the given slice, blocking
+    /// until completion.
+    ///
+    /// The transfer is split into non-blocking reads of up to `chunk_size`
+    /// elements each, which some drivers can pipeline more efficiently than a
+    /// single large blocking read. Whether this is beneficial depends on the
+    /// driver, so it should be measured before use.
+    pub fn read_chunked(self, dest: &mut [T], chunk_size: usize) -> Result<()>
+    where
+        H: HostReadable,
+    {
+        dependencies::wait_for_writes(&[self.buffer.as_buffer().raw()])?;
+
+        let queue = self.queue;
+        let mut progress = self.progress;
+        let chunk_size = throttle::batch_size(queue, chunk_size);
+        let buffer = self.buffer.as_buffer().raw();
+        let offset = self.offset.unwrap_or(0);
+        let len = dest.len();
+        let dest = dest.as_mut_ptr();
+
+        unsafe {
+            transfer_chunked::<T>(len, chunk_size, progress.as_mut(), |start, count, event| {
+                let result = wrap_result!("clEnqueueReadBuffer" => clEnqueueReadBuffer(
+                    queue.raw(),
+                    buffer,
+                    CL_FALSE,
+                    offset + start * size_of::<T>(),
+                    count * size_of::<T>(),
+                    dest.add(start) as _,
+                    0,
+                    null_mut(),
+                    event
+                ));
+                journal::record(
+                    queue,
+                    "read buffer",
+                    || (None, labels::get(buffer), vec![count * size_of::<T>()]),
+                    *event,
+                    result,
+                )?;
+
+                Ok(())
+            })?;
+        }
+
+        deterministic::sync(self.queue)
+    }
+
+    /// Perform a chunked write of the given slice into the buffer, blocking
+    /// until completion. See `read_chunked` for details.
+    pub fn write_chunked(self, src: &[T], chunk_size: usize) -> Result<()>
+    where
+        H: HostWritable,
+    {
+        dependencies::wait_for_writes(&[self.buffer.as_buffer().raw()])?;
+
+        let queue = self.queue;
+        let mut progress = self.progress;
+        let chunk_size = throttle::batch_size(queue, chunk_size);
+        let buffer = self.buffer.as_buffer().raw();
+        let offset = self.offset.unwrap_or(0);
+
+        unsafe {
+            transfer_chunked::<T>(
+                src.len(),
+                chunk_size,
+                progress.as_mut(),
+                |start, count, event| {
+                    let result = wrap_result!("clEnqueueWriteBuffer" => clEnqueueWriteBuffer(
+                        queue.raw(),
+                        buffer,
+                        CL_FALSE,
+                        offset + start * size_of::<T>(),
+                        count * size_of::<T>(),
+                        src.as_ptr().add(start) as _,
+                        0,
+                        null_mut(),
+                        event
+                    ));
+                    journal::record(
+                        queue,
+                        "write buffer",
+                        || (None, labels::get(buffer), vec![count * size_of::<T>()]),
+                        *event,
+                        result,
+                    )?;
+
+                    Ok(())
+                },
+            )?;
+        }
+
+        deterministic::sync(self.queue)
+    }
+
+    /// Write several ranges of the given slice to the buffer, blocking until
+    /// completion. Each pair is a range of elements in `src`, and the element
+    /// offset in the buffer (relative to the offset of this command, if set) to
+    /// write it to.
+    ///
+    /// Pairs which are contiguous both in `src` and in the buffer are merged,
+    /// and the remaining transfers are enqueued together as non-blocking
+    /// commands, which suits sparse updates of large buffers (e.g. only the
+    /// ranges which changed since the last frame). Since the transfers may
+    /// execute in any order, the destination ranges shouldn't overlap.
+    ///
+    /// Panics if any range is out of bounds for `src`.
+    pub fn write_ranges(self, src: &[T], ranges: &[(Range<usize>, usize)]) -> Result<()>
+    where
+        H: HostWritable,
+    {
+        dependencies::wait_for_writes(&[self.buffer.as_buffer().raw()])?;
+
+        let queue = self.queue;
+        let mut progress = self.progress;
+        let buffer = self.buffer.as_buffer().raw();
+        let offset = self.offset.unwrap_or(0);
+        let spans = coalesce_ranges(src.len(), ranges);
+
+        unsafe {
+            transfer_spans::<T>(spans, progress.as_mut(), |host, device, count, event| {
+                let result = wrap_result!("clEnqueueWriteBuffer" => clEnqueueWriteBuffer(
+                    queue.raw(),
+                    buffer,
+                    CL_FALSE,
+                    offset + device * size_of::<T>(),
+                    count * size_of::<T>(),
+                    src.as_ptr().add(host) as _,
+                    0,
+                    null_mut(),
+                    event
+                ));
+                journal::record(
+                    queue,
+                    "write buffer",
+                    || (None, labels::get(buffer), vec![count * size_of::<T>()]),
+                    *event,
+                    result,
+                )?;
+
+                Ok(())
+            })?;
+        }
+
+        deterministic::sync(self.queue)
+    }
+
+    /// Read several ranges of the buffer into the given slice, blocking until
+    /// completion. Each pair is a range of elements in `dest`, and the element
+    /// offset in the buffer (relative to the offset of this command, if set) to
+    /// read it from. Transfers are merged and enqueued as with `write_ranges`.
+    ///
+    /// Panics if any range is out of bounds for `dest`, or if any two ranges
+    /// of `dest` overlap.
+    pub fn read_ranges(self, dest: &mut [T], ranges: &[(Range<usize>, usize)]) -> Result<()>
+    where
+        H: HostReadable,
+    {
+        dependencies::wait_for_writes(&[self.buffer.as_buffer().raw()])?;
+
+        let mut host_ranges: Vec<&Range<usize>> = ranges
+            .iter()
+            .map(|(r, _)| r)
+            .filter(|r| !r.is_empty())
+            .collect();
+        host_ranges.sort_by_key(|r| r.start);
+        assert!(
+            host_ranges.windows(2).all(|w| w[0].end <= w[1].start),
+            "host ranges must not overlap"
+        );
+
+        let queue = self.queue;
+        let mut progress = self.progress;
+        let buffer = self.buffer.as_buffer().raw();
+        let offset = self.offset.unwrap_or(0);
+        let spans = coalesce_ranges(dest.len(), ranges);
+        let ptr = dest.as_mut_ptr();
+
+        unsafe {
+            transfer_spans::<T>(spans, progress.as_mut(), |host, device, count, event| {
+                let result = wrap_result!("clEnqueueReadBuffer" => clEnqueueReadBuffer(
+                    queue.raw(),
+                    buffer,
+                    CL_FALSE,
+                    offset + device * size_of::<T>(),
+                    count * size_of::<T>(),
+                    ptr.add(host) as _,
+                    0,
+                    null_mut(),
+                    event
+                ));
+                journal::record(
+                    queue,
+                    "read buffer",
+                    || (None, labels::get(buffer), vec![count * size_of::<T>()]),
+                    *event,
+                    result,
+                )?;
+
+                Ok(())
+            })?;
+        }
+
+        deterministic::sync(self.queue)
+    }
+
+    /// Fill the buffer with the given pattern, blocking until completion.
+    pub fn fill(self, pattern: &T) -> Result<()> {
+        unsafe { self.fill_raw(pattern as *const T as _, size_of::<T>()) }
+    }
+
+    /// Fill the buffer with the given pattern of bytes, which may be smaller
+    /// or larger than the element type (e.g. a 16-byte pattern over a `u8`
+    /// buffer), blocking until completion.
+    ///
+    /// The pattern length must be a power of two no greater than 128 bytes,
+    /// and the offset and size of the filled region must be multiples of it,
+    /// as required by `clEnqueueFillBuffer` - otherwise, this will panic. If
+    /// the offset is past the end of the buffer, `Error::InvalidDataLength` is
+    /// returned.
+    pub fn fill_pattern(mut self, pattern: &[u8]) -> Result<()> {
+        let len = pattern.len();
+        assert!(
+            len.is_power_of_two() && len <= 128,
+            "fill pattern length must be a power of two no greater than 128, got {}",
+            len
+        );
+
+        let offset = self.offset.unwrap_or(0);
+        let size = self.fill_size()?;
+        assert!(
+            (offset | size) & (len - 1) == 0,
+            "fill offset ({}) and size ({}) must be multiples of the pattern length ({})",
+            offset,
+            size,
+            len
+        );
+
+        unsafe { self.fill_raw(pattern.as_ptr() as _, len) }
+    }
+
+    /// Set every byte of the buffer to zero, blocking until completion.
+    pub fn clear(self) -> Result<()> {
+        self.fill_pattern(&[0])
+    }
+
+    /// Get the size in bytes of the region from the offset to the end of the
+    /// buffer, returning `Error::InvalidDataLength` if the offset is past the
+    /// end
+    fn fill_size(&mut self) -> Result<usize> {
+        let offset = self.offset.unwrap_or(0);
+        let len = self.buffer.as_buffer().rust_size();
+
+        len.checked_sub(offset).ok_or(Error::InvalidDataLength {
+            expected: offset,
+            actual: len,
+        })
+    }
+
+    /// Fill the buffer from the offset to the end with the given pattern,
+    /// which must be valid for reads of `pattern_size` bytes
+    unsafe fn fill_raw(mut self, pattern: *const c_void, pattern_size: usize) -> Result<()> {
+        dependencies::wait_for_writes(&[self.buffer.as_buffer().raw()])?;
+
+        let offset = self.offset.unwrap_or(0);
+        let size = self.fill_size()?;
+        let mut event = null_mut();
+
+        let result = wrap_result!("clEnqueueFillBuffer" => clEnqueueFillBuffer(
+            self.queue.raw(),
+            self.buffer.as_buffer().raw(),
+            pattern,
+            pattern_size,
+            offset,
+            size,
+            0,
+            null_mut(),
+            &mut event as _
+        ));
+        journal::record(
+            self.queue,
+            "fill buffer",
+            || (None, labels::get(self.buffer.as_buffer().raw()), vec![size]),
+            event,
+            result,
+        )?;
+
+        Event(event).wait()?;
+
+        deterministic::sync(self.queue)
+    }
+}
+
+/// Enqueue a transfer of `len` elements as non-blocking commands of up to
+/// `chunk_size` elements each, then wait for all of them to complete.
+unsafe fn transfer_chunked<T>(
+    len: usize,
+    chunk_size: usize,
+    progress: Option<&mut Progress>,
+    mut enqueue: impl FnMut(usize, usize, *mut cl_event) -> Result<()>,
+) -> Result<()> {
+    assert!(chunk_size > 0, "chunk size must be nonzero");
+
+    let spans = (0..len)
+        .step_by(chunk_size)
+        .map(|start| (start, start, chunk_size.min(len - start)));
+
+    transfer_spans::<T>(spans, progress, |start, _, count, event| {
+        enqueue(start, count, event)
+    })
+}
+
+/// Enqueue a transfer of each `(host start, device start, length)` span of
+/// elements of type `T` as a non-blocking command, then wait for all of them
+/// to complete, reporting progress as each one completes.
+unsafe fn transfer_spans<T>(
+    spans: impl IntoIterator<Item = (usize, usize, usize)>,
+    progress: Option<&mut Progress>,
+    mut enqueue: impl FnMut(usize, usize, usize, *mut cl_event) -> Result<()>,
+) -> Result<()> {
+    let spans: Vec<(usize, usize, usize)> = spans.into_iter().collect();
+    let total = spans.iter().map(|&(_, _, count)| count).sum::<usize>() * size_of::<T>();
+    let mut events = Vec::with_capacity(spans.len());
+    let mut result = Ok(());
+
+    for (host, device, count) in spans {
+        let mut event = null_mut();
+
+        if let Err(e) = enqueue(host, device, count, &mut event as _) {
+            result = Err(e);
+            break;
+        }
+
+        events.push((Event(event), count * size_of::<T>()));
+    }
+
+    let mut waited = Ok(());
+
+    if let (Some(progress), true) = (progress, result.is_ok()) {
+        let mut done = 0;
+
+        for (event, bytes) in &events {
+            if let Err(e) = event.wait() {
+                waited = Err(e);
+                break;
+            }
+
+            done += bytes;
+            progress(done, total);
+        }
+    }
+
+    // spans which were already enqueued still reference host memory, so they
+    // must complete before returning, even if a later span failed
+    let events: Vec<&Event> = events.iter().map(|(e, _)| e).collect();
+    result.and(waited).and(Event::wait_all(&events))
+}
+
+/// Convert `(host range, device offset)` pairs into `(host start, device
+/// start, length)` spans, skipping empty ranges and merging pairs which are
+/// contiguous both on the host and on the device. Panics if a host range is
+/// out of bounds for a slice of length `len`.
+fn coalesce_ranges(len: usize, ranges: &[(Range<usize>, usize)]) -> Vec<(usize, usize, usize)> {
+    let mut sorted: Vec<(Range<usize>, usize)> = ranges
+        .iter()
+        .filter(|(r, _)| !r.is_empty())
+        .cloned()
+        .collect();
+    sorted.sort_by_key(|(r, device)| (*device, r.start));
+
+    let mut spans: Vec<(usize, usize, usize)> = vec![];
+
+    for (range, device) in sorted {
+        assert!(
+            range.end <= len,
+            "host range {:?} is out of bounds for slice of length {}",
+            range,
+            len
+        );
+
+        match spans.last_mut() {
+            Some((h, d, n)) if *h + *n == range.start && *d + *n == device => *n += range.len(),
+            _ => spans.push((range.start, device, range.len())),
+        }
+    }
+
+    spans
+}
+
+/// Get the name of the given kernel, for the command journal
+fn kernel_name<K: KernelInfo>(kernel: &K) -> Option<String> {
+    kernel
+        .function_name()
+        .ok()
+        .map(|n| n.to_string_lossy().into_owned())
+}
+
+/// Get the size of each dimension of the given work size, for the command
+/// journal
+fn work_size<W: WorkDims>(work_size: &W) -> Vec<usize> {
+    unsafe { std::slice::from_raw_parts(work_size.as_ptr(), W::NUM_WORK_DIMS as _).to_vec() }
+}
+
+/// The work sizes of a single kernel launch. Only the first `dims` entries of
+/// each size are used.
+pub(super) struct NdRange {
+    dims: u32,
+    offset: Option<[usize; 3]>,
+    global: [usize; 3],
+    local: Option<[usize; 3]>,
+}
+
+impl NdRange {
+    /// Validate a launch of the given kernel and get the sizes to launch it
+    /// with. The global work size is rounded up to a multiple of the local
+    /// work size if requested and the device doesn't support non-uniform work
+    /// groups.
+    pub(super) fn new<T: KernelArgList, W: WorkDims>(
+        queue: &Queue,
+        kernel: &Kernel<T>,
+        global_work_size: &W,
+        global_work_offset: Option<&W>,
+        local_work_size: Option<&W>,
+        round: bool,
+    ) -> Result<Self> {
+        validation::check_launch(queue, kernel, global_work_size, local_work_size);
+
+        let dims = W::NUM_WORK_DIMS as usize;
+        let padded = |w: &W| {
+            let mut padded = [0; 3];
+            padded[..dims].copy_from_slice(&work_size(w));
+            padded
+        };
+
+        let mut global = [1; 3];
+        global[..dims].copy_from_slice(&work_size(global_work_size));
+        let local = local_work_size.map(padded);
+
+        if let Some(local) = local.filter(|_| round) {
+            let uniform = global
+                .iter()
+                .zip(&local)
+                .all(|(&g, &l)| l == 0 || g / l * l == g);
+
+            if !uniform && !queue.device()?.supports_non_uniform_work_groups()? {
+                for (g, &l) in global.iter_mut().zip(&local).filter(|(_, &l)| l != 0) {
+                    *g = g.div_ceil(l) * l;
+                }
+            }
         }
+
+        Ok(Self {
+            dims: W::NUM_WORK_DIMS,
+            offset: global_work_offset.map(padded),
+            global,
+            local,
+        })
     }
 }
 
+/// Enqueue a single launch of the given kernel, after pacing the queue. The
+/// launch waits for the given events as well as previous writes to the
+/// kernel's buffer arguments, and is recorded in the command journal under the
+/// given command name.
+pub(super) unsafe fn enqueue_ndrange<T: KernelArgList>(
+    queue: &Queue,
+    kernel: &Kernel<T>,
+    command: &'static str,
+    sizes: &NdRange,
+    events: &[cl_event],
+) -> Result<Event> {
+    throttle::pace(queue);
+
+    let mems = T::buffer_mems(kernel);
+    let inferred = dependencies::wait_list(&mems);
+    let wait_list: Vec<cl_event> = events
+        .iter()
+        .copied()
+        .chain(inferred.iter().map(Event::raw))
+        .collect();
+
+    let mut event = null_mut();
+    let result = wrap_result!("clEnqueueNDRangeKernel" => clEnqueueNDRangeKernel(
+        queue.raw(),
+        kernel.as_unbound().raw(),
+        sizes.dims,
+        sizes.offset.as_ref().map(|o| o.as_ptr()).unwrap_or(null()),
+        sizes.global.as_ptr(),
+        sizes.local.as_ref().map(|l| l.as_ptr()).unwrap_or(null()),
+        wait_list.len() as _,
+        if wait_list.is_empty() { null() } else { wait_list.as_ptr() },
+        &mut event as _
+    ));
+    journal::record(
+        queue,
+        command,
+        || {
+            (
+                kernel_name(kernel),
+                labels::get(kernel.as_unbound().raw()),
+                sizes.global[..sizes.dims as usize].to_vec(),
+            )
+        },
+        event,
+        result,
+    )?;
+    dependencies::record_write(&mems, event);
+
+    Ok(Event(event))
+}
+
 /// A trait implemented for types which can be used to specify kernel work
 /// sizes/offsets
 pub trait WorkDims {
@@ -219,16 +921,36 @@ impl WorkDims for [usize; 3] {
     }
 }
 
+/// How to split the work of `KernelCmd::exec_ndrange_sliced` into launches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slicing {
+    /// Launch slices of the given size along the last work dimension
+    Size(usize),
+    /// Adjust the size of each slice so that it takes approximately the given
+    /// duration, based on the measured duration of previous slices. The first
+    /// slice is as small as possible, and slices grow by at most a factor of
+    /// four at a time.
+    TargetDuration(Duration),
+}
+
 /// A partially built command to execute a kernel
 #[must_use]
 pub struct KernelCmd<'q, T: KernelArgList, W: WorkDims> {
     pub(super) queue: &'q Queue,
-    pub(super) kernel: &'q Kernel<T>,
+    pub(super) kernel: &'q mut Kernel<T>,
     pub(super) global_work_offset: Option<W>,
     pub(super) local_work_size: Option<W>,
+    pub(super) round_global_work_size: bool,
+    pub(super) events: Vec<Event>,
 }
 
 impl<'q, T: KernelArgList, W: WorkDims> KernelCmd<'q, T, W> {
+    /// Add an event which must complete before the kernel is executed
+    pub fn wait_for(mut self, event: &Event) -> Result<Self> {
+        self.events.push(event.try_clone()?);
+        Ok(self)
+    }
+
     /// Set the global work offset
     pub fn global_work_offset(self, global_work_offset: impl Into<Option<W>>) -> Self {
         Self {
@@ -245,30 +967,371 @@ impl<'q, T: KernelArgList, W: WorkDims> KernelCmd<'q, T, W> {
         }
     }
 
+    /// Set the size of a `__local` memory argument for this launch, computed
+    /// from the local work size by the given function, which is passed the
+    /// size of each dimension and returns the size in bytes. For example, a
+    /// kernel needing one `float` of scratch space per work item would use
+    /// `|local| local.iter().product::<usize>() * size_of::<f32>()`.
+    ///
+    /// The argument is set immediately, so the local work size must already
+    /// have been set with `local_work_size`. The argument at the given index
+    /// should be bound as a `LocalSize`, whose bound value is overridden, and
+    /// the computed size remains set for later launches until the argument is
+    /// set again.
+    pub fn scale_local_alloc(
+        self,
+        index: cl_uint,
+        size: impl FnOnce(&[usize]) -> usize,
+    ) -> Result<Self> {
+        let local_work_size = self
+            .local_work_size
+            .as_ref()
+            .expect("scale_local_alloc requires a local work size to be set");
+        let num_args = self.kernel.num_args()?;
+        assert!(
+            index < num_args,
+            "index {} is out of range for kernel of arity {}",
+            index,
+            num_args
+        );
+
+        unsafe {
+            LocalSize(size(&work_size(local_work_size)))
+                .set_raw_kernel_arg(self.kernel.as_unbound().raw(), index)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Set whether the global work size should be rounded up to a multiple of
+    /// the local work size when the device doesn't support non-uniform work
+    /// groups. Disabled by default.
+    ///
+    /// Without rounding, a local work size which doesn't evenly divide the
+    /// global work size is passed to the driver as-is, which fails with
+    /// `CL_INVALID_WORK_GROUP_SIZE` unless the device supports non-uniform work
+    /// groups (see `Device::supports_non_uniform_work_groups`). With rounding,
+    /// the kernel may be executed for extra work items beyond the requested
+    /// global work size, so it must check its global ID against the size it
+    /// was intended for.
+    pub fn round_global_work_size(self, round: bool) -> Self {
+        Self {
+            round_global_work_size: round,
+            ..self
+        }
+    }
+
+    /// Prepare this command to be executed repeatedly with the given global
+    /// work size, without rebuilding the command for each launch.
+    pub fn prepare(self, global_work_size: W) -> PreparedLaunch<'q, T, W> {
+        PreparedLaunch {
+            queue: self.queue,
+            kernel: self.kernel,
+            global_work_size,
+            global_work_offset: self.global_work_offset,
+            local_work_size: self.local_work_size,
+            round_global_work_size: self.round_global_work_size,
+            wait_list: self.events.iter().map(Event::raw).collect(),
+            events: self.events,
+        }
+    }
+
+    /// Validate a launch of this command and get the sizes to launch it with
+    fn sizes(&self, global_work_size: &W) -> Result<NdRange> {
+        NdRange::new(
+            self.queue,
+            self.kernel,
+            global_work_size,
+            self.global_work_offset.as_ref(),
+            self.local_work_size.as_ref(),
+            self.round_global_work_size,
+        )
+    }
+
+    /// Record a launch of this command with the given global work size, for
+    /// later replay. This reads the current contents of the kernel's buffer
+    /// arguments, so it should be called before the command is executed. See
+    /// the `replay` module for details.
+    pub fn record_launch(&self, global_work_size: &W) -> Result<LaunchRecord> {
+        LaunchRecord::capture(
+            self.queue,
+            self.kernel,
+            work_size(global_work_size),
+            self.global_work_offset.as_ref().map(work_size),
+            self.local_work_size.as_ref().map(work_size),
+        )
+    }
+
     /// Execute this kernel with the given global work size, blocking until
     /// completion.
     pub fn exec_ndrange(self, global_work_size: W) -> Result<()> {
+        let sizes = self.sizes(&global_work_size)?;
+        let wait_list: Vec<cl_event> = self.events.iter().map(Event::raw).collect();
+
         unsafe {
-            let mut event = null_mut();
+            enqueue_ndrange(
+                self.queue,
+                self.kernel,
+                "ndrange kernel",
+                &sizes,
+                &wait_list,
+            )?
+        }
+        .wait()?;
 
-            wrap_result!("clEnqueueNDRangeKernel" => clEnqueueNDRangeKernel(
-                self.queue.raw(),
-                self.kernel.as_unbound().raw(),
-                W::NUM_WORK_DIMS,
-                self.global_work_offset.map(|o| o.as_ptr()).unwrap_or(null()),
-                global_work_size.as_ptr(),
-                self.local_work_size.map(|o| o.as_ptr()).unwrap_or(null()),
-                0,
-                null(),
-                &mut event as _
-            ))?;
+        deterministic::sync(self.queue)
+    }
 
-            wrap_result!("clWaitForEvents" => clWaitForEvents(
-                1,
-                &event as _
-            ))?;
+    /// Execute this kernel with the given global work size, blocking until
+    /// completion or until the given timeout has elapsed. Requires OpenCL 1.1+.
+    ///
+    /// On timeout, `Error::Timeout` is returned and the kernel is left running.
+    /// Since the kernel may still access its arguments after this returns, it
+    /// can only be used with kernels whose arguments don't borrow host memory.
+    pub fn exec_ndrange_timeout(self, global_work_size: W, timeout: Duration) -> Result<()>
+    where
+        T: 'static,
+    {
+        let sizes = self.sizes(&global_work_size)?;
+        let wait_list: Vec<cl_event> = self.events.iter().map(Event::raw).collect();
+
+        let event = unsafe {
+            enqueue_ndrange(
+                self.queue,
+                self.kernel,
+                "ndrange kernel",
+                &sizes,
+                &wait_list,
+            )?
+        };
+        unsafe { wrap_result!("clFlush" => clFlush(self.queue.raw()))? };
+        event.wait_timeout(timeout)
+    }
 
+    /// Execute this kernel with the given global work size as a sequence of
+    /// smaller launches, blocking until all of them have completed. Requires
+    /// OpenCL 1.1+.
+    ///
+    /// The work is split along the last work dimension, using the global work
+    /// offset so that `get_global_id` returns the same values as for a single
+    /// launch, and each slice waits for the previous one. This keeps each
+    /// launch short enough to avoid resets by the OS GPU watchdog when running
+    /// heavy kernels on a GPU which also drives a display, at the cost of some
+    /// launch overhead. Kernels which rely on all work items running in the
+    /// same launch (e.g. through global atomics signalling between work
+    /// groups) shouldn't be sliced.
+    ///
+    /// If a local work size is set, slices are rounded to multiples of it.
+    pub fn exec_ndrange_sliced(self, global_work_size: W, slicing: Slicing) -> Result<()> {
+        let mut sizes = self.sizes(&global_work_size)?;
+
+        let axis = W::NUM_WORK_DIMS as usize - 1;
+        let granularity = sizes
+            .local
+            .map(|l| l[axis])
+            .filter(|&l| l != 0)
+            .unwrap_or(1);
+
+        let total = sizes.global[axis];
+        let base = sizes.offset.map_or(0, |o| o[axis]);
+        let mut offset = sizes.offset.unwrap_or([0; 3]);
+        let mut slice = match slicing {
+            Slicing::Size(size) => size,
+            Slicing::TargetDuration(_) => granularity,
+        };
+
+        let mut wait_list: Vec<cl_event> = self.events.iter().map(Event::raw).collect();
+        let mut start = 0;
+
+        while start < total {
+            let count = (slice.max(1).div_ceil(granularity) * granularity).min(total - start);
+            offset[axis] = base + start;
+            sizes.offset = Some(offset);
+            sizes.global[axis] = count;
+
+            let started = Instant::now();
+
+            let event = unsafe {
+                enqueue_ndrange(
+                    self.queue,
+                    self.kernel,
+                    "ndrange kernel slice",
+                    &sizes,
+                    &wait_list,
+                )?
+            };
+
+            wait_list.clear();
+            event.wait()?;
+
+            if let Slicing::TargetDuration(target) = slicing {
+                // prefer the device's measurement, which excludes launch and
+                // scheduling overhead, if the queue has profiling enabled
+                let elapsed = event
+                    .profiling_duration()
+                    .unwrap_or_else(|_| started.elapsed())
+                    .as_secs_f64();
+                let scale = if elapsed > 0.0 {
+                    (target.as_secs_f64() / elapsed).min(4.0)
+                } else {
+                    4.0
+                };
+                slice = ((count as f64 * scale) as usize).max(granularity);
+            }
+
+            start += count;
+        }
+
+        deterministic::sync(self.queue)
+    }
+
+    /// Execute this kernel with the given global work size, blocking until
+    /// completion, and forward any `printf` output from the kernel to the `log`
+    /// crate (at info level, tagged with the kernel name).
+    ///
+    /// Since OpenCL implementations write `printf` output to standard output,
+    /// this works by redirecting the process's standard output until the queue
+    /// has finished, so output from other threads during that time will also
+    /// be captured. If standard output can't be redirected, a warning is logged
+    /// and the kernel is executed normally.
+    #[cfg(unix)]
+    pub fn exec_ndrange_logging_printf(self, global_work_size: W) -> Result<()> {
+        use super::printf::PrintfCapture;
+
+        let queue = self.queue;
+        let name = self.kernel.function_name()?;
+        let name = name.to_string_lossy();
+        let capacity = queue.device()?.printf_buffer_size()?;
+
+        let capture = PrintfCapture::start(capacity)
+            .map_err(|e| log::warn!("Couldn't capture printf output of {}: {}", name, e))
+            .ok();
+
+        let result = self.exec_ndrange(global_work_size).and_then(|_| unsafe {
+            wrap_result!("clFinish" => clFinish(queue.raw()))?;
             Ok(())
+        });
+
+        match capture.map(PrintfCapture::finish) {
+            Some(Ok(output)) => {
+                for line in String::from_utf8_lossy(&output).lines() {
+                    log::info!("[{}] {}", name, line);
+                }
+            }
+            Some(Err(e)) => log::warn!("Couldn't capture printf output of {}: {}", name, e),
+            None => {}
+        }
+
+        result
+    }
+}
+
+/// A kernel execution command which has been prepared once and can be launched
+/// repeatedly, as created by `KernelCmd::prepare`.
+///
+/// The work sizes and offsets are fixed when the command is prepared, and any
+/// events added to the wait list are waited on by every launch.
+#[must_use]
+pub struct PreparedLaunch<'q, T: KernelArgList, W: WorkDims> {
+    queue: &'q Queue,
+    kernel: &'q mut Kernel<T>,
+    global_work_size: W,
+    global_work_offset: Option<W>,
+    local_work_size: Option<W>,
+    round_global_work_size: bool,
+    events: Vec<Event>,
+    wait_list: Vec<cl_event>,
+}
+
+impl<'q, T: KernelArgList, W: WorkDims> PreparedLaunch<'q, T, W> {
+    /// Add an event which must complete before each launch of this command.
+    /// The event is retained for as long as the prepared command exists.
+    pub fn wait_for(mut self, event: &Event) -> Result<Self> {
+        let event = event.try_clone()?;
+        self.wait_list.push(event.raw());
+        self.events.push(event);
+        Ok(self)
+    }
+
+    /// Get a mutable reference to the kernel launched by this command
+    pub fn kernel(&mut self) -> &mut Kernel<T> {
+        self.kernel
+    }
+
+    /// Launch the kernel, blocking until completion.
+    pub fn launch(&mut self) -> Result<()> {
+        let sizes = NdRange::new(
+            self.queue,
+            self.kernel,
+            &self.global_work_size,
+            self.global_work_offset.as_ref(),
+            self.local_work_size.as_ref(),
+            self.round_global_work_size,
+        )?;
+
+        unsafe {
+            enqueue_ndrange(
+                self.queue,
+                self.kernel,
+                "ndrange kernel",
+                &sizes,
+                &self.wait_list,
+            )?
         }
+        .wait()?;
+
+        deterministic::sync(self.queue)
+    }
+
+    /// Update the kernel arguments using the given function, then launch the
+    /// kernel, blocking until completion.
+    pub fn launch_with_args(
+        &mut self,
+        update: impl FnOnce(&mut Kernel<T>) -> Result<()>,
+    ) -> Result<()> {
+        update(self.kernel)?;
+        self.launch()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::coalesce_ranges;
+
+    #[test]
+    fn test_coalesce_contiguous_ranges() {
+        assert_eq!(
+            coalesce_ranges(16, &[(0..4, 8), (4..6, 12), (8..10, 14)]),
+            vec![(0, 8, 6), (8, 14, 2)]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_sorts_by_device_offset() {
+        assert_eq!(
+            coalesce_ranges(16, &[(4..8, 4), (0..4, 0), (8..12, 100)]),
+            vec![(0, 0, 8), (8, 100, 4)]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_keeps_discontiguous_ranges() {
+        // contiguous on the device but not on the host, and vice versa
+        assert_eq!(
+            coalesce_ranges(16, &[(0..4, 0), (8..12, 4), (12..16, 16)]),
+            vec![(0, 0, 4), (8, 4, 4), (12, 16, 4)]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_skips_empty_ranges() {
+        assert_eq!(coalesce_ranges(4, &[(2..2, 0), (0..4, 8)]), vec![(0, 8, 4)]);
+        assert!(coalesce_ranges(0, &[]).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_coalesce_rejects_out_of_bounds() {
+        coalesce_ranges(4, &[(2..6, 0)]);
     }
 }
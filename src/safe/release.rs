@@ -0,0 +1,89 @@
+//! Release error handling
+//!
+//! OpenCL objects are released when their wrappers are dropped, where errors
+//! can't be returned to the caller. By default, such errors are logged as
+//! warnings, but a handler can be set with `set_release_error_handler` to
+//! route them elsewhere - e.g. to telemetry, or a panic in tests.
+
+use crate::Error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::RwLock;
+
+/// A handler for errors releasing OpenCL objects
+pub type ReleaseErrorHandler = fn(&ReleaseError);
+
+lazy_static::lazy_static! {
+    static ref HANDLER: RwLock<ReleaseErrorHandler> = RwLock::new(log_release_error);
+}
+
+/// An error releasing an OpenCL object while dropping its wrapper
+pub struct ReleaseError<'a> {
+    what: &'static str,
+    object: &'a dyn Debug,
+    error: Error,
+}
+
+impl ReleaseError<'_> {
+    /// Get a description of the type of object which couldn't be released
+    /// (e.g. `OpenCL kernel`)
+    pub fn what(&self) -> &'static str {
+        self.what
+    }
+
+    /// Get the wrapper for the object which couldn't be released. Since the
+    /// object may be invalid, formatting it may not show any information.
+    pub fn object(&self) -> &dyn Debug {
+        self.object
+    }
+
+    /// Get the error returned while releasing the object
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+}
+
+impl Debug for ReleaseError<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("ReleaseError")
+            .field("what", &self.what)
+            .field("object", self.object)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl Display for ReleaseError<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Error releasing {} {:?}: {:?}",
+            self.what, self.object, self.error
+        )
+    }
+}
+
+/// The default handler, which logs errors as warnings
+pub fn log_release_error(error: &ReleaseError) {
+    log::warn!("{}", error);
+}
+
+/// Set the handler called for errors releasing OpenCL objects while dropping
+/// their wrappers, replacing the default handler `log_release_error`.
+///
+/// The handler may be called from any thread, and from any drop, so it
+/// shouldn't block. Panicking in the handler is allowed, but will abort the
+/// process if the drop happens during unwinding.
+pub fn set_release_error_handler(handler: ReleaseErrorHandler) {
+    *HANDLER.write().unwrap() = handler;
+}
+
+/// Report an error releasing an object to the current handler
+pub(crate) fn report(what: &'static str, object: &dyn Debug, error: impl Into<Error>) {
+    let handler = *HANDLER.read().unwrap();
+
+    handler(&ReleaseError {
+        what,
+        object,
+        error: error.into(),
+    });
+}
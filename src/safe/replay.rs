@@ -0,0 +1,501 @@
+//! Kernel launch recording and replay
+//!
+//! A `LaunchRecord` is a self-contained description of a kernel launch - the
+//! program source and build options, the value of every argument (including
+//! the contents of buffer arguments), and the NDRange - which can be saved to
+//! a file and replayed elsewhere, e.g. attached to a bug report to reproduce a
+//! driver issue without the rest of the application. Records are captured
+//! with `KernelCmd::record_launch` before the launch:
+//!
+//! ```no_run
+//! # use dynamic_ocl::kernel::Kernel;
+//! # use dynamic_ocl::queue::Queue;
+//! # fn f(queue: &Queue, kernel: &mut Kernel<(u32, u32)>) -> dynamic_ocl::Result<()> {
+//! let cmd = queue.kernel_cmd(kernel);
+//! let record = cmd.record_launch(&1024)?;
+//! record.save("launch.oclreplay").unwrap();
+//! cmd.exec_ndrange(1024)
+//! # }
+//! ```
+//!
+//! The `replay` example loads and runs a saved record, and prints the contents
+//! of its buffers afterwards.
+//!
+//! Only programs built from source can be replayed. Arguments which aren't
+//! plain values, buffers, or local memory allocations (e.g. SVM pointers or
+//! device queues) are recorded as their raw values, which won't be meaningful
+//! when replayed.
+
+use crate::buffer::flags::HostReadWrite;
+use crate::buffer::Buffer;
+use crate::context::Context;
+use crate::dependencies;
+use crate::event::Event;
+use crate::kernel::sealed::RawArg;
+use crate::kernel::{Kernel, KernelArgList, KernelInfo};
+use crate::program::Program;
+use crate::queue::Queue;
+use crate::raw::*;
+use crate::{Error, Result};
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::mem::{size_of, ManuallyDrop};
+use std::path::Path;
+use std::ptr::{null, null_mut};
+
+/// The header identifying a replay file, followed by the format version
+const MAGIC: &[u8; 8] = b"OCLRPLAY";
+const VERSION: u32 = 1;
+
+/// The recorded value of a kernel argument
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordedArg {
+    /// A plain value, as its raw bytes
+    Value(Vec<u8>),
+    /// A buffer, as a snapshot of its contents
+    Buffer(Vec<u8>),
+    /// A local memory allocation of the given size in bytes
+    Local(usize),
+}
+
+/// A recorded kernel launch. See the module documentation for details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchRecord {
+    /// The name of the kernel function
+    pub kernel_name: String,
+    /// The source of the program the kernel belongs to
+    pub source: String,
+    /// The options the program was built with
+    pub options: String,
+    /// The values of the kernel's arguments, in order
+    pub args: Vec<RecordedArg>,
+    /// The global work size of the launch
+    pub global_work_size: Vec<usize>,
+    /// The global work offset of the launch, if any
+    pub global_work_offset: Option<Vec<usize>>,
+    /// The local work size of the launch, if any
+    pub local_work_size: Option<Vec<usize>>,
+}
+
+impl LaunchRecord {
+    /// Record a launch of the given kernel, reading the current contents of
+    /// its buffer arguments
+    pub(crate) fn capture<T: KernelArgList>(
+        queue: &Queue,
+        kernel: &Kernel<T>,
+        global_work_size: Vec<usize>,
+        global_work_offset: Option<Vec<usize>>,
+        local_work_size: Option<Vec<usize>>,
+    ) -> Result<Self> {
+        let program = ManuallyDrop::new(Program(kernel.program_raw()?, None));
+        let options = program.build_info(queue.device()?)?.options()?;
+        let context = queue.context()?;
+
+        let args = T::raw_args(kernel)
+            .into_iter()
+            .map(|arg| match arg {
+                RawArg::Value(bytes) => Ok(RecordedArg::Value(bytes)),
+                RawArg::Mem(mem) => snapshot(queue, &context, mem).map(RecordedArg::Buffer),
+                RawArg::Local(size) => Ok(RecordedArg::Local(size)),
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            kernel_name: kernel.function_name()?.to_string_lossy().into_owned(),
+            source: program.source()?.to_string_lossy().into_owned(),
+            options: options.to_string_lossy().into_owned(),
+            args,
+            global_work_size,
+            global_work_offset,
+            local_work_size,
+        })
+    }
+
+    /// Build the recorded program in the queue's context and run the recorded
+    /// launch on the queue, blocking until complete. Buffer arguments are
+    /// created from their snapshots, and their contents after the launch are
+    /// returned in argument order.
+    pub fn replay(&self, queue: &Queue) -> Result<Vec<Vec<u8>>> {
+        self.validate().map_err(Error::InvalidLaunchRecord)?;
+
+        let context = queue.context()?;
+        let name = CString::new(self.kernel_name.as_str())
+            .map_err(|_| Error::InvalidLaunchRecord("kernel name contains a NUL byte"))?;
+        let kernel = context.kernel_from_source(&self.source, &name, &self.options)?;
+
+        let mut buffers: Vec<Buffer<'static, HostReadWrite, u8>> = vec![];
+        for arg in &self.args {
+            if let RecordedArg::Buffer(data) = arg {
+                buffers.push(
+                    context
+                        .buffer_builder()
+                        .host_access::<HostReadWrite>()
+                        .build_copying_slice(data)?,
+                );
+            }
+        }
+
+        unsafe {
+            let mut mems = buffers.iter().map(Buffer::raw);
+
+            for (index, arg) in self.args.iter().enumerate() {
+                let mem;
+                let (size, value) = match arg {
+                    RecordedArg::Value(bytes) => (bytes.len(), bytes.as_ptr() as *const _),
+                    RecordedArg::Buffer(_) => {
+                        mem = mems.next().unwrap();
+                        (size_of::<cl_mem>(), &mem as *const cl_mem as *const _)
+                    }
+                    RecordedArg::Local(size) => (*size, null()),
+                };
+
+                wrap_result!("clSetKernelArg" => clSetKernelArg(
+                    kernel.raw(),
+                    index as _,
+                    size,
+                    value
+                ))?;
+            }
+
+            let mut event = null_mut();
+
+            wrap_result!("clEnqueueNDRangeKernel" => clEnqueueNDRangeKernel(
+                queue.raw(),
+                kernel.raw(),
+                self.global_work_size.len() as _,
+                self.global_work_offset.as_ref().map(|o| o.as_ptr()).unwrap_or(null()),
+                self.global_work_size.as_ptr(),
+                self.local_work_size.as_ref().map(|l| l.as_ptr()).unwrap_or(null()),
+                0,
+                null(),
+                &mut event as _
+            ))?;
+
+            let result = wrap_result!("clWaitForEvents" => clWaitForEvents(1, &event as _));
+            clReleaseEvent(event);
+            result?;
+        }
+
+        buffers
+            .iter_mut()
+            .map(|buffer| {
+                let mut data = vec![0; buffer.rust_size()];
+                queue.buffer_cmd(buffer).read(&mut data)?;
+                Ok(data)
+            })
+            .collect()
+    }
+
+    /// Check that the work sizes of this record describe a valid NDRange
+    fn validate(&self) -> std::result::Result<(), &'static str> {
+        let dims = self.global_work_size.len();
+        if !(1..=3).contains(&dims) {
+            return Err("global work size must have 1 to 3 dimensions");
+        }
+
+        if matches!(&self.global_work_offset, Some(o) if o.len() != dims) {
+            return Err("global work offset doesn't match the number of work dimensions");
+        }
+
+        if matches!(&self.local_work_size, Some(l) if l.len() != dims) {
+            return Err("local work size doesn't match the number of work dimensions");
+        }
+
+        Ok(())
+    }
+
+    /// Write this record in the replay file format
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+
+        write_bytes(&mut writer, self.kernel_name.as_bytes())?;
+        write_bytes(&mut writer, self.source.as_bytes())?;
+        write_bytes(&mut writer, self.options.as_bytes())?;
+
+        write_len(&mut writer, self.args.len())?;
+        for arg in &self.args {
+            match arg {
+                RecordedArg::Value(bytes) => {
+                    writer.write_all(&[0])?;
+                    write_bytes(&mut writer, bytes)?;
+                }
+                RecordedArg::Buffer(bytes) => {
+                    writer.write_all(&[1])?;
+                    write_bytes(&mut writer, bytes)?;
+                }
+                RecordedArg::Local(size) => {
+                    writer.write_all(&[2])?;
+                    write_len(&mut writer, *size)?;
+                }
+            }
+        }
+
+        write_sizes(&mut writer, Some(&self.global_work_size))?;
+        write_sizes(&mut writer, self.global_work_offset.as_deref())?;
+        write_sizes(&mut writer, self.local_work_size.as_deref())
+    }
+
+    /// Read a record in the replay file format
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not an OpenCL replay file"));
+        }
+
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        if u32::from_le_bytes(version) != VERSION {
+            return Err(invalid("unsupported replay file version"));
+        }
+
+        let kernel_name = read_string(&mut reader)?;
+        let source = read_string(&mut reader)?;
+        let options = read_string(&mut reader)?;
+
+        let num_args = read_len(&mut reader)?;
+        let mut args = Vec::with_capacity(num_args.min(64));
+        for _ in 0..num_args {
+            let mut tag = [0];
+            reader.read_exact(&mut tag)?;
+            args.push(match tag[0] {
+                0 => RecordedArg::Value(read_bytes(&mut reader)?),
+                1 => RecordedArg::Buffer(read_bytes(&mut reader)?),
+                2 => RecordedArg::Local(read_len(&mut reader)?),
+                _ => return Err(invalid("invalid argument kind")),
+            });
+        }
+
+        let global_work_size =
+            read_sizes(&mut reader)?.ok_or_else(|| invalid("missing global work size"))?;
+
+        let record = Self {
+            kernel_name,
+            source,
+            options,
+            args,
+            global_work_size,
+            global_work_offset: read_sizes(&mut reader)?,
+            local_work_size: read_sizes(&mut reader)?,
+        };
+
+        record.validate().map_err(invalid)?;
+        Ok(record)
+    }
+
+    /// Save this record to a replay file at the given path
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Load a record from a replay file at the given path
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Read the contents of a memory object, through a staging buffer so that
+/// buffers without host access can be read too
+fn snapshot(queue: &Queue, context: &Context, mem: cl_mem) -> Result<Vec<u8>> {
+    let size = unsafe {
+        let mut size: usize = 0;
+        wrap_result!("clGetMemObjectInfo" => clGetMemObjectInfo(
+            mem,
+            CL_MEM_SIZE,
+            size_of::<usize>(),
+            &mut size as *mut usize as _,
+            null_mut()
+        ))?;
+        size
+    };
+
+    let mut staging: Buffer<'static, HostReadWrite, u8> = context
+        .buffer_builder()
+        .host_access::<HostReadWrite>()
+        .build_with_size(size)?;
+
+    unsafe {
+        let inferred = dependencies::wait_list(&[mem]);
+        let wait_list: Vec<cl_event> = inferred.iter().map(Event::raw).collect();
+        let mut event = null_mut();
+
+        wrap_result!("clEnqueueCopyBuffer" => clEnqueueCopyBuffer(
+            queue.raw(),
+            mem,
+            staging.raw(),
+            0,
+            0,
+            size,
+            wait_list.len() as _,
+            if wait_list.is_empty() { null() } else { wait_list.as_ptr() },
+            &mut event as _
+        ))?;
+
+        let result = wrap_result!("clWaitForEvents" => clWaitForEvents(1, &event as _));
+        clReleaseEvent(event);
+        result?;
+    }
+
+    let mut data = vec![0; size];
+    queue.buffer_cmd(&mut staging).read(&mut data)?;
+    Ok(data)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+fn write_len(writer: &mut impl Write, len: usize) -> io::Result<()> {
+    writer.write_all(&(len as u64).to_le_bytes())
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    write_len(writer, bytes.len())?;
+    writer.write_all(bytes)
+}
+
+fn write_sizes(writer: &mut impl Write, sizes: Option<&[usize]>) -> io::Result<()> {
+    match sizes {
+        Some(sizes) => {
+            write_len(writer, sizes.len())?;
+            sizes.iter().try_for_each(|&s| write_len(writer, s))
+        }
+        None => write_len(writer, 0),
+    }
+}
+
+fn read_len(reader: &mut impl Read) -> io::Result<usize> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+
+    if len > usize::MAX as u64 {
+        return Err(invalid("length out of range"));
+    }
+
+    Ok(len as usize)
+}
+
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_len(reader)?;
+    let mut bytes = vec![];
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+
+    if bytes.len() != len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(bytes)
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    String::from_utf8(read_bytes(reader)?).map_err(|_| invalid("invalid UTF-8 string"))
+}
+
+/// Read a list of work sizes, where an empty list means none were given
+fn read_sizes(reader: &mut impl Read) -> io::Result<Option<Vec<usize>>> {
+    let len = read_len(reader)?;
+    if len > 3 {
+        return Err(invalid("too many work dimensions"));
+    }
+
+    let sizes = (0..len)
+        .map(|_| read_len(reader))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    Ok(if sizes.is_empty() { None } else { Some(sizes) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LaunchRecord, RecordedArg};
+
+    fn sample() -> LaunchRecord {
+        LaunchRecord {
+            kernel_name: "add".into(),
+            source: "__kernel void add(__global float *a, float b, __local float *c) {}".into(),
+            options: "-cl-fast-relaxed-math".into(),
+            args: vec![
+                RecordedArg::Buffer(vec![1, 2, 3, 4]),
+                RecordedArg::Value(1.5f32.to_le_bytes().to_vec()),
+                RecordedArg::Local(256),
+            ],
+            global_work_size: vec![64, 32],
+            global_work_offset: Some(vec![8, 0]),
+            local_work_size: None,
+        }
+    }
+
+    fn encode(record: &LaunchRecord) -> Vec<u8> {
+        let mut bytes = vec![];
+        record.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let record = sample();
+        let decoded = LaunchRecord::read_from(&encode(&record)[..]).unwrap();
+        assert_eq!(decoded, record);
+    }
+
+    #[test]
+    fn test_reject_bad_header() {
+        let mut bytes = encode(&sample());
+        bytes[0] = b'X';
+        assert!(LaunchRecord::read_from(&bytes[..]).is_err());
+
+        let mut bytes = encode(&sample());
+        bytes[8] = 2;
+        assert!(LaunchRecord::read_from(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_reject_truncated() {
+        let bytes = encode(&sample());
+        for len in [0, 8, 12, 20, bytes.len() - 1] {
+            assert!(LaunchRecord::read_from(&bytes[..len]).is_err(), "{}", len);
+        }
+    }
+
+    #[test]
+    fn test_reject_mismatched_dims() {
+        let mut record = sample();
+        record.local_work_size = Some(vec![8]);
+        assert!(LaunchRecord::read_from(&encode(&record)[..]).is_err());
+
+        let mut record = sample();
+        record.global_work_offset = Some(vec![0, 0, 0]);
+        assert!(LaunchRecord::read_from(&encode(&record)[..]).is_err());
+
+        let mut record = sample();
+        record.global_work_size = vec![];
+        assert!(LaunchRecord::read_from(&encode(&record)[..]).is_err());
+
+        let mut record = sample();
+        record.global_work_size = vec![1; 4];
+        assert!(LaunchRecord::read_from(&encode(&record)[..]).is_err());
+    }
+
+    #[test]
+    fn test_reject_invalid_arg_kind() {
+        let record = sample();
+        let mut bytes = encode(&record);
+
+        // the tag of the first argument follows the header, the strings, and
+        // the argument count
+        let tag = [&record.kernel_name, &record.source, &record.options]
+            .iter()
+            .map(|s| 8 + s.len())
+            .sum::<usize>()
+            + 12
+            + 8;
+        assert_eq!(bytes[tag], 1);
+        bytes[tag] = 3;
+        assert!(LaunchRecord::read_from(&bytes[..]).is_err());
+    }
+}
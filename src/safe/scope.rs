@@ -0,0 +1,91 @@
+//! Structured concurrency
+//!
+//! `ocl_scope` tracks asynchronous operations started within it, and waits
+//! for all of them to complete before it returns - including when it's left
+//! early by a panic. This prevents host memory used by an operation (e.g. the
+//! source of a non-blocking write) from being freed while the operation is
+//! still running, much like `std::thread::scope` does for threads.
+//!
+//! Tracking is manual: commands enqueued within the scope aren't tracked
+//! automatically, so the event of each asynchronous operation must be passed
+//! to `Scope::track`. Operations which aren't tracked aren't waited for.
+
+use crate::event::Event;
+use crate::raw::clFlush;
+use crate::Result;
+use std::sync::Mutex;
+
+/// A scope tracking asynchronous operations, created by `ocl_scope`
+pub struct Scope {
+    events: Mutex<Vec<Event>>,
+}
+
+impl Scope {
+    /// Track the operation associated with the given event, so that the scope
+    /// waits for it to complete before returning. The event is retained with
+    /// `clRetainEvent`, so the given wrapper may be dropped.
+    pub fn track(&self, event: &Event) -> Result<()> {
+        let event = event.try_clone()?;
+        self.events.lock().unwrap().push(event);
+        Ok(())
+    }
+
+    /// Block until all operations tracked so far have completed, and stop
+    /// tracking them.
+    ///
+    /// Every operation is waited for even if some fail, in which case the
+    /// first error is returned.
+    pub fn wait(&self) -> Result<()> {
+        let events = std::mem::take(&mut *self.events.lock().unwrap());
+        let mut result = Ok(());
+
+        for event in &events {
+            // commands which haven't been submitted to the device yet may
+            // never complete unless their queue is flushed
+            let flushed = event.command_queue_raw().and_then(|queue| unsafe {
+                if !queue.is_null() {
+                    wrap_result!("clFlush" => clFlush(queue))?;
+                }
+
+                Ok(())
+            });
+
+            if let Err(e) = flushed.and_then(|_| event.wait()) {
+                result = result.and(Err(e));
+            }
+        }
+
+        result
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if let Err(e) = self.wait() {
+            log::warn!("Error waiting for operations at end of scope: {:?}", e);
+        }
+    }
+}
+
+/// Run the given function with a scope tracking asynchronous operations,
+/// blocking until all tracked operations have completed before returning.
+///
+/// Only operations whose events are passed to `Scope::track` are waited for.
+/// They're waited for even if the function panics, before the panic continues
+/// to unwind, so host memory borrowed by the function and used by tracked
+/// operations is never freed while still in use. Errors while waiting on a
+/// normal return are returned instead of the function's result, and errors
+/// while unwinding are logged.
+///
+/// Waiting for an operation which can never complete (e.g. one depending on
+/// a user event which is never set) blocks forever, since OpenCL provides no
+/// way to abort enqueued commands.
+pub fn ocl_scope<R>(f: impl FnOnce(&Scope) -> R) -> Result<R> {
+    let scope = Scope {
+        events: Mutex::new(vec![]),
+    };
+
+    let result = f(&scope);
+    scope.wait()?;
+    Ok(result)
+}
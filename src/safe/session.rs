@@ -0,0 +1,178 @@
+//! A simplified, all-in-one API
+//!
+//! `ComputeSession` bundles a device, context, and command queue, and wraps
+//! the most common operations - uploading data, building and running kernels,
+//! and reading results - in a handful of methods. It's intended for
+//! applications which just want optional GPU acceleration, and don't need
+//! control over the rest of the OpenCL object model:
+//!
+//! ```no_run
+//! use dynamic_ocl::session::ComputeSession;
+//! use std::ffi::CString;
+//!
+//! const SOURCE: &str = "
+//! __kernel void double_all(__global int *data) {
+//!     data[get_global_id(0)] *= 2;
+//! }
+//! ";
+//!
+//! if let Some(session) = ComputeSession::try_new() {
+//!     let data = session.buffer_from(&[1, 2, 3]).unwrap();
+//!     let name = CString::new("double_all").unwrap();
+//!     let mut kernel = session.run(SOURCE, &name, data, 3).unwrap();
+//!     let mut data = kernel.arguments();
+//!     assert_eq!(session.read(&mut data).unwrap(), [2, 4, 6]);
+//! }
+//! ```
+//!
+//! Programs are built through `Context::get_or_build_program`, so running the
+//! same source repeatedly only compiles it once, and scratch buffers are taken
+//! from the queue's pool by `with_temporaries`. The underlying objects remain
+//! accessible for anything the session doesn't cover.
+
+use crate::buffer::flags::{HostReadWrite, HostReadable};
+use crate::buffer::{AsBuffer, Buffer, MemSafe};
+use crate::context::Context;
+use crate::device::{Device, DeviceType};
+use crate::kernel::{Kernel, KernelArgList, UnboundKernel};
+use crate::queue::{Arena, Queue, WorkDims};
+use crate::raw::CL_DEVICE_NOT_FOUND;
+use crate::{all_devices, ApiError, Error, Result};
+use std::ffi::CStr;
+use std::mem::size_of;
+
+/// A device, context, and command queue, with convenience methods for simple
+/// compute workloads. See the module documentation for details.
+#[derive(Debug)]
+pub struct ComputeSession {
+    device: Device,
+    context: Context,
+    queue: Queue,
+}
+
+impl ComputeSession {
+    /// Load OpenCL and create a session on the best available device, as
+    /// chosen by `new`, or return `None` if that isn't possible. The reason is
+    /// logged, so applications can fall back to running on the CPU without
+    /// handling OpenCL errors themselves.
+    pub fn try_new() -> Option<Self> {
+        if let Err(e) = crate::load_opencl() {
+            log::info!("OpenCL unavailable: {}", e);
+            return None;
+        }
+
+        match Self::new() {
+            Ok(session) => Some(session),
+            Err(e) => {
+                log::info!("Couldn't create OpenCL session: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Create a session on the best available device: the first GPU if there
+    /// is one, otherwise the first device of any type. Devices exposed by
+    /// multiple platforms are only considered once, preferring the vendor
+    /// platform.
+    pub fn new() -> Result<Self> {
+        let devices: Vec<_> = all_devices()?.dedup().map(|(_, d)| d).collect();
+
+        let device = devices
+            .iter()
+            .copied()
+            .find(|d| {
+                d.device_type()
+                    .map(|t| (t & DeviceType::GPU).raw() != 0)
+                    .unwrap_or(false)
+            })
+            .or_else(|| devices.first().copied())
+            .ok_or_else(|| Error::ApiError(ApiError::new(CL_DEVICE_NOT_FOUND, "clGetDeviceIDs")))?;
+
+        Self::with_device(device)
+    }
+
+    /// Create a session on the given device
+    pub fn with_device(device: Device) -> Result<Self> {
+        let context = device.create_context()?;
+        let queue = context.create_queue(device)?;
+
+        Ok(Self {
+            device,
+            context,
+            queue,
+        })
+    }
+
+    /// Get the device this session runs on
+    pub fn device(&self) -> Device {
+        self.device
+    }
+
+    /// Get the context of this session
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Get the command queue of this session
+    pub fn queue(&self) -> &Queue {
+        &self.queue
+    }
+
+    /// Create a buffer holding a copy of the given data
+    pub fn buffer_from<T: MemSafe>(&self, data: &[T]) -> Result<Buffer<'static, HostReadWrite, T>> {
+        self.context
+            .buffer_builder()
+            .host_access::<HostReadWrite>()
+            .build_copying_slice(data)
+    }
+
+    /// Create a buffer for `len` elements of type `T`, with unspecified
+    /// initial contents
+    pub fn buffer<T: MemSafe>(&self, len: usize) -> Result<Buffer<'static, HostReadWrite, T>> {
+        self.context
+            .buffer_builder()
+            .host_access::<HostReadWrite>()
+            .build_with_size(len)
+    }
+
+    /// Read the entire contents of the given buffer, blocking until complete
+    pub fn read<'a, H: HostReadable, T: MemSafe + Default + Clone>(
+        &self,
+        buffer: &mut dyn AsBuffer<'a, H, T>,
+    ) -> Result<Vec<T>> {
+        let len = buffer.as_buffer().rust_size() / size_of::<T>();
+        let mut data = vec![T::default(); len];
+        self.queue.buffer_cmd(buffer).read(&mut data)?;
+        Ok(data)
+    }
+
+    /// Get the kernel with the given name from the given source, building the
+    /// program only if it hasn't already been built in this session's context
+    pub fn kernel(&self, source: &str, name: &CStr) -> Result<UnboundKernel> {
+        self.context.kernel_from_source(source, name, "")
+    }
+
+    /// Get a kernel as with `kernel`, bind the given arguments, and run it over
+    /// the given global work size, blocking until complete. The kernel is
+    /// returned so that its arguments can be retrieved with
+    /// `Kernel::arguments`, e.g. to read results.
+    pub fn run<T: KernelArgList, W: WorkDims>(
+        &self,
+        source: &str,
+        name: &CStr,
+        args: T,
+        global_work_size: W,
+    ) -> Result<Kernel<T>> {
+        let mut kernel = self.kernel(source, name)?.bind_arguments(args)?;
+        self.queue
+            .kernel_cmd(&mut kernel)
+            .exec_ndrange(global_work_size)?;
+        Ok(kernel)
+    }
+
+    /// Run the given function with an arena providing pooled scratch buffers,
+    /// as with `Queue::with_temporaries`
+    pub fn with_temporaries<R>(&self, f: impl FnOnce(&Queue, &Arena) -> R) -> Result<R> {
+        self.queue.with_temporaries(f)
+    }
+}
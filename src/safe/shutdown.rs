@@ -0,0 +1,127 @@
+//! Deterministic shutdown
+//!
+//! Command queues are registered here while wrappers for them exist, so that
+//! `shutdown` can finish any outstanding work, release crate-level caches, and
+//! report handles which are still alive, rather than relying on drop order at
+//! process exit.
+
+#[cfg(feature = "primitives")]
+use crate::primitives;
+use crate::program;
+use crate::queue::temporaries;
+use crate::raw::{clFinish, clGetCommandQueueInfo, cl_command_queue, cl_uint};
+use crate::raw::{clReleaseCommandQueue, clRetainCommandQueue};
+use crate::raw::{CL_QUEUE_REFERENCE_COUNT, CL_SUCCESS};
+use crate::release;
+use crate::Result;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::ptr::null_mut;
+use std::sync::Mutex;
+
+lazy_static::lazy_static! {
+    /// Live command queue handles, and the number of wrappers for each
+    static ref QUEUES: Mutex<HashMap<usize, usize>> = Default::default();
+}
+
+/// Record a new wrapper for the given command queue
+pub(crate) fn register_queue(queue: cl_command_queue) {
+    *QUEUES.lock().unwrap().entry(queue as usize).or_insert(0) += 1;
+}
+
+/// Record that a wrapper for the given command queue has been dropped,
+/// returning whether it was the last one
+pub(crate) fn unregister_queue(queue: cl_command_queue) -> bool {
+    let mut queues = QUEUES.lock().unwrap();
+
+    if let Some(count) = queues.get_mut(&(queue as usize)) {
+        *count -= 1;
+
+        if *count == 0 {
+            queues.remove(&(queue as usize));
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Finish all outstanding work, release crate-level caches, and report any
+/// OpenCL handles still alive.
+///
+/// This calls `clFinish` on every command queue which still has a wrapper, so
+/// that no commands are running once it returns, then releases the programs
+/// cached by `primitives` and `Context::get_or_build_program` and the scratch
+/// buffers pooled for `Queue::with_temporaries`, along with the contexts they
+/// retain. Finally, it logs a warning for each queue which is still alive,
+/// along with its reference count. It's intended for applications which need
+/// a clean teardown (e.g. before unloading a plugin using this library), and
+/// should be called once all other handles have been dropped.
+///
+/// All queues are finished even if some fail, in which case the first error is
+/// returned.
+pub fn shutdown() -> Result<()> {
+    // retain each queue while the registry is locked, so that a concurrent
+    // drop of its last wrapper can't release it while it's in use here
+    let queues: Vec<(cl_command_queue, usize)> = QUEUES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(&q, &n)| (q as cl_command_queue, n))
+        .filter(|&(q, _)| unsafe { clRetainCommandQueue(q) } == CL_SUCCESS)
+        .collect();
+
+    let mut result: Result<()> = Ok(());
+
+    for &(queue, _) in &queues {
+        unsafe {
+            if let Err(e) = wrap_result!("clFinish" => clFinish(queue)) {
+                log::warn!("Error finishing command queue {:?}: {:?}", queue, e);
+
+                if result.is_ok() {
+                    result = Err(e.into());
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "primitives")]
+    primitives::clear_cache();
+    program::cache::clear_registry();
+    temporaries::release_all_pools();
+
+    for &(queue, wrappers) in &queues {
+        unsafe {
+            let mut refs: cl_uint = 0;
+            let err = clGetCommandQueueInfo(
+                queue,
+                CL_QUEUE_REFERENCE_COUNT,
+                size_of::<cl_uint>(),
+                &mut refs as *mut _ as _,
+                null_mut(),
+            );
+
+            if err == CL_SUCCESS {
+                // exclude the reference held by this function
+                log::warn!(
+                    "Command queue {:?} still alive at shutdown ({} wrapper(s), reference count {})",
+                    queue,
+                    wrappers,
+                    refs.saturating_sub(1)
+                );
+            } else {
+                log::warn!(
+                    "Command queue {:?} still alive at shutdown ({} wrapper(s))",
+                    queue,
+                    wrappers
+                );
+            }
+
+            if let Err(e) = wrap_result!("clReleaseCommandQueue" => clReleaseCommandQueue(queue)) {
+                release::report("OpenCL command queue", &queue, e);
+            }
+        }
+    }
+
+    result
+}
@@ -0,0 +1,48 @@
+//! Strict mode
+//!
+//! When strict mode is enabled, handles are validated before they're used by
+//! the safe API, by querying their reference counts. A handle which has already
+//! been released (e.g. a buffer dropped while the driver still holds an
+//! implicit reference to it) results in a panic with a description of the
+//! handle, rather than a crash deep inside the driver or silent memory
+//! corruption.
+//!
+//! Validation relies on the driver reporting an error for invalid handles,
+//! which most ICD loaders do, so it's a debugging aid rather than a guarantee.
+//! Strict mode is disabled by default, unless the `strict-mode` feature is
+//! set, and can be toggled at runtime with `set_enabled`.
+
+use crate::raw::cl_uint;
+use crate::util::sealed::OclInfoInternal;
+use crate::util::OclInfo;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(cfg!(feature = "strict-mode"));
+
+/// Enable or disable strict mode at runtime
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Check whether strict mode is currently enabled
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Validate the given handle if strict mode is enabled, panicking if it has
+/// been released. `param` must be the reference count info parameter for the
+/// handle type.
+pub(crate) fn check<T: OclInfoInternal>(handle: &T, param: T::Param, what: &str) {
+    if !is_enabled() {
+        return;
+    }
+
+    match handle.get_info::<cl_uint>(param) {
+        Ok(0) => panic!("strict mode: {} used after release", what),
+        Ok(_) => {}
+        Err(e) => panic!(
+            "strict mode: {} is invalid, and may have been used after release ({})",
+            what, e
+        ),
+    }
+}
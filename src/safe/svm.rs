@@ -0,0 +1,221 @@
+//! Shared virtual memory
+//!
+//! SVM allocations can be accessed by the host and by kernels through the
+//! same pointer. For coarse-grained allocations (the only kind every OpenCL
+//! 2.0+ device supports), the host may only access the allocation while it's
+//! mapped, and kernels may only access it while it's unmapped - getting the
+//! sequence of map, unmap, and kernel launches wrong silently corrupts data
+//! rather than failing.
+//!
+//! `SvmBuffer` enforces the correct sequencing through borrows: host access is
+//! only possible through an `SvmGuard`, which maps the allocation when created
+//! and unmaps it when dropped, and which borrows the buffer mutably so that it
+//! can't be bound to a kernel in the meantime.
+
+use crate::buffer::MemSafe;
+use crate::context::Context;
+use crate::kernel::KernelArg;
+use crate::queue::Queue;
+use crate::raw::*;
+use crate::release;
+use crate::{ApiError, Error, Result};
+use libc::size_t;
+use std::ffi::c_void;
+use std::fmt::{self, Debug, Formatter};
+use std::mem::{align_of, size_of};
+use std::ops::{Deref, DerefMut};
+use std::ptr::{null, null_mut};
+
+/// A coarse-grained SVM allocation holding `len` elements of type `T`. See
+/// the module documentation for details.
+///
+/// The allocation keeps a reference to its context, and is freed with
+/// `clSVMFree` when dropped. Since all commands using the allocation through
+/// the safe API block until completion, it's never freed while in use.
+pub struct SvmBuffer<T: MemSafe> {
+    context: Context,
+    ptr: *mut T,
+    len: usize,
+    /// The size of the allocation in bytes
+    size: usize,
+}
+
+unsafe impl<T: MemSafe> Send for SvmBuffer<T> {}
+
+impl<T: MemSafe> Drop for SvmBuffer<T> {
+    fn drop(&mut self) {
+        unsafe { clSVMFree(self.context.raw(), self.ptr as _) }
+    }
+}
+
+impl<T: MemSafe> Debug for SvmBuffer<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(&tynm::type_name::<Self>())
+            .field("ptr", &self.ptr)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<T: MemSafe> SvmBuffer<T> {
+    /// Allocate a coarse-grained SVM buffer for `len` elements of type `T`,
+    /// readable and writable by kernels. The initial contents of the buffer
+    /// are unspecified. Requires OpenCL 2.0+.
+    pub fn new(context: &Context, len: usize) -> Result<Self> {
+        unsafe {
+            check_ocl_version!("clSVMAlloc" => CL20)?;
+
+            let size = size_of::<T>()
+                .checked_mul(len)
+                .ok_or(Error::SizeOverflow("SVM allocation size"))?;
+
+            let ptr = clSVMAlloc(context.raw(), CL_MEM_READ_WRITE, size, align_of::<T>() as _);
+
+            if ptr.is_null() {
+                return Err(Error::ApiError(ApiError::new(
+                    CL_MEM_OBJECT_ALLOCATION_FAILURE,
+                    "clSVMAlloc",
+                )));
+            }
+
+            Ok(Self {
+                context: context.try_clone()?,
+                ptr: ptr as _,
+                len,
+                size,
+            })
+        }
+    }
+
+    /// Get the raw pointer to this buffer. Note that this pointer may only be
+    /// dereferenced by the host while the buffer is mapped.
+    pub fn raw(&self) -> *mut T {
+        self.ptr
+    }
+
+    /// Get the number of elements in this buffer
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether this buffer is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the context this buffer belongs to
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Map this buffer for reading and writing on the host, blocking until
+    /// the mapping is complete. The buffer is unmapped when the returned guard
+    /// is dropped, and can't be used by kernels until then.
+    pub fn map<'b>(&'b mut self, queue: &'b Queue) -> Result<SvmGuard<'b, T>> {
+        unsafe {
+            wrap_result!("clEnqueueSVMMap" => clEnqueueSVMMap(
+                queue.raw(),
+                CL_BLOCKING,
+                CL_MAP_READ | CL_MAP_WRITE,
+                self.ptr as _,
+                self.size,
+                0,
+                null(),
+                null_mut()
+            ))?;
+        }
+
+        Ok(SvmGuard {
+            queue,
+            buffer: self,
+        })
+    }
+}
+
+// SVM buffers can be used as pointer kernel args, borrowing them so that they
+// can't be mapped while bound
+impl<T: MemSafe> KernelArg for &SvmBuffer<T> {
+    type ArgType = *mut T;
+
+    fn is_param_type_compatible(c_type: &str) -> bool {
+        c_type
+            .rsplit_once('*')
+            .map(|(base, _)| T::is_param_type_compatible(base))
+            .unwrap_or(false)
+    }
+
+    fn as_raw_kernel_arg(&self) -> (size_t, &*mut T) {
+        (size_of::<*mut T>(), &self.ptr)
+    }
+
+    fn buffer_len(&self) -> Option<usize> {
+        Some(self.len)
+    }
+
+    unsafe fn set_raw_kernel_arg(&self, kernel: cl_kernel, index: cl_uint) -> Result<()> {
+        wrap_result!("clSetKernelArgSVMPointer" => clSetKernelArgSVMPointer(
+            kernel,
+            index,
+            self.ptr as *const c_void
+        ))?;
+
+        Ok(())
+    }
+}
+
+/// A host mapping of an `SvmBuffer`, which dereferences to its contents and
+/// unmaps the buffer when dropped, as created by `SvmBuffer::map`
+pub struct SvmGuard<'b, T: MemSafe> {
+    queue: &'b Queue,
+    buffer: &'b mut SvmBuffer<T>,
+}
+
+impl<T: MemSafe> SvmGuard<'_, T> {
+    unsafe fn unmap_raw(&self) -> Result<()> {
+        let mut event = null_mut();
+
+        wrap_result!("clEnqueueSVMUnmap" => clEnqueueSVMUnmap(
+            self.queue.raw(),
+            self.buffer.ptr as _,
+            0,
+            null(),
+            &mut event as _
+        ))?;
+
+        let result = wrap_result!("clWaitForEvents" => clWaitForEvents(1, &event as _));
+        clReleaseEvent(event);
+        result?;
+
+        Ok(())
+    }
+
+    /// Unmap the buffer, blocking until complete. This is equivalent to
+    /// dropping the guard, but reports errors rather than logging them.
+    pub fn unmap(self) -> Result<()> {
+        let result = unsafe { self.unmap_raw() };
+        std::mem::forget(self);
+        result
+    }
+}
+
+impl<T: MemSafe> Drop for SvmGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { self.unmap_raw() } {
+            release::report("mapping of SVM buffer", &self.buffer.ptr, e);
+        }
+    }
+}
+
+impl<T: MemSafe> Deref for SvmGuard<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.buffer.ptr, self.buffer.len) }
+    }
+}
+
+impl<T: MemSafe> DerefMut for SvmGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.buffer.ptr, self.buffer.len) }
+    }
+}
@@ -0,0 +1,219 @@
+//! Work size tuning
+//!
+//! The best local work size for a kernel depends on the kernel and the device,
+//! and is often hard to predict, so a common approach is to simply benchmark a
+//! range of candidates and use the fastest. A `Tuner` does this using
+//! profiling events, and caches the results by device, kernel name, and global
+//! work size so that each kernel only needs to be tuned once per device and
+//! problem size.
+//!
+//! Tuning requires a queue created with `QueueProperties::PROFILING_ENABLE`.
+//! Since candidate configurations are executed with the kernel's current
+//! arguments, tuning kernels with side effects (e.g. accumulating into a
+//! buffer) will modify their arguments.
+
+use crate::device::Device;
+use crate::event::Event;
+use crate::kernel::{Kernel, KernelArgList, KernelInfo};
+use crate::queue::{Queue, WorkDims};
+use crate::raw::clEnqueueNDRangeKernel;
+use crate::{Error, Result};
+use std::any::Any;
+use std::collections::HashMap;
+use std::ptr::{null, null_mut};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The result of tuning a kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Tuned<W> {
+    /// The fastest local work size
+    pub local_work_size: W,
+    /// The mean execution time using the fastest local work size
+    pub duration: Duration,
+}
+
+/// A local work size tuner, caching the fastest configuration for each device,
+/// kernel name, and global work size.
+#[derive(Debug)]
+pub struct Tuner {
+    iterations: usize,
+    cache: Mutex<HashMap<CacheKey, Box<dyn Any + Send>>>,
+}
+
+/// The device, kernel name, and global work size a result was tuned for
+type CacheKey = (Device, String, Vec<usize>);
+
+impl Default for Tuner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn dims<W: WorkDims>(w: &W) -> &[usize] {
+    unsafe { std::slice::from_raw_parts(w.as_ptr(), W::NUM_WORK_DIMS as usize) }
+}
+
+impl Tuner {
+    /// Create a new tuner, which runs each candidate 5 times
+    pub fn new() -> Self {
+        Self {
+            iterations: 5,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set the number of times each candidate is executed when benchmarking,
+    /// not counting an initial warm-up run
+    pub fn iterations(self, iterations: usize) -> Self {
+        assert!(iterations > 0, "number of iterations must be nonzero");
+
+        Self { iterations, ..self }
+    }
+
+    /// Forget all cached results
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Get the cached result for the given device, kernel name, and global
+    /// work size, if any
+    pub fn cached<W: WorkDims + Copy + Send + 'static>(
+        &self,
+        device: Device,
+        kernel_name: &str,
+        global_work_size: &W,
+    ) -> Option<Tuned<W>> {
+        let key = (
+            device,
+            kernel_name.to_string(),
+            dims(global_work_size).to_vec(),
+        );
+
+        self.cache
+            .lock()
+            .unwrap()
+            .get(&key)
+            .and_then(|r| r.downcast_ref::<Tuned<W>>())
+            .copied()
+    }
+
+    /// Find the fastest of the given candidate local work sizes for executing
+    /// the kernel with the given global work size, or return the cached result
+    /// if the kernel has already been tuned on the queue's device with the
+    /// same global work size, and the cached local work size is one of the
+    /// candidates.
+    ///
+    /// Candidates which exceed the device or kernel work group size limits,
+    /// or which don't evenly divide the global work size, are skipped. If no
+    /// candidates remain, `Error::LimitExceeded` is returned.
+    pub fn tune<T: KernelArgList, W: WorkDims + Copy + Send + 'static>(
+        &self,
+        queue: &Queue,
+        kernel: &mut Kernel<T>,
+        global_work_size: W,
+        candidates: &[W],
+    ) -> Result<Tuned<W>> {
+        assert!(!candidates.is_empty(), "no candidate work sizes given");
+
+        let device = queue.device()?;
+        let name = kernel.function_name()?.to_string_lossy().into_owned();
+
+        if let Some(tuned) = self.cached(device, &name, &global_work_size) {
+            let local = dims(&tuned.local_work_size);
+            if candidates.iter().any(|c| dims(c) == local) {
+                return Ok(tuned);
+            }
+        }
+
+        let max_group_size = kernel.as_unbound().work_group_size(device)?;
+        let max_item_sizes = device.max_work_item_sizes()?;
+
+        let valid: Vec<W> = candidates
+            .iter()
+            .copied()
+            .filter(|c| {
+                let local = dims(c);
+                local.iter().product::<usize>() <= max_group_size
+                    && local.iter().zip(&max_item_sizes).all(|(l, m)| l <= m)
+                    && local
+                        .iter()
+                        .zip(dims(&global_work_size))
+                        .all(|(&l, &g)| l != 0 && g / l * l == g)
+            })
+            .collect();
+
+        if valid.is_empty() {
+            return Err(Error::LimitExceeded {
+                limit: "CL_KERNEL_WORK_GROUP_SIZE",
+                requested: dims(&candidates[0]).iter().product(),
+                max: max_group_size,
+            });
+        }
+
+        let mut best: Option<Tuned<W>> = None;
+
+        for candidate in valid {
+            // warm up, so that one-time setup costs aren't measured
+            run(queue, kernel, &global_work_size, &candidate)?;
+
+            let mut total = Duration::ZERO;
+
+            for _ in 0..self.iterations {
+                total += run(queue, kernel, &global_work_size, &candidate)?;
+            }
+
+            let duration = total / self.iterations as u32;
+
+            log::debug!(
+                "Tuning {}: local work size {:?} took {:?}",
+                name,
+                dims(&candidate),
+                duration
+            );
+
+            if best.map(|b| duration < b.duration).unwrap_or(true) {
+                best = Some(Tuned {
+                    local_work_size: candidate,
+                    duration,
+                });
+            }
+        }
+
+        let best = best.unwrap();
+        self.cache.lock().unwrap().insert(
+            (device, name, dims(&global_work_size).to_vec()),
+            Box::new(best),
+        );
+
+        Ok(best)
+    }
+}
+
+fn run<T: KernelArgList, W: WorkDims>(
+    queue: &Queue,
+    kernel: &mut Kernel<T>,
+    global_work_size: &W,
+    local_work_size: &W,
+) -> Result<Duration> {
+    let event = unsafe {
+        let mut event = null_mut();
+
+        wrap_result!("clEnqueueNDRangeKernel" => clEnqueueNDRangeKernel(
+            queue.raw(),
+            kernel.as_unbound().raw(),
+            W::NUM_WORK_DIMS,
+            null(),
+            global_work_size.as_ptr(),
+            local_work_size.as_ptr(),
+            0,
+            null(),
+            &mut event as _
+        ))?;
+
+        Event(event)
+    };
+
+    event.wait()?;
+    event.profiling_duration()
+}
@@ -124,6 +124,10 @@ impl<T: sealed::OclInfoInternal> OclInfo for T {}
 
 /// A trait to get OpenCL information and automatically convert it to a more
 /// useful type.
+///
+/// Conversions don't panic on unexpected data from the driver - if the size of
+/// the data doesn't match the type being read (as reported by some buggy ICDs),
+/// `Error::InvalidDataLength` is returned instead.
 pub trait FromOclInfo: Sized {
     fn read<T: OclInfo>(from: &T, param_name: T::Param) -> Result<Self>;
 }
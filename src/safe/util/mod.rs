@@ -1,3 +1,11 @@
+//! Utilities
+//!
+//! `OclInfo` and `FromOclInfo` are the single framework used by every wrapper
+//! type to query OpenCL information, so new conversions only need to be added
+//! here.
+
 mod info;
+mod refcount;
 
 pub use info::*;
+pub use refcount::RefCounted;
@@ -0,0 +1,119 @@
+use super::OclInfo;
+use crate::raw::cl_uint;
+use crate::Result;
+
+pub(crate) mod sealed {
+    use crate::raw::cl_int;
+    use crate::util::sealed::OclInfoInternal;
+
+    pub trait RefCountedInternal: OclInfoInternal {
+        const REFERENCE_COUNT: Self::Param;
+        const RETAIN_CONTEXT: &'static str;
+        const RELEASE_CONTEXT: &'static str;
+
+        unsafe fn retain_raw(&self) -> cl_int;
+        unsafe fn release_raw(&self) -> cl_int;
+    }
+}
+
+/// A trait implemented by reference counted OpenCL wrapper types, providing
+/// manual control over the reference count of the underlying handle.
+///
+/// Wrappers already retain and release their handles as they're cloned and
+/// dropped, so this is only needed for advanced ownership schemes, such as
+/// caching layers or foreign code holding extra references.
+pub trait RefCounted: sealed::RefCountedInternal + OclInfo + Sized {
+    /// Increment the reference count of the underlying handle, without
+    /// creating a new wrapper. The extra reference is leaked unless released
+    /// with `release`.
+    fn retain(&self) -> Result<()> {
+        unsafe {
+            wrap_result!(Self::RETAIN_CONTEXT => self.retain_raw())?;
+            Ok(())
+        }
+    }
+
+    /// Decrement the reference count of the underlying handle, without
+    /// dropping this wrapper.
+    ///
+    /// # Safety
+    ///
+    /// Every call must be balanced by an earlier call to `retain` (or a
+    /// reference taken by foreign code), since the wrapper releases its own
+    /// reference when dropped. Releasing a reference which isn't owned may
+    /// free the handle while still in use.
+    unsafe fn release(&self) -> Result<()> {
+        wrap_result!(Self::RELEASE_CONTEXT => self.release_raw())?;
+        Ok(())
+    }
+
+    /// Check that the reference count of the underlying handle is `expected`,
+    /// logging a warning describing the handle if not.
+    ///
+    /// Reference counts reported by OpenCL may include references held
+    /// internally by the implementation, and are stale as soon as they're
+    /// returned if other threads use the handle, so this is only suitable for
+    /// debugging.
+    fn check_reference_count(&self, expected: cl_uint) -> Result<bool> {
+        let actual: cl_uint = self.get_info(Self::REFERENCE_COUNT)?;
+
+        if actual != expected {
+            log::warn!(
+                "{} reference count is {}, expected {}",
+                tynm::type_name::<Self>(),
+                actual,
+                expected
+            );
+        }
+
+        Ok(actual == expected)
+    }
+}
+
+impl<T: sealed::RefCountedInternal> RefCounted for T {}
+
+macro_rules! ref_counted {
+    ( $( $typ:ty => $retain:ident, $release:ident, $param:ident; )* ) => {
+        $(
+            impl sealed::RefCountedInternal for $typ {
+                const REFERENCE_COUNT: Self::Param = crate::raw::$param;
+                const RETAIN_CONTEXT: &'static str = stringify!($retain);
+                const RELEASE_CONTEXT: &'static str = stringify!($release);
+
+                unsafe fn retain_raw(&self) -> crate::raw::cl_int {
+                    crate::raw::$retain(self.raw())
+                }
+
+                unsafe fn release_raw(&self) -> crate::raw::cl_int {
+                    crate::raw::$release(self.raw())
+                }
+            }
+        )*
+    };
+}
+
+ref_counted! {
+    crate::context::Context => clRetainContext, clReleaseContext, CL_CONTEXT_REFERENCE_COUNT;
+    crate::queue::Queue => clRetainCommandQueue, clReleaseCommandQueue, CL_QUEUE_REFERENCE_COUNT;
+    crate::program::Program => clRetainProgram, clReleaseProgram, CL_PROGRAM_REFERENCE_COUNT;
+    crate::kernel::UnboundKernel => clRetainKernel, clReleaseKernel, CL_KERNEL_REFERENCE_COUNT;
+    crate::event::Event => clRetainEvent, clReleaseEvent, CL_EVENT_REFERENCE_COUNT;
+}
+
+impl<H, T> sealed::RefCountedInternal for crate::buffer::Buffer<'_, H, T>
+where
+    H: crate::buffer::flags::HostAccess,
+    T: crate::buffer::MemSafe,
+{
+    const REFERENCE_COUNT: Self::Param = crate::raw::CL_MEM_REFERENCE_COUNT;
+    const RETAIN_CONTEXT: &'static str = "clRetainMemObject";
+    const RELEASE_CONTEXT: &'static str = "clReleaseMemObject";
+
+    unsafe fn retain_raw(&self) -> crate::raw::cl_int {
+        crate::raw::clRetainMemObject(self.raw())
+    }
+
+    unsafe fn release_raw(&self) -> crate::raw::cl_int {
+        crate::raw::clReleaseMemObject(self.raw())
+    }
+}
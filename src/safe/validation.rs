@@ -0,0 +1,410 @@
+//! Validation layer
+//!
+//! Similar in spirit to the Vulkan validation layers, a `ValidationLayer`
+//! enables host-side checks for commands enqueued through the safe API on any
+//! queue in its context, catching mistakes which OpenCL drivers often don't
+//! report, or only report as an unhelpful error code:
+//!
+//! - kernels launched with fewer or more arguments than they declare (e.g.
+//!   when bound with `bind_arguments_unchecked`)
+//! - global work sizes of zero, local work sizes which don't divide the global
+//!   work size, or which exceed the maximum work group size of the kernel
+//! - buffers which are too small for the work size, according to hints given
+//!   with `ValidationLayer::hint_buffer`
+//! - buffers mapped for host access not permitted by their memory flags (e.g.
+//!   mapping a `CL_MEM_HOST_READ_ONLY` buffer for writing, which is possible
+//!   for buffers wrapped with `Buffer::from_raw_parts`)
+//!
+//! Problems are reported as structured `Diagnostic`s, which are logged and
+//! kept by the layer until taken with `take_diagnostics`. Commands are still
+//! enqueued when problems are found, so validation doesn't change the behavior
+//! of an application, only how much it reports. Since each check queries the
+//! driver, validation is intended for development builds.
+
+use crate::context::Context;
+use crate::kernel::{Kernel, KernelArgList, KernelInfo};
+use crate::queue::{Queue, WorkDims};
+use crate::raw::*;
+use crate::Result;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::mem::size_of;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The severity of a validation diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    /// The command is valid, but may not behave as intended
+    Warning,
+    /// The command is invalid, and will fail or access memory out of bounds
+    Error,
+}
+
+/// The check which produced a validation diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Check {
+    /// The number of arguments bound to a kernel
+    ArgumentCount,
+    /// The global or local work size of a kernel launch
+    WorkSize,
+    /// The size of a buffer passed to a kernel, compared to the work size
+    BufferSize,
+    /// The host access flags of a mapped buffer
+    HostAccess,
+}
+
+/// A problem found by a validation layer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    severity: Severity,
+    check: Check,
+    kernel: Option<String>,
+    message: String,
+}
+
+impl Diagnostic {
+    /// Get the severity of this diagnostic
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Get the check which produced this diagnostic
+    pub fn check(&self) -> Check {
+        self.check
+    }
+
+    /// Get the name of the kernel being launched, if the diagnostic was
+    /// produced for a kernel launch
+    pub fn kernel(&self) -> Option<&str> {
+        self.kernel.as_deref()
+    }
+
+    /// Get a description of the problem
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "validation {:?} ({:?}", self.severity, self.check)?;
+
+        if let Some(kernel) = &self.kernel {
+            write!(f, " in kernel {}", kernel)?;
+        }
+
+        write!(f, "): {}", self.message)
+    }
+}
+
+#[derive(Default)]
+struct LayerState {
+    layers: usize,
+    enabled: bool,
+    hints: HashMap<(String, cl_uint), usize>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl LayerState {
+    fn report(&mut self, severity: Severity, check: Check, kernel: Option<&str>, message: String) {
+        let diagnostic = Diagnostic {
+            severity,
+            check,
+            kernel: kernel.map(str::to_string),
+            message,
+        };
+
+        match severity {
+            Severity::Warning => log::warn!("{}", diagnostic),
+            Severity::Error => log::error!("{}", diagnostic),
+        }
+
+        self.diagnostics.push(diagnostic);
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Validation state for each context, keyed by raw handle
+    static ref LAYERS: Mutex<HashMap<usize, LayerState>> = Default::default();
+}
+
+/// The number of contexts with validation layers, to avoid querying the
+/// context of each queue when no layers exist
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Run the given function with the validation state for the context of the
+/// given queue, if validation is enabled for it
+fn with_state(queue: &Queue, f: impl FnOnce(&mut LayerState)) {
+    if ACTIVE.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+
+    let context = match queue.context_raw() {
+        Ok(context) => context as usize,
+        Err(_) => return,
+    };
+
+    if let Some(state) = LAYERS.lock().unwrap().get_mut(&context) {
+        if state.enabled {
+            f(state);
+        }
+    }
+}
+
+/// Validate a kernel launch, before it's enqueued
+pub(crate) fn check_launch<T: KernelArgList, W: WorkDims>(
+    queue: &Queue,
+    kernel: &Kernel<T>,
+    global_work_size: &W,
+    local_work_size: Option<&W>,
+) {
+    with_state(queue, |state| {
+        let name = kernel
+            .function_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok();
+        let name = name.as_deref();
+
+        let dims = |w: &W| unsafe {
+            std::slice::from_raw_parts(w.as_ptr(), W::NUM_WORK_DIMS as _).to_vec()
+        };
+
+        match kernel.num_args() {
+            Ok(n) if n as usize != kernel.num_bound_args() => state.report(
+                Severity::Error,
+                Check::ArgumentCount,
+                name,
+                format!(
+                    "kernel declares {} arguments, but {} are bound",
+                    n,
+                    kernel.num_bound_args()
+                ),
+            ),
+            _ => {}
+        }
+
+        let global = dims(global_work_size);
+
+        if global.contains(&0) {
+            state.report(
+                Severity::Error,
+                Check::WorkSize,
+                name,
+                format!("global work size {:?} is empty", global),
+            );
+        }
+
+        if let Some(local) = local_work_size.map(dims) {
+            if global
+                .iter()
+                .zip(&local)
+                .any(|(&g, &l)| l == 0 || g / l * l != g)
+            {
+                state.report(
+                    Severity::Warning,
+                    Check::WorkSize,
+                    name,
+                    format!(
+                        "local work size {:?} doesn't divide global work size {:?}, which requires non-uniform work groups (OpenCL 2.0+)",
+                        local, global
+                    ),
+                );
+            }
+
+            let max = queue
+                .device()
+                .and_then(|d| kernel.as_unbound().work_group_size(d));
+            let size: usize = local.iter().product();
+
+            match max {
+                Ok(max) if size > max => state.report(
+                    Severity::Error,
+                    Check::WorkSize,
+                    name,
+                    format!(
+                        "local work size {:?} has {} work items, but the kernel supports at most {}",
+                        local, size, max
+                    ),
+                ),
+                _ => {}
+            }
+        }
+
+        if let Some(name) = name {
+            let items: usize = global.iter().product();
+            let lens = T::buffer_lens(kernel);
+
+            let hints: Vec<(cl_uint, usize)> = state
+                .hints
+                .iter()
+                .filter(|((kernel, _), _)| kernel == name)
+                .map(|(&(_, arg), &per_item)| (arg, per_item))
+                .collect();
+
+            for (arg, per_item) in hints {
+                let required = items * per_item;
+
+                match lens.get(arg as usize) {
+                    Some(Some(len)) if *len < required => state.report(
+                        Severity::Error,
+                        Check::BufferSize,
+                        Some(name),
+                        format!(
+                            "argument #{} has {} elements, but {} are required for {} work items",
+                            arg, len, required, items
+                        ),
+                    ),
+                    Some(Some(_)) => {}
+                    _ => state.report(
+                        Severity::Warning,
+                        Check::BufferSize,
+                        Some(name),
+                        format!("argument #{} has a size hint, but isn't a buffer", arg),
+                    ),
+                }
+            }
+        }
+    });
+}
+
+/// Validate a buffer mapping, before it's enqueued
+pub(crate) fn check_map(queue: &Queue, mem: cl_mem, map_flags: cl_map_flags) {
+    with_state(queue, |state| {
+        let mut flags: cl_mem_flags = 0;
+
+        let result = unsafe {
+            clGetMemObjectInfo(
+                mem,
+                CL_MEM_FLAGS,
+                size_of::<cl_mem_flags>(),
+                &mut flags as *mut _ as _,
+                null_mut(),
+            )
+        };
+
+        if result != CL_SUCCESS {
+            return;
+        }
+
+        let writes = map_flags & (CL_MAP_WRITE | CL_MAP_WRITE_INVALIDATE_REGION) != 0;
+        let reads = map_flags & CL_MAP_READ != 0;
+
+        if writes && flags & (CL_MEM_HOST_READ_ONLY | CL_MEM_HOST_NO_ACCESS) != 0 {
+            state.report(
+                Severity::Error,
+                Check::HostAccess,
+                None,
+                format!(
+                    "buffer {:?} mapped for writing, but the host may not write to it",
+                    mem
+                ),
+            );
+        }
+
+        if reads && flags & (CL_MEM_HOST_WRITE_ONLY | CL_MEM_HOST_NO_ACCESS) != 0 {
+            state.report(
+                Severity::Error,
+                Check::HostAccess,
+                None,
+                format!(
+                    "buffer {:?} mapped for reading, but the host may not read from it",
+                    mem
+                ),
+            );
+        }
+    });
+}
+
+/// A validation layer for a context, which checks commands enqueued on any of
+/// its queues while it exists and is enabled. See the module documentation
+/// for details.
+///
+/// Multiple layers for the same context share their hints and diagnostics.
+pub struct ValidationLayer {
+    context: Context,
+}
+
+impl ValidationLayer {
+    /// Create a validation layer for the given context, which is initially
+    /// enabled
+    pub fn new(context: &Context) -> Result<Self> {
+        let context = context.try_clone()?;
+        let mut layers = LAYERS.lock().unwrap();
+        let state = layers.entry(context.raw() as usize).or_default();
+
+        if state.layers == 0 {
+            ACTIVE.fetch_add(1, Ordering::Relaxed);
+        }
+
+        state.layers += 1;
+        state.enabled = true;
+
+        Ok(Self { context })
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut LayerState) -> R) -> R {
+        f(LAYERS
+            .lock()
+            .unwrap()
+            .get_mut(&(self.context.raw() as usize))
+            .unwrap())
+    }
+
+    /// Get the context validated by this layer
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Enable or disable validation for the context, without discarding hints
+    /// or diagnostics
+    pub fn set_enabled(&self, enabled: bool) {
+        self.with_state(|s| s.enabled = enabled);
+    }
+
+    /// Check whether validation is currently enabled for the context
+    pub fn is_enabled(&self) -> bool {
+        self.with_state(|s| s.enabled)
+    }
+
+    /// Hint that the buffer passed as the given argument of kernels with the
+    /// given name is indexed by work item, with `elements_per_item` elements
+    /// accessed by each one. Launches of the kernel are then checked to ensure
+    /// the buffer has at least `elements_per_item` elements for each work item
+    /// in the global work size.
+    pub fn hint_buffer(&self, kernel: &str, arg: cl_uint, elements_per_item: usize) {
+        self.with_state(|s| s.hints.insert((kernel.to_string(), arg), elements_per_item));
+    }
+
+    /// Get the diagnostics reported so far
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.with_state(|s| s.diagnostics.clone())
+    }
+
+    /// Take the diagnostics reported so far, clearing them
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        self.with_state(|s| std::mem::take(&mut s.diagnostics))
+    }
+
+    /// Check whether any diagnostics with `Severity::Error` have been reported
+    pub fn has_errors(&self) -> bool {
+        self.with_state(|s| s.diagnostics.iter().any(|d| d.severity == Severity::Error))
+    }
+}
+
+impl Drop for ValidationLayer {
+    fn drop(&mut self) {
+        let mut layers = LAYERS.lock().unwrap();
+        let key = self.context.raw() as usize;
+
+        if let Some(state) = layers.get_mut(&key) {
+            state.layers -= 1;
+
+            if state.layers == 0 {
+                layers.remove(&key);
+                ACTIVE.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
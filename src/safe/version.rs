@@ -0,0 +1,258 @@
+//! OpenCL version gating
+//!
+//! Many OpenCL APIs require a minimum version of the system OpenCL library,
+//! which is otherwise checked at runtime each time they're called. Instead, a
+//! handle can be upgraded once with `RequireVersion::require_version`, which
+//! checks the version and returns a `Versioned` wrapper. APIs requiring a given
+//! version are then available on the wrapper without further checks, so using
+//! them without a version check is a compile-time error rather than a runtime
+//! one.
+//!
+//! This module also provides `NumericVersion` and `NameVersion`, which
+//! represent the structured version information reported by OpenCL 3.0
+//! runtimes.
+
+use crate::context::Context;
+use crate::device::Device;
+use crate::kernel::UnboundKernel;
+use crate::platform::Platform;
+use crate::program::Program;
+use crate::queue::Queue;
+use crate::raw::{
+    cl_name_version, cl_uint, cl_version, OpenCLVersion, CL_VERSION_MAJOR_BITS,
+    CL_VERSION_MINOR_BITS, CL_VERSION_PATCH_BITS, SYSTEM_OPENCL_VERSION,
+};
+use crate::util::{FromOclInfo, OclInfo};
+use crate::{Error, Result};
+use std::ffi::CStr;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+mod sealed {
+    pub trait ApiVersionInternal {}
+}
+
+/// A marker type representing an OpenCL version
+pub trait ApiVersion: sealed::ApiVersionInternal + Copy + Debug + 'static {
+    /// The OpenCL version represented by this type
+    const VERSION: OpenCLVersion;
+}
+
+/// A trait implemented by version markers representing version `V` or newer
+pub trait AtLeast<V: ApiVersion>: ApiVersion {}
+
+macro_rules! api_versions {
+    ( $( $name:ident = $version:ident : [ $( $older:ident ),* ] ),* $(,)? ) => {
+        $(
+            #[doc = concat!("Marker type for `OpenCLVersion::", stringify!($version), "`")]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+            pub struct $name;
+
+            impl sealed::ApiVersionInternal for $name {}
+
+            impl ApiVersion for $name {
+                const VERSION: OpenCLVersion = OpenCLVersion::$version;
+            }
+
+            impl AtLeast<$name> for $name {}
+
+            $(
+                impl AtLeast<$older> for $name {}
+            )*
+        )*
+    };
+}
+
+api_versions! {
+    Cl10 = CL10: [],
+    Cl11 = CL11: [Cl10],
+    Cl12 = CL12: [Cl10, Cl11],
+    Cl20 = CL20: [Cl10, Cl11, Cl12],
+    Cl21 = CL21: [Cl10, Cl11, Cl12, Cl20],
+    Cl22 = CL22: [Cl10, Cl11, Cl12, Cl20, Cl21],
+    Cl30 = CL30: [Cl10, Cl11, Cl12, Cl20, Cl21, Cl22],
+}
+
+/// A reference to a handle which has been checked to support OpenCL version
+/// `V`, as returned by `RequireVersion::require_version`.
+///
+/// The wrapper dereferences to the handle, so all of the handle's usual APIs
+/// remain available.
+#[derive(Debug)]
+pub struct Versioned<'a, H, V: ApiVersion> {
+    handle: &'a H,
+    _version: PhantomData<V>,
+}
+
+impl<'a, H, V: ApiVersion> Clone for Versioned<'a, H, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, H, V: ApiVersion> Copy for Versioned<'a, H, V> {}
+
+impl<'a, H, V: ApiVersion> Deref for Versioned<'a, H, V> {
+    type Target = H;
+
+    fn deref(&self) -> &H {
+        self.handle
+    }
+}
+
+impl<'a, H, V: ApiVersion> Versioned<'a, H, V> {
+    /// Get the handle this wrapper refers to
+    pub fn handle(&self) -> &'a H {
+        self.handle
+    }
+
+    /// Convert this wrapper to one for an older OpenCL version
+    pub fn downgrade<V2: ApiVersion>(self) -> Versioned<'a, H, V2>
+    where
+        V: AtLeast<V2>,
+    {
+        Versioned {
+            handle: self.handle,
+            _version: PhantomData,
+        }
+    }
+}
+
+/// A trait implemented by handles which can be upgraded to a `Versioned`
+/// wrapper
+pub trait RequireVersion: Sized {
+    /// Check that the system OpenCL library supports version `V`, returning a
+    /// wrapper providing access to APIs which require that version.
+    fn require_version<V: ApiVersion>(&self) -> Result<Versioned<'_, Self, V>> {
+        let actual = unsafe { SYSTEM_OPENCL_VERSION };
+
+        if actual < V::VERSION {
+            return Err(Error::UnsupportedVersion {
+                expected: V::VERSION,
+                actual,
+                context: "require_version",
+            });
+        }
+
+        Ok(Versioned {
+            handle: self,
+            _version: PhantomData,
+        })
+    }
+}
+
+impl RequireVersion for Platform {}
+impl RequireVersion for Device {}
+impl RequireVersion for Context {}
+impl RequireVersion for Queue {}
+impl RequireVersion for Program {}
+impl RequireVersion for UnboundKernel {}
+
+/// A version number packed into a `cl_version`, as reported by OpenCL 3.0
+/// queries such as `CL_DEVICE_NUMERIC_VERSION`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NumericVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl NumericVersion {
+    const MAJOR_BITS: u32 = CL_VERSION_MAJOR_BITS;
+    const MINOR_BITS: u32 = CL_VERSION_MINOR_BITS;
+    const PATCH_BITS: u32 = CL_VERSION_PATCH_BITS;
+
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Unpack a raw `cl_version`
+    pub fn from_raw(raw: cl_version) -> Self {
+        Self {
+            major: raw >> (Self::MINOR_BITS + Self::PATCH_BITS),
+            minor: (raw >> Self::PATCH_BITS) & ((1 << Self::MINOR_BITS) - 1),
+            patch: raw & ((1 << Self::PATCH_BITS) - 1),
+        }
+    }
+
+    /// Pack this version into a raw `cl_version`, truncating any components
+    /// which are out of range
+    pub fn raw(self) -> cl_version {
+        ((self.major & ((1 << Self::MAJOR_BITS) - 1)) << (Self::MINOR_BITS + Self::PATCH_BITS))
+            | ((self.minor & ((1 << Self::MINOR_BITS) - 1)) << Self::PATCH_BITS)
+            | (self.patch & ((1 << Self::PATCH_BITS) - 1))
+    }
+}
+
+impl Display for NumericVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromOclInfo for NumericVersion {
+    fn read<T: OclInfo>(from: &T, param_name: T::Param) -> Result<Self> {
+        cl_uint::read(from, param_name).map(Self::from_raw)
+    }
+}
+
+/// A named, versioned entry, such as an extension or OpenCL C feature, as
+/// reported by OpenCL 3.0 queries such as `CL_DEVICE_EXTENSIONS_WITH_VERSION`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NameVersion {
+    pub name: String,
+    pub version: NumericVersion,
+}
+
+impl NameVersion {
+    fn from_raw(raw: &cl_name_version) -> Self {
+        let name = unsafe { CStr::from_ptr(raw.name.as_ptr()) };
+
+        Self {
+            name: name.to_string_lossy().into_owned(),
+            version: NumericVersion::from_raw(raw.version),
+        }
+    }
+}
+
+impl FromOclInfo for Vec<NameVersion> {
+    fn read<T: OclInfo>(from: &T, param_name: T::Param) -> Result<Self> {
+        let raw = from.get_info_raw(param_name)?;
+        let size = std::mem::size_of::<cl_name_version>();
+
+        if raw.len() / size * size != raw.len() {
+            return Err(Error::InvalidDataLength {
+                expected: raw.len() / size * size,
+                actual: raw.len(),
+            });
+        }
+
+        Ok(raw
+            .chunks(size)
+            .map(|c| {
+                let mut entry: cl_name_version = unsafe { std::mem::zeroed() };
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        c.as_ptr(),
+                        &mut entry as *mut _ as *mut u8,
+                        size,
+                    );
+                }
+                // guard against drivers which don't null-terminate names
+                *entry.name.last_mut().unwrap() = 0;
+                NameVersion::from_raw(&entry)
+            })
+            .collect())
+    }
+}
+
+/// Find the version of the named entry in a list returned by an OpenCL 3.0
+/// query, if present
+pub(crate) fn find_version(list: &[NameVersion], name: &str) -> Option<NumericVersion> {
+    list.iter().find(|e| e.name == name).map(|e| e.version)
+}